@@ -0,0 +1,167 @@
+use criterion::{
+    black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput,
+};
+use rust_decimal::Decimal;
+use rusty_bank::{
+    ClientId, CsvAccountWriter, InMemoryAccountStore, ShardedTransactionProcessor, Transaction,
+    TransactionId, TransactionProcessor, TransactionRecord, TransactionType, VecTransactionReader,
+};
+
+/// Shape of a synthetic transaction stream.
+#[derive(Debug, Clone, Copy)]
+struct Workload {
+    records: u32,
+    clients: u32,
+    /// Fraction of records which open a dispute, each followed by its resolve or chargeback.
+    dispute_ratio: f64,
+}
+
+/// Generates a deterministic stream of deposits, withdrawals and disputes.
+///
+/// Client IDs are scattered with a multiplicative hash so that accesses are not sequential.
+/// Disputes reference the client's most recent deposit and are resolved straight away, apart from
+/// one in ten which is charged back and so locks the account.
+fn generate(workload: Workload) -> Vec<TransactionRecord> {
+    let mut last_deposit = vec![None; workload.clients as usize];
+    let mut records = Vec::with_capacity(workload.records as usize);
+    let mut tx: u32 = 0;
+    while records.len() < workload.records as usize {
+        tx += 1;
+        let hash = tx.wrapping_mul(2_654_435_761);
+        let client = hash % workload.clients;
+        let amount = Some(Decimal::new((hash % 10_000) as i64 + 1, 4));
+        let record = |transaction_type, tx, amount| {
+            TransactionRecord::new(
+                transaction_type,
+                ClientId(client as u16),
+                TransactionId(tx),
+                amount,
+            )
+        };
+
+        let dispute = (hash % 1_000) as f64 / 1_000.0 < workload.dispute_ratio;
+        match last_deposit[client as usize].take() {
+            Some(deposit) if dispute => {
+                records.push(record(TransactionType::Dispute, deposit, None));
+                let settle = match tx % 10 {
+                    0 => TransactionType::Chargeback,
+                    _ => TransactionType::Resolve,
+                };
+                records.push(record(settle, deposit, None));
+            }
+            _ if tx.is_multiple_of(3) => {
+                records.push(record(TransactionType::Withdrawal, tx, amount))
+            }
+            _ => {
+                records.push(record(TransactionType::Deposit, tx, amount));
+                last_deposit[client as usize] = Some(tx);
+            }
+        }
+    }
+    records.truncate(workload.records as usize);
+    records
+}
+
+fn bench_conversion(c: &mut Criterion) {
+    let records = generate(Workload {
+        records: 100_000,
+        clients: 1_000,
+        dispute_ratio: 0.1,
+    });
+
+    let mut group = c.benchmark_group("conversion");
+    group.throughput(Throughput::Elements(records.len() as u64));
+    group.bench_function("TransactionRecord into Transaction", |b| {
+        b.iter_batched(
+            || records.clone(),
+            |records| {
+                for record in records {
+                    let transaction: anyhow::Result<Transaction> = record.into();
+                    black_box(transaction.ok());
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+fn bench_process(c: &mut Criterion) {
+    let mut group = c.benchmark_group("process");
+    group.sample_size(10);
+    for clients in [100, 10_000] {
+        for dispute_ratio in [0.0, 0.1] {
+            let workload = Workload {
+                records: 1_000_000,
+                clients,
+                dispute_ratio,
+            };
+            let records = generate(workload);
+            group.throughput(Throughput::Elements(records.len() as u64));
+            group.bench_with_input(
+                BenchmarkId::new(
+                    "TransactionProcessor::process",
+                    format!("clients={}/disputes={}", clients, dispute_ratio),
+                ),
+                &records,
+                |b, records| {
+                    b.iter_batched(
+                        || VecTransactionReader::new(records.clone()),
+                        |reader| {
+                            let mut processor =
+                                TransactionProcessor::new(InMemoryAccountStore::new());
+                            processor.process(reader);
+                            let writer = CsvAccountWriter::from_writer(std::io::sink());
+                            processor.export(writer).unwrap();
+                        },
+                        BatchSize::LargeInput,
+                    )
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+fn bench_process_sharded(c: &mut Criterion) {
+    let mut group = c.benchmark_group("process_sharded");
+    group.sample_size(10);
+    let records = generate(Workload {
+        records: 1_000_000,
+        clients: 10_000,
+        dispute_ratio: 0.1,
+    });
+    group.throughput(Throughput::Elements(records.len() as u64));
+    for shards in [1, 2, 4, 8] {
+        group.bench_with_input(
+            BenchmarkId::new(
+                "ShardedTransactionProcessor::process",
+                format!("shards={}", shards),
+            ),
+            &records,
+            |b, records| {
+                b.iter_batched(
+                    || VecTransactionReader::new(records.clone()),
+                    |reader| {
+                        let mut processor = ShardedTransactionProcessor::new(shards, || {
+                            Ok(TransactionProcessor::new(InMemoryAccountStore::new()))
+                        });
+                        processor.process(reader);
+                        let writer = CsvAccountWriter::from_writer(std::io::sink());
+                        processor.export(writer).unwrap();
+                    },
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_conversion,
+    bench_process,
+    bench_process_sharded
+);
+criterion_main!(benches);
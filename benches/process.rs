@@ -0,0 +1,64 @@
+use std::io::Cursor;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use rusty_bank::{CsvTransactionReader, InMemoryAccountStore, TransactionProcessor};
+
+const RECORDS: u32 = 200_000;
+
+/// Generates a CSV input of deposits and withdrawals, with a dispute settled straight away
+/// every twenty records, and one malformed record in a hundred.
+fn generate_csv() -> Vec<u8> {
+    let mut csv = String::from("type,client,tx,amount\n");
+    let mut deposit = None;
+    for tx in 1..=RECORDS {
+        let hash = tx.wrapping_mul(2_654_435_761);
+        let client = hash % 1_000;
+        let amount = format!("{}.{:04}", hash % 100, hash % 10_000);
+        let line = match (tx % 100, tx % 20, deposit) {
+            (0, _, _) => format!("deposit,{},{},-{}\n", client, tx, amount),
+            (_, 0, Some((client, deposit))) => {
+                format!("dispute,{0},{1},\nresolve,{0},{1},\n", client, deposit)
+            }
+            _ if tx.is_multiple_of(3) => format!("withdrawal,{},{},{}\n", client, tx, amount),
+            _ => {
+                deposit = Some((client, tx));
+                format!("deposit,{},{},{}\n", client, tx, amount)
+            }
+        };
+        csv.push_str(&line);
+    }
+    csv.into_bytes()
+}
+
+fn bench_csv(c: &mut Criterion) {
+    let csv = generate_csv();
+    let records = csv.iter().filter(|&&byte| byte == b'\n').count() as u64 - 1;
+
+    let mut group = c.benchmark_group("csv");
+    group.sample_size(20);
+    group.throughput(Throughput::Elements(records));
+    group.bench_function("TransactionProcessor::process", |b| {
+        b.iter_batched(
+            || CsvTransactionReader::from_reader(Cursor::new(csv.clone())),
+            |reader| {
+                let mut processor = TransactionProcessor::new(InMemoryAccountStore::new());
+                processor.process(reader)
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("TransactionProcessor::process_iter", |b| {
+        b.iter_batched(
+            || CsvTransactionReader::from_reader(Cursor::new(csv.clone())),
+            |mut reader| {
+                let mut processor = TransactionProcessor::new(InMemoryAccountStore::new());
+                processor.process_iter(reader.iter())
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_csv);
+criterion_main!(benches);
@@ -0,0 +1,66 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use rust_decimal::Decimal;
+use rusty_bank::{AccountStore, ClientId, DenseAccountStore, InMemoryAccountStore};
+
+/// Number of store operations applied per benchmark iteration.
+const OPERATIONS: u32 = 100_000;
+
+/// Applies a synthetic workload of deposits, withdrawals and disputes spread across `clients`.
+///
+/// Client IDs are scattered with a multiplicative hash so that accesses are not sequential.
+fn apply(store: &mut impl AccountStore, clients: u32) {
+    for i in 0..OPERATIONS {
+        let client = ClientId((i.wrapping_mul(2_654_435_761) % clients) as u16);
+        let amount = Decimal::new((i % 10_000) as i64, 4);
+        let _ = match i % 10 {
+            0..=5 => store.add_funds(client, amount),
+            6..=8 => store.remove_funds(client, amount),
+            _ => store
+                .hold_funds(client, amount)
+                .and_then(|_| store.release_funds(client, amount)),
+        };
+    }
+}
+
+fn bench_stores(c: &mut Criterion) {
+    let mut group = c.benchmark_group("account_store");
+    for clients in [100, 10_000, 65_536] {
+        group.bench_with_input(
+            BenchmarkId::new("InMemoryAccountStore", clients),
+            &clients,
+            |b, &clients| {
+                b.iter(|| {
+                    let mut store = InMemoryAccountStore::new();
+                    apply(&mut store, clients);
+                    black_box(store.export().count())
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("InMemoryAccountStore::with_capacity", clients),
+            &clients,
+            |b, &clients| {
+                b.iter(|| {
+                    let mut store = InMemoryAccountStore::with_capacity(clients as usize);
+                    apply(&mut store, clients);
+                    black_box(store.export().count())
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("DenseAccountStore", clients),
+            &clients,
+            |b, &clients| {
+                b.iter(|| {
+                    let mut store = DenseAccountStore::new();
+                    apply(&mut store, clients);
+                    black_box(store.export().count())
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_stores);
+criterion_main!(benches);
@@ -0,0 +1,130 @@
+#ifndef RUSTY_BANK_H
+#define RUSTY_BANK_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Number of entries appended between writes to the file, so a crash loses at most this many.
+ */
+#define JOURNAL_FLUSH_INTERVAL 64
+
+/**
+ * Number of decimal places stored for amounts, matching the precision of the input.
+ */
+#define PARQUET_AMOUNT_SCALE AMOUNT_DECIMAL_PLACES
+
+/**
+ * Number of accounts buffered before they are written as a row group, unless configured.
+ */
+#define DEFAULT_ROW_GROUP_SIZE (64 * 1024)
+
+/**
+ * The most errors kept in a report.
+ */
+#define ProcessingReport_MAX_ERRORS 10
+
+/**
+ * Number of decimal places amounts are kept to, from the input through to the output.
+ */
+#define AMOUNT_DECIMAL_PLACES 4
+
+/**
+ * Status codes returned by the C ABI.
+ */
+typedef enum RbStatus {
+  /**
+   * The call succeeded.
+   */
+  RB_STATUS_OK = 0,
+  /**
+   * A required pointer argument was null.
+   */
+  RB_STATUS_NULL_POINTER = 1,
+  /**
+   * The buffer is not CSV with a valid header.
+   */
+  RB_STATUS_INVALID_CSV = 2,
+  /**
+   * The processor has already been exported.
+   */
+  RB_STATUS_ALREADY_EXPORTED = 3,
+  /**
+   * The accounts could not be written, or the callback reported a failure.
+   */
+  RB_STATUS_WRITE_FAILED = 4,
+  /**
+   * The call panicked; the processor should be freed and not used again.
+   */
+  RB_STATUS_PANIC = 5,
+} RbStatus;
+
+/**
+ * An opaque handle to a processor and its in-memory account store.
+ */
+typedef struct RbProcessor RbProcessor;
+
+/**
+ * Receives the exported accounts as CSV.
+ *
+ * Called with the CSV bytes, which are only valid for the duration of the call, and the
+ * `user_data` passed to [`rb_export_csv`]. Returns zero on success.
+ */
+typedef int (*RbWriteCallback)(const uint8_t *data, uintptr_t len, void *user_data);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Create a new processor with an empty in-memory account store.
+ *
+ * The processor must be released with [`rb_processor_free`]. Returns null if it could not
+ * be created.
+ */
+struct RbProcessor *rb_processor_new(void);
+
+/**
+ * Process the transactions in a buffer of CSV, including its header row.
+ *
+ * Buffers may be processed one after another, each with their own header. Records which
+ * cannot be read or applied are skipped and logged as they are by the binary.
+ *
+ * # Safety
+ * `processor` must have been returned by [`rb_processor_new`] and not yet freed, and `data`
+ * must point to `len` readable bytes.
+ */
+enum RbStatus rb_process_csv_buffer(struct RbProcessor *processor,
+                                    const uint8_t *data,
+                                    uintptr_t len);
+
+/**
+ * Export the accounts as CSV, passing them to `callback` in a single call.
+ *
+ * A processor can only be exported once, after which it must still be freed.
+ *
+ * # Safety
+ * `processor` must have been returned by [`rb_processor_new`] and not yet freed.
+ * `user_data` is passed to `callback` unchanged.
+ */
+enum RbStatus rb_export_csv(struct RbProcessor *processor,
+                            RbWriteCallback callback,
+                            void *user_data);
+
+/**
+ * Release a processor. Does nothing if `processor` is null.
+ *
+ * # Safety
+ * `processor` must have been returned by [`rb_processor_new`] and not already freed.
+ */
+void rb_processor_free(struct RbProcessor *processor);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* RUSTY_BANK_H */
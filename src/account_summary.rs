@@ -1,61 +1,186 @@
 //! Serdes for accounts
 
 use rust_decimal::Decimal;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
 
-use crate::{client::ClientId, Account};
+use crate::{client::ClientId, round_amount, Account, AccountError};
 
 /// State of a client's account
-#[derive(Debug, PartialEq, Eq, Deserialize, Serialize)]
+///
+/// Amounts are serialized to at most [`AMOUNT_DECIMAL_PLACES`](crate::AMOUNT_DECIMAL_PLACES).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
 pub struct AccountSummary {
     client: ClientId,
+    #[serde(serialize_with = "serialize_amount")]
     available: Decimal,
+    #[serde(serialize_with = "serialize_amount")]
     held: Decimal,
+    #[serde(serialize_with = "serialize_amount")]
     total: Decimal,
     locked: bool,
 }
 
 impl AccountSummary {
-    /// Create an account
-    pub fn new(client: ClientId, held: Decimal, total: Decimal, locked: bool) -> Self {
-        AccountSummary {
+    /// Create an account, with `held` and `total` rounded to
+    /// [`AMOUNT_DECIMAL_PLACES`](crate::AMOUNT_DECIMAL_PLACES).
+    ///
+    /// `available` is computed from the rounded amounts, so `available + held == total` holds.
+    /// Fails if `total - held` overflows.
+    pub fn new(
+        client: ClientId,
+        held: Decimal,
+        total: Decimal,
+        locked: bool,
+    ) -> Result<Self, AccountError> {
+        let held = round_amount(held);
+        let total = round_amount(total);
+        Ok(AccountSummary {
             client,
             // `available` is derivable from `total` and `held` and as such does not need to exist.
             // for simplicity in serialization it is kept.
-            available: total - held,
+            available: total
+                .checked_sub(held)
+                .ok_or(AccountError::BalanceOverflow(client))?,
             held,
             total,
             locked,
-        }
+        })
     }
 
     /// Create an empty account with a balance of zero
     pub fn empty(client: ClientId) -> Self {
-        AccountSummary::new(client, 0.into(), 0.into(), false)
+        AccountSummary {
+            client,
+            available: 0.into(),
+            held: 0.into(),
+            total: 0.into(),
+            locked: false,
+        }
+    }
+
+    /// The client who owns the account
+    pub fn client(&self) -> ClientId {
+        self.client
+    }
+
+    /// Funds available for withdrawal, i.e. `total - held`
+    pub fn available(&self) -> Decimal {
+        self.available
+    }
+
+    /// Funds held by open disputes
+    pub fn held(&self) -> Decimal {
+        self.held
+    }
+
+    /// Total funds, both available and held
+    pub fn total(&self) -> Decimal {
+        self.total
+    }
+
+    /// Whether the account has been frozen by a chargeback
+    pub fn locked(&self) -> bool {
+        self.locked
     }
 }
 
-impl From<Account> for AccountSummary {
+impl TryFrom<Account> for AccountSummary {
+    type Error = AccountError;
+
     /// Converts the internal [`Account`] representation into a serializable [`AccountSummary`].
+    ///
+    /// This is how [`TransactionProcessor::export`](crate::TransactionProcessor::export) produces
+    /// the summaries passed to an [`AccountWriter`](crate::AccountWriter).
     //
     //  serde does not support derivable fields so in order to write `available` another
     //  serialization friendly type is required.
-    fn from(account: Account) -> Self {
+    fn try_from(account: Account) -> Result<Self, AccountError> {
+        AccountSummary::try_from(&account)
+    }
+}
+
+impl TryFrom<&Account> for AccountSummary {
+    type Error = AccountError;
+
+    /// Converts a borrowed [`Account`] into an [`AccountSummary`], such as one lent by
+    /// [`AccountStore::for_each_account`](crate::AccountStore::for_each_account).
+    fn try_from(account: &Account) -> Result<Self, AccountError> {
         AccountSummary::new(
-            account.client,
-            account.held.normalize(),
-            account.total.normalize(),
-            account.locked,
+            account.client(),
+            account.held().normalize(),
+            account.total().normalize(),
+            account.locked(),
         )
     }
 }
 
+fn serialize_amount<S: Serializer>(amount: &Decimal, serializer: S) -> Result<S::Ok, S::Error> {
+    Serialize::serialize(&round_amount(*amount), serializer)
+}
+
+/// Change in a client's account, from its state before a run to its state after
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AccountDelta {
+    client: ClientId,
+    available: Decimal,
+    held: Decimal,
+    total: Decimal,
+    locked_changed: bool,
+}
+
+impl AccountDelta {
+    /// Create the change from one state of a client's account to another
+    ///
+    /// Fails if any change overflows, such as from a large negative balance to a large positive
+    /// one.
+    pub fn between(before: &AccountSummary, after: &AccountSummary) -> Result<Self, AccountError> {
+        let change = |to: Decimal, from: Decimal| {
+            to.checked_sub(from)
+                .map(|change| change.normalize())
+                .ok_or(AccountError::BalanceOverflow(after.client))
+        };
+        Ok(AccountDelta {
+            client: after.client,
+            available: change(after.available, before.available)?,
+            held: change(after.held, before.held)?,
+            total: change(after.total, before.total)?,
+            locked_changed: after.locked != before.locked,
+        })
+    }
+
+    /// The client who owns the account
+    pub fn client(&self) -> ClientId {
+        self.client
+    }
+
+    /// Net change in funds available for withdrawal
+    pub fn available(&self) -> Decimal {
+        self.available
+    }
+
+    /// Net change in funds held by open disputes
+    pub fn held(&self) -> Decimal {
+        self.held
+    }
+
+    /// Net change in total funds
+    pub fn total(&self) -> Decimal {
+        self.total
+    }
+
+    /// Whether the account has been locked or unlocked
+    pub fn locked_changed(&self) -> bool {
+        self.locked_changed
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     use anyhow::Result;
     use rust_decimal_macros::dec;
+    use test_case::test_case;
 
     #[test]
     fn test_serde_when_valid_csv() -> Result<()> {
@@ -93,7 +218,7 @@ mod tests {
 
         // Serialize the account
         let account: AccountSummary =
-            AccountSummary::new(ClientId(1), dec!(1.0), dec!(2.9999), false);
+            AccountSummary::new(ClientId(1), dec!(1.0), dec!(2.9999), false).unwrap();
         wtr.serialize(account)?;
 
         // Compare the result against the expected output
@@ -104,8 +229,56 @@ mod tests {
     }
 
     #[test]
+    fn test_serialize_when_more_than_four_places() -> Result<()> {
+        let expected = "\
+            client,available,held,total,locked\n\
+            1,1.0000,0.0002,1.0002,false\n\
+        ";
+        let account: AccountSummary = csv::Reader::from_reader(
+            "client,available,held,total,locked\n1,1.00005,0.00015,1.00025,false\n".as_bytes(),
+        )
+        .deserialize()
+        .next()
+        .unwrap()?;
+
+        let mut wtr = csv::Writer::from_writer(vec![]);
+        wtr.serialize(account)?;
+        assert_eq!(expected, String::from_utf8(wtr.into_inner()?)?);
+
+        Ok(())
+    }
+
+    #[test_case(dec!(1.00005),  dec!(2.00015),  dec!(1.0000),  dec!(2.0002),  dec!(1.0002);  "when midpoints")]
+    #[test_case(dec!(0.00004),  dec!(0.99996),  dec!(0.0000),  dec!(1.0000),  dec!(1.0000);  "when held rounds to zero")]
+    #[test_case(dec!(0),        dec!(79228162514264337593543950335), dec!(0), dec!(79228162514264337593543950335), dec!(79228162514264337593543950335); "when maximum total")]
+    #[test_case(dec!(0.00001),  dec!(-79228162514264.33759354395033), dec!(0.0000), dec!(-79228162514264.3376), dec!(-79228162514264.3376); "when large negative total")]
+    fn test_new_rounds_amounts_consistently(
+        held: Decimal,
+        total: Decimal,
+        expected_held: Decimal,
+        expected_total: Decimal,
+        expected_available: Decimal,
+    ) {
+        let account = AccountSummary::new(ClientId(1), held, total, false).unwrap();
+        assert_eq!(expected_held, account.held());
+        assert_eq!(expected_total, account.total());
+        assert_eq!(expected_available, account.available());
+        assert_eq!(account.total(), account.available() + account.held());
+    }
+
+    #[test]
+    fn test_new_when_available_overflows() {
+        let result = AccountSummary::new(ClientId(1), Decimal::MAX, -Decimal::MAX, false);
+        assert_eq!(
+            AccountError::BalanceOverflow(ClientId(1)),
+            result.unwrap_err()
+        );
+    }
+
+    #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_new_returns_account_with_computed_available_funds() {
-        let account = AccountSummary::new(ClientId(1), dec!(5), dec!(15), false);
+        let account = AccountSummary::new(ClientId(1), dec!(5), dec!(15), false).unwrap();
         assert_eq!(ClientId(1), account.client);
         assert_eq!(dec!(10), account.available);
         assert_eq!(dec!(5), account.held);
@@ -114,6 +287,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_empty_returns_unlocked_account_with_no_funds() {
         let account = AccountSummary::empty(ClientId(1));
         assert_eq!(ClientId(1), account.client);
@@ -123,14 +297,50 @@ mod tests {
         assert_eq!(false, account.locked);
     }
 
+    #[test]
+    fn test_getters() {
+        let account = AccountSummary::new(ClientId(3), dec!(2.5), dec!(10), true).unwrap();
+        assert_eq!(ClientId(3), account.client());
+        assert_eq!(dec!(7.5), account.available());
+        assert_eq!(dec!(2.5), account.held());
+        assert_eq!(dec!(10), account.total());
+        assert!(account.locked());
+    }
+
+    #[test]
+    fn test_delta_between() {
+        let before = AccountSummary::new(ClientId(3), dec!(2.5), dec!(10), false).unwrap();
+        let after = AccountSummary::new(ClientId(3), dec!(1.5), dec!(4), true).unwrap();
+
+        let delta = AccountDelta::between(&before, &after).unwrap();
+        assert_eq!(ClientId(3), delta.client());
+        assert_eq!(dec!(-5), delta.available());
+        assert_eq!(dec!(-1), delta.held());
+        assert_eq!(dec!(-6), delta.total());
+        assert!(delta.locked_changed());
+
+        let delta = AccountDelta::between(&after, &after).unwrap();
+        assert_eq!(dec!(0), delta.total());
+        assert!(!delta.locked_changed());
+    }
+
+    #[test]
+    fn test_delta_between_when_change_overflows() {
+        let before = AccountSummary::new(ClientId(3), dec!(0), -Decimal::MAX, false).unwrap();
+        let after = AccountSummary::new(ClientId(3), dec!(0), Decimal::MAX, false).unwrap();
+        assert_eq!(
+            AccountError::BalanceOverflow(ClientId(3)),
+            AccountDelta::between(&before, &after).unwrap_err()
+        );
+    }
+
     #[test]
     fn test_from() {
-        let account = Account {
-            client: ClientId(5),
-            held: 5.into(),
-            total: 20.into(),
-            locked: false,
-        };
+        let account = Account::builder(ClientId(5))
+            .held(5.into())
+            .total(20.into())
+            .build()
+            .unwrap();
         assert_eq!(
             AccountSummary {
                 client: ClientId(5),
@@ -139,7 +349,7 @@ mod tests {
                 total: 20.into(),
                 locked: false
             },
-            account.into()
+            AccountSummary::try_from(account).unwrap()
         )
     }
 }
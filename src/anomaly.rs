@@ -0,0 +1,186 @@
+//! Serdes for anomalies in disputes, resolves and chargebacks
+
+use anyhow::{Error, Result};
+use csv::{Writer, WriterBuilder};
+use serde::Serialize;
+
+use crate::{ClientId, TransactionId, TransactionType};
+
+/// Something unexpected about a dispute, resolve or chargeback, of interest for fraud analysis.
+///
+/// Each is also skipped, and logged, like any other transaction which cannot be applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anomaly {
+    /// The client is not the client of the referenced transaction.
+    ClientMismatch {
+        expected: ClientId,
+        got: ClientId,
+        tx: TransactionId,
+    },
+    /// A dispute references a transaction which does not exist.
+    UnknownTransaction { tx: TransactionId },
+    /// A dispute references a transaction which is already disputed.
+    DuplicateDispute { tx: TransactionId },
+    /// The client's account is locked.
+    ActionOnLockedAccount { client: ClientId, tx: TransactionId },
+}
+
+impl Anomaly {
+    /// The snake case name of the anomaly, such as `client_mismatch`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Anomaly::ClientMismatch { .. } => "client_mismatch",
+            Anomaly::UnknownTransaction { .. } => "unknown_transaction",
+            Anomaly::DuplicateDispute { .. } => "duplicate_dispute",
+            Anomaly::ActionOnLockedAccount { .. } => "action_on_locked_account",
+        }
+    }
+}
+
+/// A trait for anything recording anomalies, which ignores them unless implemented.
+#[cfg_attr(test, mockall::automock)]
+pub trait AnomalyLog {
+    /// Records an anomaly in a transaction of the given type.
+    fn record(&mut self, _transaction_type: TransactionType, _anomaly: &Anomaly) -> Result<()> {
+        Ok(())
+    }
+
+    /// Flushes any anomalies buffered.
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A row of [`CsvAnomalyLog`].
+#[derive(Serialize)]
+struct AnomalyRow {
+    #[serde(rename = "type")]
+    transaction_type: TransactionType,
+    anomaly: &'static str,
+    client: Option<ClientId>,
+    tx: TransactionId,
+    expected_client: Option<ClientId>,
+}
+
+impl AnomalyRow {
+    fn new(transaction_type: TransactionType, anomaly: &Anomaly) -> Self {
+        let (client, tx, expected_client) = match *anomaly {
+            Anomaly::ClientMismatch { expected, got, tx } => (Some(got), tx, Some(expected)),
+            Anomaly::UnknownTransaction { tx } | Anomaly::DuplicateDispute { tx } => {
+                (None, tx, None)
+            }
+            Anomaly::ActionOnLockedAccount { client, tx } => (Some(client), tx, None),
+        };
+        AnomalyRow {
+            transaction_type,
+            anomaly: anomaly.name(),
+            client,
+            tx,
+            expected_client,
+        }
+    }
+}
+
+/// Anomaly log for CSV files, with the columns `type,anomaly,client,tx,expected_client`.
+///
+/// The client is empty for anomalies which do not name one, and the expected client is only
+/// given for a client mismatch.
+pub struct CsvAnomalyLog<W>
+where
+    W: std::io::Write + Send + Sync + 'static,
+{
+    writer: Writer<W>,
+}
+
+impl<W> CsvAnomalyLog<W>
+where
+    W: std::io::Write + Send + Sync + 'static,
+{
+    /// Returns an anomaly CSV writer that writes data to wtr.
+    pub fn from_writer(wtr: W) -> Self {
+        let writer = WriterBuilder::new().has_headers(true).from_writer(wtr);
+        CsvAnomalyLog { writer }
+    }
+
+    /// Flush the contents of the internal buffer and return the underlying writer.
+    pub fn into_inner(self) -> Result<W> {
+        self.writer.into_inner().map_err(Error::from)
+    }
+}
+
+impl<W> AnomalyLog for CsvAnomalyLog<W>
+where
+    W: std::io::Write + Send + Sync + 'static,
+{
+    fn record(&mut self, transaction_type: TransactionType, anomaly: &Anomaly) -> Result<()> {
+        self.writer
+            .serialize(AnomalyRow::new(transaction_type, anomaly))
+            .map_err(Error::from)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush().map_err(Error::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() -> Result<()> {
+        let mut log = CsvAnomalyLog::from_writer(vec![]);
+        log.record(
+            TransactionType::Resolve,
+            &Anomaly::ClientMismatch {
+                expected: ClientId(1),
+                got: ClientId(2),
+                tx: TransactionId(3),
+            },
+        )?;
+        log.record(
+            TransactionType::Dispute,
+            &Anomaly::UnknownTransaction {
+                tx: TransactionId(4),
+            },
+        )?;
+        log.record(
+            TransactionType::Dispute,
+            &Anomaly::DuplicateDispute {
+                tx: TransactionId(5),
+            },
+        )?;
+        log.record(
+            TransactionType::Chargeback,
+            &Anomaly::ActionOnLockedAccount {
+                client: ClientId(6),
+                tx: TransactionId(7),
+            },
+        )?;
+
+        let expected = "\
+            type,anomaly,client,tx,expected_client\n\
+            resolve,client_mismatch,2,3,1\n\
+            dispute,unknown_transaction,,4,\n\
+            dispute,duplicate_dispute,,5,\n\
+            chargeback,action_on_locked_account,6,7,\n\
+        ";
+        assert_eq!(expected, String::from_utf8(log.into_inner()?)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_default_ignores_anomalies() -> Result<()> {
+        struct Ignored;
+        impl AnomalyLog for Ignored {}
+
+        let mut log = Ignored;
+        log.record(
+            TransactionType::Dispute,
+            &Anomaly::UnknownTransaction {
+                tx: TransactionId(1),
+            },
+        )?;
+        log.flush()
+    }
+}
@@ -0,0 +1,160 @@
+use std::path::Path;
+
+use anyhow::{Error, Result};
+use csv_async::{AsyncDeserializer, AsyncReaderBuilder, Trim};
+use futures::stream::{BoxStream, Stream, StreamExt};
+use tokio::{fs::File, io::AsyncRead};
+
+use crate::TransactionRecord;
+
+/// A trait for any asynchronous transaction reader implementation.
+pub trait AsyncTransactionReader {
+    /// Returns a stream of [`TransactionRecord`]s.
+    fn read<'a>(&'a mut self) -> BoxStream<'a, Result<TransactionRecord>>;
+}
+
+/// Asynchronous transaction reader for CSV files or streams.
+pub struct AsyncCsvTransactionReader<R>
+where
+    R: AsyncRead + Unpin + Send,
+{
+    reader: AsyncDeserializer<R>,
+}
+
+impl AsyncCsvTransactionReader<File> {
+    /// Create a new asynchronous CSV reader for the given file path.
+    pub async fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::open(path).await?;
+        Ok(AsyncCsvTransactionReader::from_reader(file))
+    }
+}
+
+impl<R> AsyncCsvTransactionReader<R>
+where
+    R: AsyncRead + Unpin + Send,
+{
+    /// Create a new asynchronous CSV reader over any [`AsyncRead`] source, such as a socket.
+    pub fn from_reader(rdr: R) -> Self {
+        // as with the synchronous reader, rows may omit trailing empty fields, such as the
+        // amount of `dispute,1,3`
+        let reader = AsyncReaderBuilder::new()
+            .trim(Trim::All)
+            .flexible(true)
+            .create_deserializer(rdr);
+        AsyncCsvTransactionReader { reader }
+    }
+}
+
+impl<R> AsyncTransactionReader for AsyncCsvTransactionReader<R>
+where
+    R: AsyncRead + Unpin + Send,
+{
+    /// Returns a stream of deserialized [`TransactionRecord`]s.
+    fn read<'a>(&'a mut self) -> BoxStream<'a, Result<TransactionRecord>> {
+        self.reader
+            .deserialize()
+            .map(|result| result.map_err(Error::from))
+            .boxed()
+    }
+}
+
+/// Adapts any stream of records, such as one received over the network, into an
+/// [`AsyncTransactionReader`].
+pub struct StreamTransactionReader<St>
+where
+    St: Stream<Item = Result<TransactionRecord>> + Send,
+{
+    stream: St,
+}
+
+impl<St> StreamTransactionReader<St>
+where
+    St: Stream<Item = Result<TransactionRecord>> + Send,
+{
+    /// Create a new reader over the given stream.
+    pub fn new(stream: St) -> Self {
+        StreamTransactionReader { stream }
+    }
+}
+
+impl<St> AsyncTransactionReader for StreamTransactionReader<St>
+where
+    St: Stream<Item = Result<TransactionRecord>> + Send + Unpin,
+{
+    fn read<'a>(&'a mut self) -> BoxStream<'a, Result<TransactionRecord>> {
+        (&mut self.stream).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::NamedTempFile;
+
+    use crate::ClientId;
+    use crate::TransactionId;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_read() -> Result<()> {
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "type,client,tx,amount\n")?;
+        writeln!(file, "deposit,1,1,10\n")?;
+        writeln!(file, "withdrawal,1,2,5\n")?;
+
+        let path = NamedTempFile::into_temp_path(file);
+        let mut rdr = AsyncCsvTransactionReader::from_path(&path).await?;
+
+        let transactions = rdr
+            .read()
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>>>()?;
+
+        assert_eq!(
+            vec![
+                TransactionRecord::deposit(ClientId(1), TransactionId(1), 10.into()),
+                TransactionRecord::withdrawal(ClientId(1), TransactionId(2), 5.into()),
+            ],
+            transactions
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_read_when_amount_column_omitted() -> Result<()> {
+        let input = "type,client,tx,amount\ndeposit,1,3,10\ndispute,1,3\n";
+        let mut rdr = AsyncCsvTransactionReader::from_reader(input.as_bytes());
+
+        let transactions = rdr
+            .read()
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>>>()?;
+
+        assert_eq!(
+            vec![
+                TransactionRecord::deposit(ClientId(1), TransactionId(3), 10.into()),
+                TransactionRecord::dispute(ClientId(1), TransactionId(3)),
+            ],
+            transactions
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_read_failure_when_invalid_record() {
+        let input = "type,client,tx,amount\nborrow,1,1,10\n";
+        let mut rdr = AsyncCsvTransactionReader::from_reader(input.as_bytes());
+
+        let results = rdr.read().collect::<Vec<_>>().await;
+        assert_eq!(1, results.len());
+        assert!(results[0].is_err());
+    }
+}
@@ -0,0 +1,243 @@
+//! Reads several CSV files in turn, as one input.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::{CsvTransactionReader, TransactionReader, TransactionRecord};
+
+/// Opens one of the files of a [`ChainedTransactionReader`].
+type Opener = Box<dyn Fn(&Path) -> Result<CsvTransactionReader>>;
+
+/// Transaction reader for a sequence of CSV files, such as hourly exports, read in order as if
+/// they were one input.
+///
+/// Each file is opened only when the one before it has been read, and has its own header, so
+/// headers do not repeat mid-stream and files may order their columns differently. Errors,
+/// including a file which cannot be opened, are prefixed with the path of the file they come
+/// from, and line numbers are those within that file.
+pub struct ChainedTransactionReader {
+    paths: Vec<PathBuf>,
+    open: Opener,
+}
+
+impl ChainedTransactionReader {
+    /// Create a new reader for the CSV files at the paths, in order.
+    pub fn from_paths(paths: Vec<PathBuf>) -> Self {
+        ChainedTransactionReader::with_opener(paths, |path| CsvTransactionReader::from_path(path))
+    }
+
+    /// Create a new reader for the files at the paths, in order, each opened by `open`, such as
+    /// to read them in another dialect.
+    pub fn with_opener(
+        paths: Vec<PathBuf>,
+        open: impl Fn(&Path) -> Result<CsvTransactionReader> + 'static,
+    ) -> Self {
+        ChainedTransactionReader {
+            paths,
+            open: Box::new(open),
+        }
+    }
+
+    /// The paths of the files read, in order.
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+}
+
+impl TransactionReader for ChainedTransactionReader {
+    /// Returns an iterator over deserialized [`Transaction`](crate::Transaction) records of
+    /// every file in turn.
+    fn read<'a>(&'a mut self) -> Box<dyn Iterator<Item = Result<TransactionRecord>> + 'a> {
+        Box::new(self.read_with_line_numbers().map(|(result, _)| result))
+    }
+
+    fn read_with_line_numbers<'a>(
+        &'a mut self,
+    ) -> Box<dyn Iterator<Item = (Result<TransactionRecord>, u64)> + 'a> {
+        let open = &self.open;
+        Box::new(self.paths.iter().flat_map(move |path| {
+            let tag = move |err: anyhow::Error| anyhow!("{}: {:#}", path.display(), err);
+            let records: Box<dyn Iterator<Item = (Result<TransactionRecord>, u64)>> =
+                match open(path).and_then(CsvTransactionReader::into_records_with_lines) {
+                    Ok(records) => Box::new(records),
+                    Err(err) => Box::new(std::iter::once((Err(err), 1))),
+                };
+            records.map(move |(result, line)| (result.map_err(tag), line))
+        }))
+    }
+}
+
+/// Expands any directories among the paths into the files within them, sorted
+/// lexicographically by name, so that files named by date or hour are read in order.
+///
+/// Subdirectories and hidden files, whose names start with `.`, are skipped. Returns an error
+/// if a path does not exist or a directory has no files.
+pub fn expand_input_paths<P: AsRef<Path>>(paths: &[P]) -> Result<Vec<PathBuf>> {
+    let mut expanded = Vec::new();
+    for path in paths {
+        let path = path.as_ref();
+        let metadata = fs::metadata(path).with_context(|| format!("Could not read {:?}", path))?;
+        if !metadata.is_dir() {
+            expanded.push(path.to_path_buf());
+            continue;
+        }
+        let mut files = Vec::new();
+        for entry in fs::read_dir(path).with_context(|| format!("Could not read {:?}", path))? {
+            let entry = entry?;
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if !hidden && entry.file_type()?.is_file() {
+                files.push(entry.path());
+            }
+        }
+        if files.is_empty() {
+            bail!("No input files in directory {:?}", path);
+        }
+        files.sort();
+        expanded.extend(files);
+    }
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+    use tempfile::TempDir;
+
+    use crate::{ClientId, CsvReaderOptions, TransactionId};
+
+    use super::*;
+
+    fn write_files(files: &[(&str, &str)]) -> Result<TempDir> {
+        let dir = tempfile::tempdir()?;
+        for (name, content) in files {
+            fs::write(dir.path().join(name), content)?;
+        }
+        Ok(dir)
+    }
+
+    #[test]
+    fn test_read_reads_files_in_order_with_own_headers() -> Result<()> {
+        let dir = write_files(&[
+            ("1.csv", "type,client,tx,amount\ndeposit,1,1,10\n"),
+            (
+                "2.csv",
+                "client,tx,type,amount\n1,2,withdrawal,4\n1,1,dispute,\n",
+            ),
+        ])?;
+        let mut reader = ChainedTransactionReader::from_paths(expand_input_paths(&[dir.path()])?);
+
+        let records: Vec<_> = reader.read().collect::<Result<_>>()?;
+        assert_eq!(
+            vec![
+                TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+                TransactionRecord::withdrawal(ClientId(1), TransactionId(2), dec!(4)),
+                TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            ],
+            records
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_with_line_numbers_tags_errors_with_file() -> Result<()> {
+        let dir = write_files(&[
+            ("a.csv", "type,client,tx,amount\ndeposit,1,1,x\n"),
+            ("b.csv", "type,client,tx,amount\ndeposit,1,2,1\n"),
+        ])?;
+        let paths = vec![
+            dir.path().join("a.csv"),
+            dir.path().join("missing.csv"),
+            dir.path().join("b.csv"),
+        ];
+        let mut reader = ChainedTransactionReader::from_paths(paths.clone());
+
+        let results: Vec<_> = reader
+            .read_with_line_numbers()
+            .map(|(result, line)| (result.map_err(|err| err.to_string()), line))
+            .collect();
+        assert_eq!(3, results.len());
+        let (first, line) = &results[0];
+        assert_eq!(2, *line);
+        let first = first.as_ref().unwrap_err();
+        assert!(
+            first.starts_with(&format!("{}: ", paths[0].display())),
+            "{}",
+            first
+        );
+        let (missing, _) = &results[1];
+        assert!(missing
+            .as_ref()
+            .unwrap_err()
+            .starts_with(&format!("{}: ", paths[1].display())));
+        assert_eq!(
+            (
+                Ok(TransactionRecord::deposit(
+                    ClientId(1),
+                    TransactionId(2),
+                    dec!(1)
+                )),
+                2
+            ),
+            results[2]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_opener() -> Result<()> {
+        let dir = write_files(&[("1.csv", "deposit;1;1;10\n"), ("2.csv", "deposit;2;2;5\n")])?;
+        let options = CsvReaderOptions {
+            delimiter: b';',
+            has_headers: false,
+            ..Default::default()
+        };
+        let mut reader = ChainedTransactionReader::with_opener(
+            expand_input_paths(&[dir.path()])?,
+            move |path| CsvTransactionReader::with_options(path, options.clone()),
+        );
+
+        assert_eq!(2, reader.read().filter(Result::is_ok).count());
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_input_paths() -> Result<()> {
+        let dir = write_files(&[
+            ("2024-01-01T10.csv", ""),
+            ("2024-01-01T09.csv", ""),
+            (".hidden", ""),
+        ])?;
+        fs::create_dir(dir.path().join("nested"))?;
+        let extra = write_files(&[("extra.csv", "")])?;
+        let extra = extra.path().join("extra.csv");
+
+        assert_eq!(
+            vec![
+                extra.clone(),
+                dir.path().join("2024-01-01T09.csv"),
+                dir.path().join("2024-01-01T10.csv"),
+            ],
+            expand_input_paths(&[extra.as_path(), dir.path()])?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_input_paths_when_invalid() -> Result<()> {
+        let dir = write_files(&[(".hidden", "")])?;
+        let err = expand_input_paths(&[dir.path()]).unwrap_err();
+        assert_eq!(
+            format!("No input files in directory {:?}", dir.path()),
+            err.to_string()
+        );
+
+        let missing = dir.path().join("missing.csv");
+        let err = expand_input_paths(&[&missing]).unwrap_err();
+        assert_eq!(format!("Could not read {:?}", missing), err.to_string());
+        Ok(())
+    }
+}
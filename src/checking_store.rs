@@ -0,0 +1,355 @@
+use anyhow::Result;
+use rust_decimal::Decimal;
+
+use crate::{Account, AccountStore, AccountVisitor, ClientId};
+
+/// An [`AccountStore`] which delegates to another and panics as soon as an operation breaks
+/// one of the accounting invariants, to catch a faulty store or processor at the operation
+/// responsible rather than in the accounts exported.
+///
+/// After every operation on a client's account it asserts that:
+/// - a failed operation left the account unchanged,
+/// - a successful one moved exactly `amount` between the total and held funds it names, so the
+///   total is always the sum of the deposits applied less the withdrawals and chargebacks,
+/// - held funds are not negative, see [`Account::validate`],
+/// - a locked account is changed by nothing but unlocking it, or a forced chargeback, and only a
+///   locked one is unlocked.
+pub struct CheckingAccountStore<S> {
+    inner: S,
+}
+
+/// The change a successful operation makes to an account.
+struct Change {
+    total: Decimal,
+    held: Decimal,
+    /// Whether the account is locked afterwards, if the operation changes it.
+    locked: Option<bool>,
+    /// Whether the operation may change a locked account.
+    bypasses_lock: bool,
+}
+
+impl Change {
+    fn funds(total: Decimal, held: Decimal) -> Self {
+        Change {
+            total,
+            held,
+            locked: None,
+            bypasses_lock: false,
+        }
+    }
+}
+
+/// The balances of an account, to compare before and after an operation.
+fn balances(account: &Option<Account>) -> Option<(Decimal, Decimal, bool)> {
+    account
+        .as_ref()
+        .map(|account| (account.total(), account.held(), account.locked()))
+}
+
+impl<S: AccountStore> CheckingAccountStore<S> {
+    /// Construct a new [`CheckingAccountStore`] checking the operations on `inner`.
+    pub fn new(inner: S) -> Self {
+        CheckingAccountStore { inner }
+    }
+
+    /// Returns the store being checked.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Applies an operation to the inner store and asserts the client's account changed as
+    /// expected.
+    ///
+    /// ### Parameters
+    /// - client: The client whose account the operation applies to.
+    /// - name: The name of the operation, for the panic message.
+    /// - change: The change to the account if the operation succeeds.
+    /// - operation: The operation to apply.
+    fn check(
+        &mut self,
+        client: ClientId,
+        name: &str,
+        change: Change,
+        operation: impl FnOnce(&mut S) -> Result<()>,
+    ) -> Result<()> {
+        let before = self.inner.account(client);
+        let result = operation(&mut self.inner);
+        let after = self.inner.account(client);
+
+        if let Err(err) = &result {
+            assert_eq!(
+                balances(&before),
+                balances(&after),
+                "{} failed for client {} but changed the account: {}",
+                name,
+                client,
+                err
+            );
+            return result;
+        }
+
+        let Some(account) = after else {
+            panic!(
+                "{} succeeded for client {} without an account",
+                name, client
+            );
+        };
+        let (total, held, locked) = balances(&before).unwrap_or_default();
+        assert!(
+            !locked || change.locked == Some(false) || change.bypasses_lock,
+            "{} succeeded for client {} whose account is locked",
+            name,
+            client
+        );
+        assert!(
+            locked || change.locked != Some(false),
+            "{} succeeded for client {} whose account is not locked",
+            name,
+            client
+        );
+        assert_eq!(
+            (
+                total + change.total,
+                held + change.held,
+                change.locked.unwrap_or(locked)
+            ),
+            (account.total(), account.held(), account.locked()),
+            "{} changed the account of client {} unexpectedly, (total, held, locked) before {:?}",
+            name,
+            client,
+            (total, held, locked)
+        );
+        if let Err(err) = account.validate() {
+            panic!("{} left an invalid account: {}", name, err);
+        }
+        result
+    }
+}
+
+impl<S: AccountStore> AccountStore for CheckingAccountStore<S> {
+    fn add_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        let change = Change::funds(amount, Decimal::ZERO);
+        self.check(client, "add_funds", change, |inner| {
+            inner.add_funds(client, amount)
+        })
+    }
+
+    fn remove_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        let change = Change::funds(-amount, Decimal::ZERO);
+        self.check(client, "remove_funds", change, |inner| {
+            inner.remove_funds(client, amount)
+        })
+    }
+
+    fn chargeback_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        let change = Change {
+            locked: Some(true),
+            ..Change::funds(-amount, -amount)
+        };
+        self.check(client, "chargeback_funds", change, |inner| {
+            inner.chargeback_funds(client, amount)
+        })
+    }
+
+    fn force_chargeback_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        let change = Change {
+            locked: Some(true),
+            bypasses_lock: true,
+            ..Change::funds(-amount, -amount)
+        };
+        self.check(client, "force_chargeback_funds", change, |inner| {
+            inner.force_chargeback_funds(client, amount)
+        })
+    }
+
+    fn hold_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        let change = Change::funds(Decimal::ZERO, amount);
+        self.check(client, "hold_funds", change, |inner| {
+            inner.hold_funds(client, amount)
+        })
+    }
+
+    fn release_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        let change = Change::funds(Decimal::ZERO, -amount);
+        self.check(client, "release_funds", change, |inner| {
+            inner.release_funds(client, amount)
+        })
+    }
+
+    fn credit_held_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        let change = Change::funds(amount, amount);
+        self.check(client, "credit_held_funds", change, |inner| {
+            inner.credit_held_funds(client, amount)
+        })
+    }
+
+    fn remove_held_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        let change = Change::funds(-amount, -amount);
+        self.check(client, "remove_held_funds", change, |inner| {
+            inner.remove_held_funds(client, amount)
+        })
+    }
+
+    fn lock(&mut self, client: ClientId) -> Result<()> {
+        let change = Change {
+            locked: Some(true),
+            ..Change::funds(Decimal::ZERO, Decimal::ZERO)
+        };
+        self.check(client, "lock", change, |inner| inner.lock(client))
+    }
+
+    fn unlock(&mut self, client: ClientId) -> Result<()> {
+        let change = Change {
+            locked: Some(false),
+            ..Change::funds(Decimal::ZERO, Decimal::ZERO)
+        };
+        self.check(client, "unlock", change, |inner| inner.unlock(client))
+    }
+
+    fn account(&self, client: ClientId) -> Option<Account> {
+        self.inner.account(client)
+    }
+
+    fn for_each_account(&self, visit: &mut AccountVisitor) -> Result<()> {
+        self.inner.for_each_account(visit)
+    }
+
+    fn export(self) -> Box<dyn Iterator<Item = Account>> {
+        Box::new(self.inner.export().inspect(|account| {
+            if let Err(err) = account.validate() {
+                panic!("Exported an invalid account: {}", err);
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+    use rust_decimal_macros::dec;
+
+    use crate::{InMemoryAccountStore, MockAccountStore};
+
+    use super::*;
+
+    fn account(total: Decimal, locked: bool) -> Option<Account> {
+        Some(
+            Account::builder(ClientId(1))
+                .total(total)
+                .locked(locked)
+                .build()
+                .unwrap(),
+        )
+    }
+
+    /// A store whose account for client 1 is `before` until an operation makes it `after`.
+    fn changing_store(before: Option<Account>, after: Option<Account>) -> MockAccountStore {
+        let mut accounts = vec![before, after].into_iter();
+        let mut store = MockAccountStore::new();
+        store
+            .expect_account()
+            .returning(move |_| accounts.next().flatten());
+        store
+    }
+
+    #[test]
+    fn test_delegates_to_inner_store() -> Result<()> {
+        let mut store = CheckingAccountStore::new(InMemoryAccountStore::new());
+        store.add_funds(ClientId(1), dec!(10))?;
+        store.remove_funds(ClientId(1), dec!(4))?;
+        store.hold_funds(ClientId(1), dec!(6))?;
+        store.release_funds(ClientId(1), dec!(1))?;
+        store.credit_held_funds(ClientId(1), dec!(2))?;
+        store.remove_held_funds(ClientId(1), dec!(2))?;
+        store.chargeback_funds(ClientId(1), dec!(5))?;
+        store.unlock(ClientId(1))?;
+        store.lock(ClientId(1))?;
+
+        let account = store.into_inner().account(ClientId(1)).unwrap();
+        assert_eq!(dec!(1), account.total());
+        assert_eq!(dec!(0), account.held());
+        assert!(account.locked());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_force_chargeback_funds_when_locked() -> Result<()> {
+        let mut store = CheckingAccountStore::new(InMemoryAccountStore::new());
+        store.add_funds(ClientId(1), dec!(10))?;
+        store.hold_funds(ClientId(1), dec!(8))?;
+        store.chargeback_funds(ClientId(1), dec!(5))?;
+        store.force_chargeback_funds(ClientId(1), dec!(3))?;
+
+        let account = store.into_inner().account(ClientId(1)).unwrap();
+        assert_eq!(dec!(2), account.total());
+        assert_eq!(dec!(0), account.held());
+        assert!(account.locked());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_passes_errors_through() -> Result<()> {
+        let mut store = CheckingAccountStore::new(InMemoryAccountStore::new());
+        assert!(store.remove_funds(ClientId(1), dec!(1)).is_err());
+        assert!(store.lock(ClientId(1)).is_err());
+
+        store.add_funds(ClientId(1), dec!(1))?;
+        assert!(store.release_funds(ClientId(1), dec!(1)).is_err());
+        assert!(store.unlock(ClientId(1)).is_err());
+        assert!(store.add_funds(ClientId(1), dec!(-1)).is_err());
+
+        assert_eq!(dec!(1), store.account(ClientId(1)).unwrap().total());
+        Ok(())
+    }
+
+    #[test]
+    fn test_export() -> Result<()> {
+        let mut store = CheckingAccountStore::new(InMemoryAccountStore::new());
+        store.add_funds(ClientId(2), dec!(1))?;
+        store.add_funds(ClientId(1), dec!(1))?;
+
+        let clients: Vec<_> = store.export().map(|account| account.client()).collect();
+        assert_eq!(vec![ClientId(1), ClientId(2)], clients);
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic(expected = "add_funds changed the account of client 1 unexpectedly")]
+    fn test_panics_when_total_changed_by_wrong_amount() {
+        let mut inner = changing_store(None, account(dec!(2), false));
+        inner.expect_add_funds().returning(|_, _| Ok(()));
+
+        let _ = CheckingAccountStore::new(inner).add_funds(ClientId(1), dec!(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "add_funds failed for client 1 but changed the account")]
+    fn test_panics_when_failed_operation_changed_account() {
+        let mut inner = changing_store(None, account(dec!(1), false));
+        inner
+            .expect_add_funds()
+            .returning(|_, _| Err(anyhow!("failed")));
+
+        let _ = CheckingAccountStore::new(inner).add_funds(ClientId(1), dec!(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "add_funds succeeded for client 1 whose account is locked")]
+    fn test_panics_when_locked_account_changed() {
+        let mut inner = changing_store(account(dec!(1), true), account(dec!(2), true));
+        inner.expect_add_funds().returning(|_, _| Ok(()));
+
+        let _ = CheckingAccountStore::new(inner).add_funds(ClientId(1), dec!(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "unlock succeeded for client 1 whose account is not locked")]
+    fn test_panics_when_unlocked_account_unlocked() {
+        let mut inner = changing_store(account(dec!(1), false), account(dec!(1), false));
+        inner.expect_unlock().returning(|_| Ok(()));
+
+        let _ = CheckingAccountStore::new(inner).unlock(ClientId(1));
+    }
+}
@@ -1,7 +1,152 @@
 //! Serdes for clients
 
-use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::transaction_record::{check_id, deserialize_id, parse_id, IdError};
 
 /// Represents a client ID as it's own type
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+///
+/// Displayed as the plain number, so messages read `client 17`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
 pub struct ClientId(pub u16);
+
+impl fmt::Display for ClientId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for ClientId {
+    type Err = IdError;
+
+    /// Parses a client ID, rejecting the same values as deserializing one, such as
+    /// `client id -1 is negative`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_id(s, "client id", u16::MAX.into()).map(|id| ClientId(id as u16))
+    }
+}
+
+impl From<u16> for ClientId {
+    fn from(id: u16) -> Self {
+        ClientId(id)
+    }
+}
+
+impl TryFrom<u64> for ClientId {
+    type Error = IdError;
+
+    /// Converts a client ID from another system, failing if it is out of range.
+    fn try_from(id: u64) -> Result<Self, Self::Error> {
+        check_id(id, "client id", u16::MAX.into()).map(|id| ClientId(id as u16))
+    }
+}
+
+impl<'de> Deserialize<'de> for ClientId {
+    /// Deserializes a client ID, rejecting negative, fractional and out of range values with
+    /// an error naming the value, such as `client id 70000 out of range 0..=65535`.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_id(deserializer, "client id", u16::MAX.into()).map(|id| ClientId(id as u16))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use csv::{ReaderBuilder, StringRecord, Trim};
+    use test_case::test_case;
+
+    fn deserialize(text: &str) -> Result<ClientId, String> {
+        let mut rdr = ReaderBuilder::new()
+            .has_headers(false)
+            .trim(Trim::All)
+            .from_reader(text.as_bytes());
+        let record = rdr
+            .records()
+            .next()
+            .map_or_else(StringRecord::new, Result::unwrap);
+        record
+            .deserialize::<(ClientId,)>(None)
+            .map(|(client,)| client)
+            .map_err(|err| match err.into_kind() {
+                csv::ErrorKind::Deserialize { err, .. } => err.kind().to_string(),
+                kind => format!("{:?}", kind),
+            })
+    }
+
+    #[test_case("0",     ClientId(0);     "when zero")]
+    #[test_case("65535", ClientId(65535); "when max")]
+    #[test_case(" 42 ",  ClientId(42);    "when padded")]
+    fn test_deserialize_when_valid(text: &str, expected: ClientId) {
+        assert_eq!(Ok(expected), deserialize(text));
+    }
+
+    #[test_case("65536",  "client id 65536 out of range 0..=65535";  "when just out of range")]
+    #[test_case("70000",  "client id 70000 out of range 0..=65535";  "when out of range")]
+    #[test_case("99999999999999999999999", "client id 99999999999999999999999 out of range 0..=65535"; "when out of range of u64")]
+    #[test_case("-1",     "client id -1 is negative";                "when negative")]
+    #[test_case("-99999999999999999999999", "client id -99999999999999999999999 is negative"; "when negative out of range of i64")]
+    #[test_case("3.0",    "client id 3.0 is not an integer";         "when zero fraction")]
+    #[test_case("3.5",    "client id 3.5 is not an integer";         "when fraction")]
+    #[test_case("abc",    "client id 'abc' is not a number";         "when not a number")]
+    #[test_case("\"\"",   "client id is missing";                    "when missing")]
+    fn test_deserialize_when_invalid(text: &str, expected: &str) {
+        assert_eq!(Err(expected.to_string()), deserialize(text));
+    }
+
+    #[test_case("42",    Ok(ClientId(42));                              "when number")]
+    #[test_case(" 7 ",   Ok(ClientId(7));                               "when padded")]
+    #[test_case("70000", Err("client id 70000 out of range 0..=65535"); "when out of range")]
+    #[test_case("-1",    Err("client id -1 is negative");               "when negative")]
+    #[test_case("1.5",   Err("client id 1.5 is not an integer");        "when fraction")]
+    #[test_case("abc",   Err("client id 'abc' is not a number");        "when not a number")]
+    #[test_case("",      Err("client id is missing");                   "when empty")]
+    fn test_from_str(text: &str, expected: Result<ClientId, &str>) {
+        let result = text.parse::<ClientId>().map_err(|err| err.to_string());
+        assert_eq!(expected.map_err(str::to_string), result);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!("17", ClientId(17).to_string());
+        assert_eq!("client 17", format!("client {}", ClientId::from(17)));
+    }
+
+    #[test_case(ClientId(0);     "when zero")]
+    #[test_case(ClientId(17);    "when number")]
+    #[test_case(ClientId(65535); "when max")]
+    fn test_display_and_from_str_round_trip(client: ClientId) {
+        assert_eq!(Ok(client), client.to_string().parse::<ClientId>());
+    }
+
+    #[test]
+    fn test_try_from_u64() {
+        assert_eq!(Ok(ClientId(65535)), ClientId::try_from(65535u64));
+        assert_eq!(
+            "client id 65536 out of range 0..=65535",
+            ClientId::try_from(65536u64).unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_ord() {
+        let mut clients = vec![ClientId(300), ClientId(2), ClientId(10)];
+        clients.sort();
+        assert_eq!(vec![ClientId(2), ClientId(10), ClientId(300)], clients);
+    }
+
+    #[test_case("1",       Ok(ClientId(1)); "when number")]
+    #[test_case("\"7\"",   Ok(ClientId(7)); "when string")]
+    #[test_case("70000",   Err("client id 70000 out of range 0..=65535"); "when out of range")]
+    #[test_case("-2",      Err("client id -2 is negative");               "when negative")]
+    #[test_case("2.5",     Err("client id 2.5 is not an integer");        "when fraction")]
+    fn test_deserialize_json(json: &str, expected: Result<ClientId, &str>) {
+        let result = serde_json::from_str::<ClientId>(json).map_err(|err| err.to_string());
+        match expected {
+            Ok(client) => assert_eq!(Ok(client), result),
+            Err(expected) => assert!(result.unwrap_err().starts_with(expected)),
+        }
+    }
+}
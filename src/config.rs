@@ -1,42 +1,478 @@
 //! Argument parsing for Rusty Bank.
 
-use anyhow::{bail, Result};
+use std::{path::PathBuf, str::FromStr};
+
+use anyhow::{bail, Context, Error, Result};
+use chrono::Duration;
+use rust_decimal::Decimal;
+
+use crate::{
+    filter::{parse_clients, ClientFilter},
+    ClientId, CsvReaderOptions, DisputePolicy, ExcessPrecision, StreamFormat,
+};
+
+/// Filename given to read the input from stdin, such as `generate_txs | rusty-bank -`.
+const STDIN_FILENAME: &str = "-";
+
+/// Format used when logging skipped transactions.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable text.
+    #[default]
+    Text,
+    /// One JSON object per skipped transaction.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => bail!("Unknown log format: {}", s),
+        }
+    }
+}
+
+/// What is written for each account.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputMode {
+    /// The balances of every account.
+    #[default]
+    Balances,
+    /// The change in each account touched by the run.
+    Deltas,
+}
+
+impl FromStr for OutputMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "balances" => Ok(OutputMode::Balances),
+            "deltas" => Ok(OutputMode::Deltas),
+            _ => bail!("Unknown output mode: {}", s),
+        }
+    }
+}
+
+/// Format the accounts are written in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// CSV with a header row.
+    #[default]
+    Csv,
+    /// JSON Lines, one JSON object per account.
+    Json,
+    /// A single JSON array of one object per account.
+    JsonArray,
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            "json-array" => Ok(OutputFormat::JsonArray),
+            _ => bail!("Unknown output format: {}", s),
+        }
+    }
+}
+
+/// A persistent store to keep the accounts in between runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoreLocation {
+    /// A [`FileAccountStore`](crate::FileAccountStore) in the directory.
+    Directory(PathBuf),
+    /// A [`SqliteAccountStore`](crate::SqliteAccountStore) in the database file, given as
+    /// `sqlite:<path>`.
+    Sqlite(PathBuf),
+}
+
+impl From<&str> for StoreLocation {
+    fn from(s: &str) -> Self {
+        match s.strip_prefix("sqlite:") {
+            Some(path) => StoreLocation::Sqlite(path.into()),
+            None => StoreLocation::Directory(s.into()),
+        }
+    }
+}
 
 /// Represents the arguments passed via the command line.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Default, PartialEq)]
 pub struct Config {
+    /// The input file, a directory of input files, or `-` for stdin.
     pub filename: String,
+    /// Further input files or directories, read in order after `filename` as one input.
+    pub more_filenames: Vec<String>,
+    /// Honour administrative transactions such as `unlock`.
+    pub allow_admin_ops: bool,
+    /// Format used when logging skipped transactions.
+    pub log_format: LogFormat,
+    /// How long after a deposit it may still be disputed, if limited.
+    pub max_dispute_age: Option<Duration>,
+    /// File to periodically save progress to, and resume from if it exists.
+    pub checkpoint: Option<String>,
+    /// Number of records processed between checkpoints.
+    pub checkpoint_interval: Option<u64>,
+    /// Journal of processed transactions, so input processed again is skipped.
+    pub journal: Option<String>,
+    /// Export an empty account for clients none of whose transactions could be applied.
+    pub export_untouched_accounts: bool,
+    /// Leave a client's other disputes open when a chargeback locks their account.
+    pub keep_disputes_on_lock: bool,
+    /// Charge back a disputed deposit even if an earlier chargeback already locked the account.
+    pub chargeback_locked_accounts: bool,
+    /// Clients whose transactions are processed.
+    pub client_filter: ClientFilter,
+    /// File to write summary statistics to, as CSV or JSON by extension.
+    pub stats: Option<String>,
+    /// Print a one line summary of the accounts to stderr.
+    pub summary: bool,
+    /// Panic as soon as an operation breaks an accounting invariant, to debug the processor.
+    pub check_invariants: bool,
+    /// File to write every dispute case to, as CSV.
+    pub disputes_output: Option<String>,
+    /// File to write every record which was not applied to, and why, as CSV.
+    pub rejected: Option<String>,
+    /// File to write every anomaly in a dispute, resolve or chargeback to, as CSV.
+    pub anomalies: Option<String>,
+    /// Accept amounts with comma or underscore thousands separators.
+    pub lenient_amounts: bool,
+    /// Accept client and transaction IDs with a zero fraction, such as `42.0`.
+    pub lenient_ids: bool,
+    /// The CSV dialect of the input.
+    pub csv_options: CsvReaderOptions,
+    /// How far a withdrawal may overdraw an account, zero by default.
+    pub overdraft_limit: Decimal,
+    /// Largest deposit or withdrawal accepted, if limited.
+    pub max_amount: Option<Decimal>,
+    /// Clients all of whose transactions are rejected.
+    pub denied_clients: Vec<ClientId>,
+    /// Number of deposits retained for disputes, if limited.
+    pub max_retained_transactions: Option<u64>,
+    /// Directory to spill deposits and dispute cases to, if any.
+    pub spill_dir: Option<String>,
+    /// Number of deposits, and of dispute cases, kept in memory when spilling, if configured.
+    pub spill_threshold: Option<u64>,
+    /// Only report problems found in the input, without processing it.
+    pub validate_only: bool,
+    /// What is written for each account.
+    pub output_mode: OutputMode,
+    /// Format the accounts are written in.
+    pub output_format: OutputFormat,
+    /// File to write the accounts to, rather than stdout.
+    pub output: Option<PathBuf>,
+    /// Encoding of the records in the input file.
+    pub input_format: StreamFormat,
+    /// Socket path, or TCP port, to accept transaction streams on instead of reading a file.
+    pub listen: Option<String>,
+    /// Encoding of the records in each stream accepted.
+    pub stream_format: StreamFormat,
+    /// File to append a snapshot of the accounts to after each stream, rather than stdout.
+    pub snapshots: Option<String>,
+    /// Persistent store to keep the accounts in between runs, if any.
+    pub store: Option<StoreLocation>,
+    /// Fail at the first malformed record rather than logging and skipping it.
+    pub strict: bool,
+    /// Whether amounts with more than four decimal places are rejected or rounded.
+    pub excess_precision: ExcessPrecision,
+    /// Whether a dispute may leave the available funds negative.
+    pub dispute_policy: DisputePolicy,
 }
 
 impl Config {
     pub fn new(args: &[String]) -> Result<Config> {
-        match args.len() {
+        let (executable, args) = match args.split_first() {
+            Some(split) => split,
             // empty args...
-            0 => {
-                unreachable!();
+            None => unreachable!(),
+        };
+
+        let mut config = Config::default();
+        let mut filenames = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--allow-admin-ops" => config.allow_admin_ops = true,
+                "--export-untouched-accounts" => config.export_untouched_accounts = true,
+                "--keep-disputes-on-lock" => config.keep_disputes_on_lock = true,
+                "--chargeback-locked-accounts" => config.chargeback_locked_accounts = true,
+                "--lenient-amounts" => config.lenient_amounts = true,
+                "--lenient-ids" => config.lenient_ids = true,
+                "--validate-only" => config.validate_only = true,
+                "--summary" => config.summary = true,
+                "--check-invariants" => config.check_invariants = true,
+                "--strict" => config.strict = true,
+                "--dispute-policy" => config.dispute_policy = value(arg, args.next())?.parse()?,
+                "--excess-precision" => {
+                    config.excess_precision = value(arg, args.next())?.parse()?
+                }
+                "--log-format" => config.log_format = value(arg, args.next())?.parse()?,
+                "--output-mode" => config.output_mode = value(arg, args.next())?.parse()?,
+                "--output-format" => config.output_format = value(arg, args.next())?.parse()?,
+                "--output" => config.output = Some(value(arg, args.next())?.into()),
+                "--format" | "--input-format" => {
+                    config.input_format = value(arg, args.next())?.parse()?
+                }
+                "--listen" => config.listen = Some(value(arg, args.next())?.to_string()),
+                "--stream-format" => config.stream_format = value(arg, args.next())?.parse()?,
+                "--snapshots" => config.snapshots = Some(value(arg, args.next())?.to_string()),
+                "--store" => config.store = Some(value(arg, args.next())?.into()),
+                "--delimiter" => {
+                    config.csv_options.delimiter = delimiter(value(arg, args.next())?)?
+                }
+                "--no-headers" => config.csv_options.has_headers = false,
+                "--columns" => config.csv_options.columns = Some(columns(value(arg, args.next())?)),
+                "--clients" => {
+                    config.client_filter = config
+                        .client_filter
+                        .with_include(parse_clients(value(arg, args.next())?)?)
+                }
+                "--exclude-clients" => {
+                    config.client_filter = config
+                        .client_filter
+                        .with_exclude(parse_clients(value(arg, args.next())?)?)
+                }
+                "--stats" => config.stats = Some(value(arg, args.next())?.to_string()),
+                "--disputes-output" => {
+                    config.disputes_output = Some(value(arg, args.next())?.to_string())
+                }
+                "--rejected" => config.rejected = Some(value(arg, args.next())?.to_string()),
+                "--anomalies" => config.anomalies = Some(value(arg, args.next())?.to_string()),
+                "--checkpoint" => config.checkpoint = Some(value(arg, args.next())?.to_string()),
+                "--idempotency-journal" | "--journal" => {
+                    config.journal = Some(value(arg, args.next())?.to_string())
+                }
+                "--checkpoint-interval" => {
+                    config.checkpoint_interval = Some(count(value(arg, args.next())?)?)
+                }
+                "--max-retained-transactions" => {
+                    config.max_retained_transactions = Some(count(value(arg, args.next())?)?)
+                }
+                "--spill-dir" => config.spill_dir = Some(value(arg, args.next())?.to_string()),
+                "--spill-threshold" => {
+                    config.spill_threshold = Some(count(value(arg, args.next())?)?)
+                }
+                "--max-amount" => config.max_amount = Some(max_amount(value(arg, args.next())?)?),
+                "--deny-clients" => config
+                    .denied_clients
+                    .extend(parse_clients(value(arg, args.next())?)?),
+                "--overdraft-limit" => {
+                    config.overdraft_limit = overdraft_limit(value(arg, args.next())?)?
+                }
+                "--max-dispute-age-days" => {
+                    config.max_dispute_age = Some(days(value(arg, args.next())?)?)
+                }
+                option if option.starts_with("--") => {
+                    bail!(
+                        "Unknown option: {}. Usage: {} [OPTIONS] filename",
+                        option,
+                        executable
+                    );
+                }
+                _ => filenames.push(arg.clone()),
+            }
+        }
+
+        // a resumed checkpoint already includes the journaled transactions
+        if config.checkpoint.is_some() && config.journal.is_some() {
+            bail!("--checkpoint cannot be used with --idempotency-journal");
+        }
+
+        if config.output_mode == OutputMode::Deltas && config.output_format != OutputFormat::Csv {
+            bail!("--output-mode deltas can only be written as csv");
+        }
+
+        // every problem is already reported when validating
+        if config.strict && config.validate_only {
+            bail!("--strict cannot be used with --validate-only");
+        }
+
+        // nothing is processed, and every problem is already reported, when validating
+        if config.rejected.is_some() && config.validate_only {
+            bail!("--rejected cannot be used with --validate-only");
+        }
+        if config.anomalies.is_some() && config.validate_only {
+            bail!("--anomalies cannot be used with --validate-only");
+        }
+
+        // a resumed run would rewrite the rejections of the run before it
+        if config.rejected.is_some() && config.checkpoint.is_some() {
+            bail!("--rejected cannot be used with --checkpoint");
+        }
+        if config.anomalies.is_some() && config.checkpoint.is_some() {
+            bail!("--anomalies cannot be used with --checkpoint");
+        }
+
+        // the store already keeps the accounts between runs
+        if config.store.is_some() && config.checkpoint.is_some() {
+            bail!("--store cannot be used with --checkpoint");
+        }
+
+        // only the accounts held in memory for a single run are checked
+        if config.check_invariants && config.store.is_some() {
+            bail!("--check-invariants cannot be used with --store");
+        }
+        if config.check_invariants && config.checkpoint.is_some() {
+            bail!("--check-invariants cannot be used with --checkpoint");
+        }
+
+        if config.input_format != StreamFormat::Csv && config.checkpoint.is_some() {
+            bail!("--checkpoint can only be used with csv input");
+        }
+
+        if config.listen.is_some() {
+            if config.input_format != StreamFormat::Csv {
+                bail!("--format cannot be used with --listen, use --stream-format instead");
+            }
+            if config.checkpoint.is_some() {
+                bail!("--checkpoint cannot be used with --listen");
             }
+            if config.output_mode == OutputMode::Deltas {
+                bail!("--output-mode deltas cannot be used with --listen");
+            }
+            if config.store.is_some() {
+                bail!("--store cannot be used with --listen");
+            }
+            if config.strict {
+                bail!("--strict cannot be used with --listen");
+            }
+            if config.rejected.is_some() {
+                bail!("--rejected cannot be used with --listen");
+            }
+            if config.anomalies.is_some() {
+                bail!("--anomalies cannot be used with --listen");
+            }
+            if config.check_invariants {
+                bail!("--check-invariants cannot be used with --listen");
+            }
+            if config.output.is_some() {
+                bail!("--output cannot be used with --listen, use --snapshots instead");
+            }
+            if !filenames.is_empty() {
+                bail!("No filename allowed with --listen. Got: {:?}", filenames);
+            }
+            return Ok(config);
+        }
+
+        match filenames.len() {
             // no parameters passed
-            1 => {
-                bail!("Usage: {} filename", args[0]);
+            0 => {
+                bail!("Usage: {} [OPTIONS] filename", executable);
             }
             // one parameter passed
-            2 => Ok(Config {
-                filename: args[1].clone(),
-            }),
-            // more than one parameter passed
+            1 => {
+                config.filename = filenames.remove(0);
+                // stdin cannot be read again from a checkpoint's position
+                if config.reads_stdin() && config.checkpoint.is_some() {
+                    bail!("--checkpoint cannot be used when reading from stdin");
+                }
+                Ok(config)
+            }
+            // several input files, read in order
             _ => {
-                bail!("Only one parameter allowed. Got: {:?}", &args[1..]);
+                if filenames.iter().any(|filename| filename == STDIN_FILENAME) {
+                    bail!(
+                        "stdin cannot be read with other input files. Got: {:?}",
+                        filenames
+                    );
+                }
+                config.filename = filenames.remove(0);
+                config.more_filenames = filenames;
+                Ok(config)
             }
         }
     }
+
+    /// Every input file or directory, in order.
+    pub fn filenames(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.filename).chain(&self.more_filenames)
+    }
+
+    /// Whether the input is read from stdin, given as the filename `-`.
+    pub fn reads_stdin(&self) -> bool {
+        self.filename == STDIN_FILENAME
+    }
+}
+
+/// Returns the value following an option, failing if it is missing.
+fn value<'a>(option: &str, value: Option<&'a String>) -> Result<&'a str> {
+    value
+        .map(String::as_str)
+        .with_context(|| format!("Missing value for option: {}", option))
+}
+
+/// Parses a non-negative overdraft limit.
+fn overdraft_limit(value: &str) -> Result<Decimal> {
+    value
+        .parse::<Decimal>()
+        .ok()
+        .filter(|limit| !limit.is_sign_negative())
+        .with_context(|| format!("Invalid overdraft limit: {}", value))
+}
+
+/// Parses a non-negative maximum amount.
+fn max_amount(value: &str) -> Result<Decimal> {
+    value
+        .parse::<Decimal>()
+        .ok()
+        .filter(|max| !max.is_sign_negative())
+        .with_context(|| format!("Invalid maximum amount: {}", value))
+}
+
+/// Parses a non-negative number of days into a [`Duration`].
+fn days(value: &str) -> Result<Duration> {
+    value
+        .parse::<u32>()
+        .ok()
+        .and_then(|days| Duration::try_days(days.into()))
+        .with_context(|| format!("Invalid number of days: {}", value))
+}
+
+/// Parses a single byte field delimiter, such as `;`, or `\t` or `tab` for tabs.
+fn delimiter(value: &str) -> Result<u8> {
+    match value {
+        "\\t" | "tab" => Ok(b'\t'),
+        _ if value.len() == 1 && value.is_ascii() => Ok(value.as_bytes()[0]),
+        _ => bail!("Invalid delimiter: {}", value),
+    }
+}
+
+/// Parses a comma separated list of column names, such as `type,client,tx,amount`.
+fn columns(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|column| column.trim().to_string())
+        .collect()
+}
+
+/// Parses a positive count.
+fn count(value: &str) -> Result<u64> {
+    value
+        .parse::<u64>()
+        .ok()
+        .filter(|count| *count > 0)
+        .with_context(|| format!("Invalid count: {}", value))
 }
 
 #[cfg(test)]
 mod tests {
     use anyhow::anyhow;
+    use test_case::test_case;
+
+    use crate::ClientId;
 
     use super::*;
 
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
     #[test]
     #[should_panic(expected = "internal error: entered unreachable code")]
     fn test_new_panics_when_empty_args() {
@@ -46,7 +482,7 @@ mod tests {
     #[test]
     fn test_new_returns_err_when_no_parameter() {
         let result = Config::new(&["./path/to/executable".to_string()]).unwrap_err();
-        let expected = anyhow!("Usage: ./path/to/executable filename");
+        let expected = anyhow!("Usage: ./path/to/executable [OPTIONS] filename");
         assert_eq!(expected.to_string(), result.to_string());
     }
 
@@ -56,15 +492,759 @@ mod tests {
             Config::new(&["./path/to/executable".to_string(), "some.csv".to_string()]).unwrap();
         let expected: Config = Config {
             filename: "some.csv".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_more_than_one_parameter() {
+        let result = Config::new(&args(&["executable", "a", "--strict", "b", "c"])).unwrap();
+        let expected = Config {
+            filename: "a".to_string(),
+            more_filenames: vec!["b".to_string(), "c".to_string()],
+            strict: true,
+            ..Default::default()
+        };
+        assert_eq!(expected, result);
+        assert_eq!(vec!["a", "b", "c"], result.filenames().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_new_returns_err_when_stdin_with_other_parameters() {
+        let result = Config::new(&args(&["executable", "a", "-"])).unwrap_err();
+        let expected = anyhow!(r#"stdin cannot be read with other input files. Got: ["a", "-"]"#);
+        assert_eq!(expected.to_string(), result.to_string());
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_allow_admin_ops() {
+        let result = Config::new(&args(&["executable", "--allow-admin-ops", "some.csv"])).unwrap();
+        let expected = Config {
+            filename: "some.csv".to_string(),
+            allow_admin_ops: true,
+            ..Default::default()
+        };
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_export_untouched_accounts() {
+        let result = Config::new(&args(&[
+            "executable",
+            "--export-untouched-accounts",
+            "a.csv",
+        ]));
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            export_untouched_accounts: true,
+            ..Default::default()
+        };
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_keep_disputes_on_lock() {
+        let result = Config::new(&args(&["executable", "--keep-disputes-on-lock", "a.csv"]));
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            keep_disputes_on_lock: true,
+            ..Default::default()
+        };
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_chargeback_locked_accounts() {
+        let result = Config::new(&args(&[
+            "executable",
+            "--chargeback-locked-accounts",
+            "a.csv",
+        ]));
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            chargeback_locked_accounts: true,
+            ..Default::default()
+        };
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_max_retained_transactions() {
+        let result = Config::new(&args(&[
+            "executable",
+            "--max-retained-transactions",
+            "1000",
+            "a.csv",
+        ]));
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            max_retained_transactions: Some(1000),
+            ..Default::default()
+        };
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_spill_dir() {
+        let result = Config::new(&args(&[
+            "executable",
+            "--spill-dir",
+            "/tmp/spill",
+            "--spill-threshold",
+            "500",
+            "a.csv",
+        ]));
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            spill_dir: Some("/tmp/spill".to_string()),
+            spill_threshold: Some(500),
+            ..Default::default()
+        };
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_overdraft_limit() {
+        let result = Config::new(&args(&[
+            "executable",
+            "--overdraft-limit",
+            "25.50",
+            "a.csv",
+        ]));
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            overdraft_limit: Decimal::new(2550, 2),
+            ..Default::default()
+        };
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test_case("-1"; "when negative")]
+    #[test_case("x";  "when not a number")]
+    fn test_new_returns_err_when_invalid_overdraft_limit(limit: &str) {
+        let result = Config::new(&args(&["executable", "--overdraft-limit", limit, "a.csv"]));
+        let expected = anyhow!("Invalid overdraft limit: {}", limit);
+        assert_eq!(expected.to_string(), result.unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_validation_rules() {
+        let result = Config::new(&args(&[
+            "executable",
+            "--max-amount",
+            "1000",
+            "--deny-clients",
+            "3,4",
+            "--deny-clients",
+            "7",
+            "a.csv",
+        ]));
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            max_amount: Some(Decimal::new(1000, 0)),
+            denied_clients: vec![ClientId(3), ClientId(4), ClientId(7)],
+            ..Default::default()
+        };
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test_case("-1"; "when negative")]
+    #[test_case("x";  "when not a number")]
+    fn test_new_returns_err_when_invalid_max_amount(max: &str) {
+        let result = Config::new(&args(&["executable", "--max-amount", max, "a.csv"]));
+        let expected = anyhow!("Invalid maximum amount: {}", max);
+        assert_eq!(expected.to_string(), result.unwrap_err().to_string());
+    }
+
+    #[test_case("--idempotency-journal"; "when idempotency journal")]
+    #[test_case("--journal";             "when journal")]
+    fn test_new_returns_ok_when_journal(option: &str) {
+        let result = Config::new(&args(&["executable", option, "a.journal", "a.csv"]));
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            journal: Some("a.journal".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test]
+    fn test_new_returns_err_when_journal_and_checkpoint() {
+        let result = Config::new(&args(&[
+            "executable",
+            "--idempotency-journal",
+            "a.journal",
+            "--checkpoint",
+            "a.checkpoint",
+            "a.csv",
+        ]));
+        let expected = anyhow!("--checkpoint cannot be used with --idempotency-journal");
+        assert_eq!(expected.to_string(), result.unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_csv_options() {
+        let result = Config::new(&args(&[
+            "executable",
+            "--delimiter",
+            ";",
+            "--no-headers",
+            "--columns",
+            "client,type,amount,tx",
+            "a.csv",
+        ]));
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            csv_options: CsvReaderOptions {
+                delimiter: b';',
+                has_headers: false,
+                columns: Some(vec![
+                    "client".to_string(),
+                    "type".to_string(),
+                    "amount".to_string(),
+                    "tx".to_string(),
+                ]),
+            },
+            ..Default::default()
+        };
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test_case("tab", b'\t'; "when tab")]
+    #[test_case("\\t", b'\t'; "when escaped tab")]
+    #[test_case("|",   b'|';  "when pipe")]
+    fn test_new_returns_ok_when_delimiter(delimiter: &str, expected: u8) {
+        let result = Config::new(&args(&["executable", "--delimiter", delimiter, "a.csv"]));
+        assert_eq!(expected, result.unwrap().csv_options.delimiter);
+    }
+
+    #[test_case(";;"; "when too long")]
+    #[test_case("€";  "when not ascii")]
+    fn test_new_returns_err_when_invalid_delimiter(delimiter: &str) {
+        let result = Config::new(&args(&["executable", "--delimiter", delimiter, "a.csv"]));
+        let expected = anyhow!("Invalid delimiter: {}", delimiter);
+        assert_eq!(expected.to_string(), result.unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_clients() {
+        let result = Config::new(&args(&[
+            "executable",
+            "--clients",
+            "1,2,3",
+            "--exclude-clients",
+            "2",
+            "a.csv",
+        ]));
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            client_filter: ClientFilter::default()
+                .with_include([ClientId(1), ClientId(2), ClientId(3)])
+                .with_exclude([ClientId(2)]),
+            ..Default::default()
+        };
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test]
+    fn test_new_returns_err_when_invalid_clients() {
+        let result = Config::new(&args(&["executable", "--clients", "1,-2", "a.csv"]));
+        let expected = anyhow!("Invalid client ID: -2");
+        assert_eq!(expected.to_string(), result.unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_lenient_amounts() {
+        let result = Config::new(&args(&["executable", "--lenient-amounts", "a.csv"]));
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            lenient_amounts: true,
+            ..Default::default()
+        };
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_lenient_ids() {
+        let result = Config::new(&args(&["executable", "--lenient-ids", "a.csv"]));
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            lenient_ids: true,
+            ..Default::default()
+        };
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_listen() {
+        let result = Config::new(&args(&[
+            "executable",
+            "--listen",
+            "/tmp/rusty-bank.sock",
+            "--stream-format",
+            "jsonl",
+            "--snapshots",
+            "snapshots.csv",
+        ]));
+        let expected = Config {
+            listen: Some("/tmp/rusty-bank.sock".to_string()),
+            stream_format: StreamFormat::JsonLines,
+            snapshots: Some("snapshots.csv".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test_case(&["--listen", "7000", "a.csv"],                      "No filename allowed with --listen. Got: [\"a.csv\"]"; "when filename")]
+    #[test_case(&["--listen", "7000", "--checkpoint", "c.json"],     "--checkpoint cannot be used with --listen";            "when checkpoint")]
+    #[test_case(&["--listen", "7000", "--output-mode", "deltas"],    "--output-mode deltas cannot be used with --listen";    "when deltas")]
+    #[test_case(&["--listen", "7000", "--strict"],                   "--strict cannot be used with --listen";                "when strict")]
+    #[test_case(&["--listen", "7000", "--rejected", "r.csv"],        "--rejected cannot be used with --listen";              "when rejected")]
+    #[test_case(&["--listen", "7000", "--anomalies", "a.csv"],       "--anomalies cannot be used with --listen";             "when anomalies")]
+    #[test_case(&["--listen", "7000", "--store", "accounts"],        "--store cannot be used with --listen";                 "when store")]
+    #[test_case(&["--listen", "7000", "--output", "a.csv"],          "--output cannot be used with --listen, use --snapshots instead"; "when output")]
+    #[test_case(&["--listen", "7000", "--format", "jsonl"],          "--format cannot be used with --listen, use --stream-format instead"; "when format")]
+    #[test_case(&["--stream-format", "xml", "a.csv"],                "Unknown stream format: xml";                           "when unknown stream format")]
+    fn test_new_returns_err_when_invalid_listen(options: &[&str], expected: &str) {
+        let mut all = vec!["executable"];
+        all.extend(options);
+        let result = Config::new(&args(&all));
+        assert_eq!(expected, result.unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_output_mode() {
+        let result = Config::new(&args(&["executable", "--output-mode", "deltas", "a.csv"]));
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            output_mode: OutputMode::Deltas,
+            ..Default::default()
+        };
+        assert_eq!(expected, result.unwrap());
+
+        let result = Config::new(&args(&["executable", "--output-mode", "diff", "a.csv"]));
+        let expected = anyhow!("Unknown output mode: diff");
+        assert_eq!(expected.to_string(), result.unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_output_format() {
+        let result = Config::new(&args(&["executable", "--output-format", "json", "a.csv"]));
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            output_format: OutputFormat::Json,
+            ..Default::default()
+        };
+        assert_eq!(expected, result.unwrap());
+
+        let result = Config::new(&args(&[
+            "executable",
+            "--output-format",
+            "json-array",
+            "a.csv",
+        ]));
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            output_format: OutputFormat::JsonArray,
+            ..Default::default()
+        };
+        assert_eq!(expected, result.unwrap());
+
+        let result = Config::new(&args(&["executable", "--output-format", "xml", "a.csv"]));
+        let expected = anyhow!("Unknown output format: xml");
+        assert_eq!(expected.to_string(), result.unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_new_returns_err_when_deltas_as_json() {
+        let result = Config::new(&args(&[
+            "executable",
+            "--output-mode",
+            "deltas",
+            "--output-format",
+            "json",
+            "a.csv",
+        ]));
+        let expected = anyhow!("--output-mode deltas can only be written as csv");
+        assert_eq!(expected.to_string(), result.unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_validate_only() {
+        let result = Config::new(&args(&["executable", "--validate-only", "a.csv"]));
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            validate_only: true,
+            ..Default::default()
+        };
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_log_format() {
+        let result = Config::new(&args(&["executable", "--log-format", "json", "a.csv"])).unwrap();
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            log_format: LogFormat::Json,
+            ..Default::default()
         };
         assert_eq!(expected, result);
     }
 
     #[test]
-    fn test_new_returns_err_when_more_than_one_parameter() {
-        let result =
-            Config::new(&["executable".to_string(), "a".to_string(), "b".to_string()]).unwrap_err();
-        let expected = anyhow!(r#"Only one parameter allowed. Got: ["a", "b"]"#);
+    fn test_new_returns_err_when_invalid_log_format() {
+        let result = Config::new(&args(&["executable", "--log-format", "xml", "a.csv"]));
+        let expected = anyhow!("Unknown log format: xml");
+        assert_eq!(expected.to_string(), result.unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_max_dispute_age_days() {
+        let result = Config::new(&args(&[
+            "executable",
+            "--max-dispute-age-days",
+            "30",
+            "a.csv",
+        ]))
+        .unwrap();
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            max_dispute_age: Some(Duration::days(30)),
+            ..Default::default()
+        };
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_new_returns_err_when_invalid_max_dispute_age_days() {
+        let result = Config::new(&args(&[
+            "executable",
+            "--max-dispute-age-days",
+            "-1",
+            "a.csv",
+        ]));
+        let expected = anyhow!("Invalid number of days: -1");
+        assert_eq!(expected.to_string(), result.unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_checkpoint() {
+        let result = Config::new(&args(&[
+            "executable",
+            "--checkpoint",
+            "run.checkpoint",
+            "--checkpoint-interval",
+            "500",
+            "a.csv",
+        ]))
+        .unwrap();
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            checkpoint: Some("run.checkpoint".to_string()),
+            checkpoint_interval: Some(500),
+            ..Default::default()
+        };
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_summary() {
+        let result = Config::new(&args(&["executable", "--summary", "a.csv"]));
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            summary: true,
+            ..Default::default()
+        };
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_check_invariants() {
+        let result = Config::new(&args(&["executable", "--check-invariants", "a.csv"]));
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            check_invariants: true,
+            ..Default::default()
+        };
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test_case(&["--store", "accounts", "a.csv"],    "--check-invariants cannot be used with --store";      "when store")]
+    #[test_case(&["--checkpoint", "c.json", "a.csv"], "--check-invariants cannot be used with --checkpoint"; "when checkpoint")]
+    #[test_case(&["--listen", "7000"],                "--check-invariants cannot be used with --listen";     "when listen")]
+    fn test_new_returns_err_when_invalid_check_invariants(options: &[&str], expected: &str) {
+        let mut all = vec!["executable", "--check-invariants"];
+        all.extend(options);
+        let result = Config::new(&args(&all));
+        assert_eq!(expected, result.unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_stats() {
+        let result = Config::new(&args(&["executable", "a.csv", "--stats", "stats.json"]));
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            stats: Some("stats.json".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_rejected() {
+        let result = Config::new(&args(&[
+            "executable",
+            "--rejected",
+            "rejected.csv",
+            "a.csv",
+        ]));
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            rejected: Some("rejected.csv".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test_case(&["--validate-only"],         "--rejected cannot be used with --validate-only"; "when validate only")]
+    #[test_case(&["--checkpoint", "c.json"],  "--rejected cannot be used with --checkpoint";    "when checkpoint")]
+    fn test_new_returns_err_when_invalid_rejected(options: &[&str], expected: &str) {
+        let mut all = vec!["executable", "--rejected", "r.csv"];
+        all.extend(options);
+        all.push("a.csv");
+        let result = Config::new(&args(&all));
+        assert_eq!(expected, result.unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_anomalies() {
+        let result = Config::new(&args(&[
+            "executable",
+            "--anomalies",
+            "anomalies.csv",
+            "a.csv",
+        ]));
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            anomalies: Some("anomalies.csv".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test_case(&["--validate-only"],         "--anomalies cannot be used with --validate-only"; "when validate only")]
+    #[test_case(&["--checkpoint", "c.json"],  "--anomalies cannot be used with --checkpoint";    "when checkpoint")]
+    fn test_new_returns_err_when_invalid_anomalies(options: &[&str], expected: &str) {
+        let mut all = vec!["executable", "--anomalies", "an.csv"];
+        all.extend(options);
+        all.push("a.csv");
+        let result = Config::new(&args(&all));
+        assert_eq!(expected, result.unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_disputes_output() {
+        let result = Config::new(&args(&[
+            "executable",
+            "--disputes-output",
+            "disputes.csv",
+            "a.csv",
+        ]));
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            disputes_output: Some("disputes.csv".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_output() {
+        let result = Config::new(&args(&["executable", "--output", "accounts.csv", "a.csv"]));
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            output: Some(PathBuf::from("accounts.csv")),
+            ..Default::default()
+        };
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test_case("--format",       "jsonl"; "when format")]
+    #[test_case("--input-format", "json";  "when input format")]
+    fn test_new_returns_ok_when_format(option: &str, format: &str) {
+        let result = Config::new(&args(&["executable", option, format, "a.jsonl"]));
+        let expected = Config {
+            filename: "a.jsonl".to_string(),
+            input_format: StreamFormat::JsonLines,
+            ..Default::default()
+        };
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test]
+    fn test_new_returns_err_when_format_with_checkpoint() {
+        let result = Config::new(&args(&[
+            "executable",
+            "--format",
+            "jsonl",
+            "--checkpoint",
+            "c.json",
+            "a.jsonl",
+        ]));
+        let expected = anyhow!("--checkpoint can only be used with csv input");
+        assert_eq!(expected.to_string(), result.unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_stdin() {
+        let config = Config::new(&args(&["executable", "-"])).unwrap();
+        assert_eq!("-", config.filename);
+        assert!(config.reads_stdin());
+        assert!(!Config::new(&args(&["executable", "a.csv"]))
+            .unwrap()
+            .reads_stdin());
+    }
+
+    #[test]
+    fn test_new_returns_err_when_stdin_and_checkpoint() {
+        let result = Config::new(&args(&["executable", "--checkpoint", "c.json", "-"]));
+        let expected = anyhow!("--checkpoint cannot be used when reading from stdin");
+        assert_eq!(expected.to_string(), result.unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_excess_precision() {
+        let result = Config::new(&args(&[
+            "executable",
+            "--excess-precision",
+            "round",
+            "a.csv",
+        ]));
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            excess_precision: ExcessPrecision::Round,
+            ..Default::default()
+        };
+        assert_eq!(expected, result.unwrap());
+
+        let result = Config::new(&args(&[
+            "executable",
+            "--excess-precision",
+            "truncate",
+            "a.csv",
+        ]));
+        let expected = anyhow!("Unknown excess precision policy: truncate");
+        assert_eq!(expected.to_string(), result.unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_dispute_policy() {
+        let result = Config::new(&args(&[
+            "executable",
+            "--dispute-policy",
+            "reject-if-insufficient",
+            "a.csv",
+        ]));
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            dispute_policy: DisputePolicy::RejectIfInsufficient,
+            ..Default::default()
+        };
+        assert_eq!(expected, result.unwrap());
+
+        let result = Config::new(&args(&["executable", "--dispute-policy", "cap", "a.csv"]));
+        let expected = anyhow!("Unknown dispute policy: cap");
+        assert_eq!(expected.to_string(), result.unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_strict() {
+        let result = Config::new(&args(&["executable", "--strict", "a.csv"]));
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            strict: true,
+            ..Default::default()
+        };
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test]
+    fn test_new_returns_err_when_strict_with_validate_only() {
+        let result = Config::new(&args(&[
+            "executable",
+            "--strict",
+            "--validate-only",
+            "a.csv",
+        ]));
+        let expected = anyhow!("--strict cannot be used with --validate-only");
+        assert_eq!(expected.to_string(), result.unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_store() {
+        let result = Config::new(&args(&["executable", "--store", "accounts", "a.csv"]));
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            store: Some("accounts".into()),
+            ..Default::default()
+        };
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test]
+    fn test_new_returns_ok_when_sqlite_store() {
+        let result = Config::new(&args(&[
+            "executable",
+            "--store",
+            "sqlite:accounts.db",
+            "a.csv",
+        ]));
+        let expected = Config {
+            filename: "a.csv".to_string(),
+            store: Some(StoreLocation::Sqlite("accounts.db".into())),
+            ..Default::default()
+        };
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test]
+    fn test_new_returns_err_when_store_with_checkpoint() {
+        let result = Config::new(&args(&[
+            "executable",
+            "--store",
+            "accounts",
+            "--checkpoint",
+            "c.json",
+            "a.csv",
+        ]));
+        let expected = anyhow!("--store cannot be used with --checkpoint");
+        assert_eq!(expected.to_string(), result.unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_new_returns_err_when_invalid_checkpoint_interval() {
+        let result = Config::new(&args(&[
+            "executable",
+            "--checkpoint-interval",
+            "0",
+            "a.csv",
+        ]));
+        let expected = anyhow!("Invalid count: 0");
+        assert_eq!(expected.to_string(), result.unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_new_returns_err_when_missing_option_value() {
+        let result = Config::new(&args(&["executable", "a.csv", "--log-format"]));
+        let expected = anyhow!("Missing value for option: --log-format");
+        assert_eq!(expected.to_string(), result.unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_new_returns_err_when_unknown_option() {
+        let result = Config::new(&args(&["executable", "--unknown", "some.csv"])).unwrap_err();
+        let expected = anyhow!("Unknown option: --unknown. Usage: executable [OPTIONS] filename");
         assert_eq!(expected.to_string(), result.to_string());
     }
 }
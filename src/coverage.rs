@@ -0,0 +1,158 @@
+//! How much of each deposit is still covered by funds in the account.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{ClientId, TransactionId};
+
+/// The part of each deposit not yet spent by later withdrawals, which are attributed to a
+/// client's deposits first in first out.
+///
+/// A dispute of a deposit can then hold only what is left of it, rather than its full amount.
+/// Withdrawals are not attributed to a deposit while it is disputed, as its held funds cannot be
+/// withdrawn.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct Coverage {
+    /// What is left of each deposit still partly covered.
+    remaining: HashMap<TransactionId, Decimal>,
+    /// Each client's deposits still partly covered, oldest first.
+    deposits: HashMap<ClientId, VecDeque<TransactionId>>,
+    /// Deposits disputed, to which withdrawals are not attributed.
+    disputed: HashSet<TransactionId>,
+}
+
+impl Coverage {
+    /// Records a deposit applied to a client's account.
+    pub(crate) fn deposit(&mut self, client: ClientId, tx: TransactionId, amount: Decimal) {
+        self.remaining.insert(tx, amount);
+        self.deposits.entry(client).or_default().push_back(tx);
+    }
+
+    /// Spends a withdrawal applied to a client's account from their oldest deposits which are
+    /// not disputed.
+    pub(crate) fn withdraw(&mut self, client: ClientId, mut amount: Decimal) {
+        let Some(deposits) = self.deposits.get_mut(&client) else {
+            return;
+        };
+        for tx in deposits.iter() {
+            if amount.is_zero() {
+                break;
+            }
+            if self.disputed.contains(tx) {
+                continue;
+            }
+            if let Some(remaining) = self.remaining.get_mut(tx) {
+                let spent = amount.min(*remaining);
+                *remaining -= spent;
+                amount -= spent;
+            }
+        }
+        let remaining = &mut self.remaining;
+        deposits.retain(|tx| match remaining.get(tx) {
+            Some(left) if !left.is_zero() => true,
+            _ => {
+                remaining.remove(tx);
+                false
+            }
+        });
+        if deposits.is_empty() {
+            self.deposits.remove(&client);
+        }
+    }
+
+    /// Returns what is left of a deposit.
+    pub(crate) fn remaining(&self, tx: TransactionId) -> Decimal {
+        self.remaining.get(&tx).copied().unwrap_or_default()
+    }
+
+    /// Stops attributing withdrawals to a deposit being disputed until the dispute is settled.
+    pub(crate) fn dispute(&mut self, tx: TransactionId) {
+        if self.remaining.contains_key(&tx) {
+            self.disputed.insert(tx);
+        }
+    }
+
+    /// Attributes withdrawals to a deposit again once its dispute is settled and it stands.
+    pub(crate) fn settle(&mut self, tx: TransactionId) {
+        self.disputed.remove(&tx);
+    }
+
+    /// Forgets a deposit reversed by a chargeback.
+    pub(crate) fn reverse(&mut self, client: ClientId, tx: TransactionId) {
+        self.disputed.remove(&tx);
+        if self.remaining.remove(&tx).is_none() {
+            return;
+        }
+        if let Some(deposits) = self.deposits.get_mut(&client) {
+            deposits.retain(|deposit| *deposit != tx);
+            if deposits.is_empty() {
+                self.deposits.remove(&client);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    #[test]
+    fn test_withdraw_spends_oldest_deposits_first() {
+        let mut coverage = Coverage::default();
+        coverage.deposit(ClientId(1), TransactionId(1), dec!(10));
+        coverage.deposit(ClientId(1), TransactionId(2), dec!(5));
+        coverage.deposit(ClientId(2), TransactionId(3), dec!(7));
+
+        coverage.withdraw(ClientId(1), dec!(12));
+
+        assert_eq!(dec!(0), coverage.remaining(TransactionId(1)));
+        assert_eq!(dec!(3), coverage.remaining(TransactionId(2)));
+        assert_eq!(dec!(7), coverage.remaining(TransactionId(3)));
+    }
+
+    #[test]
+    fn test_withdraw_when_more_than_deposited() {
+        let mut coverage = Coverage::default();
+        coverage.deposit(ClientId(1), TransactionId(1), dec!(10));
+
+        coverage.withdraw(ClientId(1), dec!(15));
+        coverage.withdraw(ClientId(2), dec!(1));
+        coverage.deposit(ClientId(1), TransactionId(2), dec!(4));
+
+        assert_eq!(dec!(0), coverage.remaining(TransactionId(1)));
+        assert_eq!(dec!(4), coverage.remaining(TransactionId(2)));
+    }
+
+    #[test]
+    fn test_withdraw_skips_disputed_deposits_until_settled() {
+        let mut coverage = Coverage::default();
+        coverage.deposit(ClientId(1), TransactionId(1), dec!(10));
+        coverage.deposit(ClientId(1), TransactionId(2), dec!(5));
+
+        coverage.dispute(TransactionId(1));
+        coverage.withdraw(ClientId(1), dec!(2));
+        coverage.settle(TransactionId(1));
+        coverage.withdraw(ClientId(1), dec!(4));
+
+        assert_eq!(dec!(6), coverage.remaining(TransactionId(1)));
+        assert_eq!(dec!(3), coverage.remaining(TransactionId(2)));
+    }
+
+    #[test]
+    fn test_reverse_forgets_deposit() {
+        let mut coverage = Coverage::default();
+        coverage.deposit(ClientId(1), TransactionId(1), dec!(10));
+        coverage.deposit(ClientId(1), TransactionId(2), dec!(5));
+
+        coverage.dispute(TransactionId(1));
+        coverage.reverse(ClientId(1), TransactionId(1));
+        coverage.withdraw(ClientId(1), dec!(2));
+
+        assert_eq!(dec!(0), coverage.remaining(TransactionId(1)));
+        assert_eq!(dec!(3), coverage.remaining(TransactionId(2)));
+    }
+}
@@ -0,0 +1,247 @@
+use anyhow::Result;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{Account, AccountError, AccountStore, AccountVisitor, ClientId};
+
+/// The number of distinct client IDs, since [`ClientId`] is a `u16`.
+const MAX_CLIENTS: usize = u16::MAX as usize + 1;
+
+/// A dense in-memory implementation of [`AccountStore`].
+///
+/// Accounts are stored in a pre-allocated slot per possible [`ClientId`] so that
+/// lookups are a direct index and the store never needs to grow or rehash.
+/// Clients that have never been touched are skipped on export.
+#[derive(Deserialize, Serialize)]
+pub struct DenseAccountStore {
+    accounts: Vec<Option<Account>>,
+}
+
+impl DenseAccountStore {
+    /// Construct a new [`DenseAccountStore`] with a slot for every client.
+    pub fn new() -> Self {
+        DenseAccountStore {
+            accounts: std::iter::repeat_with(|| None).take(MAX_CLIENTS).collect(),
+        }
+    }
+
+    /// Applies an operation to a copy of the client's account, which is only stored if it
+    /// succeeds, as [`InMemoryAccountStore`](crate::InMemoryAccountStore) does, so a failed
+    /// operation never opens an account.
+    fn update(
+        &mut self,
+        client: ClientId,
+        operation: impl FnOnce(&mut Account) -> Result<(), AccountError>,
+    ) -> Result<()> {
+        let slot = &mut self.accounts[client.0 as usize];
+        let mut account = slot.clone().unwrap_or_else(|| Account::empty(client));
+        operation(&mut account)?;
+        *slot = Some(account);
+        Ok(())
+    }
+}
+
+impl Default for DenseAccountStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AccountStore for DenseAccountStore {
+    fn add_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.update(client, |account| account.credit(amount))
+    }
+
+    fn remove_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.update(client, |account| account.debit(amount, Decimal::ZERO))
+    }
+
+    fn chargeback_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.update(client, |account| account.charge_back(amount))
+    }
+
+    fn force_chargeback_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.update(client, |account| account.force_charge_back(amount))
+    }
+
+    fn hold_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.update(client, |account| account.hold(amount))
+    }
+
+    fn release_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.update(client, |account| account.release(amount))
+    }
+
+    fn credit_held_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.update(client, |account| account.credit_held(amount))
+    }
+
+    fn remove_held_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.update(client, |account| account.debit_held(amount))
+    }
+
+    fn lock(&mut self, client: ClientId) -> Result<()> {
+        match &mut self.accounts[client.0 as usize] {
+            Some(account) => Ok(account.lock()?),
+            None => Err(AccountError::NoSuchAccount(client).into()),
+        }
+    }
+
+    fn unlock(&mut self, client: ClientId) -> Result<()> {
+        match &mut self.accounts[client.0 as usize] {
+            Some(account) => Ok(account.unlock()?),
+            None => Err(AccountError::NoSuchAccount(client).into()),
+        }
+    }
+
+    fn account(&self, client: ClientId) -> Option<Account> {
+        self.accounts[client.0 as usize].clone()
+    }
+
+    fn for_each_account(&self, visit: &mut AccountVisitor) -> Result<()> {
+        self.accounts.iter().flatten().try_for_each(visit)
+    }
+
+    fn export(self) -> Box<dyn Iterator<Item = Account>> {
+        Box::new(self.accounts.into_iter().flatten())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use itertools::Itertools;
+    use rust_decimal_macros::dec;
+
+    use crate::{AccountSummary, InMemoryAccountStore};
+
+    use super::*;
+
+    #[test]
+    fn test_add_funds_when_locked() -> Result<()> {
+        let mut store = DenseAccountStore::new();
+        store.add_funds(ClientId(u16::MAX), dec!(20))?;
+        store.hold_funds(ClientId(u16::MAX), dec!(5))?;
+        store.chargeback_funds(ClientId(u16::MAX), dec!(5))?;
+
+        assert!(store.add_funds(ClientId(u16::MAX), dec!(1)).is_err());
+        assert_eq!(dec!(15), store.account(ClientId(u16::MAX)).unwrap().total());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hold_and_release_funds() -> Result<()> {
+        let mut store = DenseAccountStore::new();
+        store.add_funds(ClientId(2), dec!(20))?;
+        store.hold_funds(ClientId(2), dec!(25))?;
+
+        let account = store.account(ClientId(2)).unwrap();
+        assert_eq!(dec!(20), account.total());
+        assert_eq!(dec!(25), account.held());
+        assert_eq!(dec!(-5), account.get_available().unwrap());
+
+        store.release_funds(ClientId(2), dec!(25))?;
+
+        let account = store.account(ClientId(2)).unwrap();
+        assert_eq!(dec!(20), account.total());
+        assert_eq!(dec!(0), account.held());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_skips_untouched_clients() -> Result<()> {
+        let mut store = DenseAccountStore::new();
+        store.add_funds(ClientId(7), dec!(1))?;
+        store.add_funds(ClientId(3), dec!(1))?;
+
+        let clients = store.export().map(|account| account.client()).collect_vec();
+        assert_eq!(vec![ClientId(3), ClientId(7)], clients);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_for_each_account_skips_untouched_clients() -> Result<()> {
+        let mut store = DenseAccountStore::new();
+        store.add_funds(ClientId(7), dec!(1))?;
+        store.add_funds(ClientId(3), dec!(1))?;
+
+        let mut clients = Vec::new();
+        store.for_each_account(&mut |account| {
+            clients.push(account.client());
+            Ok(())
+        })?;
+        assert_eq!(vec![ClientId(3), ClientId(7)], clients);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_funds_when_no_account_does_not_open_account() {
+        let mut store = DenseAccountStore::new();
+        assert!(store.remove_funds(ClientId(2), dec!(1)).is_err());
+        assert!(store.account(ClientId(2)).is_none());
+        assert_eq!(0, store.export().count());
+    }
+
+    #[test]
+    fn test_failed_operations_do_not_open_accounts_like_in_memory_store() {
+        fn apply(store: &mut impl AccountStore) -> Vec<Account> {
+            let client = ClientId(4);
+            assert!(store.add_funds(client, dec!(-1)).is_err());
+            assert!(store.remove_funds(client, dec!(1)).is_err());
+            assert!(store.hold_funds(client, dec!(0)).is_err());
+            assert!(store.release_funds(client, dec!(1)).is_err());
+            assert!(store.chargeback_funds(client, dec!(1)).is_err());
+            assert!(store.credit_held_funds(client, dec!(-1)).is_err());
+            assert!(store.remove_held_funds(client, dec!(1)).is_err());
+            assert!(store.lock(client).is_err());
+            assert!(store.unlock(client).is_err());
+            assert!(store.account(client).is_none());
+            let mut accounts = Vec::new();
+            store
+                .for_each_account(&mut |account| {
+                    accounts.push(account.clone());
+                    Ok(())
+                })
+                .unwrap();
+            accounts
+        }
+
+        assert!(apply(&mut InMemoryAccountStore::new()).is_empty());
+        assert!(apply(&mut DenseAccountStore::new()).is_empty());
+    }
+
+    #[test]
+    fn test_export_matches_in_memory_store() -> Result<()> {
+        fn apply(store: &mut impl AccountStore) {
+            for i in 0..1000u16 {
+                let client = ClientId(i % 37);
+                let amount = Decimal::new(i as i64 * 7 % 500, 2);
+                let _ = match i % 5 {
+                    0 | 1 => store.add_funds(client, amount),
+                    2 => store.remove_funds(client, amount),
+                    3 => store.hold_funds(client, amount),
+                    _ if i % 97 == 0 => store.chargeback_funds(client, amount),
+                    _ if i % 89 == 0 => store.unlock(client),
+                    _ => store.release_funds(client, amount),
+                };
+            }
+        }
+
+        let mut dense = DenseAccountStore::new();
+        let mut sparse = InMemoryAccountStore::new();
+        apply(&mut dense);
+        apply(&mut sparse);
+
+        let summarize = |accounts: Box<dyn Iterator<Item = Account>>| {
+            accounts
+                .map(|account| AccountSummary::try_from(account).unwrap())
+                .collect_vec()
+        };
+        assert_eq!(summarize(sparse.export()), summarize(dense.export()));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+//! Serdes for dispute cases
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{ClientId, TransactionId};
+
+/// Status of a dispute case, either still open or how it ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisputeStatus {
+    /// The disputed funds are still held.
+    #[serde(alias = "Open")]
+    Open,
+    /// The disputed funds were released back to the client.
+    //  Checkpoints saved before resolutions and chargebacks were told apart recorded either as
+    //  `Closed`, which is still read so they can be resumed.
+    #[serde(alias = "Closed")]
+    Resolved,
+    /// The disputed funds were withdrawn and the account locked.
+    ChargedBack,
+    /// The disputed funds were released when a chargeback of another dispute locked the account.
+    #[serde(alias = "Cancelled")]
+    Cancelled,
+    /// The disputed funds were released back to the client, as the dispute was neither resolved
+    /// nor charged back in time.
+    Expired,
+}
+
+/// State of a dispute case
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DisputeSummary {
+    tx: TransactionId,
+    client: ClientId,
+    amount: Decimal,
+    status: DisputeStatus,
+}
+
+impl DisputeSummary {
+    /// Create a dispute case
+    pub fn new(
+        tx: TransactionId,
+        client: ClientId,
+        amount: Decimal,
+        status: DisputeStatus,
+    ) -> Self {
+        DisputeSummary {
+            tx,
+            client,
+            amount,
+            status,
+        }
+    }
+
+    /// The disputed deposit
+    pub fn tx(&self) -> TransactionId {
+        self.tx
+    }
+
+    /// The client who disputed the deposit
+    pub fn client(&self) -> ClientId {
+        self.client
+    }
+
+    /// The amount of the disputed deposit
+    pub fn amount(&self) -> Decimal {
+        self.amount
+    }
+
+    /// Whether the case is still open, or how it ended
+    pub fn status(&self) -> DisputeStatus {
+        self.status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use anyhow::Result;
+    use csv::Writer;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_serialize() -> Result<()> {
+        let mut wtr = Writer::from_writer(vec![]);
+        wtr.serialize(DisputeSummary::new(
+            TransactionId(7),
+            ClientId(2),
+            dec!(1.5),
+            DisputeStatus::ChargedBack,
+        ))?;
+
+        let result = String::from_utf8(wtr.into_inner()?)?;
+        assert_eq!("tx,client,amount,status\n7,2,1.5,charged_back\n", result);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_status_from_earlier_checkpoints() -> Result<()> {
+        let statuses: Vec<DisputeStatus> =
+            serde_json::from_str(r#"["Open", "Closed", "Cancelled", "charged_back"]"#)?;
+        assert_eq!(
+            vec![
+                DisputeStatus::Open,
+                DisputeStatus::Resolved,
+                DisputeStatus::Cancelled,
+                DisputeStatus::ChargedBack
+            ],
+            statuses
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+//! A C ABI for driving a [`TransactionProcessor`] from other languages.
+//!
+//! Enabled by the `ffi` feature. The functions here are exported unmangled from the `cdylib`
+//! built with `cargo rustc --lib --release --features ffi --crate-type cdylib`, and declared in
+//! `include/rusty_bank.h`, which is regenerated with cbindgen whenever they change (see the
+//! README).
+//!
+//! A processor is created with [`rb_processor_new`], fed CSV buffers with
+//! [`rb_process_csv_buffer`], exported with [`rb_export_csv`] and finally released with
+//! [`rb_processor_free`]. Panics are caught and reported as [`RbStatus::Panic`] so they never
+//! unwind into the caller.
+
+use std::{
+    ffi::c_void,
+    os::raw::c_int,
+    panic::{catch_unwind, AssertUnwindSafe},
+    slice,
+};
+
+use crate::{CsvAccountWriter, CsvTransactionReader, InMemoryAccountStore, TransactionProcessor};
+
+/// Status codes returned by the C ABI.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RbStatus {
+    /// The call succeeded.
+    Ok = 0,
+    /// A required pointer argument was null.
+    NullPointer = 1,
+    /// The buffer is not CSV with a valid header.
+    InvalidCsv = 2,
+    /// The processor has already been exported.
+    AlreadyExported = 3,
+    /// The accounts could not be written, or the callback reported a failure.
+    WriteFailed = 4,
+    /// The call panicked; the processor should be freed and not used again.
+    Panic = 5,
+}
+
+/// An opaque handle to a processor and its in-memory account store.
+pub struct RbProcessor {
+    processor: Option<TransactionProcessor<InMemoryAccountStore>>,
+}
+
+/// Receives the exported accounts as CSV.
+///
+/// Called with the CSV bytes, which are only valid for the duration of the call, and the
+/// `user_data` passed to [`rb_export_csv`]. Returns zero on success.
+pub type RbWriteCallback =
+    Option<extern "C" fn(data: *const u8, len: usize, user_data: *mut c_void) -> c_int>;
+
+/// Runs the call, reporting a panic as a status rather than unwinding across the boundary.
+fn guard(call: impl FnOnce() -> RbStatus) -> RbStatus {
+    catch_unwind(AssertUnwindSafe(call)).unwrap_or(RbStatus::Panic)
+}
+
+/// Create a new processor with an empty in-memory account store.
+///
+/// The processor must be released with [`rb_processor_free`]. Returns null if it could not
+/// be created.
+#[no_mangle]
+pub extern "C" fn rb_processor_new() -> *mut RbProcessor {
+    catch_unwind(|| {
+        Box::into_raw(Box::new(RbProcessor {
+            processor: Some(TransactionProcessor::new(InMemoryAccountStore::new())),
+        }))
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Process the transactions in a buffer of CSV, including its header row.
+///
+/// Buffers may be processed one after another, each with their own header. Records which
+/// cannot be read or applied are skipped and logged as they are by the binary.
+///
+/// # Safety
+/// `processor` must have been returned by [`rb_processor_new`] and not yet freed, and `data`
+/// must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rb_process_csv_buffer(
+    processor: *mut RbProcessor,
+    data: *const u8,
+    len: usize,
+) -> RbStatus {
+    guard(|| {
+        if processor.is_null() || data.is_null() {
+            return RbStatus::NullPointer;
+        }
+        let processor = match (*processor).processor.as_mut() {
+            Some(processor) => processor,
+            None => return RbStatus::AlreadyExported,
+        };
+
+        let buffer = slice::from_raw_parts(data, len);
+        let mut reader = CsvTransactionReader::from_reader(buffer);
+        let records = match reader.read_with_lines() {
+            Ok(records) => records,
+            Err(_) => return RbStatus::InvalidCsv,
+        };
+        processor.process_iter(records);
+        RbStatus::Ok
+    })
+}
+
+/// Export the accounts as CSV, passing them to `callback` in a single call.
+///
+/// A processor can only be exported once, after which it must still be freed.
+///
+/// # Safety
+/// `processor` must have been returned by [`rb_processor_new`] and not yet freed.
+/// `user_data` is passed to `callback` unchanged.
+#[no_mangle]
+pub unsafe extern "C" fn rb_export_csv(
+    processor: *mut RbProcessor,
+    callback: RbWriteCallback,
+    user_data: *mut c_void,
+) -> RbStatus {
+    guard(|| {
+        let callback = match callback {
+            Some(callback) if !processor.is_null() => callback,
+            _ => return RbStatus::NullPointer,
+        };
+        let processor = match (*processor).processor.take() {
+            Some(processor) => processor,
+            None => return RbStatus::AlreadyExported,
+        };
+
+        let mut writer = CsvAccountWriter::from_writer(vec![]);
+        if processor.export(&mut writer).is_err() {
+            return RbStatus::WriteFailed;
+        }
+        let csv = match writer.into_inner() {
+            Ok(csv) => csv,
+            Err(_) => return RbStatus::WriteFailed,
+        };
+        match callback(csv.as_ptr(), csv.len(), user_data) {
+            0 => RbStatus::Ok,
+            _ => RbStatus::WriteFailed,
+        }
+    })
+}
+
+/// Release a processor. Does nothing if `processor` is null.
+///
+/// # Safety
+/// `processor` must have been returned by [`rb_processor_new`] and not already freed.
+#[no_mangle]
+pub unsafe extern "C" fn rb_processor_free(processor: *mut RbProcessor) {
+    if !processor.is_null() {
+        let _ = catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(processor))));
+    }
+}
@@ -0,0 +1,466 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufReader, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use rust_decimal::Decimal;
+
+use crate::{Account, AccountError, AccountStore, AccountVisitor, ClientId};
+
+/// Identifies an account file, and the version of its layout.
+const MAGIC: &[u8; 8] = b"RBSTORE1";
+
+/// Size of the slot holding each client's account.
+///
+/// A slot is a state byte, a locked byte, two reserved bytes, the held and total funds as
+/// serialized [`Decimal`]s and a checksum of everything before it. A slot of zeros is a client
+/// without an account.
+const SLOT_SIZE: usize = 40;
+
+const ACCOUNTS_FILE: &str = "accounts";
+
+const STATE_FILE: &str = "state.json";
+
+/// A persistent implementation of [`AccountStore`], kept in a directory so that accounts
+/// survive the process exiting.
+///
+/// Each client has a fixed slot in the accounts file, indexed by [`ClientId`], so an account is
+/// read and written in place and the file never holds more than one slot per possible client.
+/// Every change is written straight to the file. Clients without an account are skipped on
+/// export, which reads the slots in order rather than loading them all into memory.
+///
+/// The deposits and disputes needed to process later runs are not accounts, so they are kept
+/// by the [`TransactionProcessor`](crate::TransactionProcessor); save them alongside the
+/// accounts in [`FileAccountStore::state_path`].
+pub struct FileAccountStore {
+    file: File,
+    path: PathBuf,
+    dir: PathBuf,
+    overdraft_limit: Decimal,
+}
+
+impl FileAccountStore {
+    /// Open the store in the directory, creating it if it does not exist.
+    ///
+    /// Every slot of an existing store is checked, so a corrupted or unrecognised accounts file
+    /// is reported before any transactions are processed.
+    ///
+    /// ### Parameters
+    /// - dir: The directory of the store.
+    pub fn from_path<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).with_context(|| format!("Could not create store {:?}", dir))?;
+        let path = dir.join(ACCOUNTS_FILE);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("Could not open store {:?}", path))?;
+        if file.metadata()?.len() == 0 {
+            file.write_all(MAGIC)?;
+        }
+        let store = FileAccountStore {
+            file,
+            path,
+            dir,
+            overdraft_limit: Decimal::ZERO,
+        };
+        store
+            .check()
+            .with_context(|| format!("Corrupted store {:?}", store.path))?;
+        Ok(store)
+    }
+
+    /// Allow withdrawals to overdraw an account by up to `overdraft_limit`, like
+    /// [`InMemoryAccountStore::with_overdraft_limit`](crate::InMemoryAccountStore::with_overdraft_limit).
+    ///
+    /// The limit is not saved in the store.
+    pub fn with_overdraft_limit(mut self, overdraft_limit: Decimal) -> Self {
+        self.overdraft_limit = overdraft_limit;
+        self
+    }
+
+    /// The file in the store's directory in which to save the processing state, such as with
+    /// [`TransactionProcessor::save_state`](crate::TransactionProcessor::save_state).
+    pub fn state_path(&self) -> PathBuf {
+        self.dir.join(STATE_FILE)
+    }
+
+    /// Reads every slot, failing at the first which is not valid.
+    fn check(&self) -> Result<()> {
+        let mut reader = BufReader::new(&self.file);
+        reader.seek(SeekFrom::Start(0))?;
+        let mut magic = [0; MAGIC.len()];
+        match reader.read_exact(&mut magic) {
+            Ok(()) if &magic == MAGIC => {}
+            Err(err) if err.kind() != ErrorKind::UnexpectedEof => return Err(err.into()),
+            _ => bail!("Not an account store"),
+        }
+        let len = self.file.metadata()?.len() - MAGIC.len() as u64;
+        if !len.is_multiple_of(SLOT_SIZE as u64) {
+            bail!("The accounts file has been truncated");
+        }
+        for client in 0..len / SLOT_SIZE as u64 {
+            let mut slot = [0; SLOT_SIZE];
+            reader.read_exact(&mut slot)?;
+            decode(ClientId(client as u16), &slot)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the client's account, if they have one.
+    fn read(&self, client: ClientId) -> Result<Option<Account>> {
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(offset(client)))?;
+        let mut slot = [0; SLOT_SIZE];
+        match file.read_exact(&mut slot) {
+            Ok(()) => decode(client, &slot),
+            // slots past the end of the file have never been written
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+        .with_context(|| {
+            format!(
+                "Could not read client {} from store {:?}",
+                client, self.path
+            )
+        })
+    }
+
+    fn write(&mut self, account: &Account) -> Result<()> {
+        self.file.seek(SeekFrom::Start(offset(account.client())))?;
+        self.file
+            .write_all(&encode(account))
+            .with_context(|| format!("Could not write to store {:?}", self.path))
+    }
+
+    /// Applies the operation to the client's account, opening an empty one if they have none.
+    ///
+    /// As with the in-memory stores, an account opened for the operation is kept even if the
+    /// operation fails.
+    fn update(
+        &mut self,
+        client: ClientId,
+        operation: impl FnOnce(&mut Account) -> Result<(), AccountError>,
+    ) -> Result<()> {
+        let (mut account, opened) = match self.read(client)? {
+            Some(account) => (account, false),
+            None => (Account::empty(client), true),
+        };
+        let result = operation(&mut account);
+        if result.is_ok() || opened {
+            self.write(&account)?;
+        }
+        Ok(result?)
+    }
+
+    /// Applies the operation to the client's account, failing if they have none.
+    fn update_existing(
+        &mut self,
+        client: ClientId,
+        operation: impl FnOnce(&mut Account) -> Result<(), AccountError>,
+    ) -> Result<()> {
+        match self.read(client)? {
+            Some(mut account) => {
+                operation(&mut account)?;
+                self.write(&account)
+            }
+            None => Err(AccountError::NoSuchAccount(client).into()),
+        }
+    }
+}
+
+fn offset(client: ClientId) -> u64 {
+    (MAGIC.len() + client.0 as usize * SLOT_SIZE) as u64
+}
+
+fn encode(account: &Account) -> [u8; SLOT_SIZE] {
+    let mut slot = [0; SLOT_SIZE];
+    slot[0] = 1;
+    slot[1] = account.locked() as u8;
+    slot[4..20].copy_from_slice(&account.held().serialize());
+    slot[20..36].copy_from_slice(&account.total().serialize());
+    let checksum = checksum(&slot[..36]);
+    slot[36..].copy_from_slice(&checksum.to_le_bytes());
+    slot
+}
+
+fn decode(client: ClientId, slot: &[u8; SLOT_SIZE]) -> Result<Option<Account>> {
+    if slot.iter().all(|byte| *byte == 0) {
+        return Ok(None);
+    }
+    let checksum = u32::from_le_bytes(slot[36..].try_into()?);
+    if slot[0] != 1 || slot[1] > 1 || checksum != self::checksum(&slot[..36]) {
+        bail!("The account of client {} is corrupted", client);
+    }
+    let account = Account::builder(client)
+        .held(decimal(&slot[4..20])?)
+        .total(decimal(&slot[20..36])?)
+        .locked(slot[1] == 1)
+        .build()
+        .with_context(|| format!("The account of client {} is corrupted", client))?;
+    Ok(Some(account))
+}
+
+fn decimal(bytes: &[u8]) -> Result<Decimal> {
+    let bytes: [u8; 16] = bytes.try_into()?;
+    let flags = u32::from_le_bytes(bytes[..4].try_into()?);
+    // only the sign bit and a scale of at most 28 are valid
+    if flags & !0x80ff_0000 != 0 || (flags >> 16) & 0xff > 28 {
+        bail!("Invalid decimal");
+    }
+    Ok(Decimal::deserialize(bytes))
+}
+
+/// 32-bit FNV-1a hash of the bytes.
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ *byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// Reads the accounts from the slots in order.
+struct FileAccounts<R> {
+    reader: BufReader<R>,
+    path: PathBuf,
+    next: u32,
+}
+
+impl<R: Read> Iterator for FileAccounts<R> {
+    type Item = Account;
+
+    fn next(&mut self) -> Option<Account> {
+        while self.next <= u16::MAX as u32 {
+            let client = ClientId(self.next as u16);
+            self.next += 1;
+            let mut slot = [0; SLOT_SIZE];
+            let account = match self.reader.read_exact(&mut slot) {
+                Ok(()) => decode(client, &slot),
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => return None,
+                Err(err) => Err(err.into()),
+            };
+            match account {
+                Ok(Some(account)) => return Some(account),
+                Ok(None) => continue,
+                Err(err) => {
+                    log::error!("Could not export store {:?}: {:#}", self.path, err);
+                    return None;
+                }
+            }
+        }
+        None
+    }
+}
+
+impl AccountStore for FileAccountStore {
+    fn add_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.update(client, |account| account.credit(amount))
+    }
+
+    fn remove_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        let overdraft_limit = self.overdraft_limit;
+        // An unknown client has no funds, so fail without opening an account for them
+        if amount > overdraft_limit && self.read(client)?.is_none() {
+            return Err(AccountError::InsufficientFunds { client, amount }.into());
+        }
+        self.update(client, |account| account.debit(amount, overdraft_limit))
+    }
+
+    fn chargeback_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.update(client, |account| account.charge_back(amount))
+    }
+
+    fn force_chargeback_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.update(client, |account| account.force_charge_back(amount))
+    }
+
+    fn hold_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.update(client, |account| account.hold(amount))
+    }
+
+    fn release_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.update(client, |account| account.release(amount))
+    }
+
+    fn credit_held_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.update(client, |account| account.credit_held(amount))
+    }
+
+    fn remove_held_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.update(client, |account| account.debit_held(amount))
+    }
+
+    fn lock(&mut self, client: ClientId) -> Result<()> {
+        self.update_existing(client, Account::lock)
+    }
+
+    fn unlock(&mut self, client: ClientId) -> Result<()> {
+        self.update_existing(client, Account::unlock)
+    }
+
+    fn account(&self, client: ClientId) -> Option<Account> {
+        self.read(client).unwrap_or_else(|err| {
+            log::error!("{:#}", err);
+            None
+        })
+    }
+
+    fn for_each_account(&self, visit: &mut AccountVisitor) -> Result<()> {
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(MAGIC.len() as u64))
+            .with_context(|| format!("Could not read store {:?}", self.path))?;
+        let mut accounts = FileAccounts {
+            reader: BufReader::new(file),
+            path: self.path.clone(),
+            next: 0,
+        };
+        accounts.try_for_each(|account| visit(&account))
+    }
+
+    fn export(mut self) -> Box<dyn Iterator<Item = Account>> {
+        if let Err(err) = self.file.seek(SeekFrom::Start(MAGIC.len() as u64)) {
+            log::error!("Could not export store {:?}: {}", self.path, err);
+            return Box::new(std::iter::empty());
+        }
+        Box::new(FileAccounts {
+            reader: BufReader::new(self.file),
+            path: self.path,
+            next: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rust_decimal_macros::dec;
+
+    use crate::AccountSummary;
+
+    use super::*;
+
+    fn summaries(store: FileAccountStore) -> Vec<AccountSummary> {
+        store
+            .export()
+            .map(|account| account.try_into().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_from_path_when_reopened_keeps_accounts() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut store = FileAccountStore::from_path(dir.path())?;
+        store.add_funds(ClientId(7), dec!(20))?;
+        store.hold_funds(ClientId(7), dec!(5))?;
+        store.add_funds(ClientId(2), dec!(1.5))?;
+        store.add_funds(ClientId(3), dec!(3))?;
+        store.hold_funds(ClientId(3), dec!(3))?;
+        store.chargeback_funds(ClientId(3), dec!(3))?;
+        drop(store);
+
+        let store = FileAccountStore::from_path(dir.path())?;
+        assert_eq!(
+            vec![
+                AccountSummary::new(ClientId(2), dec!(0), dec!(1.5), false).unwrap(),
+                AccountSummary::new(ClientId(3), dec!(0), dec!(0), true).unwrap(),
+                AccountSummary::new(ClientId(7), dec!(5), dec!(20), false).unwrap(),
+            ],
+            summaries(store)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_for_each_account_leaves_store_usable() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut store = FileAccountStore::from_path(dir.path())?;
+        store.add_funds(ClientId(7), dec!(1))?;
+        store.add_funds(ClientId(2), dec!(1))?;
+
+        let mut clients = Vec::new();
+        store.for_each_account(&mut |account| {
+            clients.push(account.client());
+            Ok(())
+        })?;
+        assert_eq!(vec![ClientId(2), ClientId(7)], clients);
+
+        store.add_funds(ClientId(7), dec!(1))?;
+        assert_eq!(dec!(2), store.account(ClientId(7)).unwrap().total());
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_funds_when_no_account() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut store = FileAccountStore::from_path(dir.path())?;
+
+        assert!(store.remove_funds(ClientId(u16::MAX), dec!(1)).is_err());
+        assert!(store.lock(ClientId(u16::MAX)).is_err());
+        assert!(store.account(ClientId(u16::MAX)).is_none());
+        assert!(summaries(store).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_funds_when_locked() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut store = FileAccountStore::from_path(dir.path())?;
+        store.add_funds(ClientId(u16::MAX), dec!(20))?;
+        store.lock(ClientId(u16::MAX))?;
+
+        assert!(store.add_funds(ClientId(u16::MAX), dec!(1)).is_err());
+        assert_eq!(dec!(20), store.account(ClientId(u16::MAX)).unwrap().total());
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_path_when_not_a_store() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::write(dir.path().join(ACCOUNTS_FILE), "type,client,tx,amount\n")?;
+
+        let err = FileAccountStore::from_path(dir.path()).err().unwrap();
+        assert_eq!("Not an account store", err.root_cause().to_string());
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_path_when_truncated() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut store = FileAccountStore::from_path(dir.path())?;
+        store.add_funds(ClientId(1), dec!(20))?;
+        drop(store);
+        let path = dir.path().join(ACCOUNTS_FILE);
+        let data = fs::read(&path)?;
+        fs::write(&path, &data[..data.len() - 1])?;
+
+        let err = FileAccountStore::from_path(dir.path()).err().unwrap();
+        assert_eq!(
+            "The accounts file has been truncated",
+            err.root_cause().to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_path_when_account_corrupted() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut store = FileAccountStore::from_path(dir.path())?;
+        store.add_funds(ClientId(1), dec!(20))?;
+        drop(store);
+        let path = dir.path().join(ACCOUNTS_FILE);
+        let mut data = fs::read(&path)?;
+        data[offset(ClientId(1)) as usize + 25] ^= 0xff;
+        fs::write(&path, data)?;
+
+        let err = FileAccountStore::from_path(dir.path()).err().unwrap();
+        assert!(err.to_string().starts_with("Corrupted store"));
+        assert_eq!(
+            "The account of client 1 is corrupted",
+            err.root_cause().to_string()
+        );
+        Ok(())
+    }
+}
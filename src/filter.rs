@@ -0,0 +1,153 @@
+//! Filtering of transactions by client.
+
+use std::collections::HashSet;
+
+use anyhow::{Context, Result};
+
+use crate::{ClientId, TransactionReader, TransactionRecord};
+
+/// Selects the clients whose transactions are processed.
+///
+/// By default every client is selected.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ClientFilter {
+    include: Option<HashSet<ClientId>>,
+    exclude: HashSet<ClientId>,
+}
+
+impl ClientFilter {
+    /// Select only the given clients, less any excluded.
+    pub fn with_include(mut self, clients: impl IntoIterator<Item = ClientId>) -> Self {
+        self.include = Some(clients.into_iter().collect());
+        self
+    }
+
+    /// Select every client, or every included client, except those given.
+    pub fn with_exclude(mut self, clients: impl IntoIterator<Item = ClientId>) -> Self {
+        self.exclude = clients.into_iter().collect();
+        self
+    }
+
+    /// Returns true if the client's transactions should be processed.
+    pub fn is_selected(&self, client: ClientId) -> bool {
+        let included = match &self.include {
+            Some(include) => include.contains(&client),
+            None => true,
+        };
+        included && !self.exclude.contains(&client)
+    }
+
+    /// Returns true if the record should be processed.
+    ///
+    /// Records which could not be read are always kept so that they are still reported.
+    pub fn accepts(&self, result: &Result<TransactionRecord>) -> bool {
+        match result {
+            Ok(record) => self.is_selected(record.client),
+            Err(_) => true,
+        }
+    }
+}
+
+/// Parses a comma separated list of client IDs, such as `1,2,3`.
+pub(crate) fn parse_clients(value: &str) -> Result<Vec<ClientId>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|client| !client.is_empty())
+        .map(|client| {
+            client
+                .parse::<ClientId>()
+                .with_context(|| format!("Invalid client ID: {}", client))
+        })
+        .collect()
+}
+
+/// Decorates a [`TransactionReader`] so only the records of selected clients are read.
+///
+/// Records of other clients never reach the processor, so they do not touch the store and
+/// disputes referencing their deposits are skipped as unknown transactions.
+pub struct FilteredTransactionReader<R: TransactionReader> {
+    reader: R,
+    filter: ClientFilter,
+}
+
+impl<R: TransactionReader> FilteredTransactionReader<R> {
+    /// Create a new reader which reads the records of clients selected by the filter.
+    pub fn new(reader: R, filter: ClientFilter) -> Self {
+        FilteredTransactionReader { reader, filter }
+    }
+}
+
+impl<R: TransactionReader> TransactionReader for FilteredTransactionReader<R> {
+    fn read<'a>(&'a mut self) -> Box<dyn Iterator<Item = Result<TransactionRecord>> + 'a> {
+        let filter = &self.filter;
+        Box::new(
+            self.reader
+                .read()
+                .filter(move |result| filter.accepts(result)),
+        )
+    }
+
+    fn read_with_line_numbers<'a>(
+        &'a mut self,
+    ) -> Box<dyn Iterator<Item = (Result<TransactionRecord>, u64)> + 'a> {
+        let filter = &self.filter;
+        Box::new(
+            self.reader
+                .read_with_line_numbers()
+                .filter(move |(result, _)| filter.accepts(result)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+    use itertools::Itertools;
+    use rust_decimal_macros::dec;
+    use test_case::test_case;
+
+    use crate::{TransactionId, VecTransactionReader};
+
+    use super::*;
+
+    fn deposit(client: u16, tx: u32) -> TransactionRecord {
+        TransactionRecord::deposit(ClientId(client), TransactionId(tx), dec!(1))
+    }
+
+    #[test_case(ClientFilter::default(),                                       vec![1, 2, 3]; "when no filter")]
+    #[test_case(ClientFilter::default().with_include([ClientId(1), ClientId(3)]), vec![1, 3];    "when include")]
+    #[test_case(ClientFilter::default().with_exclude([ClientId(1), ClientId(3)]), vec![2];       "when exclude")]
+    #[test_case(ClientFilter::default().with_include([ClientId(1), ClientId(3)])
+                                       .with_exclude([ClientId(3)]),              vec![1];       "when include and exclude")]
+    fn test_read_selected_clients(filter: ClientFilter, expected: Vec<u16>) {
+        let reader = VecTransactionReader::new(vec![deposit(1, 1), deposit(2, 2), deposit(3, 3)]);
+        let mut reader = FilteredTransactionReader::new(reader, filter);
+
+        let clients = reader
+            .read()
+            .map(|result| result.unwrap().client.0)
+            .collect_vec();
+        assert_eq!(expected, clients);
+    }
+
+    #[test]
+    fn test_accepts_unreadable_records() {
+        let filter = ClientFilter::default().with_include([]);
+        assert!(filter.accepts(&Err(anyhow!("unreadable"))));
+        assert!(!filter.accepts(&Ok(deposit(1, 1))));
+    }
+
+    #[test]
+    fn test_parse_clients() -> Result<()> {
+        assert_eq!(
+            vec![ClientId(1), ClientId(2), ClientId(3)],
+            parse_clients("1, 2,3")?
+        );
+        assert_eq!(
+            "Invalid client ID: x",
+            parse_clients("1,x").unwrap_err().to_string()
+        );
+        Ok(())
+    }
+}
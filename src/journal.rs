@@ -0,0 +1,224 @@
+//! A persistent journal of processed transactions.
+
+use std::{
+    collections::HashSet,
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+
+use crate::{TransactionId, TransactionRecord, TransactionType};
+
+/// Number of entries appended between writes to the file, so a crash loses at most this many.
+pub const JOURNAL_FLUSH_INTERVAL: usize = 64;
+
+/// An append-only journal of every transaction applied, used to make processing idempotent.
+///
+/// Each transaction is appended as a line of JSON once it has been applied, so one which was
+/// skipped, such as a withdrawal with insufficient funds, is retried by a later run. When a
+/// journal is reopened its transactions are replayed, in order, to restore the accounts exactly,
+/// and any transaction journaled by an earlier run is skipped rather than processed again. So
+/// the same input can be processed any number of times against a journal with the same result
+/// as once. Transactions repeated within a run are left to the processor's own duplicate checks.
+///
+/// Entries are written to the file every [`JOURNAL_FLUSH_INTERVAL`] transactions, so after a
+/// crash the input can be processed again from the start: the transactions journaled are
+/// replayed and skipped, and only the few lost are processed again.
+///
+/// Transactions are identified by their type and ID, so that a dispute is distinct from the
+/// deposit it refers to.
+pub struct TransactionJournal {
+    writer: BufWriter<File>,
+    /// The transactions journaled before the journal was opened.
+    processed: HashSet<(TransactionType, TransactionId)>,
+    replay: Vec<TransactionRecord>,
+    /// Number of entries appended since the file was last written.
+    unflushed: usize,
+}
+
+impl TransactionJournal {
+    /// Open the journal at the given path, creating it if it does not exist.
+    ///
+    /// An incomplete final entry, such as one left by a crash while it was written, is
+    /// discarded.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let mut contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err).context("Could not read journal"),
+        };
+        if !contents.is_empty() && !contents.ends_with('\n') {
+            let complete = contents.rfind('\n').map_or(0, |end| end + 1);
+            log::warn!(
+                "Discarding incomplete journal entry: {}",
+                &contents[complete..]
+            );
+            contents.truncate(complete);
+            OpenOptions::new()
+                .write(true)
+                .open(path)?
+                .set_len(complete as u64)?;
+        }
+
+        let replay = contents
+            .lines()
+            .enumerate()
+            .map(|(i, line)| {
+                serde_json::from_str::<TransactionRecord>(line)
+                    .with_context(|| format!("Invalid journal entry on line {}", i + 1))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let processed = replay
+            .iter()
+            .map(|record| (record.transaction_type, record.tx))
+            .collect();
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(TransactionJournal {
+            writer: BufWriter::new(file),
+            processed,
+            replay,
+            unflushed: 0,
+        })
+    }
+
+    /// Returns true if the transaction was journaled before the journal was opened, by an
+    /// earlier run.
+    ///
+    /// Transactions appended since are not included, so a transaction repeated within a run
+    /// is still reported as a duplicate by the processor.
+    pub fn contains(&self, transaction_type: TransactionType, tx: TransactionId) -> bool {
+        self.processed.contains(&(transaction_type, tx))
+    }
+
+    /// Takes the transactions journaled before the journal was opened, which are yet to be
+    /// replayed.
+    pub(crate) fn take_replay(&mut self) -> Vec<TransactionRecord> {
+        std::mem::take(&mut self.replay)
+    }
+
+    /// Returns true if there are journaled transactions yet to be replayed.
+    pub(crate) fn has_replay(&self) -> bool {
+        !self.replay.is_empty()
+    }
+
+    /// Appends an applied transaction.
+    pub(crate) fn append(&mut self, record: &TransactionRecord) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, record)?;
+        self.writer.write_all(b"\n")?;
+        self.unflushed += 1;
+        if self.unflushed >= JOURNAL_FLUSH_INTERVAL {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Write any buffered entries to the file.
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        self.unflushed = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use rust_decimal_macros::dec;
+
+    use crate::ClientId;
+
+    use super::*;
+
+    fn records() -> Vec<TransactionRecord> {
+        vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10.5))
+                .with_timestamp(Utc.timestamp_opt(1_650_000_000, 0).unwrap()),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+        ]
+    }
+
+    #[test]
+    fn test_open_replays_appended_records() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("journal");
+
+        let mut journal = TransactionJournal::open(&path)?;
+        assert!(!journal.has_replay());
+        for record in records() {
+            journal.append(&record)?;
+        }
+        // only transactions journaled by an earlier run are contained
+        assert!(!journal.contains(TransactionType::Dispute, TransactionId(1)));
+        journal.flush()?;
+
+        let mut journal = TransactionJournal::open(&path)?;
+        assert!(journal.contains(TransactionType::Deposit, TransactionId(1)));
+        assert!(journal.contains(TransactionType::Dispute, TransactionId(1)));
+        assert!(!journal.contains(TransactionType::Resolve, TransactionId(1)));
+        assert_eq!(records(), journal.take_replay());
+        assert!(!journal.has_replay());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_discards_incomplete_entry() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("journal");
+
+        let mut journal = TransactionJournal::open(&path)?;
+        journal.append(&records()[0])?;
+        journal.flush()?;
+        drop(journal);
+        let mut file = OpenOptions::new().append(true).open(&path)?;
+        write!(file, "{{\"type\":\"dispute\",\"cli")?;
+
+        let mut journal = TransactionJournal::open(&path)?;
+        assert_eq!(vec![records()[0].clone()], journal.take_replay());
+        journal.append(&records()[1])?;
+        journal.flush()?;
+
+        let mut journal = TransactionJournal::open(&path)?;
+        assert_eq!(records(), journal.take_replay());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_writes_every_flush_interval() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("journal");
+
+        let mut journal = TransactionJournal::open(&path)?;
+        let deposit = |tx| TransactionRecord::deposit(ClientId(1), TransactionId(tx), dec!(1));
+        for tx in 0..JOURNAL_FLUSH_INTERVAL as u32 * 2 + 1 {
+            journal.append(&deposit(tx))?;
+        }
+        // a crash leaves the entries since the last flush unwritten
+        std::mem::forget(journal);
+
+        let journal = TransactionJournal::open(&path)?;
+        assert_eq!(JOURNAL_FLUSH_INTERVAL * 2, journal.replay.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_fails_when_invalid_entry() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("journal");
+        fs::write(&path, "not json\n")?;
+
+        let result = TransactionJournal::open(&path);
+        assert_eq!(
+            "Invalid journal entry on line 1",
+            result.err().unwrap().to_string()
+        );
+
+        Ok(())
+    }
+}
@@ -1,15 +1,120 @@
 //! # The library internals of Rusty Bank
+//!
+//! Transactions are read by a [`TransactionReader`], applied to client accounts held in an
+//! [`AccountStore`] by the [`TransactionProcessor`], and the resulting accounts written by an
+//! [`AccountWriter`].
+//!
+//! The whole pipeline can be run in memory, for example when testing code built on this library:
+//!
+//! ```
+//! use rust_decimal_macros::dec;
+//! use rusty_bank::{
+//!     AccountSummary, ClientId, InMemoryAccountStore, TransactionId, TransactionProcessor,
+//!     TransactionRecord, VecAccountWriter, VecTransactionReader,
+//! };
+//!
+//! let reader = VecTransactionReader::new(vec![
+//!     TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+//!     TransactionRecord::withdrawal(ClientId(1), TransactionId(2), dec!(4)),
+//!     TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+//! ]);
+//!
+//! let mut processor = TransactionProcessor::new(InMemoryAccountStore::new());
+//! let report = processor.process(reader);
+//! assert_eq!(3, report.records);
+//!
+//! let mut writer = VecAccountWriter::new();
+//! processor.export(&mut writer)?;
+//!
+//! assert_eq!(
+//!     vec![AccountSummary::new(ClientId(1), dec!(10), dec!(6), false)?],
+//!     writer.into_accounts()
+//! );
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! [`run_pipeline`] does the same in one call, and [`run_csv_file`] runs a CSV file through it.
+//! [`run`] runs the pipeline as configured by the command line arguments, as the binary does.
 mod account_summary;
+mod anomaly;
+#[cfg(feature = "async")]
+mod async_reader;
+mod chained_reader;
+mod checking_store;
 mod client;
 mod config;
+mod coverage;
+mod dense_store;
+mod dispute_summary;
+#[cfg(feature = "ffi")]
+mod ffi;
+#[cfg(feature = "file-store")]
+mod file_store;
+mod filter;
+mod journal;
+#[cfg(feature = "parquet")]
+mod parquet_writer;
+mod pipeline;
+mod policy;
 mod processor;
 mod reader;
+mod rejected_transaction;
+mod report;
+mod retained;
+mod sharded;
+mod skip;
+mod socket_reader;
+mod spill;
+#[cfg(feature = "sqlite-store")]
+mod sqlite_store;
+mod statistics;
 mod store;
 mod transaction;
+mod transaction_ids;
 mod transaction_record;
+mod transaction_validator;
+mod validator;
 mod writer;
+#[cfg(feature = "xlsx")]
+mod xlsx_reader;
 
+#[cfg(feature = "async")]
+pub use async_reader::*;
+#[cfg(feature = "ffi")]
+pub use ffi::*;
+#[cfg(feature = "file-store")]
+pub use file_store::*;
+#[cfg(feature = "parquet")]
+pub use parquet_writer::*;
+#[cfg(feature = "sqlite-store")]
+pub use sqlite_store::*;
+#[cfg(feature = "xlsx")]
+pub use xlsx_reader::*;
 pub use {
-    account_summary::*, client::ClientId, config::Config, processor::*, reader::*, store::*,
-    transaction::*, transaction_record::*, writer::*,
+    account_summary::*,
+    anomaly::*,
+    chained_reader::*,
+    checking_store::CheckingAccountStore,
+    client::ClientId,
+    config::{Config, LogFormat, OutputFormat, OutputMode, StoreLocation},
+    dense_store::*,
+    dispute_summary::*,
+    filter::{ClientFilter, FilteredTransactionReader},
+    journal::{TransactionJournal, JOURNAL_FLUSH_INTERVAL},
+    pipeline::*,
+    policy::*,
+    processor::*,
+    reader::*,
+    rejected_transaction::*,
+    report::*,
+    sharded::*,
+    skip::*,
+    socket_reader::*,
+    statistics::*,
+    store::*,
+    transaction::*,
+    transaction_record::*,
+    transaction_validator::*,
+    validator::*,
+    writer::*,
 };
@@ -1,38 +1,35 @@
 extern crate rusty_bank;
 
-use std::env;
+use std::{
+    env, process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
 use anyhow::Result;
-use rusty_bank::{
-    Config, CsvAccountWriter, CsvTransactionReader, InMemoryAccountStore, TransactionProcessor,
-};
+use rusty_bank::{Completion, Config};
+
+/// Exit code when interrupted, after the partial results have been exported.
+const EXIT_INTERRUPTED: i32 = 130;
 
 fn main() -> Result<()> {
     env_logger::init();
 
     let args: Vec<String> = env::args().collect();
     let config = Config::new(&args)?;
-    let bank = RustyBank::new(config);
-    bank.run()
-}
 
-pub struct RustyBank {
-    config: Config,
-}
-
-impl RustyBank {
-    fn new(config: Config) -> Self {
-        RustyBank { config }
-    }
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let handler = cancelled.clone();
+    ctrlc::set_handler(move || handler.store(true, Ordering::Relaxed))?;
 
-    fn run(&self) -> Result<()> {
-        log::debug!("config: {:?}", self.config);
-        let store = InMemoryAccountStore::new();
-        let reader = CsvTransactionReader::from_path(&self.config.filename)?;
-        let writer = CsvAccountWriter::from_writer(std::io::stdout());
-        let mut processor = TransactionProcessor::new(store);
-        processor.process(reader);
-        processor.export(writer)?;
-        Ok(())
+    if let Completion::Interrupted(records) = rusty_bank::run(&config, &cancelled)? {
+        eprintln!(
+            "Warning: interrupted after {} records, the accounts exported are partial",
+            records
+        );
+        process::exit(EXIT_INTERRUPTED);
     }
+    Ok(())
 }
@@ -0,0 +1,240 @@
+use std::{io::Write, sync::Arc};
+
+use anyhow::{bail, Result};
+use arrow_array::{ArrayRef, BooleanArray, Decimal128Array, RecordBatch, UInt16Array};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::{arrow::ArrowWriter, file::properties::WriterProperties};
+use rust_decimal::Decimal;
+
+use crate::{AccountSummary, AccountWriter, AMOUNT_DECIMAL_PLACES};
+
+/// Number of decimal places stored for amounts, matching the precision of the input.
+pub const PARQUET_AMOUNT_SCALE: u32 = AMOUNT_DECIMAL_PLACES;
+
+/// Number of accounts buffered before they are written as a row group, unless configured.
+pub const DEFAULT_ROW_GROUP_SIZE: usize = 64 * 1024;
+
+/// Account writer for Parquet files.
+///
+/// Amounts are written as fixed-point `decimal(38, 4)` so no precision is lost. Accounts are
+/// buffered and written a row group at a time, so memory use is bounded by the row group size
+/// rather than the number of accounts.
+///
+/// The file is only readable once [`ParquetAccountWriter::into_inner`] has written its footer.
+pub struct ParquetAccountWriter<W>
+where
+    W: Write + Send,
+{
+    writer: ArrowWriter<W>,
+    schema: SchemaRef,
+    row_group_size: usize,
+    clients: Vec<u16>,
+    available: Vec<i128>,
+    held: Vec<i128>,
+    total: Vec<i128>,
+    locked: Vec<bool>,
+}
+
+impl<W> ParquetAccountWriter<W>
+where
+    W: Write + Send,
+{
+    /// Returns an account Parquet writer that writes data to wtr.
+    pub fn from_writer(wtr: W) -> Result<Self> {
+        ParquetAccountWriter::with_row_group_size(wtr, DEFAULT_ROW_GROUP_SIZE)
+    }
+
+    /// Returns an account Parquet writer that writes data to wtr in row groups of at most
+    /// `row_group_size` accounts.
+    pub fn with_row_group_size(wtr: W, row_group_size: usize) -> Result<Self> {
+        if row_group_size == 0 {
+            bail!("Row group size must be at least 1");
+        }
+        let amount = DataType::Decimal128(38, PARQUET_AMOUNT_SCALE as i8);
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("client", DataType::UInt16, false),
+            Field::new("available", amount.clone(), false),
+            Field::new("held", amount.clone(), false),
+            Field::new("total", amount, false),
+            Field::new("locked", DataType::Boolean, false),
+        ]));
+        let properties = WriterProperties::builder()
+            .set_max_row_group_size(row_group_size)
+            .build();
+        let writer = ArrowWriter::try_new(wtr, schema.clone(), Some(properties))?;
+        Ok(ParquetAccountWriter {
+            writer,
+            schema,
+            row_group_size,
+            clients: Vec::with_capacity(row_group_size),
+            available: Vec::with_capacity(row_group_size),
+            held: Vec::with_capacity(row_group_size),
+            total: Vec::with_capacity(row_group_size),
+            locked: Vec::with_capacity(row_group_size),
+        })
+    }
+
+    /// Write any buffered accounts, close the file with its footer and return the underlying
+    /// writer.
+    pub fn into_inner(mut self) -> Result<W> {
+        self.write_row_group()?;
+        Ok(self.writer.into_inner()?)
+    }
+
+    /// Writes the buffered accounts as a row group.
+    fn write_row_group(&mut self) -> Result<()> {
+        if self.clients.is_empty() {
+            return Ok(());
+        }
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(UInt16Array::from(std::mem::take(&mut self.clients))),
+            decimal_array(&mut self.available)?,
+            decimal_array(&mut self.held)?,
+            decimal_array(&mut self.total)?,
+            Arc::new(BooleanArray::from(std::mem::take(&mut self.locked))),
+        ];
+        let batch = RecordBatch::try_new(self.schema.clone(), columns)?;
+        self.writer.write(&batch)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<W> AccountWriter for ParquetAccountWriter<W>
+where
+    W: Write + Send,
+{
+    /// Buffers an account, writing a row group once enough have been buffered.
+    ///
+    /// Fails if an amount has more than four decimal places.
+    fn write(&mut self, account: &AccountSummary) -> Result<()> {
+        let available = unscaled(account.available())?;
+        let held = unscaled(account.held())?;
+        let total = unscaled(account.total())?;
+
+        self.clients.push(account.client().0);
+        self.available.push(available);
+        self.held.push(held);
+        self.total.push(total);
+        self.locked.push(account.locked());
+
+        if self.clients.len() >= self.row_group_size {
+            self.write_row_group()?;
+        }
+        Ok(())
+    }
+}
+
+/// Converts an amount to its unscaled value at [`PARQUET_AMOUNT_SCALE`].
+fn unscaled(amount: Decimal) -> Result<i128> {
+    let amount = amount.normalize();
+    if amount.scale() > PARQUET_AMOUNT_SCALE {
+        bail!(
+            "Amount '{}' has more than {} decimal places",
+            amount,
+            PARQUET_AMOUNT_SCALE
+        );
+    }
+    // A 96 bit mantissa scaled by at most 10^4 always fits
+    Ok(amount.mantissa() * 10i128.pow(PARQUET_AMOUNT_SCALE - amount.scale()))
+}
+
+/// Takes the buffered amounts as a column.
+fn decimal_array(amounts: &mut Vec<i128>) -> Result<ArrayRef> {
+    let array = Decimal128Array::from(std::mem::take(amounts))
+        .with_precision_and_scale(38, PARQUET_AMOUNT_SCALE as i8)?;
+    Ok(Arc::new(array))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Decimal128Type, UInt16Type};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use rust_decimal_macros::dec;
+
+    use crate::ClientId;
+
+    use super::*;
+
+    /// Reads back the accounts written to a Parquet file, along with the number of row groups.
+    fn read_accounts(file: File) -> Result<(Vec<AccountSummary>, usize)> {
+        let builder = ParquetRecordBatchReaderBuilder::try_new(file)?;
+        let row_groups = builder.metadata().num_row_groups();
+        let mut accounts = vec![];
+        for batch in builder.build()? {
+            let batch = batch?;
+            let clients = batch.column(0).as_primitive::<UInt16Type>();
+            let held = batch.column(2).as_primitive::<Decimal128Type>();
+            let total = batch.column(3).as_primitive::<Decimal128Type>();
+            let locked = batch.column(4).as_boolean();
+            for row in 0..batch.num_rows() {
+                accounts.push(
+                    AccountSummary::new(
+                        ClientId(clients.value(row)),
+                        Decimal::from_i128_with_scale(held.value(row), PARQUET_AMOUNT_SCALE),
+                        Decimal::from_i128_with_scale(total.value(row), PARQUET_AMOUNT_SCALE),
+                        locked.value(row),
+                    )
+                    .unwrap(),
+                );
+            }
+        }
+        Ok((accounts, row_groups))
+    }
+
+    #[test]
+    fn test_write() -> Result<()> {
+        let file = tempfile::tempfile()?;
+        let mut wtr = ParquetAccountWriter::with_row_group_size(file, 2)?;
+
+        let accounts = vec![
+            AccountSummary::new(ClientId(1), dec!(0), dec!(50), false).unwrap(),
+            AccountSummary::new(ClientId(2), dec!(10.1234), dec!(40), false).unwrap(),
+            AccountSummary::new(ClientId(3), dec!(5), dec!(-2.5), true).unwrap(),
+            AccountSummary::new(
+                ClientId(u16::MAX),
+                dec!(0),
+                dec!(79228162514264.3375),
+                false,
+            )
+            .unwrap(),
+        ];
+        for account in &accounts {
+            wtr.write(account)?;
+        }
+
+        let (result, row_groups) = read_accounts(wtr.into_inner()?)?;
+        assert_eq!(accounts, result);
+        assert_eq!(2, row_groups);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_when_no_accounts() -> Result<()> {
+        let wtr = ParquetAccountWriter::from_writer(tempfile::tempfile()?)?;
+
+        let (result, row_groups) = read_accounts(wtr.into_inner()?)?;
+        assert!(result.is_empty());
+        assert_eq!(0, row_groups);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_when_too_many_decimal_places() -> Result<()> {
+        let mut wtr = ParquetAccountWriter::from_writer(vec![])?;
+
+        // only a deserialized summary can have more places, as `new` rounds its amounts
+        let account: AccountSummary = serde_json::from_str(
+            r#"{"client":1,"available":"1.00001","held":"0","total":"1.00001","locked":false}"#,
+        )?;
+        let expected = "Amount '1.00001' has more than 4 decimal places";
+        assert_eq!(expected, wtr.write(&account).unwrap_err().to_string());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,952 @@
+//! Runs the whole pipeline, from reading transactions to writing accounts, in one call.
+
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::{
+    fs,
+    fs::OpenOptions,
+    io::{self, BufWriter, ErrorKind, Read, Write},
+    net::TcpListener,
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use tempfile::NamedTempFile;
+
+#[cfg(feature = "file-store")]
+use crate::FileAccountStore;
+#[cfg(feature = "sqlite-store")]
+use crate::SqliteAccountStore;
+#[cfg(feature = "xlsx")]
+use crate::XlsxTransactionReader;
+use crate::{
+    expand_input_paths, AccountStore, AccountWriter, ChainedTransactionReader,
+    CheckingAccountStore, Config, CsvAccountWriter, CsvAnomalyLog, CsvDisputeWriter,
+    CsvRejectedTransactionWriter, CsvTransactionReader, DeltaAccountWriter, DeniedClientsValidator,
+    FilteredTransactionReader, InMemoryAccountStore, JsonAccountWriter, JsonArrayAccountWriter,
+    JsonLinesTransactionReader, MaxAmountValidator, OutputFormat, OutputMode, ProcessingReport,
+    SocketTransactionReader, StoreLocation, StreamFormat, SummaryStatistics, TransactionJournal,
+    TransactionProcessor, TransactionReader, TransactionRecord, TransactionValidator, Validator,
+};
+
+/// Number of records processed between checkpoints, unless configured.
+const DEFAULT_CHECKPOINT_INTERVAL: u64 = 10_000;
+
+/// Number of deposits, and of dispute cases, kept in memory when spilling, unless configured.
+const DEFAULT_SPILL_THRESHOLD: u64 = 100_000;
+
+/// How long to wait between checks for a new connection, or for an interruption.
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Processes every transaction read, applying them to the accounts in the store, and writes
+/// the accounts.
+///
+/// The processor applies the default [`ProcessingPolicies`](crate::ProcessingPolicies); to
+/// choose others, build it with a [`TransactionProcessorBuilder`](crate::TransactionProcessorBuilder)
+/// instead.
+///
+/// Returns the report of what happened to the records read, or an error if processing stopped
+/// early, see [`ProcessingReport::into_result`], or the accounts could not be written.
+///
+/// ### Parameters
+/// - reader: The transaction reader.
+/// - store: The data store implementation.
+/// - writer: The implementation of the account writer, which may be lent with `&mut` to be
+///   inspected afterwards.
+pub fn run_pipeline(
+    reader: impl TransactionReader,
+    store: impl AccountStore,
+    writer: impl AccountWriter,
+) -> Result<ProcessingReport> {
+    let mut processor = TransactionProcessor::new(store);
+    let report = processor.process(reader).into_result()?;
+    processor.export(writer)?;
+    Ok(report)
+}
+
+/// Processes the transactions in a CSV file, with the accounts held in memory, and writes the
+/// accounts to `output` as CSV.
+///
+/// Returns the report of what happened to the records read and the underlying writer.
+///
+/// ### Parameters
+/// - input: The path of the CSV file of transactions.
+/// - output: Where the accounts are written, such as a `Vec<u8>` or stdout.
+pub fn run_csv_file<W: Write + Send + Sync + 'static>(
+    input: &Path,
+    output: W,
+) -> Result<(ProcessingReport, W)> {
+    let reader = CsvTransactionReader::from_path(input)
+        .with_context(|| format!("Could not read transactions {:?}", input))?;
+    let mut writer = CsvAccountWriter::from_writer(output);
+    let report = run_pipeline(reader, InMemoryAccountStore::new(), &mut writer)?;
+    Ok((report, writer.into_inner()?))
+}
+
+/// How a [`run`] finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Completion {
+    /// Every record was processed.
+    Finished,
+    /// Stopped early, after the given number of records.
+    Interrupted(u64),
+}
+
+/// Runs the pipeline as configured on the command line, until every record is processed or
+/// `cancelled` is set, such as by a signal handler.
+///
+/// The accounts are written to the configured output, or stdout, and any summary to stderr.
+/// When cancelled, the accounts processed so far are still written.
+///
+/// ### Parameters
+/// - config: The parsed command line arguments.
+/// - cancelled: Set to stop processing.
+pub fn run(config: &Config, cancelled: &AtomicBool) -> Result<Completion> {
+    Pipeline { config, cancelled }.run()
+}
+
+/// A run of the pipeline as configured.
+struct Pipeline<'a> {
+    config: &'a Config,
+    cancelled: &'a AtomicBool,
+}
+
+impl Pipeline<'_> {
+    fn run(&self) -> Result<Completion> {
+        log::debug!("config: {:?}", self.config);
+        if let Some(address) = &self.config.listen {
+            return self.listen(address);
+        }
+        if self.config.reads_stdin() {
+            let stdin = io::stdin().lock();
+            return match self.config.input_format {
+                StreamFormat::JsonLines => {
+                    self.run_json_lines(JsonLinesTransactionReader::from_reader(stdin))
+                }
+                StreamFormat::Csv => self.run_csv(self.csv_reader(
+                    CsvTransactionReader::from_reader_with_options(
+                        stdin,
+                        self.config.csv_options.clone(),
+                    ),
+                )),
+            };
+        }
+        if !self.config.more_filenames.is_empty() || Path::new(&self.config.filename).is_dir() {
+            return self.run_files();
+        }
+        if self.config.input_format == StreamFormat::JsonLines {
+            return self.run_json_lines(JsonLinesTransactionReader::from_path(
+                &self.config.filename,
+            )?);
+        }
+        let is_xlsx = Path::new(&self.config.filename)
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("xlsx"));
+        if is_xlsx {
+            return self.run_xlsx();
+        }
+        let reader = self.csv_reader(CsvTransactionReader::with_options(
+            &self.config.filename,
+            self.config.csv_options.clone(),
+        )?);
+        match self.config.checkpoint.as_deref().map(Path::new) {
+            Some(path) if !self.config.validate_only => self.run_with_checkpoint(reader, path),
+            _ => self.run_csv(reader),
+        }
+    }
+
+    /// Applies the configured parsing options to a CSV reader.
+    fn csv_reader<R: Read>(&self, reader: CsvTransactionReader<R>) -> CsvTransactionReader<R> {
+        reader
+            .with_lenient_amounts(self.config.lenient_amounts)
+            .with_lenient_ids(self.config.lenient_ids)
+            .with_strict(self.config.strict)
+    }
+
+    /// Runs over CSV input, which may be a stream such as stdin.
+    fn run_csv<R: Read>(&self, mut reader: CsvTransactionReader<R>) -> Result<Completion> {
+        if self.config.validate_only {
+            return self.validate(reader.read_with_lines()?);
+        }
+        if let Some(store) = &self.config.store {
+            return self.run_with_store(store, reader);
+        }
+        self.run_in_memory(reader)
+    }
+
+    /// Runs over a CSV file, saving a checkpoint periodically and resuming from it if it exists.
+    fn run_with_checkpoint(
+        &self,
+        mut reader: CsvTransactionReader,
+        path: &Path,
+    ) -> Result<Completion> {
+        // a resumed run seeks to where the last one stopped, which gzip input cannot
+        if reader.get_ref().is_gzip() {
+            bail!("--checkpoint cannot be used with gzip compressed input");
+        }
+        let processor = match path.exists() {
+            true => {
+                let (processor, position) = TransactionProcessor::from_checkpoint(path)?;
+                log::info!("Resuming from checkpoint {:?} at {:?}", path, position);
+                reader.seek(position)?;
+                processor
+            }
+            false => self.new_processor(),
+        };
+        let mut processor = self.configure(processor)?;
+
+        let interval = self
+            .config
+            .checkpoint_interval
+            .unwrap_or(DEFAULT_CHECKPOINT_INTERVAL);
+        let mut processed = 0;
+        let mut report = ProcessingReport::new().with_policies(processor.policies());
+        let mut last_position = None;
+        let mut interrupted = false;
+        for (result, line, position) in reader.read_with_lines_and_positions()? {
+            if self.cancelled.load(Ordering::Relaxed) {
+                interrupted = true;
+                break;
+            }
+            if self.config.client_filter.accepts(&result) {
+                let outcome = processor.process_line(result, line);
+                if !processor.add_outcome(&mut report, outcome) {
+                    break;
+                }
+            }
+            processed += 1;
+            if processed % interval == 0 {
+                processor.save_checkpoint(path, position)?;
+            }
+            last_position = Some(position);
+        }
+        log::info!("{}", report);
+        report.into_result()?;
+        if interrupted {
+            // keep the checkpoint, up to date, so the run can be resumed
+            if let Some(position) = last_position {
+                processor.save_checkpoint(path, position)?;
+            }
+            self.export(processor)?;
+            return Ok(Completion::Interrupted(processed));
+        }
+        self.export(processor)?;
+        // the run completed so there is nothing to resume
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(Completion::Finished)
+    }
+
+    /// Runs over several CSV files, or the files in directories, read in order as one input.
+    fn run_files(&self) -> Result<Completion> {
+        if self.config.input_format != StreamFormat::Csv {
+            bail!("Only csv input can be read from more than one file");
+        }
+        if self.config.checkpoint.is_some() {
+            bail!("--checkpoint cannot be used with more than one input file");
+        }
+        if self.config.validate_only {
+            bail!("--validate-only cannot be used with more than one input file");
+        }
+        let paths = expand_input_paths(&self.config.filenames().collect::<Vec<_>>())?;
+        log::info!("Reading {} input files", paths.len());
+        let options = self.config.csv_options.clone();
+        let (lenient_amounts, lenient_ids, strict) = (
+            self.config.lenient_amounts,
+            self.config.lenient_ids,
+            self.config.strict,
+        );
+        let reader = ChainedTransactionReader::with_opener(paths, move |path| {
+            Ok(CsvTransactionReader::with_options(path, options.clone())?
+                .with_lenient_amounts(lenient_amounts)
+                .with_lenient_ids(lenient_ids)
+                .with_strict(strict))
+        });
+        if let Some(store) = &self.config.store {
+            return self.run_with_store(store, reader);
+        }
+        self.run_in_memory(reader)
+    }
+
+    /// Runs over JSON Lines input, which cannot be resumed from a checkpoint.
+    fn run_json_lines<R: Read>(&self, reader: JsonLinesTransactionReader<R>) -> Result<Completion> {
+        let mut reader = reader.with_strict(self.config.strict);
+        if self.config.validate_only {
+            return self.validate(reader.read_with_lines());
+        }
+        if let Some(store) = &self.config.store {
+            return self.run_with_store(store, reader);
+        }
+        self.run_in_memory(reader)
+    }
+
+    /// Runs over an Excel workbook, which cannot be resumed from a checkpoint.
+    #[cfg(feature = "xlsx")]
+    fn run_xlsx(&self) -> Result<Completion> {
+        if self.config.checkpoint.is_some() {
+            bail!("--checkpoint cannot be used with .xlsx input");
+        }
+        let reader = XlsxTransactionReader::from_path(&self.config.filename)?;
+        if self.config.validate_only {
+            return self.validate(reader.read_with_rows()?);
+        }
+        if let Some(store) = &self.config.store {
+            return self.run_with_store(store, reader);
+        }
+        self.run_in_memory(reader)
+    }
+
+    #[cfg(not(feature = "xlsx"))]
+    fn run_xlsx(&self) -> Result<Completion> {
+        bail!("Reading .xlsx input requires the xlsx feature");
+    }
+
+    /// Runs against the persistent store, continuing from the accounts and dispute state left
+    /// by earlier runs and saving the state for the next.
+    fn run_with_store(
+        &self,
+        store: &StoreLocation,
+        reader: impl TransactionReader,
+    ) -> Result<Completion> {
+        match store {
+            StoreLocation::Directory(dir) => self.run_with_file_store(dir, reader),
+            StoreLocation::Sqlite(path) => self.run_with_sqlite_store(path, reader),
+        }
+    }
+
+    #[cfg(feature = "file-store")]
+    fn run_with_file_store(
+        &self,
+        dir: &Path,
+        reader: impl TransactionReader,
+    ) -> Result<Completion> {
+        let store =
+            FileAccountStore::from_path(dir)?.with_overdraft_limit(self.config.overdraft_limit);
+        let state = store.state_path();
+        self.run_with_persistent_store(store, &state, reader)
+    }
+
+    #[cfg(not(feature = "file-store"))]
+    fn run_with_file_store(
+        &self,
+        _dir: &Path,
+        _reader: impl TransactionReader,
+    ) -> Result<Completion> {
+        bail!("--store requires the file-store feature");
+    }
+
+    #[cfg(feature = "sqlite-store")]
+    fn run_with_sqlite_store(
+        &self,
+        path: &Path,
+        reader: impl TransactionReader,
+    ) -> Result<Completion> {
+        let store =
+            SqliteAccountStore::from_path(path)?.with_overdraft_limit(self.config.overdraft_limit);
+        let state = store.state_path();
+        self.run_with_persistent_store(store, &state, reader)
+    }
+
+    #[cfg(not(feature = "sqlite-store"))]
+    fn run_with_sqlite_store(
+        &self,
+        _path: &Path,
+        _reader: impl TransactionReader,
+    ) -> Result<Completion> {
+        bail!("--store sqlite:<path> requires the sqlite-store feature");
+    }
+
+    /// Processes the records against a persistent store, restoring and then saving the
+    /// processor's state in the file alongside it.
+    #[cfg(any(feature = "file-store", feature = "sqlite-store"))]
+    fn run_with_persistent_store<S: AccountStore>(
+        &self,
+        store: S,
+        state: &Path,
+        reader: impl TransactionReader,
+    ) -> Result<Completion> {
+        let mut processor = self.configure(TransactionProcessor::new(store).with_state(state)?)?;
+        let report = self.process_records(&mut processor, reader);
+        // the accounts are already saved, so the state is kept in step with them even if
+        // interrupted or stopped at a malformed record
+        processor.save_state(state)?;
+        let report = report.into_result()?;
+        self.export(processor)?;
+        Ok(self.completion(&report))
+    }
+
+    /// Accepts one connection at a time, processing its stream of transactions and then writing
+    /// a snapshot of the accounts, until interrupted.
+    fn listen(&self, address: &str) -> Result<Completion> {
+        let listener = Listener::bind(address)?;
+        log::info!("Listening on {}", address);
+        let mut processor = self.configure(self.new_processor())?;
+        while !self.cancelled.load(Ordering::Relaxed) {
+            let stream = match listener.accept()? {
+                Some(stream) => stream,
+                None => {
+                    thread::sleep(ACCEPT_POLL_INTERVAL);
+                    continue;
+                }
+            };
+            let reader = SocketTransactionReader::from_reader(stream, self.config.stream_format);
+            let filter = self.config.client_filter.clone();
+            let report = processor.process_until(
+                FilteredTransactionReader::new(reader, filter),
+                self.cancelled,
+            );
+            log::info!("{} from connection", report);
+            self.snapshot(&mut processor)?;
+        }
+        listener.close()?;
+        Ok(Completion::Finished)
+    }
+
+    /// Appends a snapshot of the accounts to the snapshots file, or writes it to stdout, and
+    /// saves the summary statistics if requested.
+    fn snapshot(&self, processor: &mut TransactionProcessor<InMemoryAccountStore>) -> Result<()> {
+        let statistics = match &self.config.snapshots {
+            Some(path) => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("Could not write snapshot {:?}", path))?;
+                self.write_snapshot(processor, file)?
+            }
+            None => self.write_snapshot(processor, std::io::stdout())?,
+        };
+        self.report_statistics(&statistics)
+    }
+
+    /// Writes a snapshot of the accounts in the configured format.
+    fn write_snapshot<W: Write + Send + Sync + 'static>(
+        &self,
+        processor: &mut TransactionProcessor<InMemoryAccountStore>,
+        wtr: W,
+    ) -> Result<SummaryStatistics> {
+        match self.config.output_format {
+            OutputFormat::Csv => {
+                let mut writer = CsvAccountWriter::from_writer(wtr);
+                let statistics = processor.snapshot(&mut writer)?;
+                writer.into_inner()?;
+                Ok(statistics)
+            }
+            OutputFormat::Json => {
+                let mut writer = JsonAccountWriter::from_writer(wtr);
+                let statistics = processor.snapshot(&mut writer)?;
+                writer.into_inner()?;
+                Ok(statistics)
+            }
+            OutputFormat::JsonArray => {
+                let mut writer = JsonArrayAccountWriter::from_writer(wtr);
+                let statistics = processor.snapshot(&mut writer)?;
+                writer.into_inner()?;
+                Ok(statistics)
+            }
+        }
+    }
+
+    fn new_processor(&self) -> TransactionProcessor<InMemoryAccountStore> {
+        TransactionProcessor::new(self.new_store())
+    }
+
+    fn new_store(&self) -> InMemoryAccountStore {
+        InMemoryAccountStore::with_overdraft_limit(self.config.overdraft_limit)
+    }
+
+    /// Processes the transactions with the accounts held in memory, checking every operation
+    /// on them if asked to.
+    fn run_in_memory(&self, reader: impl TransactionReader) -> Result<Completion> {
+        let store = self.new_store();
+        match self.config.check_invariants {
+            true => {
+                let processor = TransactionProcessor::new(CheckingAccountStore::new(store));
+                self.process(self.configure(processor)?, reader)
+            }
+            false => self.process(self.configure(TransactionProcessor::new(store))?, reader),
+        }
+    }
+
+    /// Applies the options to a processor, new or resumed from a checkpoint.
+    fn configure<S: AccountStore>(
+        &self,
+        processor: TransactionProcessor<S>,
+    ) -> Result<TransactionProcessor<S>> {
+        let mut processor = processor
+            .with_admin_ops(self.config.allow_admin_ops)
+            .with_log_format(self.config.log_format)
+            .with_max_dispute_age(self.config.max_dispute_age)
+            .with_untouched_accounts(self.config.export_untouched_accounts)
+            .with_cancel_disputes_on_lock(!self.config.keep_disputes_on_lock)
+            .with_chargeback_locked_accounts(self.config.chargeback_locked_accounts)
+            .with_deltas(self.config.output_mode == OutputMode::Deltas)
+            .with_strict(self.config.strict)
+            .with_excess_precision(self.config.excess_precision)
+            .with_dispute_policy(self.config.dispute_policy)
+            .with_validators(self.validators())
+            .with_max_retained_transactions(
+                self.config
+                    .max_retained_transactions
+                    .map(|max| max.try_into().unwrap_or(usize::MAX)),
+            );
+        if let Some(path) = &self.config.journal {
+            processor = processor.with_journal(TransactionJournal::open(path)?);
+        }
+        if let Some(path) = &self.config.rejected {
+            let file = fs::File::create(path)
+                .with_context(|| format!("Could not write rejected transactions {:?}", path))?;
+            processor = processor.with_rejected(CsvRejectedTransactionWriter::from_writer(file));
+        }
+        if let Some(path) = &self.config.anomalies {
+            let file = fs::File::create(path)
+                .with_context(|| format!("Could not write anomalies {:?}", path))?;
+            processor = processor.with_anomalies(CsvAnomalyLog::from_writer(file));
+        }
+        if let Some(dir) = &self.config.spill_dir {
+            let threshold = self
+                .config
+                .spill_threshold
+                .unwrap_or(DEFAULT_SPILL_THRESHOLD);
+            processor =
+                processor.with_spill_dir(dir, threshold.try_into().unwrap_or(usize::MAX))?;
+        }
+        Ok(processor)
+    }
+
+    /// The built-in validation rules which have been configured.
+    fn validators(&self) -> Vec<Box<dyn TransactionValidator>> {
+        let mut validators: Vec<Box<dyn TransactionValidator>> = Vec::new();
+        if !self.config.denied_clients.is_empty() {
+            validators.push(Box::new(DeniedClientsValidator::new(
+                self.config.denied_clients.iter().copied(),
+            )));
+        }
+        if let Some(max) = self.config.max_amount {
+            validators.push(Box::new(MaxAmountValidator::new(max)));
+        }
+        validators
+    }
+
+    /// Processes every record, until interrupted, and writes the accounts.
+    fn process<S: AccountStore>(
+        &self,
+        mut processor: TransactionProcessor<S>,
+        reader: impl TransactionReader,
+    ) -> Result<Completion> {
+        let report = self.process_records(&mut processor, reader).into_result()?;
+        self.export(processor)?;
+        Ok(self.completion(&report))
+    }
+
+    /// Processes every record accepted by the client filter, until interrupted.
+    fn process_records<S: AccountStore>(
+        &self,
+        processor: &mut TransactionProcessor<S>,
+        reader: impl TransactionReader,
+    ) -> ProcessingReport {
+        let filter = self.config.client_filter.clone();
+        let report = processor.process_until(
+            FilteredTransactionReader::new(reader, filter),
+            self.cancelled,
+        );
+        log::info!("{}", report);
+        report
+    }
+
+    fn completion(&self, report: &ProcessingReport) -> Completion {
+        match self.cancelled.load(Ordering::Relaxed) {
+            true => Completion::Interrupted(report.records),
+            false => Completion::Finished,
+        }
+    }
+
+    /// Reports every problem found in the input, without processing it or touching any
+    /// checkpoint or journal.
+    fn validate(
+        &self,
+        records: impl IntoIterator<Item = (Result<TransactionRecord>, u64)>,
+    ) -> Result<Completion> {
+        let mut validator = Validator::new();
+        validator.validate_all(records);
+        for problem in validator.problems() {
+            println!("{}", problem);
+        }
+        if !validator.problems().is_empty() {
+            bail!(
+                "Found {} problems in {} records",
+                validator.problems().len(),
+                validator.records()
+            );
+        }
+        Ok(Completion::Finished)
+    }
+
+    /// Writes the accounts, or the change in each, and the dispute cases and summary statistics
+    /// if requested.
+    fn export<S: AccountStore>(&self, mut processor: TransactionProcessor<S>) -> Result<()> {
+        if let Some(path) = &self.config.disputes_output {
+            let file = fs::File::create(path)
+                .with_context(|| format!("Could not write disputes {:?}", path))?;
+            let mut writer = CsvDisputeWriter::from_writer(file);
+            processor.export_disputes(&mut writer)?;
+            writer.into_inner()?;
+        }
+        match &self.config.output {
+            Some(path) => {
+                // written to a temporary file which only replaces the output once complete, so
+                // a failed export never leaves a truncated file behind
+                let file = temporary_output(path)?;
+                let (statistics, file) = self.export_accounts(processor, BufWriter::new(file))?;
+                let file = file.into_inner().map_err(|err| err.into_error())?;
+                file.as_file().sync_all()?;
+                self.report_statistics(&statistics)?;
+                file.persist(path)
+                    .with_context(|| format!("Could not write output {:?}", path))?;
+            }
+            None => {
+                let (statistics, _) = self.export_accounts(processor, std::io::stdout())?;
+                self.report_statistics(&statistics)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the accounts, or the change in each, returning the summary statistics and the
+    /// underlying writer.
+    fn export_accounts<S: AccountStore, W: Write + Send + Sync + 'static>(
+        &self,
+        mut processor: TransactionProcessor<S>,
+        wtr: W,
+    ) -> Result<(SummaryStatistics, W)> {
+        match (self.config.output_mode, self.config.output_format) {
+            (OutputMode::Balances, OutputFormat::Csv) => {
+                let mut writer = CsvAccountWriter::from_writer(wtr);
+                let statistics = processor.export(&mut writer)?;
+                Ok((statistics, writer.into_inner()?))
+            }
+            (OutputMode::Balances, OutputFormat::Json) => {
+                let mut writer = JsonAccountWriter::from_writer(wtr);
+                let statistics = processor.export(&mut writer)?;
+                Ok((statistics, writer.into_inner()?))
+            }
+            (OutputMode::Balances, OutputFormat::JsonArray) => {
+                let mut writer = JsonArrayAccountWriter::from_writer(wtr);
+                let statistics = processor.export(&mut writer)?;
+                Ok((statistics, writer.into_inner()?))
+            }
+            // only written as CSV, which the config ensures
+            (OutputMode::Deltas, _) => {
+                let baseline = processor.take_baseline();
+                let mut writer = DeltaAccountWriter::from_writer(wtr, baseline);
+                let statistics = processor.export(&mut writer)?;
+                Ok((statistics, writer.into_inner()?))
+            }
+        }
+    }
+
+    /// Saves the summary statistics, and prints a summary of them to stderr, if requested.
+    fn report_statistics(&self, statistics: &SummaryStatistics) -> Result<()> {
+        if let Some(path) = &self.config.stats {
+            statistics.save(path)?;
+        }
+        if self.config.summary {
+            eprintln!("Summary: {}", statistics);
+        }
+        Ok(())
+    }
+}
+
+/// Creates a temporary file in the same directory as the output, so it can be renamed over it.
+fn temporary_output(path: &Path) -> Result<NamedTempFile> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    tempfile::Builder::new()
+        .prefix(".rusty-bank")
+        .tempfile_in(dir)
+        .with_context(|| format!("Could not write output {:?}", path))
+}
+
+/// Listens for transaction streams on a TCP port of localhost, or a Unix socket.
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener, String),
+}
+
+impl Listener {
+    /// Listens on the port, if the address is a number, or otherwise the socket path.
+    fn bind(address: &str) -> Result<Self> {
+        let listener = match address.parse::<u16>() {
+            Ok(port) => {
+                let listener = TcpListener::bind(("127.0.0.1", port))
+                    .with_context(|| format!("Could not listen on port {}", port))?;
+                listener.set_nonblocking(true)?;
+                Listener::Tcp(listener)
+            }
+            #[cfg(unix)]
+            Err(_) => {
+                let listener = UnixListener::bind(address)
+                    .with_context(|| format!("Could not listen on {:?}", address))?;
+                listener.set_nonblocking(true)?;
+                Listener::Unix(listener, address.to_string())
+            }
+            #[cfg(not(unix))]
+            Err(_) => bail!("Listening on a socket path requires Unix, use a port instead"),
+        };
+        Ok(listener)
+    }
+
+    /// Accepts a connection, if one is waiting, without blocking.
+    fn accept(&self) -> Result<Option<Box<dyn Read>>> {
+        let stream: std::io::Result<Box<dyn Read>> = match self {
+            Listener::Tcp(listener) => listener.accept().and_then(|(stream, _)| {
+                stream.set_nonblocking(false)?;
+                Ok(Box::new(stream) as Box<dyn Read>)
+            }),
+            #[cfg(unix)]
+            Listener::Unix(listener, _) => listener.accept().and_then(|(stream, _)| {
+                stream.set_nonblocking(false)?;
+                Ok(Box::new(stream) as Box<dyn Read>)
+            }),
+        };
+        match stream {
+            Ok(stream) => Ok(Some(stream)),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Stops listening, removing any socket file.
+    fn close(self) -> Result<()> {
+        #[cfg(unix)]
+        if let Listener::Unix(listener, path) = self {
+            drop(listener);
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+    use tempfile::NamedTempFile;
+
+    use crate::{
+        AccountSummary, ClientId, TransactionId, TransactionRecord, TransactionType,
+        VecAccountWriter, VecTransactionReader,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_run_pipeline() -> Result<()> {
+        let reader = VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::withdrawal(ClientId(1), TransactionId(2), dec!(4)),
+            TransactionRecord::deposit(ClientId(2), TransactionId(3), dec!(2.5)),
+            TransactionRecord::dispute(ClientId(2), TransactionId(3)),
+        ]);
+        let mut writer = CsvAccountWriter::from_writer(Vec::new());
+
+        let report = run_pipeline(reader, InMemoryAccountStore::new(), &mut writer)?;
+        assert_eq!(4, report.records);
+        assert_eq!(2, report.applied(TransactionType::Deposit));
+
+        let output = String::from_utf8(writer.into_inner()?)?;
+        let mut lines: Vec<_> = output.lines().collect();
+        lines[1..].sort();
+        assert_eq!(
+            vec![
+                "client,available,held,total,locked",
+                "1,6,0,6,false",
+                "2,0.0,2.5,2.5,false",
+            ],
+            lines
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_pipeline_when_empty() -> Result<()> {
+        let mut writer = VecAccountWriter::new();
+        let report = run_pipeline(
+            VecTransactionReader::new(Vec::new()),
+            InMemoryAccountStore::new(),
+            &mut writer,
+        )?;
+        assert_eq!(0, report.records);
+        assert_eq!(Vec::<AccountSummary>::new(), writer.into_accounts());
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_pipeline_when_malformed() -> Result<()> {
+        let reader = VecTransactionReader::new(vec![
+            TransactionRecord::new(
+                TransactionType::Deposit,
+                ClientId(1),
+                TransactionId(1),
+                None,
+            ),
+            TransactionRecord::deposit(ClientId(1), TransactionId(2), dec!(1)),
+        ]);
+        let mut writer = VecAccountWriter::new();
+
+        let report = run_pipeline(reader, InMemoryAccountStore::new(), &mut writer)?;
+        assert_eq!(1, report.malformed);
+        assert_eq!(
+            vec![AccountSummary::new(ClientId(1), dec!(0), dec!(1), false).unwrap()],
+            writer.into_accounts()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_csv_file() -> Result<()> {
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "type,client,tx,amount")?;
+        writeln!(file, "deposit,1,1,1.5")?;
+        writeln!(file, "withdrawal,1,2,0.5")?;
+        writeln!(file, "withdrawal,1,3,5")?;
+
+        let (report, output) = run_csv_file(file.path(), Vec::new())?;
+        assert_eq!(3, report.records);
+        assert_eq!(
+            "client,available,held,total,locked\n1,1,0,1,false\n",
+            String::from_utf8(output)?
+        );
+        Ok(())
+    }
+
+    /// Returns the config for the arguments, the output file and then the input file.
+    fn config(args: &[&str], output: &Path, input: &Path) -> Config {
+        let mut all = vec!["rusty-bank".to_string()];
+        all.extend(args.iter().map(|arg| arg.to_string()));
+        all.extend(["--output".to_string(), output.display().to_string()]);
+        all.push(input.display().to_string());
+        Config::new(&all).unwrap()
+    }
+
+    fn input(lines: &[&str]) -> Result<NamedTempFile> {
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "type,client,tx,amount")?;
+        for line in lines {
+            writeln!(file, "{}", line)?;
+        }
+        Ok(file)
+    }
+
+    #[test]
+    fn test_run() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let output = dir.path().join("accounts.csv");
+        let input = input(&["deposit,1,1,1.5", "deposit,2,2,2", "withdrawal,1,3,0.5"])?;
+
+        let config = config(&[], &output, input.path());
+        let completion = run(&config, &AtomicBool::new(false))?;
+        assert_eq!(Completion::Finished, completion);
+        assert_eq!(
+            "client,available,held,total,locked\n1,1,0,1,false\n2,2,0,2,false\n",
+            fs::read_to_string(&output)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_when_cancelled_writes_accounts() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let output = dir.path().join("accounts.csv");
+        let input = input(&["deposit,1,1,1.5"])?;
+
+        let config = config(&[], &output, input.path());
+        let completion = run(&config, &AtomicBool::new(true))?;
+        assert_eq!(Completion::Interrupted(0), completion);
+        assert_eq!(
+            "client,available,held,total,locked\n",
+            fs::read_to_string(&output)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_with_checkpoint() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let output = dir.path().join("accounts.csv");
+        let checkpoint = dir.path().join("checkpoint.json");
+        let input = input(&["deposit,1,1,10", "withdrawal,1,2,20", "withdrawal,1,3,4"])?;
+
+        let args = [
+            "--checkpoint",
+            checkpoint.to_str().unwrap(),
+            "--checkpoint-interval",
+            "1",
+        ];
+        let config = config(&args, &output, input.path());
+        assert_eq!(Completion::Finished, run(&config, &AtomicBool::new(false))?);
+        assert_eq!(
+            "client,available,held,total,locked\n1,6,0,6,false\n",
+            fs::read_to_string(&output)?
+        );
+        // the run completed so there is nothing to resume
+        assert!(!checkpoint.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_with_checkpoint_when_strict_stops_like_without() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let output = dir.path().join("accounts.csv");
+        let checkpoint = dir.path().join("checkpoint.json");
+        let input = input(&["deposit,1,1,10", "deposit,1,x,5", "deposit,1,3,5"])?;
+
+        let config_without = config(&["--strict"], &output, input.path());
+        let without = run(&config_without, &AtomicBool::new(false)).unwrap_err();
+
+        let args = ["--strict", "--checkpoint", checkpoint.to_str().unwrap()];
+        let config_with = config(&args, &output, input.path());
+        let with = run(&config_with, &AtomicBool::new(false)).unwrap_err();
+
+        assert!(
+            without.to_string().starts_with("Stopped at record 2: "),
+            "{}",
+            without
+        );
+        assert_eq!(without.to_string(), with.to_string());
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_csv_file_counts_read_errors() -> Result<()> {
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "type,client,tx,amount")?;
+        writeln!(file, "deposit,1,1,1.5")?;
+        writeln!(file, "deposit,1,x,5")?;
+        writeln!(file, "deposit,1,2,")?;
+
+        let (report, _) = run_csv_file(file.path(), Vec::new())?;
+        assert_eq!(3, report.records);
+        assert_eq!(1, report.read_errors);
+        assert_eq!(1, report.malformed);
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_csv_file_when_missing() {
+        let err = run_csv_file(Path::new("does-not-exist.csv"), Vec::new()).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("Could not read transactions \"does-not-exist.csv\""),
+            "{}",
+            err
+        );
+    }
+}
@@ -0,0 +1,259 @@
+//! Policies for how a processor handles transactions, and a builder to choose them.
+
+use std::str::FromStr;
+
+use anyhow::{bail, Error, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{AccountStore, TransactionProcessor};
+
+/// What to do with a deposit or withdrawal reusing the ID of an earlier one.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicatePolicy {
+    /// Skip the duplicate and carry on processing.
+    #[default]
+    Skip,
+    /// Stop processing at the duplicate, which is reported as
+    /// [`ProcessingReport::aborted`](crate::ProcessingReport::aborted).
+    Error,
+}
+
+/// What to do with a dispute of a deposit holding more than the funds available, such as
+/// when some of the deposit has since been withdrawn.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisputePolicy {
+    /// Hold the disputed amount, leaving the available funds negative.
+    #[default]
+    AllowNegative,
+    /// Skip the dispute as [`SkipReason::InsufficientFunds`](crate::SkipReason::InsufficientFunds).
+    RejectIfInsufficient,
+}
+
+impl FromStr for DisputePolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "allow-negative" => Ok(DisputePolicy::AllowNegative),
+            "reject-if-insufficient" => Ok(DisputePolicy::RejectIfInsufficient),
+            _ => bail!("Unknown dispute policy: {}", s),
+        }
+    }
+}
+
+/// What to do with a deposit or withdrawal amount with more than
+/// [`AMOUNT_DECIMAL_PLACES`](crate::AMOUNT_DECIMAL_PLACES) decimal places.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExcessPrecision {
+    /// Reject the record as malformed.
+    #[default]
+    Reject,
+    /// Round the amount with banker's rounding, see [`round_amount`](crate::round_amount).
+    Round,
+}
+
+impl FromStr for ExcessPrecision {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "reject" => Ok(ExcessPrecision::Reject),
+            "round" => Ok(ExcessPrecision::Round),
+            _ => bail!("Unknown excess precision policy: {}", s),
+        }
+    }
+}
+
+/// The policies a [`TransactionProcessor`] applies, as reported in its
+/// [`ProcessingReport`](crate::ProcessingReport).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ProcessingPolicies {
+    /// Stop at the first malformed record.
+    pub strict: bool,
+    /// Allow withdrawals, as well as deposits, to be disputed.
+    pub allow_withdrawal_disputes: bool,
+    /// What to do with a deposit or withdrawal reusing a transaction ID.
+    pub on_duplicate_tx: DuplicatePolicy,
+    /// Keep deposits and withdrawals so that they can be disputed later.
+    pub record_history: bool,
+    /// Number of records after which a dispute still open expires, releasing its held funds,
+    /// or `None` for disputes to stay open until resolved or charged back.
+    pub dispute_expiry: Option<u64>,
+    /// Hold only the part of a disputed deposit not yet spent by later withdrawals.
+    #[serde(default)]
+    pub hold_coverable_only: bool,
+    /// What to do with an amount with more decimal places than are kept.
+    #[serde(default)]
+    pub excess_precision: ExcessPrecision,
+    /// Whether a dispute of a deposit may leave the available funds negative.
+    #[serde(default)]
+    pub dispute_policy: DisputePolicy,
+}
+
+impl Default for ProcessingPolicies {
+    fn default() -> Self {
+        ProcessingPolicies {
+            strict: false,
+            allow_withdrawal_disputes: true,
+            on_duplicate_tx: DuplicatePolicy::Skip,
+            record_history: true,
+            dispute_expiry: None,
+            hold_coverable_only: false,
+            excess_precision: ExcessPrecision::Reject,
+            dispute_policy: DisputePolicy::AllowNegative,
+        }
+    }
+}
+
+/// Builds a [`TransactionProcessor`] with the chosen [`ProcessingPolicies`].
+///
+/// Any policy not chosen keeps its default, as with [`TransactionProcessor::new`].
+///
+/// ```
+/// use rusty_bank::{DuplicatePolicy, InMemoryAccountStore, TransactionProcessorBuilder};
+///
+/// let processor = TransactionProcessorBuilder::new()
+///     .strict(true)
+///     .allow_withdrawal_disputes(false)
+///     .on_duplicate_tx(DuplicatePolicy::Error)
+///     .build(InMemoryAccountStore::new());
+/// assert!(processor.policies().strict);
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct TransactionProcessorBuilder {
+    policies: ProcessingPolicies,
+}
+
+impl TransactionProcessorBuilder {
+    /// Create a builder with the default policies.
+    pub fn new() -> Self {
+        TransactionProcessorBuilder::default()
+    }
+
+    /// Stop at the first malformed record, see [`TransactionProcessor::with_strict`].
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.policies.strict = strict;
+        self
+    }
+
+    /// Allow withdrawals to be disputed, see
+    /// [`TransactionProcessor::with_withdrawal_disputes`].
+    pub fn allow_withdrawal_disputes(mut self, allow: bool) -> Self {
+        self.policies.allow_withdrawal_disputes = allow;
+        self
+    }
+
+    /// Choose what to do with a duplicate transaction ID, see
+    /// [`TransactionProcessor::with_duplicate_policy`].
+    pub fn on_duplicate_tx(mut self, policy: DuplicatePolicy) -> Self {
+        self.policies.on_duplicate_tx = policy;
+        self
+    }
+
+    /// Keep deposits and withdrawals to be disputed, see [`TransactionProcessor::with_history`].
+    pub fn record_history(mut self, record: bool) -> Self {
+        self.policies.record_history = record;
+        self
+    }
+
+    /// Expire disputes still open after this many records, see
+    /// [`TransactionProcessor::with_dispute_expiry`].
+    pub fn dispute_expiry(mut self, records: Option<u64>) -> Self {
+        self.policies.dispute_expiry = records;
+        self
+    }
+
+    /// Hold only the part of a disputed deposit not yet withdrawn, see
+    /// [`TransactionProcessor::with_hold_coverable_only`].
+    pub fn hold_coverable_only(mut self, coverable_only: bool) -> Self {
+        self.policies.hold_coverable_only = coverable_only;
+        self
+    }
+
+    /// Choose what to do with an amount with too many decimal places, see
+    /// [`TransactionProcessor::with_excess_precision`].
+    pub fn excess_precision(mut self, policy: ExcessPrecision) -> Self {
+        self.policies.excess_precision = policy;
+        self
+    }
+
+    /// Choose whether a dispute may leave the available funds negative, see
+    /// [`TransactionProcessor::with_dispute_policy`].
+    pub fn dispute_policy(mut self, policy: DisputePolicy) -> Self {
+        self.policies.dispute_policy = policy;
+        self
+    }
+
+    /// Returns a processor over the store, applying the chosen policies.
+    ///
+    /// ### Parameters
+    /// - store: The data store implementation.
+    pub fn build<S: AccountStore>(self, store: S) -> TransactionProcessor<S> {
+        let ProcessingPolicies {
+            strict,
+            allow_withdrawal_disputes,
+            on_duplicate_tx,
+            record_history,
+            dispute_expiry,
+            hold_coverable_only,
+            excess_precision,
+            dispute_policy,
+        } = self.policies;
+        TransactionProcessor::new(store)
+            .with_strict(strict)
+            .with_withdrawal_disputes(allow_withdrawal_disputes)
+            .with_duplicate_policy(on_duplicate_tx)
+            .with_history(record_history)
+            .with_dispute_expiry(dispute_expiry)
+            .with_hold_coverable_only(hold_coverable_only)
+            .with_excess_precision(excess_precision)
+            .with_dispute_policy(dispute_policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::InMemoryAccountStore;
+
+    use super::*;
+
+    #[test]
+    fn test_build_when_defaults() {
+        let processor = TransactionProcessorBuilder::new().build(InMemoryAccountStore::new());
+        assert_eq!(ProcessingPolicies::default(), processor.policies());
+        assert_eq!(
+            TransactionProcessor::new(InMemoryAccountStore::new()).policies(),
+            processor.policies()
+        );
+    }
+
+    #[test]
+    fn test_build() {
+        let processor = TransactionProcessorBuilder::new()
+            .strict(true)
+            .allow_withdrawal_disputes(false)
+            .on_duplicate_tx(DuplicatePolicy::Error)
+            .record_history(false)
+            .dispute_expiry(Some(100))
+            .hold_coverable_only(true)
+            .excess_precision(ExcessPrecision::Round)
+            .dispute_policy(DisputePolicy::RejectIfInsufficient)
+            .build(InMemoryAccountStore::new());
+        assert_eq!(
+            ProcessingPolicies {
+                strict: true,
+                allow_withdrawal_disputes: false,
+                on_duplicate_tx: DuplicatePolicy::Error,
+                record_history: false,
+                dispute_expiry: Some(100),
+                hold_coverable_only: true,
+                excess_precision: ExcessPrecision::Round,
+                dispute_policy: DisputePolicy::RejectIfInsufficient,
+            },
+            processor.policies()
+        );
+    }
+}
@@ -1,39 +1,137 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use chrono::Duration;
+use rust_decimal::Decimal;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
+#[cfg(feature = "async")]
+use crate::AsyncTransactionReader;
 use crate::{
-    AccountStore, AccountWriter, Chargeback, Deposit, Dispute, Resolve, Transaction, TransactionId,
-    TransactionReader, Withdrawal,
+    coverage::Coverage,
+    retained::{Direction, RetainedTransactions},
+    spill::SpillMap,
+    transaction_ids::TransactionIdSet,
+};
+use crate::{
+    AccountStore, AccountSummary, AccountVisitor, AccountWriter, Anomaly, AnomalyLog, Chargeback,
+    ClientId, Deposit, Dispute, DisputePolicy, DisputeStatus, DisputeSummary, DisputeWriter,
+    DuplicatePolicy, ExcessPrecision, InputPosition, LogFormat, ProcessingPolicies,
+    ProcessingReport, RecordError, RecordOutcome, RejectedTransaction, RejectedTransactionWriter,
+    Resolve, SkipEvent, SkipReason, SummaryStatistics, Transaction, TransactionId,
+    TransactionJournal, TransactionReader, TransactionRecord, TransactionType,
+    TransactionValidator, Unlock, ValidationContext, ValidationError, Withdrawal,
 };
-
-/// Indicates if a dispute is open or closed.
-#[derive(Debug)]
-enum DisputeStatus {
-    Open,
-    Closed,
-}
 
 /// Represents a dispute case
-#[derive(Debug)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 struct DisputeCase {
     detail: Dispute,
+    /// The amount of the disputed transaction, which may no longer be retained.
+    amount: Decimal,
     status: DisputeStatus,
+    /// Whether a deposit or a withdrawal was disputed.
+    #[serde(default)]
+    direction: Direction,
 }
 
 impl DisputeCase {
-    fn new(detail: Dispute) -> Self {
+    fn new(detail: Dispute, amount: Decimal, direction: Direction) -> Self {
         DisputeCase {
             detail,
+            amount,
             status: DisputeStatus::Open,
+            direction,
         }
     }
 
-    fn close(&mut self) {
-        self.status = DisputeStatus::Closed;
+    fn resolve(&mut self) {
+        self.status = DisputeStatus::Resolved;
+    }
+
+    fn charge_back(&mut self) {
+        self.status = DisputeStatus::ChargedBack;
+    }
+
+    fn cancel(&mut self) {
+        self.status = DisputeStatus::Cancelled;
+    }
+
+    fn expire(&mut self) {
+        self.status = DisputeStatus::Expired;
+    }
+
+    /// Fails unless the case is still open.
+    fn check_open(&self) -> Result<(), SkipReason> {
+        match self.status {
+            DisputeStatus::Open => Ok(()),
+            DisputeStatus::Resolved | DisputeStatus::ChargedBack | DisputeStatus::Expired => {
+                Err(SkipReason::DisputeClosed)
+            }
+            DisputeStatus::Cancelled => Err(SkipReason::DisputeCancelled),
+        }
     }
 }
 
+/// Processing state written to a checkpoint file.
+#[derive(Serialize)]
+struct CheckpointRef<'a, S> {
+    position: InputPosition,
+    store: &'a S,
+    deposits: &'a RetainedTransactions,
+    disputes: &'a SpillMap<DisputeCase>,
+    clients: &'a HashSet<ClientId>,
+    transaction_ids: &'a TransactionIdSet,
+    statistics: &'a SummaryStatistics,
+    baseline: &'a Option<HashMap<ClientId, AccountSummary>>,
+    coverage: &'a Coverage,
+}
+
+/// Processing state read from a checkpoint file.
+#[derive(Deserialize)]
+struct Checkpoint<S> {
+    position: InputPosition,
+    store: S,
+    deposits: RetainedTransactions,
+    disputes: SpillMap<DisputeCase>,
+    #[serde(default)]
+    clients: HashSet<ClientId>,
+    #[serde(default)]
+    transaction_ids: TransactionIdSet,
+    #[serde(default)]
+    statistics: SummaryStatistics,
+    #[serde(default)]
+    baseline: Option<HashMap<ClientId, AccountSummary>>,
+    #[serde(default)]
+    coverage: Coverage,
+}
+
+/// Bookkeeping saved between runs against a persistent store, which keeps the accounts itself.
+#[derive(Serialize)]
+struct StateRef<'a> {
+    deposits: &'a RetainedTransactions,
+    disputes: &'a SpillMap<DisputeCase>,
+    clients: &'a HashSet<ClientId>,
+    transaction_ids: &'a TransactionIdSet,
+    coverage: &'a Coverage,
+}
+
+/// Bookkeeping read from a state file.
+#[derive(Deserialize)]
+struct State {
+    deposits: RetainedTransactions,
+    disputes: SpillMap<DisputeCase>,
+    clients: HashSet<ClientId>,
+    transaction_ids: TransactionIdSet,
+    #[serde(default)]
+    coverage: Coverage,
+}
+
 /// A transaction processor which implements the key operations on client accounts.
 ///
 /// [`TransactionProcessor`] supports implementations of the [`AccountStore`], [`TransactionReader`]
@@ -45,8 +143,32 @@ impl DisputeCase {
 ///
 pub struct TransactionProcessor<S: AccountStore> {
     store: S,
-    deposits: HashMap<TransactionId, Deposit>,
-    disputes: HashMap<TransactionId, DisputeCase>,
+    retained: RetainedTransactions,
+    disputes: SpillMap<DisputeCase>,
+    allow_admin_ops: bool,
+    log_format: LogFormat,
+    max_dispute_age: Option<Duration>,
+    export_untouched_accounts: bool,
+    clients: HashSet<ClientId>,
+    transaction_ids: TransactionIdSet,
+    statistics: SummaryStatistics,
+    cancel_disputes_on_lock: bool,
+    chargeback_locked_accounts: bool,
+    open_disputes: HashMap<ClientId, HashSet<TransactionId>>,
+    /// Disputes to expire, oldest first, with the number of records processed when each was
+    /// raised, kept only when disputes expire.
+    expiring_disputes: VecDeque<(u64, TransactionId)>,
+    journal: Option<TransactionJournal>,
+    baseline: Option<HashMap<ClientId, AccountSummary>>,
+    validators: Vec<Box<dyn TransactionValidator>>,
+    policies: ProcessingPolicies,
+    rejected: Option<Box<dyn RejectedTransactionWriter>>,
+    anomalies: Option<Box<dyn AnomalyLog>>,
+    /// What is left of each deposit after later withdrawals, kept only when disputes hold the
+    /// coverable part of a deposit.
+    coverage: Coverage,
+    /// Number of records processed, used as the line of a record whose line is unknown.
+    records: u64,
 }
 
 impl<S: AccountStore> TransactionProcessor<S> {
@@ -58,243 +180,2309 @@ impl<S: AccountStore> TransactionProcessor<S> {
     pub fn new(store: S) -> Self {
         TransactionProcessor {
             store,
-            deposits: HashMap::new(),
-            disputes: HashMap::new(),
+            retained: RetainedTransactions::default(),
+            disputes: SpillMap::default(),
+            allow_admin_ops: false,
+            log_format: LogFormat::Text,
+            max_dispute_age: None,
+            export_untouched_accounts: false,
+            clients: HashSet::new(),
+            transaction_ids: TransactionIdSet::default(),
+            statistics: SummaryStatistics::default(),
+            cancel_disputes_on_lock: true,
+            chargeback_locked_accounts: false,
+            open_disputes: HashMap::new(),
+            expiring_disputes: VecDeque::new(),
+            journal: None,
+            baseline: None,
+            validators: Vec::new(),
+            policies: ProcessingPolicies::default(),
+            rejected: None,
+            anomalies: None,
+            coverage: Coverage::default(),
+            records: 0,
         }
     }
 
-    /// Process transactions.
+    /// Allow administrative transactions, such as unlocking a frozen account.
     ///
-    /// Using a supplied reader, reads and processes each transaction and maintains client account state.
+    /// Administrative transactions are ignored unless explicitly allowed.
     ///
     /// ### Parameters
-    /// - reader: The transaction reader.
-    pub fn process(&mut self, mut reader: impl TransactionReader) {
-        for result in reader.read() {
-            match result {
-                Ok(record) => match record.into() {
-                    Ok(tx) => self.process_transaction(tx),
-                    Err(err) => log::error!("Malformed transaction: {}", err),
-                },
-                Err(err) => log::error!("Could not read transaction record: {}", err),
-            }
-        }
+    /// - allow: Whether administrative transactions should be honoured.
+    ///
+    pub fn with_admin_ops(mut self, allow: bool) -> Self {
+        self.allow_admin_ops = allow;
+        self
     }
 
-    fn process_transaction(&mut self, transaction: Transaction) {
-        match transaction {
-            Transaction::Deposit(tx) => self.process_deposit(tx),
-            Transaction::Withdrawal(tx) => self.process_withdrawal(tx),
-            Transaction::Dispute(tx) => self.process_dispute(tx),
-            Transaction::Resolve(tx) => self.process_resolve(tx),
-            Transaction::Chargeback(tx) => self.process_chargeback(tx),
-        }
+    /// Set the format used to log skipped transactions.
+    ///
+    /// ### Parameters
+    /// - format: [`LogFormat::Text`] for human readable logs or [`LogFormat::Json`]
+    ///   for a serialized [`SkipEvent`] per skipped transaction.
+    ///
+    pub fn with_log_format(mut self, format: LogFormat) -> Self {
+        self.log_format = format;
+        self
     }
 
-    fn process_deposit(&mut self, deposit: Deposit) {
-        log::debug!("Processing deposit for {:?}", deposit);
-        if let Err(err) = self.store.add_funds(deposit.client, deposit.amount) {
-            log::info!("Cannot process {:?}: {}", deposit, err);
-            return;
-        };
+    /// Set how long after a deposit it may still be disputed.
+    ///
+    /// Disputes raised later than this are skipped. The window is only enforced when both
+    /// the deposit and the dispute carry a timestamp.
+    ///
+    /// ### Parameters
+    /// - max_age: The maximum age of a deposit which may be disputed, or `None` for no limit.
+    ///
+    pub fn with_max_dispute_age(mut self, max_age: Option<Duration>) -> Self {
+        self.max_dispute_age = max_age;
+        self
+    }
 
-        self.deposits.insert(deposit.tx, deposit);
+    /// Cancel a client's other open disputes when a chargeback locks their account.
+    ///
+    /// Enabled by default. The held funds of each cancelled dispute are released back to the
+    /// account before it is locked, and the cancelled cases can no longer be resolved or
+    /// charged back. Otherwise the other disputes remain open, and their funds held, while the
+    /// account is locked.
+    ///
+    /// ### Parameters
+    /// - cancel: Whether the other open disputes should be cancelled.
+    ///
+    pub fn with_cancel_disputes_on_lock(mut self, cancel: bool) -> Self {
+        self.cancel_disputes_on_lock = cancel;
+        self
     }
 
-    fn process_withdrawal(&mut self, withdrawal: Withdrawal) {
-        log::debug!("Processing withdrawal for {:?}", withdrawal);
-        if let Err(err) = self
-            .store
-            .remove_funds(withdrawal.client, withdrawal.amount)
-        {
-            log::info!("Cannot process {:?}: {}", withdrawal, err)
-        };
+    /// Charge back a disputed deposit even if an earlier chargeback already locked the account.
+    ///
+    /// Disabled by default, when a chargeback to a locked account is skipped as
+    /// [`SkipReason::AccountLocked`]. Only useful with the other open disputes kept on lock,
+    /// see [`Self::with_cancel_disputes_on_lock`]. A warning is logged for each chargeback
+    /// which bypasses the lock.
+    ///
+    /// ### Parameters
+    /// - allow: Whether chargebacks may change a locked account.
+    ///
+    pub fn with_chargeback_locked_accounts(mut self, allow: bool) -> Self {
+        self.chargeback_locked_accounts = allow;
+        self
     }
 
-    fn process_dispute(&mut self, dispute: Dispute) {
-        log::debug!("Processing dispute for {:?}", dispute);
+    /// Journal every transaction processed so that input processed again is skipped.
+    ///
+    /// Transactions already in the journal are replayed before any others are processed,
+    /// restoring the accounts, so they are replayed with the options set on this processor.
+    /// Replayed transactions are included in the [`SummaryStatistics`].
+    ///
+    /// ### Parameters
+    /// - journal: The journal of transactions already processed.
+    ///
+    pub fn with_journal(mut self, journal: TransactionJournal) -> Self {
+        self.journal = Some(journal);
+        self
+    }
 
-        let deposit = match self.deposits.get(&dispute.tx) {
-            Some(deposit) => deposit,
-            None => {
-                log::info!(
-                    "Cannot process dispute. No such transaction found for {:?}",
-                    dispute
-                );
-                return;
-            }
-        };
+    /// Limit the number of deposits and withdrawals retained for dispute handling.
+    ///
+    /// Every one is retained by default, so memory grows with the number of transactions.
+    /// Once more than `max` have been retained the oldest are evicted, and disputes against
+    /// them are then skipped as unknown transactions. Open disputes are unaffected, and the IDs
+    /// of evicted transactions are still remembered so that they cannot be reused.
+    ///
+    /// ### Parameters
+    /// - max: The number of transactions to retain, or `None` to retain them all.
+    ///
+    pub fn with_max_retained_transactions(mut self, max: Option<usize>) -> Self {
+        self.retained.set_max(max);
+        self
+    }
 
-        if deposit.client != dispute.client {
-            log::info!(
-                "Cannot process dispute. Client ID does not match for {:?} and {:?}",
-                dispute,
-                deposit
-            );
-            return;
-        }
+    /// Spill the oldest transactions and dispute cases to disk once there are too many in memory.
+    ///
+    /// Every deposit and withdrawal is retained in memory by default, along with every dispute case, so memory
+    /// grows with the input. Once more than `max_in_memory` of either are in memory the oldest
+    /// are written to a file in `dir`, removed when the processor is dropped, and read back when
+    /// next disputed, resolved or charged back. Unlike
+    /// [`TransactionProcessor::with_max_retained_transactions`] nothing is forgotten.
+    ///
+    /// ### Parameters
+    /// - dir: The directory to create the spill files in.
+    /// - max_in_memory: The number of transactions, and of dispute cases, to keep in memory.
+    ///
+    pub fn with_spill_dir<P: AsRef<Path>>(mut self, dir: P, max_in_memory: usize) -> Result<Self> {
+        self.retained.set_spill_dir(dir.as_ref(), max_in_memory)?;
+        self.disputes.set_spill_dir(dir.as_ref(), max_in_memory)?;
+        Ok(self)
+    }
 
-        if let Some(case) = self.disputes.get(&dispute.tx) {
-            log::info!("Cannot process dispute. A case already exists {:?}", case);
-            return;
+    /// Check business rules before each transaction is applied.
+    ///
+    /// The validators are run in order, and a transaction rejected by any of them is skipped
+    /// with the validator's reason. Disputes, resolves and chargebacks are only seen by the
+    /// validators which opt in with [`TransactionValidator::validates_disputes`].
+    ///
+    /// ### Parameters
+    /// - validators: The validators to run, replacing any already set.
+    ///
+    pub fn with_validators(mut self, validators: Vec<Box<dyn TransactionValidator>>) -> Self {
+        self.validators = validators;
+        self
+    }
+
+    /// Track the change in each account, so that the accounts can be exported as deltas.
+    ///
+    /// The state of each client's account is recorded as a baseline before the first
+    /// transaction from now on is applied to it, from the [`AccountStore`]. Journaled
+    /// transactions which are replayed do not count. Clients without a baseline were
+    /// untouched. The baseline is saved with a checkpoint, so a resumed run continues to
+    /// track the change from its original start. See [`DeltaAccountWriter`](crate::DeltaAccountWriter).
+    ///
+    /// ### Parameters
+    /// - track: Whether the change in each account should be tracked.
+    ///
+    pub fn with_deltas(mut self, track: bool) -> Self {
+        match track {
+            true => {
+                self.baseline.get_or_insert_with(HashMap::new);
+            }
+            false => self.baseline = None,
         }
+        self
+    }
 
-        if let Err(err) = self.store.hold_funds(dispute.client, deposit.amount) {
-            log::info!("Cannot process {:?}: {}", dispute, err);
-            return;
-        };
+    /// Takes the state each touched account had before it was first touched.
+    ///
+    /// Empty unless tracked with [`TransactionProcessor::with_deltas`]. Clients who had no
+    /// account before they were touched have an empty baseline.
+    pub fn take_baseline(&mut self) -> HashMap<ClientId, AccountSummary> {
+        self.baseline.take().unwrap_or_default()
+    }
 
-        self.disputes.insert(dispute.tx, DisputeCase::new(dispute));
+    /// Stop processing at the first record which cannot be read or is not a valid transaction.
+    ///
+    /// By default such records are logged and skipped. In strict mode processing stops there,
+    /// and the record is reported as [`ProcessingReport::aborted`], so that a run over input
+    /// which should be clean can fail loudly.
+    ///
+    /// ### Parameters
+    /// - strict: Whether to stop at the first malformed record.
+    ///
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.policies.strict = strict;
+        self
     }
 
-    fn process_resolve(&mut self, resolve: Resolve) {
-        log::debug!("Processing dispute resolution for {:?}", resolve);
+    /// Allow withdrawals, as well as deposits, to be disputed, as they are by default.
+    ///
+    /// Otherwise a dispute of a withdrawal is skipped, as
+    /// [`SkipReason::WithdrawalDisputesNotAllowed`].
+    ///
+    /// ### Parameters
+    /// - allow: Whether withdrawals can be disputed.
+    ///
+    pub fn with_withdrawal_disputes(mut self, allow: bool) -> Self {
+        self.policies.allow_withdrawal_disputes = allow;
+        self
+    }
 
-        let dispute = match self.disputes.get_mut(&resolve.tx) {
-            Some(dispute) => dispute,
-            None => {
-                log::info!(
-                    "Cannot process dispute resolution. No such dispute found for {:?}",
-                    resolve
-                );
-                return;
-            }
-        };
+    /// Choose what to do with a deposit or withdrawal reusing the ID of an earlier one.
+    ///
+    /// By default it is skipped, as [`SkipReason::DuplicateTransaction`]. With
+    /// [`DuplicatePolicy::Error`] processing stops there, and the record is reported as
+    /// [`ProcessingReport::aborted`].
+    ///
+    /// ### Parameters
+    /// - policy: What to do with a duplicate transaction.
+    ///
+    pub fn with_duplicate_policy(mut self, policy: DuplicatePolicy) -> Self {
+        self.policies.on_duplicate_tx = policy;
+        self
+    }
 
-        if let DisputeStatus::Closed = dispute.status {
-            log::info!(
-                "Cannot process {:?}. Case has already been closed for {:?}",
-                resolve,
-                dispute
-            );
-            return;
-        }
+    /// Keep deposits and withdrawals so that they can be disputed later, as they are by default.
+    ///
+    /// Otherwise none are kept, saving their memory, and every dispute is skipped as an unknown
+    /// transaction. Their IDs are still remembered, so duplicates are still detected.
+    ///
+    /// ### Parameters
+    /// - record: Whether deposits and withdrawals are kept.
+    ///
+    pub fn with_history(mut self, record: bool) -> Self {
+        self.policies.record_history = record;
+        self
+    }
 
-        if dispute.detail.client != resolve.client {
-            log::info!(
-                "Cannot process dispute resolution. Client ID does not match for {:?} and {:?}",
-                resolve,
-                dispute
-            );
-            return;
-        }
+    /// Expire disputes still open a number of records after they were raised, releasing their
+    /// held funds back to the client as if resolved. Disabled by default.
+    ///
+    /// The age of a dispute is the number of records this processor has processed since, as
+    /// there may be no timestamps; a dispute restored from a checkpoint or state ages from when
+    /// it was restored. An expired dispute can no longer be resolved or charged back.
+    ///
+    /// ### Parameters
+    /// - records: The number of records after which an open dispute expires, or `None` for
+    ///   disputes to stay open until resolved or charged back.
+    ///
+    pub fn with_dispute_expiry(mut self, records: Option<u64>) -> Self {
+        self.policies.dispute_expiry = records;
+        self.queue_expiring_disputes();
+        self
+    }
 
-        // If a dispute exists then a deposit must also
-        let amount = self.deposits.get(&resolve.tx).unwrap().amount;
+    /// Hold only the part of a disputed deposit not yet spent by later withdrawals, rather than
+    /// its full amount, which may leave the available funds negative. Disabled by default.
+    ///
+    /// Withdrawals are attributed to a client's deposits first in first out. A dispute which
+    /// holds less than the deposit logs the shortfall, and a chargeback of a deposit of which
+    /// nothing is left locks the account without removing any funds.
+    ///
+    /// ### Parameters
+    /// - coverable_only: Whether a dispute holds only the coverable part of a deposit.
+    ///
+    pub fn with_hold_coverable_only(mut self, coverable_only: bool) -> Self {
+        self.policies.hold_coverable_only = coverable_only;
+        self
+    }
 
-        if let Err(err) = self.store.release_funds(dispute.detail.client, amount) {
-            log::info!("Cannot process {:?}: {}", resolve, err);
-            return;
-        };
+    /// Choose what to do with a deposit or withdrawal amount with more than
+    /// [`AMOUNT_DECIMAL_PLACES`](crate::AMOUNT_DECIMAL_PLACES) decimal places. By default the
+    /// record is rejected as malformed.
+    ///
+    /// ### Parameters
+    /// - policy: Whether to reject the record or round its amount.
+    ///
+    pub fn with_excess_precision(mut self, policy: ExcessPrecision) -> Self {
+        self.policies.excess_precision = policy;
+        self
+    }
 
-        dispute.close();
+    /// Choose whether a dispute of a deposit may hold more than the funds available, leaving
+    /// them negative, as it may by default.
+    ///
+    /// ### Parameters
+    /// - policy: Whether to allow negative available funds or skip the dispute.
+    ///
+    pub fn with_dispute_policy(mut self, policy: DisputePolicy) -> Self {
+        self.policies.dispute_policy = policy;
+        self
     }
 
-    fn process_chargeback(&mut self, chargeback: Chargeback) {
-        log::debug!("Processing chargeback for {:?}", chargeback);
+    /// Returns the policies the processor applies.
+    pub fn policies(&self) -> ProcessingPolicies {
+        self.policies
+    }
 
-        let dispute = match self.disputes.get_mut(&chargeback.tx) {
-            Some(dispute) => dispute,
-            None => {
-                log::info!(
-                    "Cannot process chargeback. No such dispute found for {:?}",
-                    chargeback
-                );
-                return;
-            }
-        };
+    /// Write every record which is not applied, and why, such as for an audit of the input.
+    ///
+    /// Each [`RejectedTransaction`] has the line of the input its record was read from, when
+    /// processed by [`TransactionProcessor::process`] or [`TransactionProcessor::process_line`],
+    /// otherwise the number of the record. A rejection which cannot be written is logged.
+    ///
+    /// ### Parameters
+    /// - writer: The implementation of the rejected transaction writer.
+    ///
+    pub fn with_rejected(mut self, writer: impl RejectedTransactionWriter + 'static) -> Self {
+        self.rejected = Some(Box::new(writer));
+        self
+    }
 
-        if let DisputeStatus::Closed = dispute.status {
-            log::info!(
-                "Cannot process {:?}. Case has already been closed for {:?}",
-                chargeback,
-                dispute
-            );
-            return;
-        }
+    /// Record each [`Anomaly`] in a dispute, resolve or chargeback, such as a client mismatch,
+    /// as well as skipping and logging the transaction. An anomaly which cannot be recorded is
+    /// logged.
+    ///
+    /// ### Parameters
+    /// - log: The implementation of the anomaly log.
+    ///
+    pub fn with_anomalies(mut self, log: impl AnomalyLog + 'static) -> Self {
+        self.anomalies = Some(Box::new(log));
+        self
+    }
 
-        if dispute.detail.client != chargeback.client {
-            log::info!(
-                "Cannot process chargeback. Client ID does not match for {:?} and {:?}",
-                chargeback,
-                dispute
-            );
-            return;
+    /// Export an empty account for clients none of whose transactions could be applied.
+    ///
+    /// By default only clients with an account in the store are exported, so a client whose
+    /// only activity was rejected, such as a withdrawal with no funds, does not appear.
+    ///
+    /// ### Parameters
+    /// - export: Whether every client seen should be exported.
+    ///
+    pub fn with_untouched_accounts(mut self, export: bool) -> Self {
+        self.export_untouched_accounts = export;
+        self
+    }
+
+    /// Process transactions.
+    ///
+    /// Using a supplied reader, reads and processes each transaction and maintains client account state.
+    ///
+    /// Returns a report of what happened to the records read.
+    ///
+    /// ### Parameters
+    /// - reader: The transaction reader.
+    pub fn process(&mut self, mut reader: impl TransactionReader) -> ProcessingReport {
+        self.process_iter(reader.read_with_line_numbers())
+    }
+
+    /// Process transactions from an iterator of records, each paired with the line of the input
+    /// it starts on, such as [`CsvTransactionReader::iter`](crate::CsvTransactionReader::iter).
+    ///
+    /// Equivalent to [`TransactionProcessor::process`], except that the records are not read
+    /// through a boxed iterator, which saves a dynamic call per record.
+    ///
+    /// ### Parameters
+    /// - records: The transaction records, or the errors encountered reading them.
+    pub fn process_iter(
+        &mut self,
+        records: impl IntoIterator<Item = (Result<TransactionRecord>, u64)>,
+    ) -> ProcessingReport {
+        let mut report = ProcessingReport::new().with_policies(self.policies);
+        for (result, line) in records {
+            let outcome = self.process_line(result, line);
+            if !self.add_outcome(&mut report, outcome) {
+                break;
+            }
         }
+        report
+    }
 
-        // If a dispute exists then a deposit must also
-        let amount = self.deposits.get(&chargeback.tx).unwrap().amount;
+    /// Process transactions until cancelled.
+    ///
+    /// Equivalent to [`TransactionProcessor::process`], except that `cancelled` is checked
+    /// before each record and processing stops once it is set, such as by a signal handler.
+    /// The records already processed are kept, so the accounts can still be exported.
+    ///
+    /// Returns a report of what happened to the records processed.
+    ///
+    /// ### Parameters
+    /// - reader: The transaction reader.
+    /// - cancelled: Set to stop processing.
+    pub fn process_until(
+        &mut self,
+        mut reader: impl TransactionReader,
+        cancelled: &AtomicBool,
+    ) -> ProcessingReport {
+        let mut report = ProcessingReport::new().with_policies(self.policies);
+        for (result, line) in reader.read_with_line_numbers() {
+            if cancelled.load(Ordering::Relaxed) {
+                break;
+            }
+            let outcome = self.process_line(result, line);
+            if !self.add_outcome(&mut report, outcome) {
+                break;
+            }
+        }
+        report
+    }
 
-        if let Err(err) = self
-            .store
-            .force_remove_funds_and_lock(dispute.detail.client, amount)
-        {
-            log::info!("Cannot process {:?}: {}", chargeback, err);
-            return;
-        };
+    /// Process transactions asynchronously.
+    ///
+    /// Equivalent to [`TransactionProcessor::process`] for a reader which produces a stream
+    /// of records, such as one received over the network.
+    ///
+    /// ### Parameters
+    /// - reader: The asynchronous transaction reader.
+    #[cfg(feature = "async")]
+    pub async fn process_stream(
+        &mut self,
+        mut reader: impl AsyncTransactionReader,
+    ) -> ProcessingReport {
+        use futures::StreamExt;
+
+        let mut report = ProcessingReport::new().with_policies(self.policies);
+        let mut records = reader.read();
+        while let Some(result) = records.next().await {
+            let outcome = self.process_record(result);
+            if !self.add_outcome(&mut report, outcome) {
+                break;
+            }
+        }
+        report
+    }
 
-        dispute.close();
+    /// Adds the outcome of a record to the report, returning whether to carry on processing.
+    ///
+    /// In strict mode processing stops at the first malformed record, and with
+    /// [`DuplicatePolicy::Error`] at the first duplicate transaction, which the report keeps as
+    /// [`ProcessingReport::aborted`].
+    pub(crate) fn add_outcome(
+        &self,
+        report: &mut ProcessingReport,
+        outcome: RecordOutcome,
+    ) -> bool {
+        report.add(&outcome);
+        match outcome {
+            RecordOutcome::Malformed(message) | RecordOutcome::ReadError(message)
+                if self.policies.strict =>
+            {
+                report.aborted = Some(RecordError {
+                    record: report.records,
+                    message,
+                });
+                false
+            }
+            RecordOutcome::Skipped(event)
+                if event.reason == SkipReason::DuplicateTransaction
+                    && self.policies.on_duplicate_tx == DuplicatePolicy::Error =>
+            {
+                report.aborted = Some(RecordError {
+                    record: report.records,
+                    message: format!(
+                        "Duplicate {:?} {} for client {}",
+                        event.transaction_type, event.tx, event.client
+                    ),
+                });
+                false
+            }
+            _ => true,
+        }
     }
 
-    /// Export accounts processed.
+    /// Process a single transaction record.
     ///
-    /// Using a supplied writer, writes each client account state.
-    /// The writer is consumed to ensure it is dropped once this method completes,
-    /// allowing for files to be flushed or other resources to be released.
+    /// Allows a caller to drive processing one record at a time, for example to checkpoint
+    /// progress between records.
     ///
-    /// The [`TransactionProcessor`] is also consumed, preventing further transaction
-    /// processing modifying the state of accounts already written.
+    /// Returns what happened to the record, which can be added to a [`ProcessingReport`].
     ///
     /// ### Parameters
-    /// - writer: The implementation of the account writer.
-    pub fn export(self, mut writer: impl AccountWriter) -> Result<()> {
-        for account in self.store.export() {
-            writer.write(&account.into())?;
+    /// - result: The transaction record, or the error encountered reading it.
+    pub fn process_record(&mut self, result: Result<TransactionRecord>) -> RecordOutcome {
+        let line = self.records + 1;
+        self.process_line(result, line)
+    }
+
+    /// Process a single transaction record, read from the given line of the input.
+    ///
+    /// Equivalent to [`TransactionProcessor::process_record`], except that a rejected record
+    /// is written with its line rather than its number.
+    ///
+    /// ### Parameters
+    /// - result: The transaction record, or the error encountered reading it.
+    /// - line: The line of the input the record starts on.
+    pub fn process_line(&mut self, result: Result<TransactionRecord>, line: u64) -> RecordOutcome {
+        self.records += 1;
+        let record = match (&self.rejected, &result) {
+            (Some(_), Ok(record)) => Some(record.clone()),
+            _ => None,
+        };
+        let outcome = self.apply_record(result);
+        self.expire_disputes();
+        if let Some(writer) = &mut self.rejected {
+            if let Some(rejected) = RejectedTransaction::new(line, record.as_ref(), &outcome) {
+                if let Err(err) = writer.write(&rejected) {
+                    log::error!("Could not write {:?}: {}", rejected, err);
+                }
+            }
         }
-        Ok(())
+        outcome
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
+    fn apply_record(&mut self, result: Result<TransactionRecord>) -> RecordOutcome {
+        self.replay_journal();
+        match result {
+            Ok(record) => match Transaction::from_record(record, self.policies.excess_precision) {
+                Ok(tx) => self.process_transaction(tx),
+                Err(err) => {
+                    log::error!("Malformed transaction: {}", err);
+                    RecordOutcome::Malformed(format!("Malformed transaction: {}", err))
+                }
+            },
+            Err(err) => {
+                log::error!("Could not read transaction record: {}", err);
+                RecordOutcome::ReadError(format!("Could not read transaction record: {}", err))
+            }
+        }
+    }
 
-    use hamcrest2::assert_that;
-    use hamcrest2::matches_regex;
-    use hamcrest2::HamcrestMatcher;
-    use itertools::Itertools;
-    use log::Level;
-    use mockall::predicate::eq;
-    use mockall_double::double;
-    use rust_decimal_macros::dec;
+    fn process_transaction(&mut self, transaction: Transaction) -> RecordOutcome {
+        // a replayed transaction is counted in the statistics as it is replayed, not again here
+        if let Some(journal) = &self.journal {
+            if journal.contains(transaction.transaction_type(), transaction.tx()) {
+                return self.skip(&transaction, SkipReason::AlreadyProcessed);
+            }
+        }
 
-    use crate::Account;
-    use crate::ClientId;
-    use crate::TransactionId;
-    use crate::TransactionRecord;
-    use crate::TransactionType;
+        if self.export_untouched_accounts {
+            self.clients.insert(transaction.client());
+        }
+        self.statistics
+            .record_transaction(transaction.transaction_type());
+
+        if let Transaction::Deposit(_) | Transaction::Withdrawal(_) = transaction {
+            if let Err(reason) = self.use_transaction_id(transaction.tx()) {
+                self.statistics.skipped += 1;
+                return self.skip(&transaction, reason);
+            }
+        }
+
+        if let Err(err) = self.validate(&transaction) {
+            self.statistics.skipped += 1;
+            let event =
+                SkipEvent::new(&transaction, SkipReason::Rejected).with_detail(err.reason());
+            self.log_skip(&event);
+            return RecordOutcome::Skipped(event);
+        }
+
+        let client = transaction.client();
+        let before = match &self.baseline {
+            Some(baseline) if !baseline.contains_key(&client) => {
+                let before = self.store.account(client).map(AccountSummary::try_from);
+                match before.transpose() {
+                    Ok(before) => Some(before),
+                    Err(err) => {
+                        self.statistics.skipped += 1;
+                        return self.skip(&transaction, SkipReason::from(&err.into()));
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        let result = match &transaction {
+            Transaction::Deposit(tx) => self.process_deposit(tx),
+            Transaction::Withdrawal(tx) => self.process_withdrawal(tx),
+            Transaction::Dispute(tx) => self.process_dispute(tx),
+            Transaction::Resolve(tx) => self.process_resolve(tx),
+            Transaction::Chargeback(tx) => self.process_chargeback(tx),
+            Transaction::Unlock(tx) => self.process_unlock(tx),
+        };
+
+        match result {
+            Ok(()) => {
+                if let (Some(baseline), Some(before)) = (&mut self.baseline, before) {
+                    let before = before.unwrap_or_else(|| AccountSummary::empty(client));
+                    baseline.insert(client, before);
+                }
+                if let Some(journal) = &mut self.journal {
+                    if let Err(err) = journal.append(&(&transaction).into()) {
+                        log::error!("Could not journal {:?}: {}", transaction, err);
+                    }
+                }
+                RecordOutcome::Applied(transaction.transaction_type())
+            }
+            Err(reason) => {
+                let disputing = matches!(
+                    transaction,
+                    Transaction::Dispute(_) | Transaction::Resolve(_) | Transaction::Chargeback(_)
+                );
+                if disputing && reason == SkipReason::AccountLocked {
+                    self.record_anomaly(
+                        transaction.transaction_type(),
+                        Anomaly::ActionOnLockedAccount {
+                            client,
+                            tx: transaction.tx(),
+                        },
+                    );
+                }
+                self.statistics.skipped += 1;
+                self.skip(&transaction, reason)
+            }
+        }
+    }
+
+    /// Records an anomaly, if anomalies are recorded.
+    fn record_anomaly(&mut self, transaction_type: TransactionType, anomaly: Anomaly) {
+        if let Some(log) = &mut self.anomalies {
+            if let Err(err) = log.record(transaction_type, &anomaly) {
+                log::error!("Could not record {:?}: {}", anomaly, err);
+            }
+        }
+    }
+
+    /// Replays the journaled transactions, if they are yet to be replayed.
+    fn replay_journal(&mut self) {
+        if !self
+            .journal
+            .as_ref()
+            .is_some_and(TransactionJournal::has_replay)
+        {
+            return;
+        }
+        // the journal is set aside so replayed transactions are neither skipped nor journaled
+        // again, and the baseline so that they do not touch any account
+        let mut journal = self.journal.take().unwrap();
+        let baseline = self.baseline.take();
+        let records = journal.take_replay();
+        log::debug!("Replaying {} journaled transactions", records.len());
+        for record in records {
+            self.apply_record(Ok(record));
+        }
+        self.journal = Some(journal);
+        self.baseline = baseline;
+    }
+
+    /// Runs the validators which apply to the transaction, in order, until one rejects it.
+    fn validate(&self, transaction: &Transaction) -> Result<(), ValidationError> {
+        let is_dispute = matches!(
+            transaction,
+            Transaction::Dispute(_) | Transaction::Resolve(_) | Transaction::Chargeback(_)
+        );
+        let mut validators = self
+            .validators
+            .iter()
+            .filter(|validator| !is_dispute || validator.validates_disputes())
+            .peekable();
+        if validators.peek().is_none() {
+            return Ok(());
+        }
+        let ctx = ValidationContext::new(self.store.account(transaction.client()));
+        validators.try_for_each(|validator| validator.validate(transaction, &ctx))
+    }
+
+    /// Reports a transaction which could not be applied.
+    fn skip(&self, transaction: &Transaction, reason: SkipReason) -> RecordOutcome {
+        let event = SkipEvent::new(transaction, reason);
+        self.log_skip(&event);
+        RecordOutcome::Skipped(event)
+    }
+
+    /// All skipped transactions are reported here so that they are logged consistently
+    /// in the configured [`LogFormat`].
+    ///
+    /// Duplicate transactions are logged as warnings, since they suggest a problem upstream.
+    fn log_skip(&self, event: &SkipEvent) {
+        let level = match event.reason {
+            SkipReason::DuplicateTransaction => log::Level::Warn,
+            _ => log::Level::Info,
+        };
+        match self.log_format {
+            LogFormat::Text => match &event.detail {
+                Some(detail) => log::log!(
+                    level,
+                    "Cannot process {:?} {} for client {}: {}: {}",
+                    event.transaction_type,
+                    event.tx,
+                    event.client,
+                    event.reason,
+                    detail
+                ),
+                None => log::log!(
+                    level,
+                    "Cannot process {:?} {} for client {}: {}",
+                    event.transaction_type,
+                    event.tx,
+                    event.client,
+                    event.reason
+                ),
+            },
+            LogFormat::Json => match serde_json::to_string(&event) {
+                Ok(json) => log::log!(level, "{}", json),
+                Err(err) => log::error!("Could not serialize {:?}: {}", event, err),
+            },
+        }
+    }
+
+    fn process_deposit(&mut self, deposit: &Deposit) -> Result<(), SkipReason> {
+        log::debug!("Processing deposit for {:?}", deposit);
+        self.store
+            .add_funds(deposit.client, deposit.amount)
+            .map_err(|err| store_error(&err))?;
+
+        if self.policies.hold_coverable_only {
+            self.coverage
+                .deposit(deposit.client, deposit.tx, deposit.amount);
+        }
+
+        if self.policies.record_history {
+            self.retained.insert(deposit.tx, deposit.into());
+        }
+        Ok(())
+    }
+
+    fn process_withdrawal(&mut self, withdrawal: &Withdrawal) -> Result<(), SkipReason> {
+        log::debug!("Processing withdrawal for {:?}", withdrawal);
+        self.store
+            .remove_funds(withdrawal.client, withdrawal.amount)
+            .map_err(|err| store_error(&err))?;
+
+        if self.policies.hold_coverable_only {
+            self.coverage.withdraw(withdrawal.client, withdrawal.amount);
+        }
+
+        if self.policies.record_history {
+            self.retained.insert(withdrawal.tx, withdrawal.into());
+        }
+        Ok(())
+    }
+
+    /// Fails if a deposit or withdrawal has already used the transaction ID, whether or not it
+    /// was applied, so that a later dispute always references the original transaction.
+    fn use_transaction_id(&mut self, tx: TransactionId) -> Result<(), SkipReason> {
+        match self.transaction_ids.insert(tx) {
+            true => Ok(()),
+            false => Err(SkipReason::DuplicateTransaction),
+        }
+    }
+
+    /// Marks a transaction ID as used by a deposit or withdrawal processed elsewhere, so that
+    /// a later deposit or withdrawal reusing it is skipped as a duplicate.
+    pub(crate) fn reserve_transaction_id(&mut self, tx: TransactionId) {
+        self.transaction_ids.insert(tx);
+    }
+
+    /// Disputes a deposit or a withdrawal.
+    ///
+    /// A disputed deposit has its amount held until the dispute is settled. A disputed
+    /// withdrawal is a claim that the funds should not have been paid out, so its amount is
+    /// credited back to the account but held: a resolve reverses the credit, and a chargeback
+    /// releases it to the client before locking the account.
+    fn process_dispute(&mut self, dispute: &Dispute) -> Result<(), SkipReason> {
+        log::debug!("Processing dispute for {:?}", dispute);
+
+        let disputed = match self
+            .retained
+            .get(&dispute.tx)
+            .map_err(|err| spill_error(&err))?
+        {
+            Some(disputed) => disputed,
+            None => {
+                let anomaly = Anomaly::UnknownTransaction { tx: dispute.tx };
+                self.record_anomaly(TransactionType::Dispute, anomaly);
+                return Err(SkipReason::UnknownTransaction);
+            }
+        };
+
+        if disputed.client != dispute.client {
+            let anomaly = Anomaly::ClientMismatch {
+                expected: disputed.client,
+                got: dispute.client,
+                tx: dispute.tx,
+            };
+            self.record_anomaly(TransactionType::Dispute, anomaly);
+            return Err(SkipReason::ClientMismatch);
+        }
+
+        if disputed.direction == Direction::Debit && !self.policies.allow_withdrawal_disputes {
+            return Err(SkipReason::WithdrawalDisputesNotAllowed);
+        }
+
+        if let (Some(max_age), Some(transacted), Some(disputed)) =
+            (self.max_dispute_age, disputed.timestamp, dispute.timestamp)
+        {
+            if disputed - transacted > max_age {
+                return Err(SkipReason::DisputeExpired);
+            }
+        }
+
+        if self.disputes.contains_key(&dispute.tx) {
+            let anomaly = Anomaly::DuplicateDispute { tx: dispute.tx };
+            self.record_anomaly(TransactionType::Dispute, anomaly);
+            return Err(SkipReason::DuplicateDispute);
+        }
+
+        let mut amount = disputed.amount;
+        if disputed.direction == Direction::Credit {
+            if self.policies.hold_coverable_only {
+                ensure_unlocked(&self.store, dispute.client)?;
+                amount = amount.min(self.coverage.remaining(dispute.tx));
+            }
+            if self.policies.dispute_policy == DisputePolicy::RejectIfInsufficient {
+                let available = self
+                    .store
+                    .account(dispute.client)
+                    .map_or(Ok(Decimal::ZERO), |account| account.get_available())
+                    .map_err(|err| SkipReason::from(&err.into()))?;
+                if available < amount {
+                    return Err(SkipReason::InsufficientFunds);
+                }
+            }
+        }
+        match disputed.direction {
+            Direction::Credit if amount.is_zero() => Ok(()),
+            Direction::Credit => self.store.hold_funds(dispute.client, amount),
+            Direction::Debit => self.store.credit_held_funds(dispute.client, amount),
+        }
+        .map_err(|err| store_error(&err))?;
+
+        if disputed.direction == Direction::Credit && self.policies.hold_coverable_only {
+            self.coverage.dispute(dispute.tx);
+            if amount < disputed.amount {
+                log::info!(
+                    "Dispute of transaction {} for client {} holds {} of {}, the rest has been withdrawn",
+                    dispute.tx,
+                    dispute.client,
+                    amount,
+                    disputed.amount
+                );
+            }
+        }
+
+        self.disputes.insert(
+            dispute.tx,
+            DisputeCase::new(dispute.clone(), amount, disputed.direction),
+        );
+        self.open_disputes
+            .entry(dispute.client)
+            .or_default()
+            .insert(dispute.tx);
+        if self.policies.dispute_expiry.is_some() {
+            self.expiring_disputes.push_back((self.records, dispute.tx));
+        }
+        Ok(())
+    }
+
+    fn process_resolve(&mut self, resolve: &Resolve) -> Result<(), SkipReason> {
+        log::debug!("Processing dispute resolution for {:?}", resolve);
+
+        let dispute = dispute_case(&mut self.disputes, &resolve.tx)?;
+
+        dispute.check_open()?;
+
+        if dispute.detail.client != resolve.client {
+            let anomaly = Anomaly::ClientMismatch {
+                expected: dispute.detail.client,
+                got: resolve.client,
+                tx: resolve.tx,
+            };
+            self.record_anomaly(TransactionType::Resolve, anomaly);
+            return Err(SkipReason::ClientMismatch);
+        }
+
+        let coverage = self
+            .policies
+            .hold_coverable_only
+            .then_some(&mut self.coverage);
+        release_or_reverse(&mut self.store, coverage, dispute)?;
+
+        dispute.resolve();
+        self.remove_open_dispute(resolve.client, resolve.tx);
+        Ok(())
+    }
+
+    fn process_chargeback(&mut self, chargeback: &Chargeback) -> Result<(), SkipReason> {
+        log::debug!("Processing chargeback for {:?}", chargeback);
+
+        let dispute = dispute_case(&mut self.disputes, &chargeback.tx)?;
+
+        dispute.check_open()?;
+
+        if dispute.detail.client != chargeback.client {
+            let anomaly = Anomaly::ClientMismatch {
+                expected: dispute.detail.client,
+                got: chargeback.client,
+                tx: chargeback.tx,
+            };
+            self.record_anomaly(TransactionType::Chargeback, anomaly);
+            return Err(SkipReason::ClientMismatch);
+        }
+
+        let amount = dispute.amount;
+        let direction = dispute.direction;
+
+        // the store is changed first, and the disputes only once it all succeeds, so a failed
+        // chargeback leaves every dispute as it was
+        let cancelled: Vec<TransactionId> = match self.cancel_disputes_on_lock {
+            true => self
+                .open_disputes
+                .get(&chargeback.client)
+                .into_iter()
+                .flatten()
+                .copied()
+                .filter(|tx| *tx != chargeback.tx)
+                .collect(),
+            false => Vec::new(),
+        };
+        self.release_disputes(&cancelled)?;
+
+        let result = match direction {
+            Direction::Credit if amount.is_zero() => self.store.lock(chargeback.client),
+            Direction::Credit if self.chargeback_locked_accounts => {
+                self.store.force_chargeback_funds(chargeback.client, amount)
+            }
+            Direction::Credit => self.store.chargeback_funds(chargeback.client, amount),
+            Direction::Debit => self
+                .store
+                .release_funds(chargeback.client, amount)
+                .and_then(|_| self.store.lock(chargeback.client)),
+        };
+        if let Err(err) = result {
+            self.rehold_disputes(&cancelled);
+            return Err(store_error(&err));
+        }
+
+        self.remove_open_dispute(chargeback.client, chargeback.tx);
+        self.cancel_disputes(chargeback.client, &cancelled)?;
+
+        if direction == Direction::Credit && self.policies.hold_coverable_only {
+            self.coverage.reverse(chargeback.client, chargeback.tx);
+        }
+
+        // cancelling the other disputes may have spilled this one
+        dispute_case(&mut self.disputes, &chargeback.tx)?.charge_back();
+        Ok(())
+    }
+
+    /// Settles the held funds of disputes in the store as if resolved, without changing the
+    /// disputes, or else restores any already settled and fails.
+    fn release_disputes(&mut self, txs: &[TransactionId]) -> Result<(), SkipReason> {
+        for (released, tx) in txs.iter().enumerate() {
+            let result = dispute_case(&mut self.disputes, tx)
+                .and_then(|dispute| release_or_reverse(&mut self.store, None, dispute));
+            if let Err(reason) = result {
+                self.rehold_disputes(&txs[..released]);
+                return Err(reason);
+            }
+        }
+        Ok(())
+    }
+
+    /// Holds the funds of disputes again in the store, undoing [`Self::release_disputes`].
+    fn rehold_disputes(&mut self, txs: &[TransactionId]) {
+        for tx in txs {
+            let result = dispute_case(&mut self.disputes, tx).and_then(|dispute| {
+                let client = dispute.detail.client;
+                match dispute.direction {
+                    Direction::Credit if dispute.amount.is_zero() => Ok(()),
+                    Direction::Credit => self.store.hold_funds(client, dispute.amount),
+                    Direction::Debit => self.store.credit_held_funds(client, dispute.amount),
+                }
+                .map_err(|err| store_error(&err))
+            });
+            if let Err(reason) = result {
+                log::error!(
+                    "Could not restore dispute of transaction {}: {}",
+                    tx,
+                    reason
+                );
+            }
+        }
+    }
+
+    /// Cancels a client's open disputes, whose held funds have been settled as if resolved.
+    fn cancel_disputes(
+        &mut self,
+        client: ClientId,
+        txs: &[TransactionId],
+    ) -> Result<(), SkipReason> {
+        for tx in txs {
+            self.remove_open_dispute(client, *tx);
+            if self.policies.hold_coverable_only {
+                self.coverage.settle(*tx);
+            }
+            dispute_case(&mut self.disputes, tx)?.cancel();
+            log::debug!(
+                "Cancelled dispute of transaction {} for client {}",
+                tx,
+                client
+            );
+        }
+        Ok(())
+    }
+
+    /// Expires every dispute still open which was raised the configured number of records ago.
+    fn expire_disputes(&mut self) {
+        let expiry = match self.policies.dispute_expiry {
+            Some(expiry) => expiry,
+            None => return,
+        };
+        while let Some(&(raised, tx)) = self.expiring_disputes.front() {
+            if self.records - raised < expiry {
+                break;
+            }
+            self.expiring_disputes.pop_front();
+            if let Err(reason) = self.expire_dispute(tx) {
+                log::error!("Could not expire dispute of transaction {}: {}", tx, reason);
+            }
+        }
+    }
+
+    /// Settles an open dispute as if resolved, leaving one already settled as it is.
+    fn expire_dispute(&mut self, tx: TransactionId) -> Result<(), SkipReason> {
+        let dispute = dispute_case(&mut self.disputes, &tx)?;
+        if dispute.check_open().is_err() {
+            return Ok(());
+        }
+        let coverage = self
+            .policies
+            .hold_coverable_only
+            .then_some(&mut self.coverage);
+        release_or_reverse(&mut self.store, coverage, dispute)?;
+        dispute.expire();
+        let client = dispute.detail.client;
+        self.remove_open_dispute(client, tx);
+        log::debug!(
+            "Expired dispute of transaction {} for client {}",
+            tx,
+            client
+        );
+        Ok(())
+    }
+
+    fn remove_open_dispute(&mut self, client: ClientId, tx: TransactionId) {
+        if let Some(open) = self.open_disputes.get_mut(&client) {
+            open.remove(&tx);
+            if open.is_empty() {
+                self.open_disputes.remove(&client);
+            }
+        }
+    }
+
+    fn process_unlock(&mut self, unlock: &Unlock) -> Result<(), SkipReason> {
+        log::debug!("Processing unlock for {:?}", unlock);
+
+        if !self.allow_admin_ops {
+            return Err(SkipReason::AdminOpsNotAllowed);
+        }
+
+        self.store
+            .unlock(unlock.client)
+            .map_err(|err| store_error(&err))
+    }
+
+    /// Save the processing state to a checkpoint file.
+    ///
+    /// The file is written alongside the target and renamed over it, so an interrupted save
+    /// never leaves a partially written checkpoint behind.
+    ///
+    /// ### Parameters
+    /// - path: The checkpoint file.
+    /// - position: The input position immediately after the last record processed.
+    pub fn save_checkpoint<P: AsRef<Path>>(&self, path: P, position: InputPosition) -> Result<()>
+    where
+        S: Serialize,
+    {
+        let path = path.as_ref();
+        let checkpoint = CheckpointRef {
+            position,
+            store: &self.store,
+            deposits: &self.retained,
+            disputes: &self.disputes,
+            clients: &self.clients,
+            transaction_ids: &self.transaction_ids,
+            statistics: &self.statistics,
+            baseline: &self.baseline,
+            coverage: &self.coverage,
+        };
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(&checkpoint)?)
+            .with_context(|| format!("Could not write checkpoint {:?}", tmp))?;
+        fs::rename(&tmp, path).with_context(|| format!("Could not write checkpoint {:?}", path))?;
+        Ok(())
+    }
+
+    /// Construct a [`TransactionProcessor`] from a checkpoint file.
+    ///
+    /// Returns the processor along with the input position from which to resume reading.
+    /// Options such as [`TransactionProcessor::with_admin_ops`] are not saved and must be
+    /// set again.
+    ///
+    /// ### Parameters
+    /// - path: The checkpoint file.
+    pub fn from_checkpoint<P: AsRef<Path>>(path: P) -> Result<(Self, InputPosition)>
+    where
+        S: DeserializeOwned,
+    {
+        let path = path.as_ref();
+        let data =
+            fs::read(path).with_context(|| format!("Could not read checkpoint {:?}", path))?;
+        let checkpoint: Checkpoint<S> = serde_json::from_slice(&data)
+            .with_context(|| format!("Invalid checkpoint {:?}", path))?;
+
+        let mut processor = TransactionProcessor::new(checkpoint.store);
+        processor.retained = checkpoint.deposits;
+        processor.restore_disputes(checkpoint.disputes)?;
+        processor.clients = checkpoint.clients;
+        processor.transaction_ids = checkpoint.transaction_ids;
+        processor.statistics = checkpoint.statistics;
+        processor.baseline = checkpoint.baseline;
+        processor.coverage = checkpoint.coverage;
+        Ok((processor, checkpoint.position))
+    }
+
+    /// Save the deposits, withdrawals, dispute cases and transaction IDs needed to continue
+    /// processing in a later run against the same persistent store.
+    ///
+    /// Unlike [`TransactionProcessor::save_checkpoint`] the accounts are not saved, since the
+    /// store keeps them, and neither is any input position. The file is written alongside the
+    /// target and renamed over it.
+    ///
+    /// ### Parameters
+    /// - path: The state file.
+    pub fn save_state<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let state = StateRef {
+            deposits: &self.retained,
+            disputes: &self.disputes,
+            clients: &self.clients,
+            transaction_ids: &self.transaction_ids,
+            coverage: &self.coverage,
+        };
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(&state)?)
+            .with_context(|| format!("Could not write state {:?}", tmp))?;
+        fs::rename(&tmp, path).with_context(|| format!("Could not write state {:?}", path))?;
+        Ok(())
+    }
+
+    /// Continue from the state saved by [`TransactionProcessor::save_state`], if the file
+    /// exists.
+    ///
+    /// Set the state before [`TransactionProcessor::with_spill_dir`] and
+    /// [`TransactionProcessor::with_max_retained_transactions`], which apply to the
+    /// transactions loaded.
+    ///
+    /// ### Parameters
+    /// - path: The state file.
+    pub fn with_state<P: AsRef<Path>>(mut self, path: P) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(self);
+        }
+        let data = fs::read(path).with_context(|| format!("Could not read state {:?}", path))?;
+        let state: State =
+            serde_json::from_slice(&data).with_context(|| format!("Invalid state {:?}", path))?;
+        self.retained = state.deposits;
+        self.restore_disputes(state.disputes)?;
+        self.clients = state.clients;
+        self.transaction_ids = state.transaction_ids;
+        self.coverage = state.coverage;
+        Ok(self)
+    }
+
+    /// Replaces the dispute cases, tracking which of them are still open.
+    fn restore_disputes(&mut self, disputes: SpillMap<DisputeCase>) -> Result<()> {
+        self.open_disputes.clear();
+        for entry in disputes.iter() {
+            let (tx, dispute) = entry?;
+            if let DisputeStatus::Open = dispute.status {
+                self.open_disputes
+                    .entry(dispute.detail.client)
+                    .or_default()
+                    .insert(tx);
+            }
+        }
+        self.disputes = disputes;
+        self.queue_expiring_disputes();
+        Ok(())
+    }
+
+    /// Queues every open dispute to expire, aged from now, if disputes expire.
+    fn queue_expiring_disputes(&mut self) {
+        self.expiring_disputes.clear();
+        if self.policies.dispute_expiry.is_some() {
+            let records = self.records;
+            self.expiring_disputes = self.open_transactions().map(|tx| (records, tx)).collect();
+        }
+    }
+
+    /// Returns the transactions with an open dispute, in order.
+    fn open_transactions(&self) -> impl Iterator<Item = TransactionId> {
+        let mut open: Vec<_> = self.open_disputes.values().flatten().copied().collect();
+        open.sort_unstable_by_key(|tx| tx.0);
+        open.into_iter()
+    }
+
+    /// Returns every dispute case still open, in order of the disputed transaction.
+    ///
+    /// Cases which were spilled are read from disk, and any which cannot be read are logged and
+    /// left out.
+    pub fn open_disputes(&self) -> impl Iterator<Item = DisputeSummary> + '_ {
+        self.open_transactions()
+            .filter_map(move |tx| self.dispute_summary(tx))
+    }
+
+    /// Returns whether the dispute of a transaction is still open, or how it ended, if it was
+    /// ever disputed.
+    ///
+    /// ### Parameters
+    /// - tx: The disputed transaction.
+    pub fn dispute_status(&self, tx: TransactionId) -> Option<DisputeStatus> {
+        self.dispute_summary(tx).map(|dispute| dispute.status())
+    }
+
+    fn dispute_summary(&self, tx: TransactionId) -> Option<DisputeSummary> {
+        match self.disputes.peek(&tx) {
+            Ok(dispute) => dispute.map(|dispute| {
+                DisputeSummary::new(tx, dispute.detail.client, dispute.amount, dispute.status)
+            }),
+            Err(err) => {
+                log::error!("Could not read dispute of transaction {}: {:#}", tx, err);
+                None
+            }
+        }
+    }
+
+    /// Export every dispute case, whether still open or how it ended.
+    ///
+    /// Cases are written in order of the disputed transaction. Unlike
+    /// [`TransactionProcessor::export`] the processor is not consumed, so the disputes can be
+    /// exported before the accounts.
+    ///
+    /// ### Parameters
+    /// - writer: The implementation of the dispute writer.
+    pub fn export_disputes(&mut self, mut writer: impl DisputeWriter) -> Result<()> {
+        self.replay_journal();
+        let mut disputes = self
+            .disputes
+            .iter()
+            .map(|entry| {
+                entry.map(|(tx, dispute)| {
+                    DisputeSummary::new(tx, dispute.detail.client, dispute.amount, dispute.status)
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        disputes.sort_by_key(|dispute| dispute.tx().0);
+        disputes
+            .iter()
+            .try_for_each(|dispute| writer.write(dispute))
+    }
+
+    /// Export accounts processed.
+    ///
+    /// Using a supplied writer, writes each client account state.
+    /// The writer is consumed to ensure it is dropped once this method completes,
+    /// allowing for files to be flushed or other resources to be released.
+    ///
+    /// The [`TransactionProcessor`] is also consumed, preventing further transaction
+    /// processing modifying the state of accounts already written.
+    ///
+    /// Returns the [`SummaryStatistics`] of the accounts written and transactions processed.
+    ///
+    /// ### Parameters
+    /// - writer: The implementation of the account writer.
+    pub fn export(mut self, writer: impl AccountWriter) -> Result<SummaryStatistics> {
+        self.flush()?;
+        let store = self.store;
+        write_accounts(
+            |visit| store.export().try_for_each(|account| visit(&account)),
+            self.statistics,
+            self.clients,
+            writer,
+        )
+    }
+
+    /// Returns the current state of a client's account, if they have one.
+    ///
+    /// The account is copied from the store, which is left untouched, so this can be called
+    /// between batches while processing continues.
+    ///
+    /// ### Parameters
+    /// - client: The client whose account is looked up.
+    pub fn account(&self, client: ClientId) -> Result<Option<AccountSummary>> {
+        let account = self.store.account(client).map(AccountSummary::try_from);
+        Ok(account.transpose()?)
+    }
+
+    /// Export a snapshot of the accounts processed so far.
+    ///
+    /// Unlike [`TransactionProcessor::export`] the processor is not consumed, so processing
+    /// can continue afterwards, for example with the next connection to a daemon. The accounts
+    /// are visited in the store, see [`AccountStore::for_each_account`], rather than copying it.
+    ///
+    /// Returns the [`SummaryStatistics`] of the accounts written and transactions processed.
+    ///
+    /// ### Parameters
+    /// - writer: The implementation of the account writer.
+    pub fn snapshot(&mut self, writer: impl AccountWriter) -> Result<SummaryStatistics> {
+        self.flush()?;
+        write_accounts(
+            |visit| self.store.for_each_account(visit),
+            self.statistics.clone(),
+            self.clients.clone(),
+            writer,
+        )
+    }
+
+    /// Replays any journaled transactions yet to be replayed, and flushes the journal and any
+    /// rejected transactions and anomalies written.
+    fn flush(&mut self) -> Result<()> {
+        self.replay_journal();
+        if let Some(journal) = &mut self.journal {
+            journal.flush()?;
+        }
+        if let Some(writer) = &mut self.rejected {
+            writer.flush()?;
+        }
+        if let Some(log) = &mut self.anomalies {
+            log.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes each account, and an empty account for each other client, in ascending client order,
+/// recording them in the statistics.
+fn write_accounts(
+    for_each_account: impl FnOnce(&mut AccountVisitor) -> Result<()>,
+    mut statistics: SummaryStatistics,
+    untouched: HashSet<ClientId>,
+    mut writer: impl AccountWriter,
+) -> Result<SummaryStatistics> {
+    let mut write = |account: AccountSummary| {
+        statistics.record_account(&account);
+        writer.write(&account)
+    };
+    let mut untouched: Vec<ClientId> = untouched.into_iter().collect();
+    untouched.sort_unstable();
+    let mut untouched = untouched.into_iter().peekable();
+    for_each_account(&mut |account| {
+        while let Some(client) = untouched.next_if(|client| *client < account.client()) {
+            write(AccountSummary::empty(client))?;
+        }
+        untouched.next_if_eq(&account.client());
+        write(account.try_into()?)
+    })?;
+    for client in untouched {
+        write(AccountSummary::empty(client))?;
+    }
+    writer.finish()?;
+    Ok(statistics)
+}
+
+/// Returns the dispute case of a transaction, reading it from disk if it was spilled.
+fn dispute_case<'a>(
+    disputes: &'a mut SpillMap<DisputeCase>,
+    tx: &TransactionId,
+) -> Result<&'a mut DisputeCase, SkipReason> {
+    disputes
+        .get_mut(tx)
+        .map_err(|err| spill_error(&err))?
+        .ok_or(SkipReason::UnknownDispute)
+}
+
+/// Settles a dispute so the original transaction stands, releasing the held funds of a
+/// disputed deposit or reversing the held credit of a disputed withdrawal.
+///
+/// Later withdrawals are attributed to a deposit again, if `coverage` is kept.
+fn release_or_reverse<S: AccountStore>(
+    store: &mut S,
+    coverage: Option<&mut Coverage>,
+    dispute: &DisputeCase,
+) -> Result<(), SkipReason> {
+    let client = dispute.detail.client;
+    match dispute.direction {
+        Direction::Credit if dispute.amount.is_zero() => ensure_unlocked(store, client)?,
+        Direction::Credit => store
+            .release_funds(client, dispute.amount)
+            .map_err(|err| store_error(&err))?,
+        Direction::Debit => {
+            return store
+                .remove_held_funds(client, dispute.amount)
+                .map_err(|err| store_error(&err))
+        }
+    }
+    if let Some(coverage) = coverage {
+        coverage.settle(dispute.detail.tx);
+    }
+    Ok(())
+}
+
+/// Fails like the store would to change a locked account, for a dispute holding nothing which
+/// leaves the store untouched.
+fn ensure_unlocked<S: AccountStore>(store: &S, client: ClientId) -> Result<(), SkipReason> {
+    match store.account(client) {
+        Some(account) if account.locked() => Err(SkipReason::AccountLocked),
+        _ => Ok(()),
+    }
+}
+
+/// Reports an error reading a spilled transaction or dispute case, which is unexpected.
+fn spill_error(err: &anyhow::Error) -> SkipReason {
+    log::error!("Could not read spilled transaction: {:#}", err);
+    SkipReason::SpillError
+}
+
+/// Classifies an error returned by the [`AccountStore`], keeping its detail in the debug log.
+fn store_error(err: &anyhow::Error) -> SkipReason {
+    log::debug!("Account store rejected operation: {}", err);
+    SkipReason::from(err)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use anyhow::anyhow;
+    use chrono::{TimeZone, Utc};
+    use hamcrest2::assert_that;
+    use hamcrest2::matches_regex;
+    use hamcrest2::HamcrestMatcher;
+    use itertools::Itertools;
+    use log::Level;
+    use mockall::predicate::eq;
+    use mockall_double::double;
+    use rust_decimal_macros::dec;
+    use test_case::test_case;
+
+    use crate::Account;
+    use crate::AccountError;
+    use crate::CheckingAccountStore;
+    use crate::CsvDisputeWriter;
+    use crate::DeltaAccountWriter;
+    use crate::DeniedClientsValidator;
+    use crate::InMemoryAccountStore;
+    use crate::RejectionReason;
+    use crate::TransactionId;
+    use crate::TransactionJournal;
+    use crate::TransactionProcessorBuilder;
+    use crate::TransactionRecord;
+    use crate::TransactionType;
+    use crate::VecAccountWriter;
+    use crate::VecTransactionReader;
 
     #[double]
-    use crate::AccountStore as MockAccountStore;
-    #[double]
-    use crate::AccountWriter as MockAccountWriter;
-    #[double]
-    use crate::TransactionReader as MockTransactionReader;
+    use crate::AccountStore as MockAccountStore;
+    #[double]
+    use crate::AccountWriter as MockAccountWriter;
+
+    /// Parses the skip events logged in [`LogFormat::Json`].
+    fn skip_events(captured_logs: &[testing_logger::CapturedLog]) -> Vec<SkipEvent> {
+        captured_logs
+            .iter()
+            .filter(|log| log.level <= Level::Info)
+            .map(|log| serde_json::from_str(&log.body).unwrap())
+            .collect()
+    }
+
+    fn skip_event(
+        transaction_type: TransactionType,
+        client: u16,
+        tx: u32,
+        reason: SkipReason,
+    ) -> SkipEvent {
+        SkipEvent {
+            tx: TransactionId(tx),
+            client: ClientId(client),
+            transaction_type,
+            reason,
+            detail: None,
+        }
+    }
+
+    #[test]
+    fn test_process_deposit_updates_store() {
+        let reader = VecTransactionReader::new(vec![TransactionRecord::deposit(
+            ClientId(1),
+            TransactionId(1),
+            10.into(),
+        )]);
+
+        let mut store = MockAccountStore::new();
+        store
+            .expect_add_funds()
+            .once()
+            .with(eq(ClientId(1)), eq(dec!(10)))
+            .returning(|_, _| Ok(()));
+
+        let mut processor = TransactionProcessor::new(store);
+        processor.process(reader);
+    }
+
+    #[test]
+    fn test_process_withdrawal_updates_store() {
+        let reader = VecTransactionReader::new(vec![TransactionRecord::withdrawal(
+            ClientId(1),
+            TransactionId(2),
+            5.into(),
+        )]);
+
+        let mut store = MockAccountStore::new();
+        store
+            .expect_remove_funds()
+            .once()
+            .with(eq(ClientId(1)), eq(dec!(5)))
+            .returning(|_, _| Ok(()));
+
+        let mut processor = TransactionProcessor::new(store);
+        processor.process(reader);
+    }
+
+    #[test]
+    fn test_process_duplicate_transaction_ids_does_not_update_store() {
+        testing_logger::setup();
+
+        let reader = VecTransactionReader::new(vec![
+            // Ok
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            // Err: Transaction ID has already been used
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(99)),
+            // Ok
+            TransactionRecord::withdrawal(ClientId(1), TransactionId(2), dec!(5)),
+            // Err: Transaction ID has already been used
+            TransactionRecord::deposit(ClientId(1), TransactionId(2), dec!(7)),
+            // Err: Transaction ID has already been used
+            TransactionRecord::withdrawal(ClientId(1), TransactionId(1), dec!(1)),
+            // Ok: holds the original amount
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+        ]);
+
+        let mut store = MockAccountStore::new();
+        store
+            .expect_add_funds()
+            .once()
+            .with(eq(ClientId(1)), eq(dec!(10)))
+            .returning(|_, _| Ok(()));
+        store
+            .expect_remove_funds()
+            .once()
+            .with(eq(ClientId(1)), eq(dec!(5)))
+            .returning(|_, _| Ok(()));
+        store
+            .expect_hold_funds()
+            .once()
+            .with(eq(ClientId(1)), eq(dec!(10)))
+            .returning(|_, _| Ok(()));
+
+        let mut processor = TransactionProcessor::new(store).with_log_format(LogFormat::Json);
+        processor.process(reader);
+
+        testing_logger::validate(|captured_logs| {
+            let warnings: Vec<SkipEvent> = captured_logs
+                .iter()
+                .filter(|log| log.level == Level::Warn)
+                .map(|log| serde_json::from_str(&log.body).unwrap())
+                .collect();
+            assert_eq!(
+                vec![
+                    skip_event(
+                        TransactionType::Deposit,
+                        1,
+                        1,
+                        SkipReason::DuplicateTransaction
+                    ),
+                    skip_event(
+                        TransactionType::Deposit,
+                        1,
+                        2,
+                        SkipReason::DuplicateTransaction
+                    ),
+                    skip_event(
+                        TransactionType::Withdrawal,
+                        1,
+                        1,
+                        SkipReason::DuplicateTransaction
+                    ),
+                ],
+                warnings
+            );
+        });
+    }
 
     #[test]
-    fn test_process_deposit_updates_store() {
-        let mut reader = MockTransactionReader::new();
-        reader.expect_read().returning(|| {
-            let transactions = vec![TransactionRecord::new(
+    fn test_process_logs_skips_as_text_with_plain_ids() {
+        testing_logger::setup();
+
+        let reader = VecTransactionReader::new(vec![
+            TransactionRecord::withdrawal(ClientId(17), TransactionId(3), dec!(5)),
+            TransactionRecord::dispute(ClientId(17), TransactionId(9)),
+        ]);
+        let mut processor = TransactionProcessor::new(InMemoryAccountStore::new());
+        processor.process(reader);
+
+        testing_logger::validate(|captured_logs| {
+            let logs = captured_logs
+                .iter()
+                .filter(|log| log.level <= Level::Info)
+                .map(|log| log.body.as_str())
+                .collect_vec();
+            assert_eq!(
+                vec![
+                    "Cannot process Withdrawal 3 for client 17: Insufficient funds available",
+                    "Cannot process Dispute 9 for client 17: No such transaction found",
+                ],
+                logs
+            );
+        });
+    }
+
+    #[test]
+    fn test_process_returns_report() {
+        let reader = VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::new(
                 TransactionType::Deposit,
                 ClientId(1),
-                TransactionId(1),
-                Some(10.into()),
-            )]
-            .into_iter()
-            .map(Ok);
-            Box::new(transactions)
+                TransactionId(2),
+                None,
+            ),
+            TransactionRecord::withdrawal(ClientId(1), TransactionId(3), dec!(50)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(9)),
+            TransactionRecord::dispute(ClientId(2), TransactionId(1)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            TransactionRecord::chargeback(ClientId(1), TransactionId(1)),
+            TransactionRecord::deposit(ClientId(1), TransactionId(4), dec!(1)),
+        ]);
+
+        let mut processor = TransactionProcessor::new(InMemoryAccountStore::new());
+        let report = processor.process(reader);
+
+        assert_eq!(9, report.records);
+        assert_eq!(1, report.applied(TransactionType::Deposit));
+        assert_eq!(1, report.applied(TransactionType::Dispute));
+        assert_eq!(1, report.applied(TransactionType::Chargeback));
+        assert_eq!(1, report.malformed);
+        assert_eq!(1, report.skipped(SkipReason::InsufficientFunds));
+        assert_eq!(1, report.skipped(SkipReason::UnknownTransaction));
+        assert_eq!(1, report.skipped(SkipReason::ClientMismatch));
+        assert_eq!(1, report.skipped(SkipReason::DuplicateDispute));
+        assert_eq!(1, report.skipped(SkipReason::AccountLocked));
+        assert_eq!(
+            vec![2, 3, 4, 5, 7, 9],
+            report
+                .errors
+                .iter()
+                .map(|error| error.record)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test_case(false, 3, None;    "when lenient")]
+    #[test_case(true,  2, Some(2); "when strict")]
+    fn test_process_with_strict(strict: bool, records: u64, aborted: Option<u64>) {
+        let reader = VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::new(
+                TransactionType::Deposit,
+                ClientId(1),
+                TransactionId(2),
+                None,
+            ),
+            TransactionRecord::deposit(ClientId(1), TransactionId(3), dec!(1)),
+        ]);
+
+        let mut processor =
+            TransactionProcessor::new(InMemoryAccountStore::new()).with_strict(strict);
+        let report = processor.process(reader);
+
+        assert_eq!(records, report.records);
+        assert_eq!(aborted, report.aborted.as_ref().map(|error| error.record));
+        assert_eq!(strict, report.into_result().is_err());
+    }
+
+    #[test]
+    fn test_process_with_strict_when_read_error() {
+        let mut processor =
+            TransactionProcessor::new(InMemoryAccountStore::new()).with_strict(true);
+        let report = processor.process_iter(vec![
+            (
+                Ok(TransactionRecord::deposit(
+                    ClientId(1),
+                    TransactionId(1),
+                    dec!(10),
+                )),
+                2,
+            ),
+            (Err(anyhow!("bad row")), 3),
+            (
+                Ok(TransactionRecord::deposit(
+                    ClientId(1),
+                    TransactionId(2),
+                    dec!(1),
+                )),
+                4,
+            ),
+        ]);
+
+        assert_eq!(2, report.records);
+        assert_eq!(1, report.read_errors);
+        assert_eq!(0, report.malformed);
+        assert_eq!(
+            "Stopped at record 2: Could not read transaction record: bad row",
+            report.into_result().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_process_record_tells_read_errors_from_malformed() {
+        let mut processor = TransactionProcessor::new(InMemoryAccountStore::new());
+        assert_eq!(
+            RecordOutcome::ReadError("Could not read transaction record: bad row".to_string()),
+            processor.process_record(Err(anyhow!("bad row")))
+        );
+
+        let outcome = processor.process_record(Ok(TransactionRecord::new(
+            TransactionType::Deposit,
+            ClientId(1),
+            TransactionId(1),
+            None,
+        )));
+        assert!(
+            matches!(&outcome, RecordOutcome::Malformed(message) if message.starts_with("Malformed transaction: ")),
+            "{:?}",
+            outcome
+        );
+    }
+
+    #[test_case(true;  "when allowed")]
+    #[test_case(false; "when not allowed")]
+    fn test_process_with_withdrawal_disputes(allow: bool) {
+        let reader = VecTransactionReader::new(vec![
+            TransactionRecord::withdrawal(ClientId(1), TransactionId(1), dec!(5)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+        ]);
+
+        let mut store = MockAccountStore::new();
+        store.expect_remove_funds().once().returning(|_, _| Ok(()));
+        store
+            .expect_credit_held_funds()
+            .times(usize::from(allow))
+            .with(eq(ClientId(1)), eq(dec!(5)))
+            .returning(|_, _| Ok(()));
+
+        let mut processor = TransactionProcessorBuilder::new()
+            .allow_withdrawal_disputes(allow)
+            .build(store);
+        let report = processor.process(reader);
+
+        assert_eq!(u64::from(allow), report.applied(TransactionType::Dispute));
+        assert_eq!(
+            u64::from(!allow),
+            report.skipped(SkipReason::WithdrawalDisputesNotAllowed)
+        );
+        assert_eq!(allow, report.policies.allow_withdrawal_disputes);
+    }
+
+    #[test_case(DuplicatePolicy::Skip,  3, None;    "when skip")]
+    #[test_case(DuplicatePolicy::Error, 2, Some(2); "when error")]
+    fn test_process_with_duplicate_policy(
+        policy: DuplicatePolicy,
+        records: u64,
+        aborted: Option<u64>,
+    ) {
+        let reader = VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(99)),
+            TransactionRecord::deposit(ClientId(1), TransactionId(2), dec!(1)),
+        ]);
+
+        let mut store = MockAccountStore::new();
+        store
+            .expect_add_funds()
+            .once()
+            .with(eq(ClientId(1)), eq(dec!(10)))
+            .returning(|_, _| Ok(()));
+        store
+            .expect_add_funds()
+            .times(usize::from(aborted.is_none()))
+            .with(eq(ClientId(1)), eq(dec!(1)))
+            .returning(|_, _| Ok(()));
+
+        let mut processor = TransactionProcessorBuilder::new()
+            .on_duplicate_tx(policy)
+            .build(store);
+        let report = processor.process(reader);
+
+        assert_eq!(records, report.records);
+        assert_eq!(1, report.skipped(SkipReason::DuplicateTransaction));
+        assert_eq!(policy, report.policies.on_duplicate_tx);
+        assert_eq!(aborted, report.aborted.as_ref().map(|error| error.record));
+        if aborted.is_some() {
+            assert_eq!(
+                "Stopped at record 2: Duplicate Deposit 1 for client 1",
+                report.into_result().unwrap_err().to_string()
+            );
+        }
+    }
+
+    #[test_case(false, dec!(10); "when holding the full amount")]
+    #[test_case(true,  dec!(2);  "when holding the coverable amount")]
+    fn test_process_with_hold_coverable_only(coverable_only: bool, held: Decimal) {
+        let mut processor = TransactionProcessorBuilder::new()
+            .hold_coverable_only(coverable_only)
+            .build(CheckingAccountStore::new(InMemoryAccountStore::new()));
+        processor.process(VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::withdrawal(ClientId(1), TransactionId(2), dec!(8)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+        ]));
+
+        assert_eq!(
+            Some(AccountSummary::new(ClientId(1), held, dec!(2), false).unwrap()),
+            processor.account(ClientId(1)).unwrap()
+        );
+    }
+
+    #[test_case(DisputePolicy::AllowNegative,        false, dec!(10), 0; "when allowing negative")]
+    #[test_case(DisputePolicy::RejectIfInsufficient, false, dec!(0),  1; "when rejecting if insufficient")]
+    #[test_case(DisputePolicy::RejectIfInsufficient, true,  dec!(2),  0; "when rejecting if insufficient and holding the coverable amount")]
+    fn test_process_with_dispute_policy(
+        policy: DisputePolicy,
+        coverable_only: bool,
+        held: Decimal,
+        skipped: u64,
+    ) {
+        let mut processor = TransactionProcessorBuilder::new()
+            .dispute_policy(policy)
+            .hold_coverable_only(coverable_only)
+            .build(CheckingAccountStore::new(InMemoryAccountStore::new()));
+        let report = processor.process(VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::withdrawal(ClientId(1), TransactionId(2), dec!(8)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+        ]));
+
+        assert_eq!(skipped, report.skipped(SkipReason::InsufficientFunds));
+        assert_eq!(
+            Some(AccountSummary::new(ClientId(1), held, dec!(2), false).unwrap()),
+            processor.account(ClientId(1)).unwrap()
+        );
+    }
+
+    #[test_case(false, dec!(-8); "when holding the full amount")]
+    #[test_case(true,  dec!(0);  "when holding the coverable amount")]
+    fn test_process_chargeback_with_hold_coverable_only(coverable_only: bool, total: Decimal) {
+        let mut processor = TransactionProcessorBuilder::new()
+            .hold_coverable_only(coverable_only)
+            .build(CheckingAccountStore::new(InMemoryAccountStore::new()));
+        processor.process(VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::withdrawal(ClientId(1), TransactionId(2), dec!(8)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            TransactionRecord::chargeback(ClientId(1), TransactionId(1)),
+        ]));
+
+        assert_eq!(
+            Some(AccountSummary::new(ClientId(1), dec!(0), total, true).unwrap()),
+            processor.account(ClientId(1)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_process_dispute_when_available_would_overflow_with_overdraft() -> Result<()> {
+        let store = InMemoryAccountStore::with_overdraft_limit(Decimal::MAX);
+        let mut processor = TransactionProcessor::new(store);
+        let report = processor.process(VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(2), Decimal::MAX),
+            TransactionRecord::withdrawal(ClientId(1), TransactionId(3), Decimal::MAX),
+            TransactionRecord::withdrawal(ClientId(1), TransactionId(4), Decimal::MAX),
+            TransactionRecord::dispute(ClientId(1), TransactionId(2)),
+        ]));
+
+        assert_eq!(1, report.skipped(SkipReason::BalanceOverflow));
+        let expected = AccountSummary::new(ClientId(1), dec!(0), -Decimal::MAX, false)?;
+        assert_eq!(Some(expected), processor.account(ClientId(1))?);
+
+        let mut writer = VecAccountWriter::new();
+        processor.export(&mut writer)?;
+        assert_eq!(vec![expected], writer.into_accounts());
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_dispute_when_available_would_overflow_after_unlock() -> Result<()> {
+        let mut processor =
+            TransactionProcessor::new(InMemoryAccountStore::new()).with_admin_ops(true);
+        let report = processor.process(VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), Decimal::MAX),
+            TransactionRecord::withdrawal(ClientId(1), TransactionId(2), Decimal::MAX),
+            TransactionRecord::deposit(ClientId(1), TransactionId(3), Decimal::MAX),
+            TransactionRecord::withdrawal(ClientId(1), TransactionId(4), Decimal::MAX),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            TransactionRecord::chargeback(ClientId(1), TransactionId(1)),
+            TransactionRecord::unlock(ClientId(1), TransactionId(0)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(3)),
+        ]));
+
+        assert_eq!(1, report.skipped(SkipReason::BalanceOverflow));
+        let expected = AccountSummary::new(ClientId(1), dec!(0), -Decimal::MAX, false)?;
+        assert_eq!(Some(expected), processor.account(ClientId(1))?);
+
+        let mut writer = VecAccountWriter::new();
+        processor.export(&mut writer)?;
+        assert_eq!(vec![expected], writer.into_accounts());
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_with_hold_coverable_only_when_nothing_coverable() {
+        testing_logger::setup();
+
+        let mut processor = TransactionProcessorBuilder::new()
+            .hold_coverable_only(true)
+            .build(CheckingAccountStore::new(InMemoryAccountStore::new()));
+        let report = processor.process(VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::deposit(ClientId(1), TransactionId(2), dec!(5)),
+            TransactionRecord::withdrawal(ClientId(1), TransactionId(3), dec!(12)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(2)),
+            TransactionRecord::chargeback(ClientId(1), TransactionId(1)),
+            TransactionRecord::resolve(ClientId(1), TransactionId(2)),
+        ]));
+
+        // the chargeback of nothing still locks the account, cancelling the other dispute
+        assert_eq!(1, report.skipped(SkipReason::DisputeCancelled));
+        assert_eq!(
+            Some(AccountSummary::new(ClientId(1), dec!(0), dec!(3), true).unwrap()),
+            processor.account(ClientId(1)).unwrap()
+        );
+        testing_logger::validate(|captured_logs| {
+            let logs = captured_logs
+                .iter()
+                .filter(|log| log.level == Level::Info)
+                .map(|log| log.body.as_str())
+                .collect_vec();
+            assert_eq!(
+                vec![
+                    "Dispute of transaction 1 for client 1 holds 0 of 10, the rest has been withdrawn",
+                    "Dispute of transaction 2 for client 1 holds 3 of 5, the rest has been withdrawn",
+                    "Cannot process Resolve 2 for client 1: Case was cancelled when the account was locked",
+                ],
+                logs
+            );
+        });
+    }
+
+    #[test]
+    fn test_process_with_hold_coverable_only_when_resolved_covers_withdrawals() {
+        let mut processor = TransactionProcessorBuilder::new()
+            .hold_coverable_only(true)
+            .build(CheckingAccountStore::new(InMemoryAccountStore::new()));
+        processor.process(VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::deposit(ClientId(1), TransactionId(2), dec!(5)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            TransactionRecord::resolve(ClientId(1), TransactionId(1)),
+            TransactionRecord::withdrawal(ClientId(1), TransactionId(3), dec!(12)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(2)),
+        ]));
+
+        // the resolved deposit is spent first, leaving all but 2 of the later one
+        assert_eq!(
+            Some(AccountSummary::new(ClientId(1), dec!(3), dec!(3), false).unwrap()),
+            processor.account(ClientId(1)).unwrap()
+        );
+    }
+
+    #[test_case(true;  "when recorded")]
+    #[test_case(false; "when not recorded")]
+    fn test_process_with_history(record: bool) {
+        let reader = VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+        ]);
+
+        let mut store = MockAccountStore::new();
+        store.expect_add_funds().once().returning(|_, _| Ok(()));
+        store
+            .expect_hold_funds()
+            .times(usize::from(record))
+            .with(eq(ClientId(1)), eq(dec!(10)))
+            .returning(|_, _| Ok(()));
+
+        let mut processor = TransactionProcessorBuilder::new()
+            .record_history(record)
+            .build(store);
+        let report = processor.process(reader);
+
+        assert_eq!(
+            u64::from(!record),
+            report.skipped(SkipReason::UnknownTransaction)
+        );
+        // the ID is remembered either way
+        assert_eq!(1, report.skipped(SkipReason::DuplicateTransaction));
+    }
+
+    #[test]
+    fn test_process_reports_strict_policy() {
+        let mut processor = TransactionProcessorBuilder::new()
+            .strict(true)
+            .build(InMemoryAccountStore::new());
+        let report = processor.process(VecTransactionReader::new(vec![]));
+        assert!(report.policies.strict);
+    }
+
+    #[test]
+    fn test_account() -> Result<()> {
+        let mut processor = TransactionProcessor::new(InMemoryAccountStore::new());
+        processor.process(VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+        ]));
+
+        assert_eq!(
+            Some(AccountSummary::new(ClientId(1), dec!(10), dec!(10), false).unwrap()),
+            processor.account(ClientId(1)).unwrap()
+        );
+        assert_eq!(None, processor.account(ClientId(42)).unwrap());
+
+        // looking up a client does not open an account for them
+        let mut writer = VecAccountWriter::new();
+        processor.export(&mut writer)?;
+        assert_eq!(1, writer.into_accounts().len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_account_between_batches() -> Result<()> {
+        let mut processor = TransactionProcessor::new(InMemoryAccountStore::new());
+        processor.process(VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::deposit(ClientId(2), TransactionId(2), dec!(5)),
+        ]));
+        assert_eq!(
+            Some(AccountSummary::new(ClientId(1), dec!(0), dec!(10), false).unwrap()),
+            processor.account(ClientId(1)).unwrap()
+        );
+
+        processor.process(VecTransactionReader::new(vec![
+            TransactionRecord::withdrawal(ClientId(1), TransactionId(3), dec!(4)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+        ]));
+        assert_eq!(
+            Some(AccountSummary::new(ClientId(1), dec!(10), dec!(6), false).unwrap()),
+            processor.account(ClientId(1)).unwrap()
+        );
+
+        let mut writer = VecAccountWriter::new();
+        processor.export(&mut writer)?;
+        assert_eq!(
+            vec![
+                AccountSummary::new(ClientId(1), dec!(10), dec!(6), false).unwrap(),
+                AccountSummary::new(ClientId(2), dec!(0), dec!(5), false).unwrap(),
+            ],
+            writer.into_accounts()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_state_continues_from_saved_state() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("state.json");
+        let mut processor = TransactionProcessor::new(InMemoryAccountStore::new());
+        processor.process(VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+        ]));
+        processor.save_state(&path)?;
+
+        // the accounts are kept by a persistent store, rather than the state
+        let mut store = InMemoryAccountStore::new();
+        store.add_funds(ClientId(1), dec!(10))?;
+        store.hold_funds(ClientId(1), dec!(10))?;
+        let mut processor = TransactionProcessor::new(store).with_state(&path)?;
+        let report = processor.process(VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(5)),
+            TransactionRecord::resolve(ClientId(1), TransactionId(1)),
+        ]));
+
+        assert_eq!(1, report.skipped(SkipReason::DuplicateTransaction));
+        assert_eq!(1, report.applied(TransactionType::Resolve));
+        assert_eq!(
+            Some(AccountSummary::new(ClientId(1), dec!(0), dec!(10), false).unwrap()),
+            processor.account(ClientId(1)).unwrap()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_dispute_updates_store() {
+        let reader = VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), 10.into()),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+        ]);
+
+        let mut store = MockAccountStore::new();
+        store
+            .expect_add_funds()
+            .once()
+            .with(eq(ClientId(1)), eq(dec!(10)))
+            .returning(|_, _| Ok(()));
+        store
+            .expect_hold_funds()
+            .once()
+            .with(eq(ClientId(1)), eq(dec!(10)))
+            .returning(|_, _| Ok(()));
+
+        let mut processor = TransactionProcessor::new(store);
+        processor.process(reader);
+    }
+
+    #[test]
+    fn test_process_dispute_when_invalid_transaction_does_not_update_store() {
+        testing_logger::setup();
+
+        let reader = VecTransactionReader::new(vec![
+            // Err: No such transaction found
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            // Ok
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(50)),
+            // Err: Client ID does not match
+            TransactionRecord::dispute(ClientId(5), TransactionId(1)),
+            // Ok
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            // Err: A case already exists
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+        ]);
+
+        let mut store = MockAccountStore::new();
+        store
+            .expect_add_funds()
+            .once()
+            .with(eq(ClientId(1)), eq(dec!(50)))
+            .returning(|_, _| Ok(()));
+        store
+            .expect_hold_funds()
+            .once()
+            .with(eq(ClientId(1)), eq(dec!(50)))
+            .returning(|_, _| Ok(()));
+
+        let mut processor = TransactionProcessor::new(store).with_log_format(LogFormat::Json);
+        processor.process(reader);
+
+        testing_logger::validate(|captured_logs| {
+            assert_eq!(
+                vec![
+                    skip_event(
+                        TransactionType::Dispute,
+                        1,
+                        1,
+                        SkipReason::UnknownTransaction
+                    ),
+                    skip_event(TransactionType::Dispute, 5, 1, SkipReason::ClientMismatch),
+                    skip_event(TransactionType::Dispute, 1, 1, SkipReason::DuplicateDispute),
+                ],
+                skip_events(captured_logs)
+            );
+        });
+    }
+
+    #[test]
+    fn test_process_dispute_when_expired_does_not_update_store() {
+        testing_logger::setup();
+
+        let deposited = Utc.with_ymd_and_hms(2022, 5, 1, 12, 0, 0).unwrap();
+        let reader = VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10))
+                .with_timestamp(deposited),
+            TransactionRecord::deposit(ClientId(1), TransactionId(2), dec!(20))
+                .with_timestamp(deposited),
+            TransactionRecord::deposit(ClientId(1), TransactionId(3), dec!(30)),
+            // Ok: Within the window
+            TransactionRecord::dispute(ClientId(1), TransactionId(1))
+                .with_timestamp(deposited + Duration::days(30)),
+            // Err: Dispute window has expired
+            TransactionRecord::dispute(ClientId(1), TransactionId(2))
+                .with_timestamp(deposited + Duration::days(30) + Duration::seconds(1)),
+            // Ok: Deposit has no timestamp
+            TransactionRecord::dispute(ClientId(1), TransactionId(3))
+                .with_timestamp(deposited + Duration::days(365)),
+        ]);
+
+        let mut store = MockAccountStore::new();
+        store.expect_add_funds().times(3).returning(|_, _| Ok(()));
+        store
+            .expect_hold_funds()
+            .once()
+            .with(eq(ClientId(1)), eq(dec!(10)))
+            .returning(|_, _| Ok(()));
+        store
+            .expect_hold_funds()
+            .once()
+            .with(eq(ClientId(1)), eq(dec!(30)))
+            .returning(|_, _| Ok(()));
+
+        let mut processor = TransactionProcessor::new(store)
+            .with_log_format(LogFormat::Json)
+            .with_max_dispute_age(Some(Duration::days(30)));
+        processor.process(reader);
+
+        testing_logger::validate(|captured_logs| {
+            assert_eq!(
+                vec![skip_event(
+                    TransactionType::Dispute,
+                    1,
+                    2,
+                    SkipReason::DisputeExpired
+                )],
+                skip_events(captured_logs)
+            );
+        });
+    }
+
+    #[test]
+    fn test_process_resolve_updates_store() {
+        let reader = VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), 10.into()),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            TransactionRecord::resolve(ClientId(1), TransactionId(1)),
+        ]);
+
+        let mut store = MockAccountStore::new();
+        store
+            .expect_add_funds()
+            .once()
+            .with(eq(ClientId(1)), eq(dec!(10)))
+            .returning(|_, _| Ok(()));
+        store
+            .expect_hold_funds()
+            .once()
+            .with(eq(ClientId(1)), eq(dec!(10)))
+            .returning(|_, _| Ok(()));
+        store
+            .expect_release_funds()
+            .once()
+            .with(eq(ClientId(1)), eq(dec!(10)))
+            .returning(|_, _| Ok(()));
+
+        let mut processor = TransactionProcessor::new(store);
+        processor.process(reader);
+    }
+
+    #[test]
+    fn test_process_resolve_when_invalid_transaction_does_not_update_store() {
+        testing_logger::setup();
+
+        let reader = VecTransactionReader::new(vec![
+            // Err: No such dispute found
+            TransactionRecord::resolve(ClientId(1), TransactionId(1)),
+            // Ok
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(50)),
+            // Ok
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            // Err: Client ID does not match
+            TransactionRecord::resolve(ClientId(5), TransactionId(1)),
+            // Ok
+            TransactionRecord::resolve(ClientId(1), TransactionId(1)),
+            // Err: Case has already been closed
+            TransactionRecord::resolve(ClientId(1), TransactionId(1)),
+        ]);
+
+        let mut store = MockAccountStore::new();
+        store
+            .expect_add_funds()
+            .once()
+            .with(eq(ClientId(1)), eq(dec!(50)))
+            .returning(|_, _| Ok(()));
+        store
+            .expect_hold_funds()
+            .once()
+            .with(eq(ClientId(1)), eq(dec!(50)))
+            .returning(|_, _| Ok(()));
+        store
+            .expect_release_funds()
+            .once()
+            .with(eq(ClientId(1)), eq(dec!(50)))
+            .returning(|_, _| Ok(()));
+
+        let mut processor = TransactionProcessor::new(store).with_log_format(LogFormat::Json);
+        processor.process(reader);
+
+        testing_logger::validate(|captured_logs| {
+            assert_eq!(
+                vec![
+                    skip_event(TransactionType::Resolve, 1, 1, SkipReason::UnknownDispute),
+                    skip_event(TransactionType::Resolve, 5, 1, SkipReason::ClientMismatch),
+                    skip_event(TransactionType::Resolve, 1, 1, SkipReason::DisputeClosed),
+                ],
+                skip_events(captured_logs)
+            );
         });
+    }
+
+    #[test]
+    fn test_process_chargeback_updates_store() {
+        let reader = VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), 10.into()),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            TransactionRecord::chargeback(ClientId(1), TransactionId(1)),
+        ]);
 
         let mut store = MockAccountStore::new();
         store
@@ -302,68 +2490,383 @@ mod test {
             .once()
             .with(eq(ClientId(1)), eq(dec!(10)))
             .returning(|_, _| Ok(()));
+        store
+            .expect_hold_funds()
+            .once()
+            .with(eq(ClientId(1)), eq(dec!(10)))
+            .returning(|_, _| Ok(()));
+        store
+            .expect_chargeback_funds()
+            .once()
+            .with(eq(ClientId(1)), eq(dec!(10)))
+            .returning(|_, _| Ok(()));
 
         let mut processor = TransactionProcessor::new(store);
         processor.process(reader);
     }
 
     #[test]
-    fn test_process_withdrawal_updates_store() {
-        let mut reader = MockTransactionReader::new();
-        reader.expect_read().returning(|| {
-            let transactions = vec![TransactionRecord::new(
-                TransactionType::Withdrawal,
-                ClientId(1),
-                TransactionId(2),
-                Some(5.into()),
-            )]
-            .into_iter()
-            .map(Ok);
-            Box::new(transactions)
+    fn test_process_chargeback_when_invalid_transaction_does_not_update_store() {
+        testing_logger::setup();
+
+        let reader = VecTransactionReader::new(vec![
+            // Err: No such dispute found
+            TransactionRecord::chargeback(ClientId(1), TransactionId(1)),
+            // Ok
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(50)),
+            // Ok
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            // Err: Client ID does not match
+            TransactionRecord::chargeback(ClientId(5), TransactionId(1)),
+            // Ok
+            TransactionRecord::chargeback(ClientId(1), TransactionId(1)),
+            // Err: Case has already been closed
+            TransactionRecord::chargeback(ClientId(1), TransactionId(1)),
+        ]);
+
+        let mut store = MockAccountStore::new();
+        store
+            .expect_add_funds()
+            .once()
+            .with(eq(ClientId(1)), eq(dec!(50)))
+            .returning(|_, _| Ok(()));
+        store
+            .expect_hold_funds()
+            .once()
+            .with(eq(ClientId(1)), eq(dec!(50)))
+            .returning(|_, _| Ok(()));
+        store
+            .expect_chargeback_funds()
+            .once()
+            .with(eq(ClientId(1)), eq(dec!(50)))
+            .returning(|_, _| Ok(()));
+
+        let mut processor = TransactionProcessor::new(store).with_log_format(LogFormat::Json);
+        processor.process(reader);
+
+        testing_logger::validate(|captured_logs| {
+            assert_eq!(
+                vec![
+                    skip_event(
+                        TransactionType::Chargeback,
+                        1,
+                        1,
+                        SkipReason::UnknownDispute
+                    ),
+                    skip_event(
+                        TransactionType::Chargeback,
+                        5,
+                        1,
+                        SkipReason::ClientMismatch
+                    ),
+                    skip_event(TransactionType::Chargeback, 1, 1, SkipReason::DisputeClosed),
+                ],
+                skip_events(captured_logs)
+            );
         });
+    }
+
+    #[test_case(true,  0; "when cancelled")]
+    #[test_case(false, 5; "when kept")]
+    fn test_process_chargeback_with_other_open_disputes(cancel: bool, held: u32) -> Result<()> {
+        let reader = VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::deposit(ClientId(1), TransactionId(2), dec!(5)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(2)),
+            TransactionRecord::chargeback(ClientId(1), TransactionId(1)),
+        ]);
+
+        let mut processor = TransactionProcessor::new(InMemoryAccountStore::new())
+            .with_cancel_disputes_on_lock(cancel);
+        processor.process(reader);
+
+        let expected_status = match cancel {
+            true => Err(SkipReason::DisputeCancelled),
+            false => Ok(()),
+        };
+        assert_eq!(
+            expected_status,
+            processor
+                .disputes
+                .get(&TransactionId(2))
+                .unwrap()
+                .unwrap()
+                .check_open()
+        );
+
+        let mut writer = VecAccountWriter::new();
+        processor.export(&mut writer)?;
+        assert_eq!(
+            vec![AccountSummary::new(ClientId(1), held.into(), dec!(5), true).unwrap()],
+            writer.into_accounts()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_chargeback_with_chargeback_locked_accounts() -> Result<()> {
+        let mut processor =
+            TransactionProcessor::new(CheckingAccountStore::new(InMemoryAccountStore::new()))
+                .with_cancel_disputes_on_lock(false)
+                .with_chargeback_locked_accounts(true);
+        let report = processor.process(VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::deposit(ClientId(1), TransactionId(2), dec!(5)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(2)),
+            TransactionRecord::chargeback(ClientId(1), TransactionId(1)),
+            TransactionRecord::chargeback(ClientId(1), TransactionId(2)),
+        ]));
+
+        assert_eq!(0, report.skipped(SkipReason::AccountLocked));
+        assert_eq!(
+            Some(DisputeStatus::ChargedBack),
+            processor.dispute_status(TransactionId(2))
+        );
+
+        let mut writer = VecAccountWriter::new();
+        processor.export(&mut writer)?;
+        assert_eq!(
+            vec![AccountSummary::new(ClientId(1), dec!(0), dec!(0), true).unwrap()],
+            writer.into_accounts()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_chargeback_when_store_fails_keeps_other_open_disputes() {
+        let mut processor = TransactionProcessor::new(InMemoryAccountStore::new())
+            .with_cancel_disputes_on_lock(false);
+        let report = processor.process(VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::deposit(ClientId(1), TransactionId(2), dec!(5)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(2)),
+            TransactionRecord::chargeback(ClientId(1), TransactionId(1)),
+            // Err: Account is locked
+            TransactionRecord::chargeback(ClientId(1), TransactionId(2)),
+        ]));
+
+        assert_eq!(1, report.skipped(SkipReason::AccountLocked));
+        assert_eq!(
+            Some(DisputeStatus::Open),
+            processor.dispute_status(TransactionId(2))
+        );
+        assert_eq!(
+            vec![DisputeSummary::new(
+                TransactionId(2),
+                ClientId(1),
+                dec!(5),
+                DisputeStatus::Open
+            )],
+            processor.open_disputes().collect_vec()
+        );
+    }
+
+    #[test_case(true;  "when charging back fails")]
+    #[test_case(false; "when cancelling fails")]
+    fn test_process_chargeback_when_store_fails_leaves_disputes_open(releases: bool) {
+        let reader = VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::deposit(ClientId(1), TransactionId(2), dec!(5)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(2)),
+            TransactionRecord::chargeback(ClientId(1), TransactionId(1)),
+        ]);
 
         let mut store = MockAccountStore::new();
+        store.expect_add_funds().times(2).returning(|_, _| Ok(()));
         store
-            .expect_remove_funds()
+            .expect_hold_funds()
             .once()
+            .with(eq(ClientId(1)), eq(dec!(10)))
+            .returning(|_, _| Ok(()));
+        // the funds released by cancelling the other dispute are held again
+        store
+            .expect_hold_funds()
+            .times(if releases { 2 } else { 1 })
             .with(eq(ClientId(1)), eq(dec!(5)))
             .returning(|_, _| Ok(()));
+        store
+            .expect_release_funds()
+            .once()
+            .with(eq(ClientId(1)), eq(dec!(5)))
+            .returning(move |_, _| match releases {
+                true => Ok(()),
+                false => Err(anyhow!("disk on fire")),
+            });
+        store
+            .expect_chargeback_funds()
+            .times(if releases { 1 } else { 0 })
+            .with(eq(ClientId(1)), eq(dec!(10)))
+            .returning(|_, _| Err(anyhow!("disk on fire")));
 
         let mut processor = TransactionProcessor::new(store);
-        processor.process(reader);
+        let report = processor.process(reader);
+
+        assert_eq!(1, report.skipped(SkipReason::StoreError));
+        for tx in [TransactionId(1), TransactionId(2)] {
+            assert_eq!(Some(DisputeStatus::Open), processor.dispute_status(tx));
+        }
+        assert_eq!(2, processor.open_disputes().count());
     }
 
     #[test]
-    fn test_process_dispute_updates_store() {
-        let mut reader = MockTransactionReader::new();
-        reader.expect_read().returning(|| {
-            let transactions = vec![
-                TransactionRecord::new(
-                    TransactionType::Deposit,
-                    ClientId(1),
-                    TransactionId(1),
-                    Some(10.into()),
-                ),
-                TransactionRecord::new(
-                    TransactionType::Dispute,
-                    ClientId(1),
-                    TransactionId(1),
-                    None,
-                ),
-            ]
-            .into_iter()
-            .map(Ok);
-            Box::new(transactions)
-        });
+    fn test_open_disputes_and_dispute_status() {
+        let mut processor = TransactionProcessor::new(InMemoryAccountStore::new());
+        processor.process(VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::deposit(ClientId(2), TransactionId(2), dec!(5)),
+            TransactionRecord::deposit(ClientId(1), TransactionId(3), dec!(1)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(3)),
+            TransactionRecord::dispute(ClientId(2), TransactionId(2)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+        ]));
+        assert_eq!(
+            vec![
+                DisputeSummary::new(TransactionId(1), ClientId(1), dec!(10), DisputeStatus::Open),
+                DisputeSummary::new(TransactionId(2), ClientId(2), dec!(5), DisputeStatus::Open),
+                DisputeSummary::new(TransactionId(3), ClientId(1), dec!(1), DisputeStatus::Open),
+            ],
+            processor.open_disputes().collect_vec()
+        );
+
+        processor.process(VecTransactionReader::new(vec![
+            TransactionRecord::resolve(ClientId(2), TransactionId(2)),
+            TransactionRecord::chargeback(ClientId(1), TransactionId(3)),
+        ]));
+        assert_eq!(0, processor.open_disputes().count());
+        assert_eq!(
+            Some(DisputeStatus::Cancelled),
+            processor.dispute_status(TransactionId(1))
+        );
+        assert_eq!(
+            Some(DisputeStatus::Resolved),
+            processor.dispute_status(TransactionId(2))
+        );
+        assert_eq!(
+            Some(DisputeStatus::ChargedBack),
+            processor.dispute_status(TransactionId(3))
+        );
+        assert_eq!(None, processor.dispute_status(TransactionId(4)));
+    }
+
+    #[test]
+    fn test_open_disputes_when_spilled() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut processor =
+            TransactionProcessor::new(InMemoryAccountStore::new()).with_spill_dir(dir.path(), 1)?;
+        processor.process(VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::deposit(ClientId(1), TransactionId(2), dec!(5)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(2)),
+        ]));
+
+        let open = processor
+            .open_disputes()
+            .map(|dispute| dispute.tx())
+            .collect_vec();
+        assert_eq!(vec![TransactionId(1), TransactionId(2)], open);
+        Ok(())
+    }
+
+    #[test_case(None,    dec!(10), Some(DisputeStatus::Open);    "when disabled")]
+    #[test_case(Some(4), dec!(10), Some(DisputeStatus::Open);    "when not yet expired")]
+    #[test_case(Some(3), dec!(0),  Some(DisputeStatus::Expired); "when expired")]
+    fn test_process_with_dispute_expiry(
+        expiry: Option<u64>,
+        held: Decimal,
+        status: Option<DisputeStatus>,
+    ) -> Result<()> {
+        let mut processor = TransactionProcessorBuilder::new()
+            .dispute_expiry(expiry)
+            .build(InMemoryAccountStore::new());
+        processor.process(VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            TransactionRecord::deposit(ClientId(2), TransactionId(2), dec!(1)),
+            TransactionRecord::deposit(ClientId(2), TransactionId(3), dec!(1)),
+            TransactionRecord::deposit(ClientId(2), TransactionId(4), dec!(1)),
+        ]));
+        assert_eq!(status, processor.dispute_status(TransactionId(1)));
+
+        let mut writer = VecAccountWriter::new();
+        processor.export(&mut writer)?;
+        assert_eq!(
+            AccountSummary::new(ClientId(1), held, dec!(10), false).unwrap(),
+            writer.into_accounts()[0]
+        );
+        Ok(())
+    }
+
+    #[test_case(TransactionRecord::resolve(ClientId(1), TransactionId(1)),    SkipReason::DisputeClosed; "when resolved after expiry")]
+    #[test_case(TransactionRecord::chargeback(ClientId(1), TransactionId(1)), SkipReason::DisputeClosed; "when charged back after expiry")]
+    fn test_process_when_dispute_expired(record: TransactionRecord, reason: SkipReason) {
+        let mut processor =
+            TransactionProcessor::new(InMemoryAccountStore::new()).with_dispute_expiry(Some(1));
+        processor.process(VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            TransactionRecord::deposit(ClientId(1), TransactionId(2), dec!(1)),
+        ]));
+
+        let report = processor.process(VecTransactionReader::new(vec![record]));
+        assert_eq!(1, report.skipped(reason));
+        assert_eq!(
+            Some(DisputeStatus::Expired),
+            processor.dispute_status(TransactionId(1))
+        );
+    }
+
+    #[test]
+    fn test_process_with_dispute_expiry_when_settled_first() {
+        let mut processor =
+            TransactionProcessor::new(InMemoryAccountStore::new()).with_dispute_expiry(Some(2));
+        processor.process(VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            TransactionRecord::resolve(ClientId(1), TransactionId(1)),
+            TransactionRecord::deposit(ClientId(1), TransactionId(2), dec!(1)),
+            TransactionRecord::deposit(ClientId(1), TransactionId(3), dec!(1)),
+        ]));
+        assert_eq!(
+            Some(DisputeStatus::Resolved),
+            processor.dispute_status(TransactionId(1))
+        );
+        assert_eq!(
+            Some(dec!(12)),
+            processor
+                .account(ClientId(1))
+                .unwrap()
+                .map(|a| a.available())
+        );
+    }
+
+    #[test]
+    fn test_process_resolve_when_withdrawal_updates_store() {
+        let reader = VecTransactionReader::new(vec![
+            TransactionRecord::withdrawal(ClientId(1), TransactionId(1), 10.into()),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            TransactionRecord::resolve(ClientId(1), TransactionId(1)),
+        ]);
 
         let mut store = MockAccountStore::new();
         store
-            .expect_add_funds()
+            .expect_remove_funds()
             .once()
             .with(eq(ClientId(1)), eq(dec!(10)))
             .returning(|_, _| Ok(()));
         store
-            .expect_hold_funds()
+            .expect_credit_held_funds()
+            .once()
+            .with(eq(ClientId(1)), eq(dec!(10)))
+            .returning(|_, _| Ok(()));
+        store
+            .expect_remove_held_funds()
             .once()
             .with(eq(ClientId(1)), eq(dec!(10)))
             .returning(|_, _| Ok(()));
@@ -373,210 +2876,585 @@ mod test {
     }
 
     #[test]
-    fn test_process_dispute_when_invalid_transaction_does_not_update_store() {
-        testing_logger::setup();
-
-        let mut reader = MockTransactionReader::new();
-        reader.expect_read().returning(|| {
-            let transactions = vec![
-                // Err: No such transaction found
-                TransactionRecord::new(
-                    TransactionType::Dispute,
-                    ClientId(1),
-                    TransactionId(1),
-                    None,
-                ),
-                // Ok
-                TransactionRecord::new(
-                    TransactionType::Deposit,
-                    ClientId(1),
-                    TransactionId(1),
-                    Some(dec!(50)),
-                ),
-                // Err: Client ID does not match
-                TransactionRecord::new(
-                    TransactionType::Dispute,
-                    ClientId(5),
-                    TransactionId(1),
-                    None,
-                ),
-                // Ok
-                TransactionRecord::new(
-                    TransactionType::Dispute,
-                    ClientId(1),
-                    TransactionId(1),
-                    None,
-                ),
-                // Err: A case already exists
-                TransactionRecord::new(
-                    TransactionType::Dispute,
-                    ClientId(1),
-                    TransactionId(1),
-                    None,
-                ),
-            ]
-            .into_iter()
-            .map(Ok);
-            Box::new(transactions)
-        });
+    fn test_process_chargeback_when_withdrawal_updates_store() {
+        let reader = VecTransactionReader::new(vec![
+            TransactionRecord::withdrawal(ClientId(1), TransactionId(1), 10.into()),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            TransactionRecord::chargeback(ClientId(1), TransactionId(1)),
+        ]);
 
         let mut store = MockAccountStore::new();
         store
-            .expect_add_funds()
+            .expect_remove_funds()
             .once()
-            .with(eq(ClientId(1)), eq(dec!(50)))
+            .with(eq(ClientId(1)), eq(dec!(10)))
             .returning(|_, _| Ok(()));
         store
-            .expect_hold_funds()
+            .expect_credit_held_funds()
             .once()
-            .with(eq(ClientId(1)), eq(dec!(50)))
+            .with(eq(ClientId(1)), eq(dec!(10)))
+            .returning(|_, _| Ok(()));
+        store
+            .expect_release_funds()
+            .once()
+            .with(eq(ClientId(1)), eq(dec!(10)))
             .returning(|_, _| Ok(()));
+        store
+            .expect_lock()
+            .once()
+            .with(eq(ClientId(1)))
+            .returning(|_| Ok(()));
 
         let mut processor = TransactionProcessor::new(store);
         processor.process(reader);
+    }
+
+    #[test]
+    fn test_process_chargeback_cancels_open_withdrawal_dispute() -> Result<()> {
+        let reader = VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::withdrawal(ClientId(1), TransactionId(2), dec!(4)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(2)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            TransactionRecord::chargeback(ClientId(1), TransactionId(1)),
+        ]);
+
+        let mut processor = TransactionProcessor::new(InMemoryAccountStore::new());
+        processor.process(reader);
+
+        let mut writer = VecAccountWriter::new();
+        processor.export(&mut writer)?;
+        assert_eq!(
+            vec![AccountSummary::new(ClientId(1), dec!(0), dec!(-4), true).unwrap()],
+            writer.into_accounts()
+        );
+
+        Ok(())
+    }
+
+    /// Reader which sets a cancellation flag once some of its records have been read.
+    struct CancellingReader<'a> {
+        records: Vec<TransactionRecord>,
+        cancel_after: usize,
+        cancelled: &'a AtomicBool,
+    }
+
+    impl TransactionReader for CancellingReader<'_> {
+        fn read<'a>(&'a mut self) -> Box<dyn Iterator<Item = Result<TransactionRecord>> + 'a> {
+            let (cancel_after, cancelled) = (self.cancel_after, self.cancelled);
+            Box::new(self.records.drain(..).enumerate().map(move |(i, record)| {
+                if i == cancel_after {
+                    cancelled.store(true, Ordering::Relaxed);
+                }
+                Ok(record)
+            }))
+        }
+    }
+
+    #[test]
+    fn test_process_until_cancelled_keeps_processed_records() -> Result<()> {
+        let cancelled = AtomicBool::new(false);
+        let reader = CancellingReader {
+            records: (1..=5)
+                .map(|tx| TransactionRecord::deposit(ClientId(1), TransactionId(tx), dec!(10)))
+                .collect(),
+            cancel_after: 3,
+            cancelled: &cancelled,
+        };
+
+        let mut processor = TransactionProcessor::new(InMemoryAccountStore::new());
+        assert_eq!(3, processor.process_until(reader, &cancelled).records);
+
+        let mut writer = VecAccountWriter::new();
+        processor.export(&mut writer)?;
+        assert_eq!(
+            vec![AccountSummary::new(ClientId(1), dec!(0), dec!(30), false).unwrap()],
+            writer.into_accounts()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_until_not_cancelled_processes_all_records() {
+        let cancelled = AtomicBool::new(false);
+        let reader = VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::withdrawal(ClientId(1), TransactionId(2), dec!(5)),
+        ]);
+
+        let mut processor = TransactionProcessor::new(InMemoryAccountStore::new());
+        assert_eq!(2, processor.process_until(reader, &cancelled).records);
+    }
+
+    fn journaled_records() -> Vec<TransactionRecord> {
+        vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::deposit(ClientId(2), TransactionId(2), dec!(5)),
+            // Err: insufficient funds, but not once the later deposit is applied
+            TransactionRecord::withdrawal(ClientId(1), TransactionId(3), dec!(15)),
+            TransactionRecord::deposit(ClientId(1), TransactionId(4), dec!(10)),
+            TransactionRecord::dispute(ClientId(2), TransactionId(2)),
+            TransactionRecord::chargeback(ClientId(2), TransactionId(2)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+        ]
+    }
+
+    fn run_journaled(path: &Path, times: usize) -> Result<Vec<AccountSummary>> {
+        let journal = TransactionJournal::open(path)?;
+        let mut processor =
+            TransactionProcessor::new(InMemoryAccountStore::new()).with_journal(journal);
+        for _ in 0..times {
+            processor.process(VecTransactionReader::new(journaled_records()));
+        }
+
+        let mut writer = VecAccountWriter::new();
+        processor.export(&mut writer)?;
+        let mut accounts = writer.into_accounts();
+        accounts.sort_by_key(|account| account.client().0);
+        Ok(accounts)
+    }
+
+    #[test]
+    fn test_process_with_journal_when_processed_twice_matches_once() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let expected = vec![
+            AccountSummary::new(ClientId(1), dec!(10), dec!(20), false).unwrap(),
+            AccountSummary::new(ClientId(2), dec!(0), dec!(0), true).unwrap(),
+        ];
+
+        assert_eq!(expected, run_journaled(&dir.path().join("once"), 1)?);
+        assert_eq!(expected, run_journaled(&dir.path().join("twice"), 2)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_with_journal_reports_duplicates_within_a_run() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let journal = TransactionJournal::open(dir.path().join("journal"))?;
+        let mut processor = TransactionProcessorBuilder::new()
+            .on_duplicate_tx(DuplicatePolicy::Error)
+            .build(InMemoryAccountStore::new())
+            .with_journal(journal);
+
+        let report = processor.process(VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+        ]));
+        assert_eq!(1, report.skipped(SkipReason::DuplicateDispute));
+        assert_eq!(0, report.skipped(SkipReason::AlreadyProcessed));
+
+        let report =
+            processor.process(VecTransactionReader::new(vec![TransactionRecord::deposit(
+                ClientId(1),
+                TransactionId(1),
+                dec!(99),
+            )]));
+        assert_eq!(1, report.skipped(SkipReason::DuplicateTransaction));
+        assert_eq!(Some(1), report.aborted.as_ref().map(|error| error.record));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_with_journal_retries_transactions_not_applied() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("journal");
+        let records = vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            // Err: insufficient funds
+            TransactionRecord::withdrawal(ClientId(1), TransactionId(2), dec!(15)),
+        ];
+
+        let mut processor = TransactionProcessor::new(InMemoryAccountStore::new())
+            .with_journal(TransactionJournal::open(&path)?);
+        let report = processor.process(VecTransactionReader::new(records.clone()));
+        assert_eq!(1, report.skipped(SkipReason::InsufficientFunds));
+        processor.export(VecAccountWriter::new())?;
+
+        let journal = TransactionJournal::open(&path)?;
+        assert!(journal.contains(TransactionType::Deposit, TransactionId(1)));
+        assert!(!journal.contains(TransactionType::Withdrawal, TransactionId(2)));
+
+        let mut processor =
+            TransactionProcessor::new(InMemoryAccountStore::new()).with_journal(journal);
+        let report = processor.process(VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(3), dec!(10)),
+            records[1].clone(),
+        ]));
+        assert_eq!(1, report.applied(TransactionType::Withdrawal));
+
+        let mut writer = VecAccountWriter::new();
+        processor.export(&mut writer)?;
+        assert_eq!(
+            vec![AccountSummary::new(ClientId(1), dec!(0), dec!(5), false).unwrap()],
+            writer.into_accounts()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_with_journal_when_run_twice_matches_once() -> Result<()> {
+        testing_logger::setup();
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("journal");
+
+        let once = run_journaled(&path, 1)?;
+        assert_eq!(once, run_journaled(&path, 1)?);
 
         testing_logger::validate(|captured_logs| {
-            let captured_logs = captured_logs
+            let skipped = captured_logs
                 .iter()
-                .filter(|log| log.level <= Level::Info)
-                .collect_vec();
-            assert_eq!(captured_logs.len(), 3);
-            assert_that!(
-                captured_logs[0].body.to_owned(),
-                matches_regex("No such transaction found")
-            );
-            assert_that!(
-                captured_logs[1].body.to_owned(),
-                matches_regex("Client ID does not match")
-            );
-            assert_that!(
-                captured_logs[2].body.to_owned(),
-                matches_regex("A case already exists")
+                .filter(|log| log.body.ends_with("Transaction has already been processed"))
+                .count();
+            // the withdrawal was not applied, so was not journaled and is retried
+            assert_eq!(journaled_records().len() - 1, skipped);
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_with_journal_when_crashed_and_resumed_matches_single_pass() -> Result<()> {
+        // every client is funded first, as a withdrawal skipped for insufficient funds before
+        // the crash would be retried against the balance after it
+        let funding = (0..7u16).map(|client| {
+            TransactionRecord::deposit(
+                ClientId(client),
+                TransactionId(1000 + client as u32),
+                dec!(100),
+            )
+        });
+        let records: Vec<_> = funding
+            .chain((1..=500u32).map(|tx| {
+                let client = ClientId((tx % 7) as u16);
+                match tx % 5 {
+                    0 => TransactionRecord::withdrawal(client, TransactionId(tx), dec!(3)),
+                    1 => TransactionRecord::dispute(client, TransactionId(tx - 1)),
+                    2 if tx % 3 == 0 => TransactionRecord::resolve(client, TransactionId(tx - 2)),
+                    _ => TransactionRecord::deposit(client, TransactionId(tx), dec!(1.5)),
+                }
+            }))
+            .collect();
+        let export = |processor: TransactionProcessor<InMemoryAccountStore>| {
+            let mut writer = VecAccountWriter::new();
+            processor
+                .export(&mut writer)
+                .map(|_| writer.into_accounts())
+        };
+
+        let mut processor = TransactionProcessor::new(InMemoryAccountStore::new());
+        processor.process(VecTransactionReader::new(records.clone()));
+        let expected = export(processor)?;
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("journal");
+        let mut processor = TransactionProcessor::new(InMemoryAccountStore::new())
+            .with_journal(TransactionJournal::open(&path)?);
+        processor.process(VecTransactionReader::new(records[..300].to_vec()));
+        // a crash neither exports nor flushes the journal
+        std::mem::forget(processor);
+
+        let journal = TransactionJournal::open(&path)?;
+        assert!(journal.has_replay());
+        let mut processor =
+            TransactionProcessor::new(InMemoryAccountStore::new()).with_journal(journal);
+        processor.process(VecTransactionReader::new(records));
+        assert_eq!(expected, export(processor)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_deltas_of_touched_accounts() -> Result<()> {
+        let mut store = InMemoryAccountStore::new();
+        store.add_funds(ClientId(1), dec!(100))?;
+        store.add_funds(ClientId(2), dec!(50))?;
+        store.add_funds(ClientId(3), dec!(5))?;
+
+        let reader = VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::withdrawal(ClientId(1), TransactionId(2), dec!(30)),
+            TransactionRecord::deposit(ClientId(2), TransactionId(3), dec!(20)),
+            TransactionRecord::dispute(ClientId(2), TransactionId(3)),
+            TransactionRecord::chargeback(ClientId(2), TransactionId(3)),
+            // Err: insufficient funds, so client 3 is untouched
+            TransactionRecord::withdrawal(ClientId(3), TransactionId(4), dec!(100)),
+            TransactionRecord::deposit(ClientId(4), TransactionId(5), dec!(7.5)),
+        ]);
+
+        let mut processor = TransactionProcessor::new(store).with_deltas(true);
+        processor.process(reader);
+
+        let baseline = processor.take_baseline();
+        let mut writer = DeltaAccountWriter::from_writer(vec![], baseline);
+        processor.export(&mut writer)?;
+
+        let output = String::from_utf8(writer.into_inner()?)?;
+        let expected = vec![
+            "1,-20,0,-20,false",
+            "2,0,0,0,true",
+            "4,7.5,0,7.5,false",
+            "client,available,held,total,locked_changed",
+        ];
+        assert_eq!(expected, output.lines().sorted().collect_vec());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_disputes_writes_each_status() -> Result<()> {
+        let reader = VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::deposit(ClientId(1), TransactionId(2), dec!(20)),
+            TransactionRecord::deposit(ClientId(1), TransactionId(3), dec!(30)),
+            TransactionRecord::deposit(ClientId(2), TransactionId(4), dec!(40.5)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            TransactionRecord::resolve(ClientId(1), TransactionId(1)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(3)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(2)),
+            // locks client 1, cancelling the dispute of transaction 3
+            TransactionRecord::chargeback(ClientId(1), TransactionId(2)),
+            TransactionRecord::dispute(ClientId(2), TransactionId(4)),
+        ]);
+
+        let mut processor = TransactionProcessor::new(InMemoryAccountStore::new());
+        processor.process(reader);
+
+        let mut writer = CsvDisputeWriter::from_writer(vec![]);
+        processor.export_disputes(&mut writer)?;
+
+        let output = String::from_utf8(writer.into_inner()?)?;
+        let expected = "\
+            tx,client,amount,status\n\
+            1,1,10,resolved\n\
+            2,1,20,charged_back\n\
+            3,1,30,cancelled\n\
+            4,2,40.5,open\n\
+        ";
+        assert_eq!(expected, output);
+
+        // the accounts can still be exported afterwards
+        let mut writer = VecAccountWriter::new();
+        processor.export(&mut writer)?;
+        assert_eq!(2, writer.into_accounts().len());
+
+        Ok(())
+    }
+
+    #[test_case(true;  "when cancelled on lock")]
+    #[test_case(false; "when kept on lock")]
+    fn test_process_with_spill_dir_matches_in_memory(cancel: bool) -> Result<()> {
+        let mut records = Vec::new();
+        for tx in 1..=20 {
+            let client = (tx % 3 + 1) as u16;
+            records.push(TransactionRecord::deposit(
+                ClientId(client),
+                TransactionId(tx),
+                tx.into(),
+            ));
+        }
+        for tx in 1..=20 {
+            let client = (tx % 3 + 1) as u16;
+            records.push(TransactionRecord::dispute(
+                ClientId(client),
+                TransactionId(tx),
+            ));
+        }
+        for tx in 1..=20 {
+            let client = (tx % 3 + 1) as u16;
+            match tx % 4 {
+                0 => records.push(TransactionRecord::resolve(
+                    ClientId(client),
+                    TransactionId(tx),
+                )),
+                1 => records.push(TransactionRecord::chargeback(
+                    ClientId(client),
+                    TransactionId(tx),
+                )),
+                _ => {}
+            }
+        }
+        // already closed or cancelled, or deposited by another client
+        records.push(TransactionRecord::resolve(ClientId(1), TransactionId(3)));
+        records.push(TransactionRecord::chargeback(ClientId(2), TransactionId(4)));
+        records.push(TransactionRecord::dispute(ClientId(2), TransactionId(5)));
+
+        let run = |processor: TransactionProcessor<InMemoryAccountStore>| -> Result<_> {
+            let mut processor = processor.with_cancel_disputes_on_lock(cancel);
+            processor.process(VecTransactionReader::new(records.clone()));
+
+            let mut disputes = CsvDisputeWriter::from_writer(vec![]);
+            processor.export_disputes(&mut disputes)?;
+            let mut accounts = VecAccountWriter::new();
+            let statistics = processor.export(&mut accounts)?;
+            Ok((
+                String::from_utf8(disputes.into_inner()?)?,
+                accounts
+                    .into_accounts()
+                    .into_iter()
+                    .sorted_by_key(|a| a.client().0)
+                    .collect_vec(),
+                statistics,
+            ))
+        };
+
+        let dir = tempfile::tempdir()?;
+        let expected = run(TransactionProcessor::new(InMemoryAccountStore::new()))?;
+        let spilled =
+            run(TransactionProcessor::new(InMemoryAccountStore::new())
+                .with_spill_dir(dir.path(), 2)?)?;
+        assert_eq!(expected, spilled);
+        assert_eq!(21, expected.0.lines().count());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_checkpoint_when_spilled_restores_spilled_state() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("checkpoint.json");
+        let deposit =
+            |client, tx| TransactionRecord::deposit(ClientId(client), TransactionId(tx), dec!(10));
+        let dispute = |client, tx| TransactionRecord::dispute(ClientId(client), TransactionId(tx));
+
+        let mut processor =
+            TransactionProcessor::new(InMemoryAccountStore::new()).with_spill_dir(dir.path(), 1)?;
+        processor.process(VecTransactionReader::new(vec![
+            deposit(1, 1),
+            deposit(1, 2),
+            deposit(2, 3),
+            dispute(1, 1),
+            dispute(2, 3),
+        ]));
+        processor.save_checkpoint(&path, InputPosition::default())?;
+
+        let (processor, _) = TransactionProcessor::<InMemoryAccountStore>::from_checkpoint(&path)?;
+        let mut processor = processor.with_spill_dir(dir.path(), 1)?;
+        processor.process(VecTransactionReader::new(vec![
+            TransactionRecord::resolve(ClientId(1), TransactionId(1)),
+            dispute(1, 2),
+        ]));
+
+        let mut writer = VecAccountWriter::new();
+        processor.export(&mut writer)?;
+        assert_eq!(
+            vec![
+                AccountSummary::new(ClientId(1), dec!(10), dec!(20), false).unwrap(),
+                AccountSummary::new(ClientId(2), dec!(10), dec!(10), false).unwrap(),
+            ],
+            writer
+                .into_accounts()
+                .into_iter()
+                .sorted_by_key(|a| a.client().0)
+                .collect_vec()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_take_baseline_excludes_replayed_journal() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("journal");
+        run_journaled(&path, 1)?;
+
+        let mut processor = TransactionProcessor::new(InMemoryAccountStore::new())
+            .with_journal(TransactionJournal::open(&path)?)
+            .with_deltas(true);
+        processor.process_record(Ok(TransactionRecord::deposit(
+            ClientId(1),
+            TransactionId(100),
+            dec!(5),
+        )));
+
+        assert_eq!(
+            HashMap::from([(
+                ClientId(1),
+                AccountSummary::new(ClientId(1), dec!(10), dec!(20), false).unwrap()
+            )]),
+            processor.take_baseline()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_dispute_when_max_retained_transactions() -> Result<()> {
+        testing_logger::setup();
+
+        let reader = VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(1)),
+            TransactionRecord::deposit(ClientId(1), TransactionId(2), dec!(2)),
+            // Ok: at the limit, so the oldest deposit is still retained
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            TransactionRecord::deposit(ClientId(1), TransactionId(3), dec!(4)),
+            TransactionRecord::deposit(ClientId(1), TransactionId(4), dec!(8)),
+            // Err: beyond the limit the oldest deposits have been evicted
+            TransactionRecord::dispute(ClientId(1), TransactionId(2)),
+            // Ok: the disputed deposit was evicted but the case remains open
+            TransactionRecord::chargeback(ClientId(1), TransactionId(1)),
+        ]);
+
+        let mut processor = TransactionProcessor::new(InMemoryAccountStore::new())
+            .with_max_retained_transactions(Some(2))
+            .with_log_format(LogFormat::Json);
+        processor.process(reader);
+
+        testing_logger::validate(|captured_logs| {
+            assert_eq!(
+                vec![skip_event(
+                    TransactionType::Dispute,
+                    1,
+                    2,
+                    SkipReason::UnknownTransaction
+                )],
+                skip_events(captured_logs)
             );
         });
+
+        let mut writer = VecAccountWriter::new();
+        processor.export(&mut writer)?;
+        assert_eq!(
+            vec![AccountSummary::new(ClientId(1), dec!(0), dec!(14), true).unwrap()],
+            writer.into_accounts()
+        );
+
+        Ok(())
     }
 
     #[test]
-    fn test_process_resolve_updates_store() {
-        let mut reader = MockTransactionReader::new();
-        reader.expect_read().returning(|| {
-            let transactions = vec![
-                TransactionRecord::new(
-                    TransactionType::Deposit,
-                    ClientId(1),
-                    TransactionId(1),
-                    Some(10.into()),
-                ),
-                TransactionRecord::new(
-                    TransactionType::Dispute,
-                    ClientId(1),
-                    TransactionId(1),
-                    None,
-                ),
-                TransactionRecord::new(
-                    TransactionType::Resolve,
-                    ClientId(1),
-                    TransactionId(1),
-                    None,
-                ),
-            ]
-            .into_iter()
-            .map(Ok);
-            Box::new(transactions)
-        });
+    fn test_process_unlock_updates_store_when_admin_ops_allowed() {
+        let reader = VecTransactionReader::new(vec![TransactionRecord::unlock(
+            ClientId(1),
+            TransactionId(1),
+        )]);
 
         let mut store = MockAccountStore::new();
         store
-            .expect_add_funds()
-            .once()
-            .with(eq(ClientId(1)), eq(dec!(10)))
-            .returning(|_, _| Ok(()));
-        store
-            .expect_hold_funds()
-            .once()
-            .with(eq(ClientId(1)), eq(dec!(10)))
-            .returning(|_, _| Ok(()));
-        store
-            .expect_release_funds()
+            .expect_unlock()
             .once()
-            .with(eq(ClientId(1)), eq(dec!(10)))
-            .returning(|_, _| Ok(()));
+            .with(eq(ClientId(1)))
+            .returning(|_| Ok(()));
 
-        let mut processor = TransactionProcessor::new(store);
+        let mut processor = TransactionProcessor::new(store).with_admin_ops(true);
         processor.process(reader);
     }
 
     #[test]
-    fn test_process_resolve_when_invalid_transaction_does_not_update_store() {
+    fn test_process_unlock_when_admin_ops_not_allowed_does_not_update_store() {
         testing_logger::setup();
 
-        let mut reader = MockTransactionReader::new();
-        reader.expect_read().returning(|| {
-            let transactions = vec![
-                // Err: No such dispute found
-                TransactionRecord::new(
-                    TransactionType::Resolve,
-                    ClientId(1),
-                    TransactionId(1),
-                    None,
-                ),
-                // Ok
-                TransactionRecord::new(
-                    TransactionType::Deposit,
-                    ClientId(1),
-                    TransactionId(1),
-                    Some(dec!(50)),
-                ),
-                // Ok
-                TransactionRecord::new(
-                    TransactionType::Dispute,
-                    ClientId(1),
-                    TransactionId(1),
-                    None,
-                ),
-                // Err: Client ID does not match
-                TransactionRecord::new(
-                    TransactionType::Resolve,
-                    ClientId(5),
-                    TransactionId(1),
-                    None,
-                ),
-                // Ok
-                TransactionRecord::new(
-                    TransactionType::Resolve,
-                    ClientId(1),
-                    TransactionId(1),
-                    None,
-                ),
-                // Err: Case has already been closed
-                TransactionRecord::new(
-                    TransactionType::Resolve,
-                    ClientId(1),
-                    TransactionId(1),
-                    None,
-                ),
-            ]
-            .into_iter()
-            .map(Ok);
-            Box::new(transactions)
-        });
+        let reader = VecTransactionReader::new(vec![TransactionRecord::unlock(
+            ClientId(1),
+            TransactionId(1),
+        )]);
 
         let mut store = MockAccountStore::new();
-        store
-            .expect_add_funds()
-            .once()
-            .with(eq(ClientId(1)), eq(dec!(50)))
-            .returning(|_, _| Ok(()));
-        store
-            .expect_hold_funds()
-            .once()
-            .with(eq(ClientId(1)), eq(dec!(50)))
-            .returning(|_, _| Ok(()));
-        store
-            .expect_release_funds()
-            .once()
-            .with(eq(ClientId(1)), eq(dec!(50)))
-            .returning(|_, _| Ok(()));
+        store.expect_unlock().never();
 
         let mut processor = TransactionProcessor::new(store);
         processor.process(reader);
@@ -586,166 +3464,192 @@ mod test {
                 .iter()
                 .filter(|log| log.level <= Level::Info)
                 .collect_vec();
-            assert_eq!(captured_logs.len(), 3);
+            assert_eq!(captured_logs.len(), 1);
             assert_that!(
                 captured_logs[0].body.to_owned(),
-                matches_regex("No such dispute found")
-            );
-            assert_that!(
-                captured_logs[1].body.to_owned(),
-                matches_regex("Client ID does not match")
-            );
-            assert_that!(
-                captured_logs[2].body.to_owned(),
-                matches_regex("Case has already been closed")
+                matches_regex("Admin operations are not allowed")
             );
         });
     }
 
-    #[test]
-    fn test_process_chargeback_updates_store() {
-        let mut reader = MockTransactionReader::new();
-        reader.expect_read().returning(|| {
-            let transactions = vec![
-                TransactionRecord::new(
-                    TransactionType::Deposit,
-                    ClientId(1),
-                    TransactionId(1),
-                    Some(10.into()),
-                ),
-                TransactionRecord::new(
-                    TransactionType::Dispute,
-                    ClientId(1),
-                    TransactionId(1),
-                    None,
-                ),
-                TransactionRecord::new(
-                    TransactionType::Chargeback,
-                    ClientId(1),
-                    TransactionId(1),
-                    None,
-                ),
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_process_stream_matches_process() -> Result<()> {
+        use futures::stream;
+
+        use crate::InMemoryAccountStore;
+        use crate::StreamTransactionReader;
+
+        let records = || {
+            vec![
+                TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+                TransactionRecord::deposit(ClientId(2), TransactionId(2), dec!(7.5)),
+                TransactionRecord::withdrawal(ClientId(1), TransactionId(3), dec!(2.25)),
+                TransactionRecord::dispute(ClientId(2), TransactionId(2)),
+                TransactionRecord::chargeback(ClientId(2), TransactionId(2)),
             ]
-            .into_iter()
-            .map(Ok);
-            Box::new(transactions)
-        });
-
-        let mut store = MockAccountStore::new();
-        store
-            .expect_add_funds()
-            .once()
-            .with(eq(ClientId(1)), eq(dec!(10)))
-            .returning(|_, _| Ok(()));
-        store
-            .expect_hold_funds()
-            .once()
-            .with(eq(ClientId(1)), eq(dec!(10)))
-            .returning(|_, _| Ok(()));
-        store
-            .expect_force_remove_funds_and_lock()
-            .once()
-            .with(eq(ClientId(1)), eq(dec!(10)))
-            .returning(|_, _| Ok(()));
+        };
+        let export = |processor: TransactionProcessor<InMemoryAccountStore>| {
+            let mut writer = VecAccountWriter::new();
+            processor.export(&mut writer)?;
+            Ok::<_, anyhow::Error>(
+                writer
+                    .into_accounts()
+                    .into_iter()
+                    .sorted_by_key(|account| account.client().0)
+                    .collect_vec(),
+            )
+        };
 
-        let mut processor = TransactionProcessor::new(store);
+        let reader = VecTransactionReader::new(records());
+        let mut processor = TransactionProcessor::new(InMemoryAccountStore::new());
         processor.process(reader);
+        let expected = export(processor)?;
+
+        let reader = StreamTransactionReader::new(stream::iter(records().into_iter().map(Ok)));
+        let mut processor = TransactionProcessor::new(InMemoryAccountStore::new());
+        processor.process_stream(reader).await;
+        assert_eq!(expected, export(processor)?);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_process_stream_from_duplex_matches_process() -> Result<()> {
+        use tokio::io::AsyncWriteExt;
+
+        use crate::AsyncCsvTransactionReader;
+        use crate::CsvTransactionReader;
+
+        let input = "type,client,tx,amount\n\
+                     deposit,1,1,10\n\
+                     deposit,2,2,7.5\n\
+                     withdrawal,1,3,2.25\n\
+                     dispute,2,2,\n\
+                     withdrawal,2,4,1\n\
+                     chargeback,2,2,\n";
+        let export = |processor: TransactionProcessor<InMemoryAccountStore>| {
+            let mut writer = VecAccountWriter::new();
+            processor.export(&mut writer)?;
+            Ok::<_, anyhow::Error>(writer.into_accounts())
+        };
+
+        let mut processor = TransactionProcessor::new(InMemoryAccountStore::new());
+        processor.process(CsvTransactionReader::from_reader(input.as_bytes()));
+        let expected = export(processor)?;
+        let clients = expected
+            .iter()
+            .map(|account| account.client())
+            .collect_vec();
+        assert_eq!(vec![ClientId(1), ClientId(2)], clients);
+
+        let (mut client, server) = tokio::io::duplex(16);
+        let send = async move {
+            client.write_all(input.as_bytes()).await?;
+            client.shutdown().await
+        };
+        let mut processor = TransactionProcessor::new(InMemoryAccountStore::new());
+        let (sent, _) = tokio::join!(
+            send,
+            processor.process_stream(AsyncCsvTransactionReader::from_reader(server))
+        );
+        sent?;
+        assert_eq!(expected, export(processor)?);
+
+        Ok(())
     }
 
     #[test]
-    fn test_process_chargeback_when_invalid_transaction_does_not_update_store() {
-        testing_logger::setup();
+    fn test_from_checkpoint_restores_saved_state() -> Result<()> {
+        use crate::InMemoryAccountStore;
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("checkpoint.json");
+        let position = InputPosition {
+            byte: 42,
+            line: 3,
+            record: 2,
+        };
 
-        let mut reader = MockTransactionReader::new();
-        reader.expect_read().returning(|| {
-            let transactions = vec![
-                // Err: No such dispute found
-                TransactionRecord::new(
-                    TransactionType::Chargeback,
-                    ClientId(1),
-                    TransactionId(1),
-                    None,
-                ),
-                // Ok
-                TransactionRecord::new(
-                    TransactionType::Deposit,
-                    ClientId(1),
-                    TransactionId(1),
-                    Some(dec!(50)),
-                ),
-                // Ok
-                TransactionRecord::new(
-                    TransactionType::Dispute,
-                    ClientId(1),
-                    TransactionId(1),
-                    None,
-                ),
-                // Err: Client ID does not match
-                TransactionRecord::new(
-                    TransactionType::Chargeback,
-                    ClientId(5),
-                    TransactionId(1),
-                    None,
-                ),
-                // Ok
-                TransactionRecord::new(
-                    TransactionType::Chargeback,
-                    ClientId(1),
-                    TransactionId(1),
-                    None,
-                ),
-                // Err: Case has already been closed
-                TransactionRecord::new(
-                    TransactionType::Chargeback,
-                    ClientId(1),
-                    TransactionId(1),
-                    None,
-                ),
-            ]
-            .into_iter()
-            .map(Ok);
-            Box::new(transactions)
-        });
+        let mut processor =
+            TransactionProcessor::new(InMemoryAccountStore::new()).with_deltas(true);
+        for record in [
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+        ] {
+            processor.process_record(Ok(record));
+        }
+        processor.save_checkpoint(&path, position)?;
+
+        let (mut processor, restored) =
+            TransactionProcessor::<InMemoryAccountStore>::from_checkpoint(&path)?;
+        assert_eq!(position, restored);
+
+        // The dispute case must survive the checkpoint for the chargeback to apply
+        processor.process_record(Ok(TransactionRecord::chargeback(
+            ClientId(1),
+            TransactionId(1),
+        )));
+        // As must the baseline, for the change since the run started
+        assert_eq!(
+            HashMap::from([(ClientId(1), AccountSummary::empty(ClientId(1)))]),
+            processor.take_baseline()
+        );
+        let mut writer = VecAccountWriter::new();
+        processor.export(&mut writer)?;
+        assert_eq!(
+            vec![AccountSummary::new(ClientId(1), dec!(0), dec!(0), true).unwrap()],
+            writer.into_accounts()
+        );
 
-        let mut store = MockAccountStore::new();
-        store
-            .expect_add_funds()
-            .once()
-            .with(eq(ClientId(1)), eq(dec!(50)))
-            .returning(|_, _| Ok(()));
-        store
-            .expect_hold_funds()
-            .once()
-            .with(eq(ClientId(1)), eq(dec!(50)))
-            .returning(|_, _| Ok(()));
-        store
-            .expect_force_remove_funds_and_lock()
-            .once()
-            .with(eq(ClientId(1)), eq(dec!(50)))
-            .returning(|_, _| Ok(()));
+        Ok(())
+    }
 
-        let mut processor = TransactionProcessor::new(store);
+    #[test]
+    fn test_export_returns_statistics() -> Result<()> {
+        use crate::InMemoryAccountStore;
+
+        let reader = VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::deposit(ClientId(2), TransactionId(2), dec!(20)),
+            TransactionRecord::deposit(ClientId(3), TransactionId(3), dec!(5.5)),
+            // Err: Insufficient funds
+            TransactionRecord::withdrawal(ClientId(1), TransactionId(4), dec!(50)),
+            TransactionRecord::dispute(ClientId(2), TransactionId(2)),
+            TransactionRecord::dispute(ClientId(3), TransactionId(3)),
+            TransactionRecord::chargeback(ClientId(3), TransactionId(3)),
+        ]);
+
+        let mut processor = TransactionProcessor::new(InMemoryAccountStore::new());
         processor.process(reader);
+        let statistics = processor.export(VecAccountWriter::new())?;
+
+        let expected = SummaryStatistics {
+            clients: 3,
+            total: dec!(30),
+            held: dec!(20),
+            locked: 1,
+            deposits: 3,
+            withdrawals: 1,
+            disputes: 2,
+            chargebacks: 1,
+            skipped: 1,
+            largest_client: Some(ClientId(2)),
+            largest_total: dec!(20),
+            ..Default::default()
+        };
+        assert_eq!(expected, statistics);
 
-        testing_logger::validate(|captured_logs| {
-            let captured_logs = captured_logs
-                .iter()
-                .filter(|log| log.level <= Level::Info)
-                .collect_vec();
-            assert_eq!(captured_logs.len(), 3);
-            assert_that!(
-                captured_logs[0].body.to_owned(),
-                matches_regex("No such dispute found")
-            );
-            assert_that!(
-                captured_logs[1].body.to_owned(),
-                matches_regex("Client ID does not match")
-            );
-            assert_that!(
-                captured_logs[2].body.to_owned(),
-                matches_regex("Case has already been closed")
-            );
-        });
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_returns_statistics_when_empty() -> Result<()> {
+        let processor = TransactionProcessor::new(InMemoryAccountStore::new());
+        let statistics = processor.export(VecAccountWriter::new())?;
+        assert_eq!(SummaryStatistics::default(), statistics);
+        Ok(())
     }
 
     #[test]
@@ -763,8 +3667,299 @@ mod test {
 
         let mut writer = MockAccountWriter::new();
         writer.expect_write().times(3).returning(|_| Ok(()));
+        writer.expect_finish().once().returning(|| Ok(()));
 
         let processor = TransactionProcessor::new(store);
-        processor.export(writer)
+        processor.export(writer)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_continues_processing() -> Result<()> {
+        let deposit =
+            |tx, amount| TransactionRecord::deposit(ClientId(1), TransactionId(tx), amount);
+        let mut processor = TransactionProcessor::new(InMemoryAccountStore::new());
+
+        processor.process(VecTransactionReader::new(vec![deposit(1, dec!(10))]));
+        let mut writer = VecAccountWriter::new();
+        processor.snapshot(&mut writer)?;
+        assert_eq!(
+            vec![AccountSummary::new(ClientId(1), dec!(0), dec!(10), false).unwrap()],
+            writer.into_accounts()
+        );
+
+        processor.process(VecTransactionReader::new(vec![deposit(2, dec!(5))]));
+        let mut writer = VecAccountWriter::new();
+        let statistics = processor.snapshot(&mut writer)?;
+        assert_eq!(
+            vec![AccountSummary::new(ClientId(1), dec!(0), dec!(15), false).unwrap()],
+            writer.into_accounts()
+        );
+        assert_eq!(2, statistics.deposits);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_when_untouched_accounts_writes_empty_accounts() -> Result<()> {
+        let mut store = MockAccountStore::new();
+        store.expect_remove_funds().returning(|client, amount| {
+            Err(AccountError::InsufficientFunds { client, amount }.into())
+        });
+        store
+            .expect_export()
+            .returning(|| Box::new(vec![Account::empty(ClientId(1))].into_iter()));
+
+        let mut processor = TransactionProcessor::new(store).with_untouched_accounts(true);
+        for client in [1, 2, 2] {
+            processor.process_record(Ok(TransactionRecord::withdrawal(
+                ClientId(client),
+                TransactionId(1),
+                dec!(5),
+            )));
+        }
+
+        let mut writer = VecAccountWriter::new();
+        processor.export(&mut writer)?;
+        assert_eq!(
+            vec![
+                AccountSummary::empty(ClientId(1)),
+                AccountSummary::empty(ClientId(2))
+            ],
+            writer.into_accounts()
+        );
+
+        Ok(())
+    }
+
+    /// Records the type of every transaction it validates, accepting them all.
+    struct RecordingValidator {
+        seen: std::rc::Rc<std::cell::RefCell<Vec<TransactionType>>>,
+        validates_disputes: bool,
+    }
+
+    impl TransactionValidator for RecordingValidator {
+        fn validate(
+            &self,
+            tx: &Transaction,
+            _ctx: &ValidationContext,
+        ) -> Result<(), ValidationError> {
+            self.seen.borrow_mut().push(tx.transaction_type());
+            Ok(())
+        }
+
+        fn validates_disputes(&self) -> bool {
+            self.validates_disputes
+        }
+    }
+
+    #[test_case(false, vec![TransactionType::Deposit, TransactionType::Deposit]; "when not opted in")]
+    #[test_case(true,  vec![TransactionType::Deposit, TransactionType::Deposit, TransactionType::Dispute,
+                            TransactionType::Resolve, TransactionType::Dispute, TransactionType::Chargeback];
+                "when opted in")]
+    fn test_process_validators_see_disputes(
+        validates_disputes: bool,
+        expected: Vec<TransactionType>,
+    ) {
+        let reader = VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::deposit(ClientId(1), TransactionId(2), dec!(5)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            TransactionRecord::resolve(ClientId(1), TransactionId(1)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(2)),
+            TransactionRecord::chargeback(ClientId(1), TransactionId(2)),
+        ]);
+        let seen = std::rc::Rc::default();
+        let validator = RecordingValidator {
+            seen: std::rc::Rc::clone(&seen),
+            validates_disputes,
+        };
+
+        let mut processor = TransactionProcessor::new(InMemoryAccountStore::new())
+            .with_validators(vec![Box::new(validator)]);
+        processor.process(reader);
+
+        assert_eq!(expected, *seen.borrow());
+        let account = processor.store.account(ClientId(1)).unwrap();
+        assert_eq!(dec!(10), account.total());
+        assert!(account.locked());
+    }
+
+    #[test]
+    fn test_process_when_rejected_skips_without_running_later_validators() {
+        testing_logger::setup();
+
+        let reader = VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            // Err: Client 3 is denied
+            TransactionRecord::deposit(ClientId(3), TransactionId(2), dec!(5)),
+        ]);
+        let seen = std::rc::Rc::default();
+        let validator = RecordingValidator {
+            seen: std::rc::Rc::clone(&seen),
+            validates_disputes: false,
+        };
+
+        let mut store = MockAccountStore::new();
+        store.expect_account().returning(|_| None);
+        store
+            .expect_add_funds()
+            .once()
+            .with(eq(ClientId(1)), eq(dec!(10)))
+            .returning(|_, _| Ok(()));
+
+        let mut processor = TransactionProcessor::new(store)
+            .with_log_format(LogFormat::Json)
+            .with_validators(vec![
+                Box::new(DeniedClientsValidator::new([ClientId(3)])),
+                Box::new(validator),
+            ]);
+        processor.process(reader);
+
+        assert_eq!(vec![TransactionType::Deposit], *seen.borrow());
+        assert_eq!(1, processor.statistics.skipped);
+        testing_logger::validate(|captured_logs| {
+            assert_eq!(
+                vec![
+                    skip_event(TransactionType::Deposit, 3, 2, SkipReason::Rejected)
+                        .with_detail("Client 3 is denied")
+                ],
+                skip_events(captured_logs)
+            );
+        });
+    }
+
+    #[test]
+    fn test_with_anomalies_records_anomalies() {
+        use std::{cell::RefCell, rc::Rc};
+
+        #[derive(Default)]
+        struct RecordingAnomalyLog(Rc<RefCell<Vec<(TransactionType, Anomaly)>>>);
+
+        impl AnomalyLog for RecordingAnomalyLog {
+            fn record(
+                &mut self,
+                transaction_type: TransactionType,
+                anomaly: &Anomaly,
+            ) -> Result<()> {
+                self.0.borrow_mut().push((transaction_type, *anomaly));
+                Ok(())
+            }
+        }
+
+        let log = RecordingAnomalyLog::default();
+        let recorded = log.0.clone();
+        let mut processor =
+            TransactionProcessor::new(InMemoryAccountStore::new()).with_anomalies(log);
+        processor.process(VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+            TransactionRecord::deposit(ClientId(1), TransactionId(2), dec!(5)),
+            TransactionRecord::dispute(ClientId(2), TransactionId(1)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(9)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            TransactionRecord::resolve(ClientId(2), TransactionId(1)),
+            TransactionRecord::chargeback(ClientId(1), TransactionId(1)),
+            TransactionRecord::deposit(ClientId(1), TransactionId(3), dec!(1)),
+            TransactionRecord::dispute(ClientId(1), TransactionId(2)),
+        ]));
+
+        assert_eq!(
+            vec![
+                (
+                    TransactionType::Dispute,
+                    Anomaly::ClientMismatch {
+                        expected: ClientId(1),
+                        got: ClientId(2),
+                        tx: TransactionId(1)
+                    }
+                ),
+                (
+                    TransactionType::Dispute,
+                    Anomaly::UnknownTransaction {
+                        tx: TransactionId(9)
+                    }
+                ),
+                (
+                    TransactionType::Dispute,
+                    Anomaly::DuplicateDispute {
+                        tx: TransactionId(1)
+                    }
+                ),
+                (
+                    TransactionType::Resolve,
+                    Anomaly::ClientMismatch {
+                        expected: ClientId(1),
+                        got: ClientId(2),
+                        tx: TransactionId(1)
+                    }
+                ),
+                // the deposit to the locked account is not an anomaly, unlike the dispute
+                (
+                    TransactionType::Dispute,
+                    Anomaly::ActionOnLockedAccount {
+                        client: ClientId(1),
+                        tx: TransactionId(2)
+                    }
+                ),
+            ],
+            *recorded.borrow()
+        );
+    }
+
+    #[test]
+    fn test_with_anomalies_flushes_on_export() {
+        let mut log = crate::MockAnomalyLog::new();
+        log.expect_record().never();
+        log.expect_flush().times(1).returning(|| Ok(()));
+
+        let mut processor =
+            TransactionProcessor::new(InMemoryAccountStore::new()).with_anomalies(log);
+        processor.process(VecTransactionReader::new(vec![TransactionRecord::deposit(
+            ClientId(1),
+            TransactionId(1),
+            dec!(5),
+        )]));
+        processor.export(VecAccountWriter::new()).unwrap();
+    }
+
+    #[test]
+    fn test_with_rejected_writes_records_not_applied() {
+        let mut writer = crate::MockRejectedTransactionWriter::new();
+        let mut sequence = mockall::Sequence::new();
+        for (line, reason) in [
+            (2, RejectionReason::Skipped(SkipReason::InsufficientFunds)),
+            (3, RejectionReason::Malformed),
+            (4, RejectionReason::Skipped(SkipReason::UnknownTransaction)),
+        ] {
+            writer
+                .expect_write()
+                .withf(move |rejected| rejected.line() == line && rejected.reason() == reason)
+                .times(1)
+                .in_sequence(&mut sequence)
+                .returning(|_| Ok(()));
+        }
+        writer.expect_flush().times(1).returning(|| Ok(()));
+
+        let mut processor =
+            TransactionProcessor::new(InMemoryAccountStore::new()).with_rejected(writer);
+        let report = processor.process(VecTransactionReader::new(vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(5)),
+            TransactionRecord::withdrawal(ClientId(1), TransactionId(2), dec!(6)),
+            TransactionRecord::new(
+                TransactionType::Deposit,
+                ClientId(1),
+                TransactionId(3),
+                None,
+            ),
+        ]));
+        assert_eq!(3, report.records);
+        // records processed one at a time carry on the numbering
+        processor.process_record(Ok(TransactionRecord::dispute(
+            ClientId(1),
+            TransactionId(9),
+        )));
+
+        processor.export(VecAccountWriter::new()).unwrap();
     }
 }
@@ -1,39 +1,587 @@
-use std::{fs::File, path::Path};
+use std::{
+    borrow::BorrowMut,
+    fs::File,
+    io::{self, BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom},
+    path::Path,
+};
 
-use anyhow::{Error, Result};
-use csv::{ReaderBuilder, Trim};
+use anyhow::{anyhow, Context, Error, Result};
+use csv::{DeserializeErrorKind, Position, ReaderBuilder, StringRecord, Trim};
+use flate2::read::MultiGzDecoder;
+use serde::{Deserialize, Serialize};
 
-use crate::TransactionRecord;
+use crate::{
+    transaction_record::{strip_thousands_separators, strip_zero_fraction},
+    Transaction, TransactionRecord,
+};
 
 /// A trait for any transaction reader implementation.
 #[cfg_attr(test, mockall::automock)]
 pub trait TransactionReader {
     /// Returns an iterator over [`Transaction`] records.
     fn read<'a>(&'a mut self) -> Box<dyn Iterator<Item = Result<TransactionRecord>> + 'a>;
+
+    /// Returns an iterator over [`Transaction`] records, each paired with the line of the input
+    /// it starts on.
+    ///
+    /// Readers which do not track lines number the records instead, starting at 1.
+    fn read_with_line_numbers<'a>(
+        &'a mut self,
+    ) -> Box<dyn Iterator<Item = (Result<TransactionRecord>, u64)> + 'a> {
+        Box::new(self.read().zip(1..))
+    }
+}
+
+/// A position within the input, used to resume reading from a checkpoint.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct InputPosition {
+    /// Byte offset from the start of the input.
+    pub byte: u64,
+    /// Line number, starting at 1.
+    pub line: u64,
+    /// Record index, counting the header.
+    pub record: u64,
+}
+
+impl From<&Position> for InputPosition {
+    fn from(position: &Position) -> Self {
+        InputPosition {
+            byte: position.byte(),
+            line: position.line(),
+            record: position.record(),
+        }
+    }
 }
 
-/// Transaction reader for CSV files.
-pub struct CsvTransactionReader {
-    reader: csv::Reader<File>,
+impl From<InputPosition> for Position {
+    fn from(position: InputPosition) -> Self {
+        let mut pos = Position::new();
+        pos.set_byte(position.byte)
+            .set_line(position.line)
+            .set_record(position.record);
+        pos
+    }
+}
+
+/// Columns of the input, in order, when it has no header row and none are given.
+pub const DEFAULT_COLUMNS: [&str; 5] = ["type", "client", "tx", "amount", "timestamp"];
+
+/// The CSV dialect of the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvReaderOptions {
+    /// Byte separating fields, `,` by default.
+    pub delimiter: u8,
+    /// Whether the first row is a header, `true` by default.
+    pub has_headers: bool,
+    /// Field of each column, by position, such as `["client", "type", "amount", "tx"]`.
+    ///
+    /// When given, any header row is skipped rather than used, so headers need not use the
+    /// field names. Without a header row the columns default to [`DEFAULT_COLUMNS`].
+    pub columns: Option<Vec<String>>,
+}
+
+impl Default for CsvReaderOptions {
+    fn default() -> Self {
+        CsvReaderOptions {
+            delimiter: b',',
+            has_headers: true,
+            columns: None,
+        }
+    }
+}
+
+/// The first bytes of a gzip stream.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// A file of transactions, decompressed as it is read if gzipped.
+pub enum TransactionFile {
+    Plain(File),
+    /// Possibly several concatenated gzip members, as appended by `gzip` or written by `pigz`.
+    Gzip(Box<BufReader<MultiGzDecoder<File>>>),
+}
+
+impl TransactionFile {
+    /// Opens the file, which is gzipped if it has a `.gz` extension or starts with the gzip
+    /// magic bytes.
+    ///
+    /// Returns an error if the file cannot be opened, or is gzipped but its header is invalid.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let mut file = File::open(path)?;
+        let mut magic = Vec::with_capacity(GZIP_MAGIC.len());
+        (&mut file)
+            .take(GZIP_MAGIC.len() as u64)
+            .read_to_end(&mut magic)?;
+        file.rewind()?;
+        let gzipped = magic == GZIP_MAGIC
+            || path
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("gz"));
+        if !gzipped {
+            return Ok(TransactionFile::Plain(file));
+        }
+        let mut decoder = BufReader::new(MultiGzDecoder::new(file));
+        // the header is only parsed once read from, so fail here rather than on the first record
+        decoder
+            .fill_buf()
+            .with_context(|| format!("Invalid gzip input {:?}", path))?;
+        Ok(TransactionFile::Gzip(Box::new(decoder)))
+    }
+
+    /// Whether the file is decompressed as it is read.
+    pub fn is_gzip(&self) -> bool {
+        matches!(self, TransactionFile::Gzip(_))
+    }
+}
+
+impl Read for TransactionFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            TransactionFile::Plain(file) => file.read(buf),
+            TransactionFile::Gzip(decoder) => decoder.read(buf),
+        }
+    }
+}
+
+impl Seek for TransactionFile {
+    /// Seeks within a plain file, but fails for a gzipped one, which can only be read in order.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            TransactionFile::Plain(file) => file.seek(pos),
+            TransactionFile::Gzip(_) => Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "Cannot seek within gzip compressed input",
+            )),
+        }
+    }
+}
+
+/// Transaction reader for CSV files, or any other [`Read`] stream such as stdin.
+///
+/// Files opened by path may be gzipped, see [`TransactionFile`].
+pub struct CsvTransactionReader<R: Read = TransactionFile> {
+    reader: csv::Reader<R>,
+    columns: Option<StringRecord>,
+    delimiter: u8,
+    lenient_amounts: bool,
+    lenient_ids: bool,
+    strict: bool,
 }
 
 impl CsvTransactionReader {
     /// Create a new CSV reader for the given file path.
     pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let path: &Path = path.as_ref();
-        let reader = ReaderBuilder::new().trim(Trim::All).from_path(path)?;
-        Ok(CsvTransactionReader { reader })
+        CsvTransactionReader::with_options(path, CsvReaderOptions::default())
+    }
+
+    /// Create a new CSV reader for the given file path, in the given dialect.
+    pub fn with_options<P: AsRef<Path>>(path: P, options: CsvReaderOptions) -> Result<Self> {
+        let file = TransactionFile::open(path)?;
+        Ok(CsvTransactionReader::from_reader_with_options(
+            file, options,
+        ))
+    }
+}
+
+impl<R: Read> CsvTransactionReader<R> {
+    /// Create a new CSV reader over any [`Read`] stream, such as stdin.
+    pub fn from_reader(rdr: R) -> Self {
+        CsvTransactionReader::from_reader_with_options(rdr, CsvReaderOptions::default())
+    }
+
+    /// Create a new CSV reader over any [`Read`] stream, in the given dialect.
+    pub fn from_reader_with_options(rdr: R, options: CsvReaderOptions) -> Self {
+        // rows may omit trailing empty fields, such as the amount of `dispute,1,3`, which are
+        // then missing when deserialized rather than an error
+        let reader = ReaderBuilder::new()
+            .trim(Trim::Headers)
+            .delimiter(options.delimiter)
+            .has_headers(options.has_headers)
+            .flexible(true)
+            .from_reader(rdr);
+        let columns = match (options.columns, options.has_headers) {
+            (Some(columns), _) => Some(StringRecord::from(columns)),
+            (None, true) => None,
+            (None, false) => Some(StringRecord::from(DEFAULT_COLUMNS.to_vec())),
+        };
+        CsvTransactionReader {
+            reader,
+            columns,
+            delimiter: options.delimiter,
+            lenient_amounts: false,
+            lenient_ids: false,
+            strict: false,
+        }
+    }
+
+    /// Accept amounts with comma or underscore thousands separators, such as `1,234.56` or `1_000`.
+    ///
+    /// Amounts with a decimal comma, such as `1.234,56`, are still rejected as ambiguous.
+    pub fn with_lenient_amounts(mut self, lenient: bool) -> Self {
+        self.lenient_amounts = lenient;
+        self
+    }
+
+    /// Accept client and transaction IDs with a zero fraction, such as `42.0`, as written by
+    /// spreadsheets.
+    ///
+    /// IDs with any other fraction, such as `42.5`, are still rejected.
+    pub fn with_lenient_ids(mut self, lenient: bool) -> Self {
+        self.lenient_ids = lenient;
+        self
+    }
+
+    /// Report records which are not valid transactions, such as a deposit without an amount,
+    /// as errors with their line and content, rather than leaving them to the processor.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Returns an iterator over deserialized [`Transaction`] records, each paired with the
+    /// position immediately after it, from which reading can later be resumed.
+    ///
+    /// An error is returned if the header cannot be read.
+    pub fn read_with_positions(
+        &mut self,
+    ) -> Result<impl Iterator<Item = (Result<TransactionRecord>, InputPosition)> + '_> {
+        Ok(self
+            .read_with_lines_and_positions()?
+            .map(|(result, _, position)| (result, position)))
+    }
+
+    /// Returns an iterator over deserialized [`Transaction`] records, each paired with the line
+    /// number it starts on.
+    ///
+    /// An error is returned if the header cannot be read.
+    pub fn read_with_lines(
+        &mut self,
+    ) -> Result<impl Iterator<Item = (Result<TransactionRecord>, u64)> + '_> {
+        Ok(self
+            .read_with_lines_and_positions()?
+            .map(|(result, line, _)| (result, line)))
+    }
+
+    /// Returns an iterator over deserialized [`Transaction`] records, each paired with the line
+    /// it starts on, as [`TransactionReader::read_with_line_numbers`] does without boxing it,
+    /// for [`TransactionProcessor::process_iter`](crate::TransactionProcessor::process_iter).
+    ///
+    /// An error reading the header is the only item.
+    pub fn iter(&mut self) -> impl Iterator<Item = (Result<TransactionRecord>, u64)> + '_ {
+        let (records, header) = match self.read_with_lines() {
+            Ok(records) => (Some(records), None),
+            Err(err) => (None, Some((Err(err), 1))),
+        };
+        header.into_iter().chain(records.into_iter().flatten())
+    }
+
+    /// Returns an iterator over deserialized [`Transaction`] records, each paired with the line
+    /// it starts on and the position immediately after it.
+    ///
+    /// An error is returned if the header cannot be read.
+    pub fn read_with_lines_and_positions(
+        &mut self,
+    ) -> Result<impl Iterator<Item = (Result<TransactionRecord>, u64, InputPosition)> + '_> {
+        CsvTransactionReader::records(self)
+    }
+
+    /// Returns an iterator over deserialized [`Transaction`] records, each paired with the line
+    /// number it starts on, which owns the reader so the input is closed once it is dropped.
+    ///
+    /// An error is returned if the header cannot be read.
+    pub fn into_records_with_lines(
+        self,
+    ) -> Result<impl Iterator<Item = (Result<TransactionRecord>, u64)>> {
+        Ok(CsvTransactionReader::records(self)?.map(|(result, line, _)| (result, line)))
+    }
+
+    /// Iterates over the records of a reader, either borrowed or owned.
+    fn records<B: BorrowMut<Self>>(
+        mut this: B,
+    ) -> Result<impl Iterator<Item = (Result<TransactionRecord>, u64, InputPosition)>> {
+        let reader = this.borrow_mut();
+        let headers = match &reader.columns {
+            Some(columns) => columns.clone(),
+            None => reader.reader.headers()?.clone(),
+        };
+        let column = |name: &str| headers.iter().position(|header| header == name);
+        let amount = column("amount").filter(|_| reader.lenient_amounts);
+        let ids: Vec<usize> = match reader.lenient_ids {
+            true => column("client").into_iter().chain(column("tx")).collect(),
+            false => Vec::new(),
+        };
+        let delimiter = char::from(reader.delimiter).to_string();
+        let strict = reader.strict;
+        let mut record = StringRecord::new();
+        Ok(std::iter::from_fn(move || {
+            let this = this.borrow_mut();
+            let start = this.reader.position().line();
+            let (result, line) = match this.reader.read_record(&mut record) {
+                Ok(false) => return None,
+                Ok(true) => {
+                    // Fields are trimmed here rather than by the reader so that line breaks
+                    // within quoted fields can be counted back from the end of the record,
+                    // as the position the reader gives records ignores skipped blank lines
+                    let breaks = record.as_slice().matches('\n').count() as u64;
+                    record.trim();
+                    // The content is only joined for an error, or before a field is rewritten
+                    let mut raw = None;
+                    if let Some(amount) = amount {
+                        rewrite_field(
+                            &mut record,
+                            &mut raw,
+                            &delimiter,
+                            amount,
+                            strip_thousands_separators,
+                        );
+                    }
+                    for &id in &ids {
+                        rewrite_field(&mut record, &mut raw, &delimiter, id, strip_zero_fraction);
+                    }
+                    let raw = || {
+                        raw.clone()
+                            .unwrap_or_else(|| join_fields(&record, &delimiter))
+                    };
+                    let end = this.reader.position().line();
+                    let line = match this.reader.is_done() {
+                        true => end - breaks,
+                        false => end - breaks - 1,
+                    };
+                    let line = line.max(start);
+                    // Rows missing trailing fields are tolerated, but not rows with extra ones
+                    let result = match record.len() > headers.len() {
+                        true => Err(anyhow!(
+                            "CSV error: found record with {} fields, but the header has {} fields at line {}: {}",
+                            record.len(),
+                            headers.len(),
+                            line,
+                            raw()
+                        )),
+                        false => record.deserialize(Some(&headers)).map_err(|err| {
+                            deserialize_error(err, headers.get(record.len()), line, &raw())
+                        }),
+                    };
+                    (
+                        result.and_then(|record| check_transaction(record, strict, line, raw)),
+                        line,
+                    )
+                }
+                Err(err) => {
+                    let line = err.position().map_or(start, Position::line);
+                    (Err(Error::from(err)), line)
+                }
+            };
+            Some((result, line, this.reader.position().into()))
+        }))
+    }
+
+    /// Returns the underlying stream.
+    pub fn get_ref(&self) -> &R {
+        self.reader.get_ref()
+    }
+}
+
+impl<R: Read + Seek> CsvTransactionReader<R> {
+    /// Seek to a position previously returned by [`CsvTransactionReader::read_with_positions`].
+    ///
+    /// The header is read before seeking so records after the position are still deserialized
+    /// by column name.
+    pub fn seek(&mut self, position: InputPosition) -> Result<()> {
+        self.reader.seek(position.into())?;
+        Ok(())
+    }
+}
+
+/// Joins the fields of a record with the delimiter, to describe it in an error.
+fn join_fields(record: &StringRecord, delimiter: &str) -> String {
+    record.iter().collect::<Vec<_>>().join(delimiter)
+}
+
+/// Rewrites a field of a record, if `rewrite` returns a replacement for it, first keeping the
+/// content of the record as read in `raw`, unless an earlier rewrite did.
+fn rewrite_field(
+    record: &mut StringRecord,
+    raw: &mut Option<String>,
+    delimiter: &str,
+    index: usize,
+    rewrite: fn(&str) -> Option<String>,
+) {
+    if let Some(replacement) = record.get(index).and_then(rewrite) {
+        raw.get_or_insert_with(|| join_fields(record, delimiter));
+        let mut rewritten = StringRecord::with_capacity(record.as_slice().len(), record.len());
+        for (i, field) in record.iter().enumerate() {
+            rewritten.push_field(if i == index { &replacement } else { field });
+        }
+        rewritten.set_position(record.position().cloned());
+        *record = rewritten;
+    }
+}
+
+/// Describes why a record could not be deserialized, the line it starts on and its content,
+/// such as `CSV deserialize error: client id 70000 out of range 0..=65535 at line 3: deposit,70000,1,5`.
+///
+/// A row ending before a required field names the first column it lacks, `next`, such as
+/// ``missing field `tx` ``.
+fn deserialize_error(err: csv::Error, next: Option<&str>, line: u64, raw: &str) -> Error {
+    match err.kind() {
+        csv::ErrorKind::Deserialize { err, .. } => {
+            let missing = match err.kind() {
+                DeserializeErrorKind::UnexpectedEndOfRow => next,
+                _ => None,
+            };
+            match missing {
+                Some(field) => anyhow!(
+                    "CSV deserialize error: missing field `{}` at line {}: {}",
+                    field,
+                    line,
+                    raw
+                ),
+                None => anyhow!(
+                    "CSV deserialize error: {} at line {}: {}",
+                    err.kind(),
+                    line,
+                    raw
+                ),
+            }
+        }
+        _ => Error::from(err),
+    }
+}
+
+/// When strict, fails unless the record is a valid transaction, describing why along with the
+/// line it is on and its content.
+fn check_transaction(
+    record: TransactionRecord,
+    strict: bool,
+    line: u64,
+    raw: impl FnOnce() -> String,
+) -> Result<TransactionRecord> {
+    if !strict {
+        return Ok(record);
+    }
+    match Transaction::try_from(record.clone()) {
+        Ok(_) => Ok(record),
+        Err(err) => Err(anyhow!(
+            "Malformed transaction: {} at line {}: {}",
+            err,
+            line,
+            raw()
+        )),
+    }
+}
+
+impl<R: Read> TransactionReader for CsvTransactionReader<R> {
+    /// Returns an iterator over deserialized [`Transaction`] records.
+    fn read<'a>(&'a mut self) -> Box<dyn Iterator<Item = Result<TransactionRecord>> + 'a> {
+        match self.read_with_positions() {
+            Ok(records) => Box::new(records.map(|(result, _)| result)),
+            Err(err) => Box::new(std::iter::once(Err(err))),
+        }
+    }
+
+    fn read_with_line_numbers<'a>(
+        &'a mut self,
+    ) -> Box<dyn Iterator<Item = (Result<TransactionRecord>, u64)> + 'a> {
+        Box::new(self.iter())
+    }
+}
+
+/// Transaction reader for JSON Lines files, one JSON object per record.
+///
+/// Amounts are strings, such as `"amount": "1.5"`, so no precision is lost. Blank lines are
+/// skipped.
+pub struct JsonLinesTransactionReader<R: Read = File> {
+    reader: BufReader<R>,
+    strict: bool,
+}
+
+impl JsonLinesTransactionReader {
+    /// Create a new JSON Lines reader for the given file path.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::open(path.as_ref())?;
+        Ok(JsonLinesTransactionReader::from_reader(file))
+    }
+}
+
+impl<R: Read> JsonLinesTransactionReader<R> {
+    /// Create a new JSON Lines reader over any [`Read`] stream, such as stdin.
+    pub fn from_reader(rdr: R) -> Self {
+        JsonLinesTransactionReader {
+            reader: BufReader::new(rdr),
+            strict: false,
+        }
+    }
+
+    /// Report records which are not valid transactions as errors with their line and content,
+    /// like [`CsvTransactionReader::with_strict`].
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Returns an iterator over deserialized [`Transaction`] records, each paired with the line
+    /// number it is on.
+    pub fn read_with_lines(
+        &mut self,
+    ) -> impl Iterator<Item = (Result<TransactionRecord>, u64)> + '_ {
+        let strict = self.strict;
+        (&mut self.reader)
+            .lines()
+            .zip(1..)
+            .filter_map(move |(result, line)| match result {
+                Ok(text) if text.trim().is_empty() => None,
+                Ok(text) => Some((
+                    serde_json::from_str(&text)
+                        .map_err(|err| {
+                            anyhow!("JSON deserialize error: {} at line {}: {}", err, line, text)
+                        })
+                        .and_then(|record| check_transaction(record, strict, line, || text)),
+                    line,
+                )),
+                Err(err) => Some((Err(Error::from(err)), line)),
+            })
     }
 }
 
-impl TransactionReader for CsvTransactionReader {
+impl<R: Read> TransactionReader for JsonLinesTransactionReader<R> {
     /// Returns an iterator over deserialized [`Transaction`] records.
     fn read<'a>(&'a mut self) -> Box<dyn Iterator<Item = Result<TransactionRecord>> + 'a> {
-        Box::new(
-            self.reader
-                .deserialize()
-                .map(|result| result.map_err(Error::from)),
-        )
+        Box::new(self.read_with_lines().map(|(result, _)| result))
+    }
+
+    fn read_with_line_numbers<'a>(
+        &'a mut self,
+    ) -> Box<dyn Iterator<Item = (Result<TransactionRecord>, u64)> + 'a> {
+        Box::new(self.read_with_lines())
+    }
+}
+
+/// Transaction reader for records already held in memory.
+///
+/// Useful for testing and benchmarking, where records are generated rather than read from a file.
+/// Records are yielded once, in order; subsequent reads return nothing.
+#[derive(Debug, Default)]
+pub struct VecTransactionReader {
+    records: Vec<TransactionRecord>,
+}
+
+impl VecTransactionReader {
+    /// Create a new reader over the given records.
+    pub fn new(records: Vec<TransactionRecord>) -> Self {
+        VecTransactionReader { records }
+    }
+}
+
+impl From<Vec<TransactionRecord>> for VecTransactionReader {
+    fn from(records: Vec<TransactionRecord>) -> Self {
+        VecTransactionReader::new(records)
+    }
+}
+
+impl TransactionReader for VecTransactionReader {
+    /// Returns an iterator which takes each record from the reader.
+    fn read<'a>(&'a mut self) -> Box<dyn Iterator<Item = Result<TransactionRecord>> + 'a> {
+        Box::new(self.records.drain(..).map(Ok))
     }
 }
 
@@ -41,15 +589,85 @@ impl TransactionReader for CsvTransactionReader {
 mod tests {
     use std::io::Write;
 
+    use flate2::{write::GzEncoder, Compression};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
     use tempfile::NamedTempFile;
     use test_case::test_case;
 
     use crate::ClientId;
     use crate::TransactionId;
-    use crate::TransactionType;
 
     use super::*;
 
+    #[test]
+    fn test_read_when_trailing_amount_missing() -> Result<()> {
+        let input = "type,client,tx,amount\ndeposit,1,3,10\ndispute,1,3\nresolve,1,3,\n";
+        let mut rdr = CsvTransactionReader::from_reader(input.as_bytes());
+        let transactions = rdr.read().collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            vec![
+                TransactionRecord::deposit(ClientId(1), TransactionId(3), 10.into()),
+                TransactionRecord::dispute(ClientId(1), TransactionId(3)),
+                TransactionRecord::resolve(ClientId(1), TransactionId(3)),
+            ],
+            transactions
+        );
+        Ok(())
+    }
+
+    #[test_case("deposit,1",  "CSV deserialize error: missing field `tx` at line 2: deposit,1";   "when tx missing")]
+    #[test_case("deposit",    "CSV deserialize error: missing field `client` at line 2: deposit"; "when client missing")]
+    fn test_read_when_row_truncated(row: &str, expected: &str) {
+        let input = format!("type,client,tx,amount\n{}\ndeposit,1,2,5\n", row);
+        let mut rdr = CsvTransactionReader::from_reader(input.as_bytes());
+        let results: Vec<_> = rdr.read().collect();
+        assert_eq!(2, results.len());
+        assert_eq!(expected, results[0].as_ref().unwrap_err().to_string());
+        assert!(results[1].is_ok());
+    }
+
+    #[test]
+    fn test_read_when_row_has_extra_field() {
+        let input = "type,client,tx,amount\nwithdrawal,2,10,5,extra\n";
+        let mut rdr = CsvTransactionReader::from_reader(input.as_bytes());
+        let results: Vec<_> = rdr.read().collect();
+        assert_eq!(1, results.len());
+        assert_eq!(
+            "CSV error: found record with 5 fields, but the header has 4 fields at line 2: withdrawal,2,10,5,extra",
+            results[0].as_ref().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_from_reader() -> Result<()> {
+        let input = "type,client,tx,amount\ndeposit,1,1,10\n";
+        let mut rdr = CsvTransactionReader::from_reader(input.as_bytes());
+        let transactions = rdr.read().collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            vec![TransactionRecord::deposit(
+                ClientId(1),
+                TransactionId(1),
+                10.into()
+            )],
+            transactions
+        );
+
+        let input = r#"{"type":"deposit","client":1,"tx":1,"amount":"10"}"#;
+        let mut rdr = JsonLinesTransactionReader::from_reader(input.as_bytes());
+        let transactions = rdr.read().collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            vec![TransactionRecord::deposit(
+                ClientId(1),
+                TransactionId(1),
+                10.into()
+            )],
+            transactions
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_read() -> Result<()> {
         let mut file = NamedTempFile::new()?;
@@ -68,18 +686,8 @@ mod tests {
 
         assert_eq!(
             vec![
-                TransactionRecord::new(
-                    TransactionType::Deposit,
-                    ClientId(1),
-                    TransactionId(1),
-                    Some(10.into())
-                ),
-                TransactionRecord::new(
-                    TransactionType::Withdrawal,
-                    ClientId(1),
-                    TransactionId(2),
-                    Some(5.into())
-                ),
+                TransactionRecord::deposit(ClientId(1), TransactionId(1), 10.into()),
+                TransactionRecord::withdrawal(ClientId(1), TransactionId(2), 5.into()),
             ],
             transactions
         );
@@ -87,6 +695,432 @@ mod tests {
         Ok(())
     }
 
+    const GZIP_INPUT: &str = "type, client, tx, amount
+deposit, 1, 1, 10
+withdrawal, 1, 2, 5
+dispute, 1, 1
+deposit, 2, 3, 1.5
+";
+
+    fn gzip(input: &[u8]) -> Result<Vec<u8>> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(input)?;
+        Ok(encoder.finish()?)
+    }
+
+    fn read_all(reader: &mut impl TransactionReader) -> Vec<String> {
+        reader
+            .read()
+            .map(|result| format!("{:?}", result.map_err(|err| err.to_string())))
+            .collect()
+    }
+
+    #[test_case(".csv.gz"; "with extension")]
+    #[test_case(".csv";    "without extension")]
+    fn test_read_when_gzipped(suffix: &str) -> Result<()> {
+        let mut plain = NamedTempFile::with_suffix(".csv")?;
+        plain.write_all(GZIP_INPUT.as_bytes())?;
+        let mut gzipped = NamedTempFile::with_suffix(suffix)?;
+        gzipped.write_all(&gzip(GZIP_INPUT.as_bytes())?)?;
+
+        let mut expected = CsvTransactionReader::from_path(plain.path())?;
+        let mut rdr = CsvTransactionReader::from_path(gzipped.path())?;
+        assert!(rdr.get_ref().is_gzip());
+        assert!(!expected.get_ref().is_gzip());
+        assert_eq!(read_all(&mut expected), read_all(&mut rdr));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_when_gzip_members_concatenated() -> Result<()> {
+        let (first, second) = GZIP_INPUT.split_at(GZIP_INPUT.find("dispute").unwrap());
+        let mut file = NamedTempFile::with_suffix(".csv.gz")?;
+        file.write_all(&gzip(first.as_bytes())?)?;
+        file.write_all(&gzip(second.as_bytes())?)?;
+
+        let mut rdr = CsvTransactionReader::from_path(file.path())?;
+        assert_eq!(4, rdr.read().filter(Result::is_ok).count());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_path_when_gzip_header_invalid() -> Result<()> {
+        let mut file = NamedTempFile::with_suffix(".csv.gz")?;
+        file.write_all(GZIP_INPUT.as_bytes())?;
+
+        let err = CsvTransactionReader::from_path(file.path())
+            .err()
+            .expect("invalid gzip header should fail");
+        assert!(err.to_string().starts_with("Invalid gzip input"), "{}", err);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_when_gzip_stream_corrupt() -> Result<()> {
+        let mut input = String::from("type,client,tx,amount\n");
+        for tx in 1..=1000 {
+            input.push_str(&format!(
+                "deposit,{},{},{}.{}\n",
+                tx % 7,
+                tx,
+                tx * 31 % 997,
+                tx % 10
+            ));
+        }
+        let mut compressed = gzip(input.as_bytes())?;
+        compressed.truncate(compressed.len() / 2);
+        let mut file = NamedTempFile::with_suffix(".csv.gz")?;
+        file.write_all(&compressed)?;
+
+        let mut rdr = CsvTransactionReader::from_path(file.path())?;
+        let results: Vec<_> = rdr.read().collect();
+        let (last, records) = results.split_last().unwrap();
+        // the records decompressed before the corruption are read, then reading stops
+        assert!(!records.is_empty());
+        assert!(records.iter().all(Result::is_ok));
+        assert!(last.is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_seek_when_gzipped() -> Result<()> {
+        let mut file = NamedTempFile::with_suffix(".csv.gz")?;
+        file.write_all(&gzip(GZIP_INPUT.as_bytes())?)?;
+
+        let mut rdr = CsvTransactionReader::from_path(file.path())?;
+        let (_, position) = rdr.read_with_positions()?.next().unwrap();
+        rdr.read().for_each(drop);
+        assert!(rdr.seek(position).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_with_positions_resumes_after_seek() -> Result<()> {
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "type, client, tx, amount")?;
+        writeln!(file, "deposit, 1, 1, 10")?;
+        writeln!(file, "deposit, 1, 2, 20")?;
+        writeln!(file, "withdrawal, 1, 3, 5")?;
+
+        let path = NamedTempFile::into_temp_path(file);
+        let mut rdr = CsvTransactionReader::from_path(&path)?;
+        let (_, position) = rdr.read_with_positions()?.next().unwrap();
+
+        let mut rdr = CsvTransactionReader::from_path(&path)?;
+        rdr.seek(position)?;
+        let transactions = rdr
+            .read_with_positions()?
+            .map(|(result, _)| result)
+            .collect::<Result<Vec<_>>>()?;
+
+        assert_eq!(
+            vec![
+                TransactionRecord::deposit(ClientId(1), TransactionId(2), 20.into()),
+                TransactionRecord::withdrawal(ClientId(1), TransactionId(3), 5.into()),
+            ],
+            transactions
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_with_lines() -> Result<()> {
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "type, client, tx, amount")?;
+        writeln!(file, "deposit, 1, 1, 10")?;
+        writeln!(file)?;
+        writeln!(file, "borrow, 1, 2, 20")?;
+        writeln!(file, "deposit,1,3,\"5\n\"")?;
+        write!(file, "withdrawal, 1, 4, 5")?;
+
+        let path = NamedTempFile::into_temp_path(file);
+        let mut rdr = CsvTransactionReader::from_path(&path)?;
+        let lines: Vec<_> = rdr
+            .read_with_lines()?
+            .map(|(result, line)| (result.is_ok(), line))
+            .collect();
+
+        assert_eq!(vec![(true, 2), (false, 4), (true, 5), (true, 7)], lines);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut rdr = CsvTransactionReader::from_reader(
+            "type,client,tx,amount\ndeposit,1,1,10\n\nborrow,1,2,20\n".as_bytes(),
+        );
+        let lines: Vec<_> = rdr
+            .iter()
+            .map(|(result, line)| (result.is_ok(), line))
+            .collect();
+        assert_eq!(vec![(true, 2), (false, 4)], lines);
+
+        let mut rdr = CsvTransactionReader::from_reader(&b"type,\xff\ndeposit,1,1,10\n"[..]);
+        let lines: Vec<_> = rdr
+            .iter()
+            .map(|(result, line)| (result.is_ok(), line))
+            .collect();
+        assert_eq!(vec![(false, 1)], lines);
+    }
+
+    #[test]
+    fn test_read_when_rewritten_and_malformed_shows_content_as_read() {
+        let input = "type,client,tx,amount\ndeposit,1,1,\"-1,000\"\n";
+        let mut rdr = CsvTransactionReader::from_reader(input.as_bytes())
+            .with_lenient_amounts(true)
+            .with_strict(true);
+
+        let err = rdr.read().next().unwrap().unwrap_err().to_string();
+        assert!(
+            err.starts_with("Malformed transaction: Expected positive amount")
+                && err.ends_with("at line 2: deposit,1,1,-1,000"),
+            "{}",
+            err
+        );
+    }
+
+    #[test_case(false, vec![None, None];                          "when strict")]
+    #[test_case(true,  vec![Some(dec!(1234.56)), None];           "when lenient")]
+    fn test_read_when_thousands_separators(lenient: bool, expected: Vec<Option<Decimal>>) {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "type,client,tx,amount").unwrap();
+        writeln!(file, "deposit,1,1,\"1,234.56\"").unwrap();
+        writeln!(file, "deposit,1,2,\"1.234,56\"").unwrap();
+
+        let path = NamedTempFile::into_temp_path(file);
+        let mut rdr = CsvTransactionReader::from_path(path)
+            .unwrap()
+            .with_lenient_amounts(lenient);
+
+        let amounts = rdr
+            .read()
+            .map(|result| result.ok().and_then(|record| record.amount))
+            .collect::<Vec<_>>();
+        assert_eq!(expected, amounts);
+    }
+
+    #[test_case(false, vec![None, None, None, None, None];                            "when strict")]
+    #[test_case(true,  vec![Some(dec!(1234.5678)), Some(dec!(1000)), None, None, None]; "when lenient")]
+    fn test_read_when_formatted_amounts(lenient: bool, expected: Vec<Option<Decimal>>) {
+        let input = "\
+            type,client,tx,amount\n\
+            deposit,1,1,\" 1,234.5678 \"\n\
+            deposit,1,2, 1_000 \n\
+            deposit,1,3,(5.00)\n\
+            deposit,1,4,1.2.3\n\
+            deposit,1,5,\n\
+            deposit,1,6,12\n\
+        ";
+        let mut rdr = CsvTransactionReader::from_reader(input.as_bytes())
+            .with_lenient_amounts(lenient)
+            .with_strict(true);
+
+        let results: Vec<_> = rdr.read().collect();
+        assert_eq!(6, results.len());
+        assert_eq!(
+            Some(dec!(12)),
+            results[5].as_ref().ok().and_then(|record| record.amount)
+        );
+        let amounts = results[..5]
+            .iter()
+            .map(|result| result.as_ref().ok().and_then(|record| record.amount))
+            .collect::<Vec<_>>();
+        assert_eq!(expected, amounts);
+
+        let err = results[2].as_ref().unwrap_err().to_string();
+        assert!(
+            err.contains("'(5.00)'") && err.ends_with("at line 4: deposit,1,3,(5.00)"),
+            "{}",
+            err
+        );
+        let err = results[4].as_ref().unwrap_err().to_string();
+        assert!(
+            err.starts_with("Malformed transaction: Expected amount")
+                && err.ends_with("at line 6: deposit,1,5,"),
+            "{}",
+            err
+        );
+    }
+
+    #[test_case(false, vec![None, None, None];                     "when strict")]
+    #[test_case(true,  vec![Some((42, 7)), Some((3, 100)), None];  "when lenient")]
+    fn test_read_when_zero_fraction_ids(lenient: bool, expected: Vec<Option<(u16, u32)>>) {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "type,client,tx,amount").unwrap();
+        writeln!(file, "deposit,42.0,7,1").unwrap();
+        writeln!(file, "deposit,3,100.00,1").unwrap();
+        writeln!(file, "deposit,3,8.5,1").unwrap();
+
+        let path = NamedTempFile::into_temp_path(file);
+        let mut rdr = CsvTransactionReader::from_path(path)
+            .unwrap()
+            .with_lenient_ids(lenient);
+
+        let ids = rdr
+            .read()
+            .map(|result| result.ok().map(|record| (record.client.0, record.tx.0)))
+            .collect::<Vec<_>>();
+        assert_eq!(expected, ids);
+    }
+
+    #[test_case(false, 2; "when lenient")]
+    #[test_case(true,  1; "when strict")]
+    fn test_read_when_invalid_transaction(strict: bool, records: usize) -> Result<()> {
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "type,client,tx,amount")?;
+        writeln!(file, "deposit,1,1,10")?;
+        writeln!(file, "deposit,1,2,")?;
+
+        let path = NamedTempFile::into_temp_path(file);
+        let mut rdr = CsvTransactionReader::from_path(&path)?.with_strict(strict);
+        let results: Vec<_> = rdr.read().collect();
+
+        assert_eq!(
+            records,
+            results.iter().filter(|result| result.is_ok()).count()
+        );
+        if strict {
+            let err = results[1].as_ref().unwrap_err().to_string();
+            assert!(
+                err.starts_with("Malformed transaction: Expected amount"),
+                "{}",
+                err
+            );
+            assert!(err.ends_with("at line 3: deposit,1,2,"), "{}", err);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_when_invalid_id_reports_line() -> Result<()> {
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "type, client, tx, amount")?;
+        writeln!(file, "deposit, 1, 1, 10")?;
+        writeln!(file)?;
+        writeln!(file, "deposit, 70000, 2, 10")?;
+
+        let path = NamedTempFile::into_temp_path(file);
+        let mut rdr = CsvTransactionReader::from_path(&path)?;
+        let errors: Vec<_> = rdr
+            .read()
+            .filter_map(|result| result.err().map(|err| err.to_string()))
+            .collect();
+
+        assert_eq!(
+            vec![
+                "CSV deserialize error: client id 70000 out of range 0..=65535 at line 4: \
+                deposit,70000,2,10"
+            ],
+            errors
+        );
+
+        Ok(())
+    }
+
+    #[test_case(
+        "type;client;tx;amount\ndeposit;1;1;10\nwithdrawal;1;2;5\n",
+        CsvReaderOptions { delimiter: b';', ..Default::default() };
+        "when semicolon delimited"
+    )]
+    #[test_case(
+        "deposit,1,1,10\nwithdrawal,1,2,5\n",
+        CsvReaderOptions { has_headers: false, ..Default::default() };
+        "when no headers"
+    )]
+    #[test_case(
+        "10;1;deposit;1\n5;2;withdrawal;1\n",
+        CsvReaderOptions {
+            delimiter: b';',
+            has_headers: false,
+            columns: Some(vec!["amount".into(), "tx".into(), "type".into(), "client".into()]),
+        };
+        "when no headers and columns reordered"
+    )]
+    #[test_case(
+        "Betrag;Transaktion;Typ;Kunde\n10;1;deposit;1\n5;2;withdrawal;1\n",
+        CsvReaderOptions {
+            delimiter: b';',
+            has_headers: true,
+            columns: Some(vec!["amount".into(), "tx".into(), "type".into(), "client".into()]),
+        };
+        "when headers replaced by columns"
+    )]
+    fn test_read_with_options(input: &str, options: CsvReaderOptions) -> Result<()> {
+        let mut file = NamedTempFile::new()?;
+        write!(file, "{}", input)?;
+
+        let path = NamedTempFile::into_temp_path(file);
+        let mut rdr = CsvTransactionReader::with_options(path, options)?;
+
+        assert_eq!(
+            vec![
+                TransactionRecord::deposit(ClientId(1), TransactionId(1), 10.into()),
+                TransactionRecord::withdrawal(ClientId(1), TransactionId(2), 5.into()),
+            ],
+            rdr.read().collect::<Result<Vec<_>>>()?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_lines_reader_read_with_lines() -> Result<()> {
+        let mut file = NamedTempFile::new()?;
+        let lines = [
+            r#"{"type":"deposit","client":1,"tx":1,"amount":"10.5"}"#,
+            "",
+            r#"{"type":"borrow","client":1,"tx":2,"amount":"1"}"#,
+            r#"{"type":"dispute","client":1,"tx":1}"#,
+        ];
+        // the last line has no line break
+        write!(file, "{}", lines.join("\n"))?;
+
+        let mut rdr = JsonLinesTransactionReader::from_path(file.path())?;
+        let records: Vec<_> = rdr.read_with_lines().collect();
+
+        assert_eq!(3, records.len());
+        assert_eq!(
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10.5)),
+            *records[0].0.as_ref().unwrap()
+        );
+        assert_eq!(1, records[0].1);
+        let err = records[1].0.as_ref().unwrap_err().to_string();
+        assert!(err.starts_with("JSON deserialize error"), "{}", err);
+        assert!(
+            err.ends_with(&format!("at line 3: {}", lines[2])),
+            "{}",
+            err
+        );
+        assert_eq!(
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+            *records[2].0.as_ref().unwrap()
+        );
+        assert_eq!(4, records[2].1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_vec_reader_yields_records_once() -> Result<()> {
+        let records = vec![
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), 10.into()),
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+        ];
+        let mut rdr = VecTransactionReader::new(records.clone());
+
+        assert_eq!(records, rdr.read().collect::<Result<Vec<_>>>()?);
+        assert_eq!(0, rdr.read().count());
+
+        Ok(())
+    }
+
     #[test]
     #[should_panic(expected = "No such file or directory")]
     fn test_from_path_when_no_such_file() {
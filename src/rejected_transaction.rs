@@ -0,0 +1,122 @@
+//! Serdes for rejected transactions
+
+use rust_decimal::Decimal;
+use serde::{Serialize, Serializer};
+
+use crate::{
+    ClientId, RecordOutcome, SkipReason, TransactionId, TransactionRecord, TransactionType,
+};
+
+/// Why a record was rejected, serialized as a stable snake case value such as `malformed` or
+/// `insufficient_funds`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RejectionReason {
+    /// The record could not be read, or was not a valid transaction.
+    Malformed,
+    /// The transaction was valid but could not be applied.
+    Skipped(SkipReason),
+}
+
+impl Serialize for RejectionReason {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            RejectionReason::Malformed => serializer.serialize_str("malformed"),
+            RejectionReason::Skipped(reason) => reason.serialize(serializer),
+        }
+    }
+}
+
+/// A record which was not applied, with the line of the input it was read from.
+///
+/// The fields of a record which could not be read are empty.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RejectedTransaction {
+    line: u64,
+    #[serde(rename = "type")]
+    transaction_type: Option<TransactionType>,
+    client: Option<ClientId>,
+    tx: Option<TransactionId>,
+    amount: Option<Decimal>,
+    reason: RejectionReason,
+}
+
+impl RejectedTransaction {
+    /// Create a rejected transaction, from the record read and what happened to it.
+    ///
+    /// Returns `None` if the record was applied.
+    pub fn new(
+        line: u64,
+        record: Option<&TransactionRecord>,
+        outcome: &RecordOutcome,
+    ) -> Option<Self> {
+        let reason = match outcome {
+            RecordOutcome::Applied(_) => return None,
+            RecordOutcome::Malformed(_) | RecordOutcome::ReadError(_) => RejectionReason::Malformed,
+            RecordOutcome::Skipped(event) => RejectionReason::Skipped(event.reason),
+        };
+        Some(RejectedTransaction {
+            line,
+            transaction_type: record.map(|record| record.transaction_type),
+            client: record.map(|record| record.client),
+            tx: record.map(|record| record.tx),
+            amount: record.and_then(|record| record.amount),
+            reason,
+        })
+    }
+
+    /// The line of the input the record was read from
+    pub fn line(&self) -> u64 {
+        self.line
+    }
+
+    /// Why the record was rejected
+    pub fn reason(&self) -> RejectionReason {
+        self.reason
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use rust_decimal_macros::dec;
+
+    use crate::{SkipEvent, Transaction, Withdrawal};
+
+    use super::*;
+
+    #[test]
+    fn test_new_when_applied() {
+        let record = TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(1));
+        let outcome = RecordOutcome::Applied(TransactionType::Deposit);
+        assert_eq!(None, RejectedTransaction::new(2, Some(&record), &outcome));
+    }
+
+    #[test]
+    fn test_serialize_csv() -> Result<()> {
+        let record = TransactionRecord::withdrawal(ClientId(1), TransactionId(2), dec!(1.5));
+        let transaction = Transaction::Withdrawal(Withdrawal {
+            client: ClientId(1),
+            tx: TransactionId(2),
+            amount: dec!(1.5),
+        });
+        let skipped =
+            RecordOutcome::Skipped(SkipEvent::new(&transaction, SkipReason::InsufficientFunds));
+        let malformed = RecordOutcome::Malformed("Malformed transaction".to_string());
+        let unreadable = RecordOutcome::ReadError("Could not read transaction record".to_string());
+
+        let mut wtr = csv::Writer::from_writer(vec![]);
+        wtr.serialize(RejectedTransaction::new(3, Some(&record), &skipped).unwrap())?;
+        wtr.serialize(RejectedTransaction::new(4, None, &malformed).unwrap())?;
+        wtr.serialize(RejectedTransaction::new(5, None, &unreadable).unwrap())?;
+
+        let expected = "\
+            line,type,client,tx,amount,reason\n\
+            3,withdrawal,1,2,1.5,insufficient_funds\n\
+            4,,,,,malformed\n\
+            5,,,,,malformed\n\
+        ";
+        assert_eq!(expected, String::from_utf8(wtr.into_inner()?)?);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,283 @@
+//! Reports of what happened to the records processed.
+
+use std::{collections::HashMap, fmt};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{ProcessingPolicies, SkipEvent, SkipReason, TransactionType};
+
+/// What happened to a single record when it was processed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordOutcome {
+    /// The transaction was applied.
+    Applied(TransactionType),
+    /// The record was read but is not a valid transaction, such as a deposit without an amount.
+    Malformed(String),
+    /// The record could not be read, such as a row which is not valid CSV or an I/O error.
+    ReadError(String),
+    /// The transaction was valid but could not be applied.
+    Skipped(SkipEvent),
+}
+
+/// An error processing a record, and the record it was found in.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RecordError {
+    /// Number of the record in the input processed, starting at 1.
+    pub record: u64,
+    /// Description of the error.
+    pub message: String,
+}
+
+/// Counts of what happened to the records processed, such as by
+/// [`TransactionProcessor::process`](crate::TransactionProcessor::process).
+///
+/// Only the first [`ProcessingReport::MAX_ERRORS`] errors are kept, so a report stays small
+/// however bad the input.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ProcessingReport {
+    /// Number of records read, whatever happened to them.
+    pub records: u64,
+    /// Number of transactions applied, by type.
+    pub applied: HashMap<TransactionType, u64>,
+    /// Number of records which were read but were not valid transactions.
+    pub malformed: u64,
+    /// Number of records which could not be read.
+    #[serde(default)]
+    pub read_errors: u64,
+    /// Number of transactions which could not be applied, by reason.
+    pub skipped: HashMap<SkipReason, u64>,
+    /// The first errors, whether malformed records or skipped transactions.
+    pub errors: Vec<RecordError>,
+    /// The record at which processing stopped, if malformed in strict mode, or a duplicate
+    /// transaction with [`DuplicatePolicy::Error`](crate::DuplicatePolicy::Error).
+    #[serde(default)]
+    pub aborted: Option<RecordError>,
+    /// The policies the records were processed with.
+    #[serde(default)]
+    pub policies: ProcessingPolicies,
+}
+
+impl ProcessingReport {
+    /// The most errors kept in a report.
+    pub const MAX_ERRORS: usize = 10;
+
+    /// Create an empty report.
+    pub fn new() -> Self {
+        ProcessingReport::default()
+    }
+
+    /// Records the policies the records are processed with.
+    pub fn with_policies(mut self, policies: ProcessingPolicies) -> Self {
+        self.policies = policies;
+        self
+    }
+
+    /// Counts the outcome of the next record.
+    pub fn add(&mut self, outcome: &RecordOutcome) {
+        self.add_numbered(self.records + 1, outcome);
+    }
+
+    /// Counts the outcome of a record, numbered by its position in the whole input rather than
+    /// among the records in this report.
+    pub(crate) fn add_numbered(&mut self, record: u64, outcome: &RecordOutcome) {
+        self.records += 1;
+        let message = match outcome {
+            RecordOutcome::Applied(transaction_type) => {
+                *self.applied.entry(*transaction_type).or_default() += 1;
+                return;
+            }
+            RecordOutcome::Malformed(message) => {
+                self.malformed += 1;
+                message.clone()
+            }
+            RecordOutcome::ReadError(message) => {
+                self.read_errors += 1;
+                message.clone()
+            }
+            RecordOutcome::Skipped(event) => {
+                *self.skipped.entry(event.reason).or_default() += 1;
+                match &event.detail {
+                    Some(detail) => format!(
+                        "{:?} {} for client {}: {}: {}",
+                        event.transaction_type, event.tx, event.client, event.reason, detail
+                    ),
+                    None => format!(
+                        "{:?} {} for client {}: {}",
+                        event.transaction_type, event.tx, event.client, event.reason
+                    ),
+                }
+            }
+        };
+        if self.errors.len() < Self::MAX_ERRORS {
+            self.errors.push(RecordError { record, message });
+        }
+    }
+
+    /// Adds the counts of another report, such as from another part of the same input, keeping
+    /// the first errors of both, and its policies.
+    pub fn merge(&mut self, other: ProcessingReport) {
+        self.policies = other.policies;
+        self.records += other.records;
+        for (transaction_type, count) in other.applied {
+            *self.applied.entry(transaction_type).or_default() += count;
+        }
+        self.malformed += other.malformed;
+        self.read_errors += other.read_errors;
+        for (reason, count) in other.skipped {
+            *self.skipped.entry(reason).or_default() += count;
+        }
+        self.errors.extend(other.errors);
+        self.errors.sort_by_key(|error| error.record);
+        self.errors.truncate(Self::MAX_ERRORS);
+        if let Some(aborted) = other.aborted {
+            if self
+                .aborted
+                .as_ref()
+                .is_none_or(|error| aborted.record < error.record)
+            {
+                self.aborted = Some(aborted);
+            }
+        }
+    }
+
+    /// Returns the report, or an error describing the record at which processing stopped.
+    pub fn into_result(self) -> Result<Self> {
+        match &self.aborted {
+            Some(error) => bail!("Stopped at record {}: {}", error.record, error.message),
+            None => Ok(self),
+        }
+    }
+
+    /// Returns the number of transactions of the given type applied.
+    pub fn applied(&self, transaction_type: TransactionType) -> u64 {
+        self.applied.get(&transaction_type).copied().unwrap_or(0)
+    }
+
+    /// Returns the number of transactions skipped for the given reason.
+    pub fn skipped(&self, reason: SkipReason) -> u64 {
+        self.skipped.get(&reason).copied().unwrap_or(0)
+    }
+}
+
+impl fmt::Display for ProcessingReport {
+    /// Summarises the counts, such as
+    /// `Processed 6 records: 3 applied, 1 malformed, 1 unreadable, 1 skipped (Insufficient funds
+    /// available: 1)`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let skipped: u64 = self.skipped.values().sum();
+        write!(
+            f,
+            "Processed {} records: {} applied, {} malformed, {} unreadable, {} skipped",
+            self.records,
+            self.applied.values().sum::<u64>(),
+            self.malformed,
+            self.read_errors,
+            skipped
+        )?;
+        if skipped > 0 {
+            let mut reasons: Vec<_> = self
+                .skipped
+                .iter()
+                .map(|(reason, count)| format!("{}: {}", reason, count))
+                .collect();
+            reasons.sort_unstable();
+            write!(f, " ({})", reasons.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ClientId, Transaction, TransactionId, Withdrawal};
+
+    use super::*;
+
+    fn skipped(reason: SkipReason) -> RecordOutcome {
+        let withdrawal = Transaction::Withdrawal(Withdrawal {
+            client: ClientId(1),
+            tx: TransactionId(2),
+            amount: 5.into(),
+        });
+        RecordOutcome::Skipped(SkipEvent::new(&withdrawal, reason))
+    }
+
+    #[test]
+    fn test_add() {
+        let mut report = ProcessingReport::new();
+        report.add(&RecordOutcome::Applied(TransactionType::Deposit));
+        report.add(&RecordOutcome::Malformed("bad record".to_string()));
+        report.add(&skipped(SkipReason::InsufficientFunds));
+        report.add(&RecordOutcome::Applied(TransactionType::Deposit));
+        report.add(&RecordOutcome::ReadError("bad row".to_string()));
+
+        assert_eq!(5, report.records);
+        assert_eq!(2, report.applied(TransactionType::Deposit));
+        assert_eq!(0, report.applied(TransactionType::Withdrawal));
+        assert_eq!(1, report.malformed);
+        assert_eq!(1, report.read_errors);
+        assert_eq!(1, report.skipped(SkipReason::InsufficientFunds));
+        assert_eq!(
+            vec![
+                RecordError {
+                    record: 2,
+                    message: "bad record".to_string()
+                },
+                RecordError {
+                    record: 3,
+                    message: "Withdrawal 2 for client 1: Insufficient funds available".to_string()
+                },
+                RecordError {
+                    record: 5,
+                    message: "bad row".to_string()
+                },
+            ],
+            report.errors
+        );
+        assert_eq!(
+            "Processed 5 records: 2 applied, 1 malformed, 1 unreadable, 1 skipped \
+            (Insufficient funds available: 1)",
+            report.to_string()
+        );
+    }
+
+    #[test]
+    fn test_add_keeps_first_errors() {
+        let mut report = ProcessingReport::new();
+        for _ in 0..ProcessingReport::MAX_ERRORS + 5 {
+            report.add(&skipped(SkipReason::AccountLocked));
+        }
+
+        assert_eq!(15, report.skipped(SkipReason::AccountLocked));
+        assert_eq!(ProcessingReport::MAX_ERRORS, report.errors.len());
+        assert_eq!(10, report.errors.last().unwrap().record);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut report = ProcessingReport::new();
+        report.add_numbered(1, &RecordOutcome::Applied(TransactionType::Deposit));
+        report.add_numbered(4, &skipped(SkipReason::InsufficientFunds));
+        let mut other = ProcessingReport::new();
+        other.add_numbered(2, &RecordOutcome::Applied(TransactionType::Deposit));
+        other.add_numbered(3, &RecordOutcome::Malformed("bad record".to_string()));
+        other.add_numbered(5, &RecordOutcome::ReadError("bad row".to_string()));
+
+        report.merge(other);
+
+        assert_eq!(5, report.records);
+        assert_eq!(2, report.applied(TransactionType::Deposit));
+        assert_eq!(1, report.malformed);
+        assert_eq!(1, report.read_errors);
+        assert_eq!(1, report.skipped(SkipReason::InsufficientFunds));
+        assert_eq!(
+            vec![3, 4, 5],
+            report
+                .errors
+                .iter()
+                .map(|error| error.record)
+                .collect::<Vec<_>>()
+        );
+    }
+}
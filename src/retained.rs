@@ -0,0 +1,176 @@
+//! Deposits and withdrawals retained so that they can later be disputed.
+
+use std::{collections::VecDeque, path::Path};
+
+use anyhow::Result;
+
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{spill::SpillMap, ClientId, Deposit, TransactionId, Withdrawal};
+
+/// Whether a retained transaction paid funds into or out of the account.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Direction {
+    /// A deposit, which paid funds in.
+    #[default]
+    Credit,
+    /// A withdrawal, which paid funds out.
+    Debit,
+}
+
+/// The detail of a deposit or withdrawal needed to dispute it.
+///
+/// The transaction ID is the key it is retained under so it is not repeated here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) struct RetainedTransaction {
+    pub(crate) client: ClientId,
+    pub(crate) amount: Decimal,
+    pub(crate) timestamp: Option<DateTime<Utc>>,
+    /// Defaults to a deposit, as only deposits were retained by earlier checkpoints.
+    #[serde(default)]
+    pub(crate) direction: Direction,
+}
+
+impl From<&Deposit> for RetainedTransaction {
+    fn from(deposit: &Deposit) -> Self {
+        RetainedTransaction {
+            client: deposit.client,
+            amount: deposit.amount,
+            timestamp: deposit.timestamp,
+            direction: Direction::Credit,
+        }
+    }
+}
+
+impl From<&Withdrawal> for RetainedTransaction {
+    fn from(withdrawal: &Withdrawal) -> Self {
+        RetainedTransaction {
+            client: withdrawal.client,
+            amount: withdrawal.amount,
+            timestamp: None,
+            direction: Direction::Debit,
+        }
+    }
+}
+
+/// Transactions retained for dispute handling, optionally limited to the most recent.
+///
+/// When limited the order transactions were retained in is also tracked, so the oldest can be
+/// evicted once the limit is exceeded. Transactions may also be spilled to disk, when
+/// [`RetainedTransactions::set_spill_dir`] is set, and are then still retained.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct RetainedTransactions {
+    #[serde(rename = "deposits")]
+    transactions: SpillMap<RetainedTransaction>,
+    order: VecDeque<TransactionId>,
+    #[serde(skip)]
+    max: Option<usize>,
+}
+
+impl RetainedTransactions {
+    /// Limit the number of transactions retained, evicting the oldest if there are already more.
+    ///
+    /// If the order transactions were retained in was not tracked, as they were not limited,
+    /// they are assumed to have been retained in order of transaction ID.
+    pub(crate) fn set_max(&mut self, max: Option<usize>) {
+        self.max = max;
+        match max {
+            Some(_) if self.order.len() != self.transactions.len() => {
+                let mut order: Vec<_> = self.transactions.keys().collect();
+                order.sort_unstable_by_key(|tx| tx.0);
+                self.order = order.into();
+            }
+            Some(_) => {}
+            None => self.order = VecDeque::new(),
+        }
+        self.evict();
+    }
+
+    /// Spill transactions beyond `max_in_memory` to a file in `dir`.
+    pub(crate) fn set_spill_dir(&mut self, dir: &Path, max_in_memory: usize) -> Result<()> {
+        self.transactions.set_spill_dir(dir, max_in_memory)
+    }
+
+    /// Retain a transaction, evicting the oldest if the limit is exceeded.
+    pub(crate) fn insert(&mut self, tx: TransactionId, transaction: RetainedTransaction) {
+        let replaced = self.transactions.contains_key(&tx);
+        self.transactions.insert(tx, transaction);
+        if self.max.is_some() && !replaced {
+            self.order.push_back(tx);
+            self.evict();
+        }
+    }
+
+    /// Returns the transaction, if it is retained, reading it from disk if it was spilled.
+    pub(crate) fn get(&mut self, tx: &TransactionId) -> Result<Option<RetainedTransaction>> {
+        Ok(self.transactions.get(tx)?.copied())
+    }
+
+    fn evict(&mut self) {
+        if let Some(max) = self.max {
+            while self.order.len() > max {
+                if let Some(tx) = self.order.pop_front() {
+                    self.transactions.remove(&tx);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    fn deposit(client: u16) -> RetainedTransaction {
+        RetainedTransaction {
+            client: ClientId(client),
+            amount: dec!(10),
+            timestamp: None,
+            direction: Direction::Credit,
+        }
+    }
+
+    #[test]
+    fn test_insert_when_unlimited_retains_all() {
+        let mut deposits = RetainedTransactions::default();
+        for tx in 1..=100 {
+            deposits.insert(TransactionId(tx), deposit(1));
+        }
+        assert!((1..=100).all(|tx| deposits.get(&TransactionId(tx)).unwrap().is_some()));
+        assert!(deposits.order.is_empty());
+    }
+
+    #[test]
+    fn test_insert_when_limited_evicts_oldest() {
+        let mut deposits = RetainedTransactions::default();
+        deposits.set_max(Some(2));
+        deposits.insert(TransactionId(3), deposit(1));
+        deposits.insert(TransactionId(1), deposit(2));
+        deposits.insert(TransactionId(1), deposit(3));
+        assert_eq!(Some(deposit(3)), deposits.get(&TransactionId(1)).unwrap());
+        assert!(deposits.get(&TransactionId(3)).unwrap().is_some());
+
+        deposits.insert(TransactionId(2), deposit(4));
+        assert!(deposits.get(&TransactionId(3)).unwrap().is_none());
+        assert!(deposits.get(&TransactionId(1)).unwrap().is_some());
+        assert!(deposits.get(&TransactionId(2)).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_set_max_when_not_tracked_evicts_lowest_transaction_ids() {
+        let mut deposits = RetainedTransactions::default();
+        for tx in [5, 2, 9, 7] {
+            deposits.insert(TransactionId(tx), deposit(1));
+        }
+        deposits.set_max(Some(2));
+
+        let mut retained: Vec<_> = deposits.transactions.keys().map(|tx| tx.0).collect();
+        retained.sort_unstable();
+        assert_eq!(vec![7, 9], retained);
+    }
+}
@@ -0,0 +1,354 @@
+//! Processing transactions concurrently, with clients partitioned between worker threads.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        mpsc::{self, Receiver, Sender, SyncSender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    AccountStore, AccountSummary, AccountWriter, ClientId, ProcessingReport, SummaryStatistics,
+    Transaction, TransactionId, TransactionProcessor, TransactionReader, TransactionRecord,
+    TransactionType, VecAccountWriter,
+};
+
+/// Number of messages queued for each shard before the reader waits for it to catch up.
+const CHANNEL_CAPACITY: usize = 1024;
+
+enum Message {
+    /// A record, numbered by its position in the input, and the line it was read from.
+    Record(u64, u64, Result<TransactionRecord>),
+    /// A transaction ID used by a deposit or withdrawal of a client in another shard.
+    Reserve(TransactionId),
+    /// A request for the report of the records processed since the last request.
+    Report(Sender<ProcessingReport>),
+}
+
+/// The accounts and statistics exported by a shard once its input is closed.
+type ShardExport = Result<(Vec<AccountSummary>, SummaryStatistics)>;
+
+struct Shard {
+    sender: SyncSender<Message>,
+    worker: JoinHandle<ShardExport>,
+}
+
+/// Processes transactions on a number of worker threads, each with its own
+/// [`TransactionProcessor`] and so its own store, deposits and disputes.
+///
+/// Transactions only ever affect the account of their client, so each client is assigned to
+/// one shard and their transactions are processed in the order they were read. Records are fed
+/// to the shards over bounded channels, so a slow shard holds up reading rather than buffering
+/// the input in memory.
+///
+/// Transaction IDs are unique across clients, so a deposit or withdrawal reusing the ID of
+/// another client's deposit or withdrawal is still skipped as a duplicate. The accounts
+/// exported are the same as processing the records in order with a single processor, however a
+/// dispute of another client's transaction is skipped as an unknown transaction rather than as
+/// the wrong client, since the shard never saw it.
+///
+/// ```no_run
+/// use rusty_bank::{
+///     CsvAccountWriter, CsvTransactionReader, InMemoryAccountStore, ShardedTransactionProcessor,
+///     TransactionProcessor,
+/// };
+///
+/// let mut processor = ShardedTransactionProcessor::new(4, || {
+///     Ok(TransactionProcessor::new(InMemoryAccountStore::new()))
+/// });
+/// let report = processor.process(CsvTransactionReader::from_path("transactions.csv")?);
+/// println!("{}", report);
+/// processor.export(CsvAccountWriter::from_writer(std::io::stdout()))?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub struct ShardedTransactionProcessor {
+    shards: Vec<Shard>,
+    /// The shard of the client whose deposit or withdrawal first used each transaction ID.
+    owners: HashMap<TransactionId, usize>,
+    records: u64,
+}
+
+impl ShardedTransactionProcessor {
+    /// Create a processor with the given number of shards, each running on its own thread.
+    ///
+    /// Each shard's processor is created on its thread, so it can be configured as usual.
+    /// Shards must not share a journal or spill directory.
+    ///
+    /// ### Parameters
+    /// - shards: The number of shards, which must be at least one.
+    /// - factory: Creates the processor of a shard.
+    pub fn new<S, F>(shards: usize, factory: F) -> Self
+    where
+        S: AccountStore + 'static,
+        F: Fn() -> Result<TransactionProcessor<S>> + Send + Sync + 'static,
+    {
+        assert!(shards > 0, "At least one shard is required");
+        let factory = Arc::new(factory);
+        let shards = (0..shards)
+            .map(|_| {
+                let (sender, receiver) = mpsc::sync_channel(CHANNEL_CAPACITY);
+                let factory = Arc::clone(&factory);
+                let worker = thread::spawn(move || run_shard(factory(), receiver));
+                Shard { sender, worker }
+            })
+            .collect();
+        ShardedTransactionProcessor {
+            shards,
+            owners: HashMap::new(),
+            records: 0,
+        }
+    }
+
+    /// Process transactions read from the reader, shared between the shards by client.
+    ///
+    /// Returns once every shard has processed its transactions, with the combined
+    /// [`ProcessingReport`].
+    ///
+    /// ### Parameters
+    /// - reader: The implementation of the transaction reader.
+    pub fn process(&mut self, mut reader: impl TransactionReader) -> ProcessingReport {
+        for (result, line) in reader.read_with_line_numbers() {
+            self.dispatch(result, line);
+        }
+        self.report()
+    }
+
+    /// Send a record to the shard of its client.
+    fn dispatch(&mut self, result: Result<TransactionRecord>, line: u64) {
+        self.records += 1;
+        let shard = match &result {
+            Ok(record) => self.shard(record.client),
+            // an unreadable record has no client, but still needs to be counted and logged once
+            Err(_) => 0,
+        };
+        if let Ok(record) = &result {
+            if is_well_formed_deposit_or_withdrawal(record) {
+                let owner = *self.owners.entry(record.tx).or_insert(shard);
+                if owner != shard {
+                    self.send(shard, Message::Reserve(record.tx));
+                }
+            }
+        }
+        self.send(shard, Message::Record(self.records, line, result));
+    }
+
+    fn shard(&self, client: ClientId) -> usize {
+        client.0 as usize % self.shards.len()
+    }
+
+    /// Sends a message to a shard, unless it has stopped, in which case its error is returned
+    /// by [`ShardedTransactionProcessor::export`].
+    fn send(&self, shard: usize, message: Message) {
+        let _ = self.shards[shard].sender.send(message);
+    }
+
+    /// Waits for each shard to process the records sent, and combines their reports.
+    fn report(&self) -> ProcessingReport {
+        let (sender, receiver) = mpsc::channel();
+        for shard in 0..self.shards.len() {
+            self.send(shard, Message::Report(sender.clone()));
+        }
+        drop(sender);
+        receiver
+            .into_iter()
+            .fold(ProcessingReport::new(), |mut report, shard_report| {
+                report.merge(shard_report);
+                report
+            })
+    }
+
+    /// Export the accounts of every shard, along with an empty account for untouched clients
+    /// if configured, in ascending client order.
+    ///
+    /// Returns the combined [`SummaryStatistics`] of the accounts written and transactions
+    /// processed.
+    ///
+    /// ### Parameters
+    /// - writer: The implementation of the account writer.
+    pub fn export(self, mut writer: impl AccountWriter) -> Result<SummaryStatistics> {
+        // every input is closed first so that the shards export concurrently
+        let workers: Vec<_> = self
+            .shards
+            .into_iter()
+            .map(|Shard { sender, worker }| {
+                drop(sender);
+                worker
+            })
+            .collect();
+
+        let mut statistics = SummaryStatistics::default();
+        let mut accounts = Vec::new();
+        for worker in workers {
+            let (shard_accounts, shard_statistics) = worker
+                .join()
+                .map_err(|_| anyhow!("A shard stopped unexpectedly"))??;
+            accounts.extend(shard_accounts);
+            statistics.merge(&shard_statistics);
+        }
+        // each client belongs to one shard, so sorting orders every account
+        accounts.sort_unstable_by_key(AccountSummary::client);
+        for account in accounts {
+            writer.write(&account)?;
+        }
+        writer.finish()?;
+        Ok(statistics)
+    }
+}
+
+/// Whether the record is a deposit or withdrawal which will use its transaction ID when
+/// processed.
+fn is_well_formed_deposit_or_withdrawal(record: &TransactionRecord) -> bool {
+    matches!(
+        record.transaction_type,
+        TransactionType::Deposit | TransactionType::Withdrawal
+    ) && Result::<Transaction>::from(record.clone()).is_ok()
+}
+
+/// Processes the messages sent to a shard until its input is closed, then exports its accounts.
+fn run_shard<S: AccountStore>(
+    processor: Result<TransactionProcessor<S>>,
+    receiver: Receiver<Message>,
+) -> ShardExport {
+    let mut processor = processor?;
+    let mut report = ProcessingReport::new().with_policies(processor.policies());
+    for message in receiver {
+        match message {
+            Message::Record(number, line, result) => {
+                report.add_numbered(number, &processor.process_line(result, line))
+            }
+            Message::Reserve(tx) => processor.reserve_transaction_id(tx),
+            Message::Report(sender) => {
+                let _ = sender.send(std::mem::take(&mut report));
+            }
+        }
+    }
+    let mut writer = VecAccountWriter::new();
+    let statistics = processor.export(&mut writer)?;
+    Ok((writer.into_accounts(), statistics))
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use crate::{InMemoryAccountStore, SkipReason, TransactionType, VecTransactionReader};
+
+    use super::*;
+
+    fn record(
+        transaction_type: TransactionType,
+        client: u16,
+        tx: u32,
+        amount: Option<rust_decimal::Decimal>,
+    ) -> TransactionRecord {
+        TransactionRecord::new(
+            transaction_type,
+            ClientId(client),
+            TransactionId(tx),
+            amount,
+        )
+    }
+
+    fn processor(shards: usize) -> ShardedTransactionProcessor {
+        ShardedTransactionProcessor::new(shards, || {
+            Ok(TransactionProcessor::new(InMemoryAccountStore::new()))
+        })
+    }
+
+    fn export(processor: ShardedTransactionProcessor) -> Result<Vec<AccountSummary>> {
+        let mut writer = VecAccountWriter::new();
+        processor.export(&mut writer)?;
+        Ok(writer.into_accounts())
+    }
+
+    #[test]
+    fn test_process_when_transaction_id_reused_by_other_shard() -> Result<()> {
+        let mut processor = processor(2);
+        let report = processor.process(VecTransactionReader::new(vec![
+            record(TransactionType::Deposit, 1, 1, Some(dec!(10))),
+            record(TransactionType::Deposit, 2, 1, Some(dec!(5))),
+            record(TransactionType::Deposit, 2, 2, Some(dec!(3))),
+        ]));
+
+        assert_eq!(3, report.records);
+        assert_eq!(2, report.applied(TransactionType::Deposit));
+        assert_eq!(1, report.skipped(SkipReason::DuplicateTransaction));
+        assert_eq!(2, report.errors[0].record);
+        assert_eq!(
+            vec![
+                AccountSummary::new(ClientId(1), dec!(0), dec!(10), false).unwrap(),
+                AccountSummary::new(ClientId(2), dec!(0), dec!(3), false).unwrap(),
+            ],
+            export(processor)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_combines_statistics() -> Result<()> {
+        let mut processor = processor(3);
+        processor.process(VecTransactionReader::new(
+            (1..=6)
+                .map(|client| {
+                    record(
+                        TransactionType::Deposit,
+                        client,
+                        client as u32,
+                        Some(dec!(2)),
+                    )
+                })
+                .collect(),
+        ));
+
+        let statistics = processor.export(VecAccountWriter::new())?;
+
+        assert_eq!(6, statistics.clients);
+        assert_eq!(6, statistics.deposits);
+        assert_eq!(dec!(12), statistics.total);
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_in_client_order() -> Result<()> {
+        let mut processor = processor(3);
+        processor.process(VecTransactionReader::new(
+            [9, 4, 1, 8, 3, 2]
+                .into_iter()
+                .map(|client| {
+                    record(
+                        TransactionType::Deposit,
+                        client,
+                        client as u32,
+                        Some(dec!(1)),
+                    )
+                })
+                .collect(),
+        ));
+
+        let clients: Vec<_> = export(processor)?
+            .iter()
+            .map(AccountSummary::client)
+            .collect();
+        assert_eq!([1, 2, 3, 4, 8, 9].map(ClientId).to_vec(), clients);
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_when_shard_cannot_be_created() {
+        let processor = ShardedTransactionProcessor::new(2, || {
+            Err::<TransactionProcessor<InMemoryAccountStore>, _>(anyhow!("no spill dir"))
+        });
+
+        assert_eq!(
+            "no spill dir",
+            processor
+                .export(VecAccountWriter::new())
+                .unwrap_err()
+                .to_string()
+        );
+    }
+}
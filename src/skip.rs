@@ -0,0 +1,193 @@
+//! Serdes for skipped transactions
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{client::ClientId, AccountError, Transaction, TransactionId, TransactionType};
+
+/// The reason a transaction was skipped rather than applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// The referenced transaction does not exist.
+    UnknownTransaction,
+    /// The client does not match the client of the referenced transaction.
+    ClientMismatch,
+    /// A dispute case already exists for the referenced transaction.
+    DuplicateDispute,
+    /// No dispute case exists for the referenced transaction.
+    UnknownDispute,
+    /// The dispute case for the referenced transaction has already been closed.
+    DisputeClosed,
+    /// The dispute case for the referenced transaction was cancelled when the account was locked.
+    DisputeCancelled,
+    /// The dispute was raised too long after the referenced transaction.
+    DisputeExpired,
+    /// Administrative transactions have not been allowed.
+    AdminOpsNotAllowed,
+    /// The client's account is locked.
+    AccountLocked,
+    /// The client's account does not have sufficient available funds.
+    InsufficientFunds,
+    /// The client's account is not locked.
+    AccountNotLocked,
+    /// The client does not have an account.
+    UnknownAccount,
+    /// The account store failed for any other reason.
+    StoreError,
+    /// The transaction is already in the journal of processed transactions.
+    AlreadyProcessed,
+    /// The referenced transaction was spilled to disk and could not be read back.
+    SpillError,
+    /// A [`TransactionValidator`](crate::TransactionValidator) rejected the transaction.
+    Rejected,
+    /// An earlier deposit or withdrawal already used the transaction ID.
+    DuplicateTransaction,
+    /// The referenced transaction is a withdrawal, and disputes of withdrawals have not been
+    /// allowed.
+    WithdrawalDisputesNotAllowed,
+    /// Applying the transaction would overflow the client's balances.
+    BalanceOverflow,
+}
+
+impl From<&anyhow::Error> for SkipReason {
+    /// Classifies an error returned by an [`AccountStore`](crate::AccountStore).
+    fn from(err: &anyhow::Error) -> Self {
+        match err.downcast_ref::<AccountError>() {
+            Some(AccountError::Locked(_)) => SkipReason::AccountLocked,
+            Some(AccountError::InsufficientFunds { .. }) => SkipReason::InsufficientFunds,
+            Some(AccountError::NotLocked(_)) => SkipReason::AccountNotLocked,
+            Some(AccountError::NoSuchAccount(_)) => SkipReason::UnknownAccount,
+            Some(AccountError::Overflow { .. }) | Some(AccountError::BalanceOverflow(_)) => {
+                SkipReason::BalanceOverflow
+            }
+            Some(AccountError::InsufficientHeldFunds { .. })
+            | Some(AccountError::NegativeHeldFunds { .. })
+            | Some(AccountError::NonPositiveAmount { .. })
+            | None => SkipReason::StoreError,
+        }
+    }
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            SkipReason::UnknownTransaction => "No such transaction found",
+            SkipReason::ClientMismatch => "Client ID does not match",
+            SkipReason::DuplicateDispute => "A case already exists",
+            SkipReason::UnknownDispute => "No such dispute found",
+            SkipReason::DisputeClosed => "Case has already been closed",
+            SkipReason::DisputeCancelled => "Case was cancelled when the account was locked",
+            SkipReason::DisputeExpired => "Dispute window has expired",
+            SkipReason::AdminOpsNotAllowed => "Admin operations are not allowed",
+            SkipReason::AccountLocked => "Account is locked",
+            SkipReason::InsufficientFunds => "Insufficient funds available",
+            SkipReason::AccountNotLocked => "Account is not locked",
+            SkipReason::UnknownAccount => "No such account found",
+            SkipReason::StoreError => "Account store error",
+            SkipReason::AlreadyProcessed => "Transaction has already been processed",
+            SkipReason::SpillError => "Spilled transaction could not be read",
+            SkipReason::Rejected => "Rejected by a validation rule",
+            SkipReason::DuplicateTransaction => "Transaction ID has already been used",
+            SkipReason::WithdrawalDisputesNotAllowed => "Disputes of withdrawals are not allowed",
+            SkipReason::BalanceOverflow => "Balance would overflow",
+        };
+        f.write_str(message)
+    }
+}
+
+/// Machine-readable record of a skipped transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SkipEvent {
+    pub tx: TransactionId,
+    pub client: ClientId,
+    #[serde(rename = "type")]
+    pub transaction_type: TransactionType,
+    pub reason: SkipReason,
+    /// Further detail of the reason, such as why a validator rejected the transaction.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl SkipEvent {
+    /// Create an event for a transaction skipped for the given reason.
+    pub fn new(transaction: &Transaction, reason: SkipReason) -> Self {
+        SkipEvent {
+            tx: transaction.tx(),
+            client: transaction.client(),
+            transaction_type: transaction.transaction_type(),
+            reason,
+            detail: None,
+        }
+    }
+
+    /// Adds further detail of the reason.
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use anyhow::anyhow;
+    use rust_decimal_macros::dec;
+    use test_case::test_case;
+
+    use crate::Dispute;
+
+    #[test]
+    fn test_serde_json() -> anyhow::Result<()> {
+        let transaction = Transaction::Dispute(Dispute {
+            client: ClientId(2),
+            tx: TransactionId(7),
+            timestamp: None,
+        });
+        let event = SkipEvent::new(&transaction, SkipReason::UnknownTransaction);
+
+        let json = serde_json::to_string(&event)?;
+        assert_eq!(
+            r#"{"tx":7,"client":2,"type":"dispute","reason":"unknown_transaction"}"#,
+            json
+        );
+        assert_eq!(event, serde_json::from_str(&json)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_serde_json_with_detail() -> anyhow::Result<()> {
+        let transaction = Transaction::Dispute(Dispute {
+            client: ClientId(2),
+            tx: TransactionId(7),
+            timestamp: None,
+        });
+        let event =
+            SkipEvent::new(&transaction, SkipReason::Rejected).with_detail("Client 2 is denied");
+
+        let json = serde_json::to_string(&event)?;
+        assert_eq!(
+            r#"{"tx":7,"client":2,"type":"dispute","reason":"rejected","detail":"Client 2 is denied"}"#,
+            json
+        );
+        assert_eq!(event, serde_json::from_str(&json)?);
+
+        Ok(())
+    }
+
+    #[test_case(AccountError::Locked(ClientId(1)).into(),          SkipReason::AccountLocked;     "when locked")]
+    #[test_case(AccountError::InsufficientFunds { client: ClientId(1), amount: dec!(1) }.into(),
+                                                                 SkipReason::InsufficientFunds; "when insufficient funds")]
+    #[test_case(AccountError::NotLocked(ClientId(1)).into(),       SkipReason::AccountNotLocked;  "when not locked")]
+    #[test_case(AccountError::NoSuchAccount(ClientId(1)).into(),   SkipReason::UnknownAccount;    "when no such account")]
+    #[test_case(AccountError::Overflow { client: ClientId(1), amount: dec!(1) }.into(),
+                SkipReason::BalanceOverflow; "when overflow")]
+    #[test_case(AccountError::BalanceOverflow(ClientId(1)).into(), SkipReason::BalanceOverflow;   "when balances overflow")]
+    #[test_case(anyhow!("disk on fire"),                           SkipReason::StoreError;        "when other error")]
+    fn test_from_store_error(err: anyhow::Error, expected: SkipReason) {
+        assert_eq!(expected, SkipReason::from(&err));
+    }
+}
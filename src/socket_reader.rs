@@ -0,0 +1,230 @@
+use std::{
+    io::{self, BufRead, BufReader, ErrorKind, Read},
+    str::FromStr,
+};
+
+use anyhow::{bail, Error, Result};
+use csv::{ReaderBuilder, Trim};
+
+use crate::{TransactionReader, TransactionRecord};
+
+/// Encoding of the records in a stream.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StreamFormat {
+    /// CSV with a header row, one record per line.
+    #[default]
+    Csv,
+    /// One JSON object per line, with amounts as strings, such as `"amount": "1.5"`.
+    JsonLines,
+}
+
+impl FromStr for StreamFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "csv" => Ok(StreamFormat::Csv),
+            // `json` as for `--output-format`, whose JSON is also one object per line
+            "jsonl" | "json" => Ok(StreamFormat::JsonLines),
+            _ => bail!("Unknown stream format: {}", s),
+        }
+    }
+}
+
+/// Passes on only the complete lines of a stream.
+///
+/// A stream which ends, or is dropped, part way through a line has that partial line discarded
+/// with a warning, so it is never decoded as a truncated record.
+struct CompleteLines<R: Read> {
+    reader: BufReader<R>,
+    line: Vec<u8>,
+    consumed: usize,
+}
+
+impl<R: Read> CompleteLines<R> {
+    fn new(reader: R) -> Self {
+        CompleteLines {
+            reader: BufReader::new(reader),
+            line: Vec::new(),
+            consumed: 0,
+        }
+    }
+
+    /// Reads the next complete line, including its line break, or returns `false` at the end of
+    /// the stream.
+    fn next_line(&mut self) -> io::Result<bool> {
+        self.line.clear();
+        self.consumed = 0;
+        match self.reader.read_until(b'\n', &mut self.line) {
+            Ok(_) if self.line.is_empty() || self.line.ends_with(b"\n") => {
+                Ok(!self.line.is_empty())
+            }
+            Ok(_) => {
+                self.discard("the stream ended");
+                Ok(false)
+            }
+            Err(err) if is_disconnect(&err) => {
+                self.discard(&err.to_string());
+                Ok(false)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn discard(&mut self, cause: &str) {
+        if !self.line.is_empty() {
+            log::warn!(
+                "Discarding partial line, as {}: {:?}",
+                cause,
+                String::from_utf8_lossy(&self.line)
+            );
+        }
+        self.line.clear();
+    }
+}
+
+impl<R: Read> Read for CompleteLines<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.consumed == self.line.len() && !self.next_line()? {
+            return Ok(0);
+        }
+        let remaining = &self.line[self.consumed..];
+        let len = remaining.len().min(buf.len());
+        buf[..len].copy_from_slice(&remaining[..len]);
+        self.consumed += len;
+        Ok(len)
+    }
+}
+
+/// Whether an error means the other end of the stream has gone away.
+fn is_disconnect(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::BrokenPipe
+            | ErrorKind::UnexpectedEof
+    )
+}
+
+enum Decoder<R: Read> {
+    Csv(csv::Reader<CompleteLines<R>>),
+    JsonLines(CompleteLines<R>),
+}
+
+/// Transaction reader for a stream, such as a socket connection.
+///
+/// Records are read as they arrive, until the stream ends, so reading blocks while waiting
+/// for more. Each record must be on a single line.
+pub struct SocketTransactionReader<R: Read> {
+    decoder: Decoder<R>,
+}
+
+impl<R: Read> SocketTransactionReader<R> {
+    /// Create a new reader over any [`Read`] stream, decoding records in the given format.
+    pub fn from_reader(rdr: R, format: StreamFormat) -> Self {
+        let lines = CompleteLines::new(rdr);
+        let decoder = match format {
+            StreamFormat::Csv => {
+                Decoder::Csv(ReaderBuilder::new().trim(Trim::All).from_reader(lines))
+            }
+            StreamFormat::JsonLines => Decoder::JsonLines(lines),
+        };
+        SocketTransactionReader { decoder }
+    }
+}
+
+impl<R: Read> TransactionReader for SocketTransactionReader<R> {
+    /// Returns an iterator over deserialized [`TransactionRecord`]s, until the stream ends.
+    fn read<'a>(&'a mut self) -> Box<dyn Iterator<Item = Result<TransactionRecord>> + 'a> {
+        match &mut self.decoder {
+            Decoder::Csv(reader) => Box::new(
+                reader
+                    .deserialize()
+                    .map(|result| result.map_err(Error::from)),
+            ),
+            Decoder::JsonLines(lines) => Box::new(std::iter::from_fn(move || loop {
+                match lines.next_line() {
+                    Ok(true) => {}
+                    Ok(false) => return None,
+                    Err(err) => return Some(Err(Error::from(err))),
+                }
+                let line = String::from_utf8_lossy(&lines.line);
+                if !line.trim().is_empty() {
+                    return Some(serde_json::from_str(&line).map_err(Error::from));
+                }
+            })),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+    use test_case::test_case;
+
+    use crate::{ClientId, TransactionId};
+
+    use super::*;
+
+    fn read(input: &str, format: StreamFormat) -> Vec<Result<TransactionRecord>> {
+        SocketTransactionReader::from_reader(input.as_bytes(), format)
+            .read()
+            .collect()
+    }
+
+    fn deposit(tx: u32, amount: rust_decimal::Decimal) -> TransactionRecord {
+        TransactionRecord::deposit(ClientId(1), TransactionId(tx), amount)
+    }
+
+    #[test_case(
+        "type, client, tx, amount\ndeposit, 1, 1, 10\ndeposit, 1, 2, 2.5\n",
+        StreamFormat::Csv;
+        "when csv"
+    )]
+    #[test_case(
+        "{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":\"10\"}\n\n\
+         {\"type\":\"deposit\",\"client\":1,\"tx\":2,\"amount\":\"2.5\"}\n",
+        StreamFormat::JsonLines;
+        "when json lines"
+    )]
+    fn test_read(input: &str, format: StreamFormat) -> Result<()> {
+        let records = read(input, format)
+            .into_iter()
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(vec![deposit(1, dec!(10)), deposit(2, dec!(2.5))], records);
+
+        Ok(())
+    }
+
+    #[test_case("type,client,tx,amount\ndeposit,1,1,10\ndeposit,1,2,2", StreamFormat::Csv;       "when csv")]
+    #[test_case("{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":\"10\"}\n{\"type\":", StreamFormat::JsonLines; "when json lines")]
+    fn test_read_discards_partial_last_line(input: &str, format: StreamFormat) -> Result<()> {
+        let records = read(input, format)
+            .into_iter()
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(vec![deposit(1, dec!(10))], records);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_when_invalid_record_continues() {
+        let results = read(
+            "type,client,tx,amount\nborrow,1,1,10\ndeposit,1,2,5\n",
+            StreamFormat::Csv,
+        );
+        assert!(results[0].is_err());
+        assert_eq!(deposit(2, dec!(5)), *results[1].as_ref().unwrap());
+    }
+
+    #[test]
+    fn test_stream_format_from_str() {
+        assert_eq!(StreamFormat::JsonLines, "jsonl".parse().unwrap());
+        assert_eq!(StreamFormat::JsonLines, "json".parse().unwrap());
+        assert_eq!(
+            "Unknown stream format: xml",
+            "xml".parse::<StreamFormat>().unwrap_err().to_string()
+        );
+    }
+}
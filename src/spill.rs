@@ -0,0 +1,541 @@
+//! Maps of transactions which can spill their older entries to disk.
+
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet, VecDeque},
+    fs::File,
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{de::DeserializeOwned, ser::SerializeMap, Deserialize, Deserializer, Serialize};
+
+use crate::{transaction_ids::TransactionIdSet, TransactionId};
+
+/// Number of transaction IDs each page of a [`SpillIndex`] has a slot for.
+const PAGE_SLOTS: u64 = 4096;
+
+/// Length of a slot of a [`SpillIndex`]: the offset and length of an entry.
+const SLOT_LEN: u64 = 12;
+
+/// Where each spilled entry is in the spill file, itself kept on disk.
+///
+/// The index file is split into pages, each with a slot for a range of [`PAGE_SLOTS`]
+/// transaction IDs, added as the first entry in the range is spilled. Only the offset of each
+/// page is kept in memory, at most a million of them.
+#[derive(Debug)]
+struct SpillIndex {
+    file: File,
+    pages: HashMap<u32, u64>,
+}
+
+impl SpillIndex {
+    fn new(dir: &Path) -> Result<Self> {
+        let file = tempfile::tempfile_in(dir)
+            .with_context(|| format!("Could not create spill index in {:?}", dir))?;
+        Ok(SpillIndex {
+            file,
+            pages: HashMap::new(),
+        })
+    }
+
+    /// Returns the offset and length of a spilled entry.
+    fn get(&self, tx: &TransactionId) -> Result<(u64, u32)> {
+        let page = self
+            .pages
+            .get(&page(tx))
+            .with_context(|| format!("Spilled transaction {} is not indexed", tx))?;
+        let mut slot = [0; SLOT_LEN as usize];
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(page + slot_offset(tx)))?;
+        file.read_exact(&mut slot)?;
+        let (offset, len) = slot.split_at(8);
+        Ok((
+            u64::from_le_bytes(offset.try_into()?),
+            u32::from_le_bytes(len.try_into()?),
+        ))
+    }
+
+    /// Sets the offset and length of a spilled entry, adding a page for it if needed.
+    fn set(&mut self, tx: TransactionId, (offset, len): (u64, u32)) -> Result<()> {
+        let page = match self.pages.get(&page(&tx)) {
+            Some(&start) => start,
+            None => {
+                let start = self.file.seek(SeekFrom::End(0))?;
+                self.file.set_len(start + PAGE_SLOTS * SLOT_LEN)?;
+                self.pages.insert(page(&tx), start);
+                start
+            }
+        };
+        let mut slot = [0; SLOT_LEN as usize];
+        slot[..8].copy_from_slice(&offset.to_le_bytes());
+        slot[8..].copy_from_slice(&len.to_le_bytes());
+        self.file.seek(SeekFrom::Start(page + slot_offset(&tx)))?;
+        self.file.write_all(&slot)?;
+        Ok(())
+    }
+}
+
+/// Returns the index page with a slot for the transaction.
+fn page(tx: &TransactionId) -> u32 {
+    (u64::from(tx.0) / PAGE_SLOTS) as u32
+}
+
+/// Returns the offset of the slot for the transaction within its index page.
+fn slot_offset(tx: &TransactionId) -> u64 {
+    u64::from(tx.0) % PAGE_SLOTS * SLOT_LEN
+}
+
+/// Entries of a [`SpillMap`] written to disk.
+///
+/// Each entry is appended to an anonymous file, which is removed once closed, and found again
+/// through the index. Entries loaded back into memory or removed are left in the file until
+/// they outnumber those still spilled, when the file is compacted, so it stays within twice
+/// the size of the entries spilled.
+#[derive(Debug)]
+struct Spill {
+    dir: PathBuf,
+    file: File,
+    index: SpillIndex,
+    /// Entries written to the file, including any since loaded back or removed.
+    written: usize,
+    spilled: TransactionIdSet,
+    max_in_memory: usize,
+    /// Order entries were brought into memory, oldest first, including some since spilled or
+    /// removed, though never more than twice as many as are in memory.
+    order: VecDeque<TransactionId>,
+}
+
+impl Spill {
+    fn new(dir: &Path, max_in_memory: usize) -> Result<Self> {
+        Ok(Spill {
+            dir: dir.to_path_buf(),
+            file: spill_file(dir)?,
+            index: SpillIndex::new(dir)?,
+            written: 0,
+            spilled: TransactionIdSet::default(),
+            max_in_memory,
+            order: VecDeque::new(),
+        })
+    }
+
+    /// Reads a spilled entry from disk, leaving it there.
+    fn load<V: DeserializeOwned>(&self, tx: &TransactionId) -> Result<Option<V>> {
+        if !self.spilled.contains(tx) {
+            return Ok(None);
+        }
+        let data = read_entry(&self.file, self.index.get(tx)?)
+            .with_context(|| format!("Could not read spilled transaction {}", tx))?;
+        Ok(Some(serde_json::from_slice(&data)?))
+    }
+
+    /// Rewrites the file with only the entries still spilled once they are outnumbered by those
+    /// loaded back or removed.
+    fn compact(&mut self) -> Result<()> {
+        if self.written <= 2 * self.spilled.len() {
+            return Ok(());
+        }
+        let mut file = spill_file(&self.dir)?;
+        let mut index = SpillIndex::new(&self.dir)?;
+        for tx in self.spilled.iter() {
+            let data = read_entry(&self.file, self.index.get(&tx)?)?;
+            index.set(tx, append_entry(&mut file, &data)?)?;
+        }
+        self.file = file;
+        self.index = index;
+        self.written = self.spilled.len();
+        Ok(())
+    }
+}
+
+/// A map of transactions, all held in memory unless a spill directory is set.
+///
+/// Once there are more than the configured number of entries in memory the oldest are written
+/// to disk, and loaded back into memory when next accessed. Entries serialize as a plain map,
+/// so a map saved in a checkpoint is restored entirely into memory.
+///
+/// Which entries are spilled is tracked in a [`TransactionIdSet`], and where they are in an
+/// index on disk, so memory does not grow with the number of entries spilled.
+#[derive(Debug)]
+pub(crate) struct SpillMap<V> {
+    memory: HashMap<TransactionId, V>,
+    spill: Option<Spill>,
+}
+
+impl<V> Default for SpillMap<V> {
+    fn default() -> Self {
+        SpillMap {
+            memory: HashMap::new(),
+            spill: None,
+        }
+    }
+}
+
+impl<V: Serialize + DeserializeOwned> SpillMap<V> {
+    /// Spill entries beyond `max_in_memory` to a file in `dir`, spilling any already beyond it.
+    ///
+    /// At least one entry is always kept in memory, the one last accessed.
+    pub(crate) fn set_spill_dir(&mut self, dir: &Path, max_in_memory: usize) -> Result<()> {
+        let mut spill = Spill::new(dir, max_in_memory.max(1))?;
+        let mut order: Vec<_> = self.memory.keys().copied().collect();
+        order.sort_unstable_by_key(|tx| tx.0);
+        spill.order = order.into();
+        self.spill = Some(spill);
+        self.evict();
+        Ok(())
+    }
+
+    /// Returns the number of entries, in memory or on disk.
+    pub(crate) fn len(&self) -> usize {
+        self.memory.len() + self.spill.as_ref().map_or(0, |spill| spill.spilled.len())
+    }
+
+    /// Returns whether there is an entry for the transaction, without loading it.
+    pub(crate) fn contains_key(&self, tx: &TransactionId) -> bool {
+        self.memory.contains_key(tx)
+            || self
+                .spill
+                .as_ref()
+                .is_some_and(|spill| spill.spilled.contains(tx))
+    }
+
+    /// Returns the transaction of every entry, in memory or on disk.
+    pub(crate) fn keys(&self) -> impl Iterator<Item = TransactionId> + '_ {
+        self.memory
+            .keys()
+            .copied()
+            .chain(self.spill.iter().flat_map(|spill| spill.spilled.iter()))
+    }
+
+    /// Returns the entry for the transaction, loading it back into memory if it was spilled.
+    pub(crate) fn get(&mut self, tx: &TransactionId) -> Result<Option<&V>> {
+        Ok(self.get_mut(tx)?.map(|value| &*value))
+    }
+
+    /// Returns the entry for the transaction, loading it back into memory if it was spilled.
+    pub(crate) fn get_mut(&mut self, tx: &TransactionId) -> Result<Option<&mut V>> {
+        if !self.memory.contains_key(tx) {
+            match self.load(tx)? {
+                Some(value) => self.insert(*tx, value),
+                None => return Ok(None),
+            }
+        }
+        Ok(self.memory.get_mut(tx))
+    }
+
+    /// Inserts an entry, spilling the oldest entries if there are then too many in memory.
+    ///
+    /// An entry which cannot be spilled is kept in memory, so is never lost.
+    pub(crate) fn insert(&mut self, tx: TransactionId, value: V) {
+        if let Some(spill) = &mut self.spill {
+            spill.spilled.remove(&tx);
+            if !self.memory.contains_key(&tx) {
+                spill.order.push_back(tx);
+            }
+        }
+        self.memory.insert(tx, value);
+        self.evict();
+    }
+
+    /// Removes an entry, returning whether there was one.
+    pub(crate) fn remove(&mut self, tx: &TransactionId) -> bool {
+        let spilled = self
+            .spill
+            .as_mut()
+            .is_some_and(|spill| spill.spilled.remove(tx));
+        let removed = self.memory.remove(tx).is_some() || spilled;
+        self.tidy();
+        removed
+    }
+
+    /// Returns the entry for the transaction, read from disk if it was spilled but left there.
+    pub(crate) fn peek(&self, tx: &TransactionId) -> Result<Option<Cow<'_, V>>>
+    where
+        V: Clone,
+    {
+        match self.memory.get(tx) {
+            Some(value) => Ok(Some(Cow::Borrowed(value))),
+            None => Ok(self.load(tx)?.map(Cow::Owned)),
+        }
+    }
+
+    /// Returns every entry, those spilled read from disk.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = Result<(TransactionId, Cow<'_, V>)>>
+    where
+        V: Clone,
+    {
+        let memory = self
+            .memory
+            .iter()
+            .map(|(tx, value)| Ok((*tx, Cow::Borrowed(value))));
+        let spilled = self
+            .spill
+            .iter()
+            .flat_map(|spill| spill.spilled.iter())
+            .map(|tx| {
+                let value = self.load(&tx)?.context("Spilled entry is missing")?;
+                Ok((tx, Cow::Owned(value)))
+            });
+        memory.chain(spilled)
+    }
+
+    /// Reads a spilled entry from disk, leaving it there.
+    fn load(&self, tx: &TransactionId) -> Result<Option<V>> {
+        match &self.spill {
+            Some(spill) => spill.load(tx),
+            None => Ok(None),
+        }
+    }
+
+    /// Spills the oldest entries in memory until there are no more than the maximum.
+    fn evict(&mut self) {
+        let spill = match &mut self.spill {
+            Some(spill) => spill,
+            None => return,
+        };
+        while self.memory.len() > spill.max_in_memory {
+            let tx = match spill.order.pop_front() {
+                Some(tx) => tx,
+                None => break,
+            };
+            let value = match self.memory.get(&tx) {
+                Some(value) => value,
+                // removed or already spilled
+                None => continue,
+            };
+            let spilled = serde_json::to_vec(value)
+                .map_err(anyhow::Error::from)
+                .and_then(|data| append_entry(&mut spill.file, &data))
+                .and_then(|location| spill.index.set(tx, location));
+            match spilled {
+                Ok(()) => {
+                    spill.written += 1;
+                    spill.spilled.insert(tx);
+                    self.memory.remove(&tx);
+                }
+                Err(err) => {
+                    log::error!("Could not spill transaction {}: {}", tx, err);
+                    spill.order.push_front(tx);
+                    break;
+                }
+            }
+        }
+        self.tidy();
+    }
+
+    /// Drops entries from the order which are no longer in memory, keeping the latest position
+    /// of any brought back, once they outnumber those in memory, and compacts the spill file.
+    fn tidy(&mut self) {
+        let spill = match &mut self.spill {
+            Some(spill) => spill,
+            None => return,
+        };
+        if spill.order.len() > 2 * self.memory.len() {
+            let mut seen = HashSet::new();
+            let mut order: Vec<_> = spill
+                .order
+                .iter()
+                .rev()
+                .filter(|tx| self.memory.contains_key(tx) && seen.insert(**tx))
+                .copied()
+                .collect();
+            order.reverse();
+            spill.order = order.into();
+        }
+        if let Err(err) = spill.compact() {
+            log::error!("Could not compact spill file: {}", err);
+        }
+    }
+}
+
+/// Creates an anonymous file in `dir` for spilled entries.
+fn spill_file(dir: &Path) -> Result<File> {
+    tempfile::tempfile_in(dir).with_context(|| format!("Could not create spill file in {:?}", dir))
+}
+
+/// Appends an entry to a spill file, returning its offset and length.
+fn append_entry(file: &mut File, data: &[u8]) -> Result<(u64, u32)> {
+    let offset = file.seek(SeekFrom::End(0))?;
+    file.write_all(data)?;
+    Ok((offset, data.len().try_into()?))
+}
+
+/// Reads an entry from a spill file at its offset and length.
+fn read_entry(mut file: &File, (offset, len): (u64, u32)) -> Result<Vec<u8>> {
+    let mut data = vec![0; len as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut data)?;
+    Ok(data)
+}
+
+impl<V: Serialize + DeserializeOwned + Clone> Serialize for SpillMap<V> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.len()))?;
+        for entry in self.iter() {
+            let (tx, value) = entry.map_err(serde::ser::Error::custom)?;
+            map.serialize_entry(&tx, &value)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de, V: Deserialize<'de>> Deserialize<'de> for SpillMap<V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(SpillMap {
+            memory: HashMap::deserialize(deserializer)?,
+            spill: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spill_map(max_in_memory: usize) -> Result<SpillMap<String>> {
+        let mut map = SpillMap::default();
+        map.set_spill_dir(&std::env::temp_dir(), max_in_memory)?;
+        Ok(map)
+    }
+
+    #[test]
+    fn test_insert_spills_oldest_entries() -> Result<()> {
+        let mut map = spill_map(2)?;
+        for tx in 1..=5 {
+            map.insert(TransactionId(tx), format!("entry {}", tx));
+        }
+
+        assert_eq!(2, map.memory.len());
+        assert_eq!(5, map.len());
+        assert!(map.memory.contains_key(&TransactionId(5)));
+        assert!(map.contains_key(&TransactionId(1)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_mut_loads_spilled_entry() -> Result<()> {
+        let mut map = spill_map(1)?;
+        map.insert(TransactionId(1), "first".to_string());
+        map.insert(TransactionId(2), "second".to_string());
+
+        map.get_mut(&TransactionId(1))?
+            .unwrap()
+            .push_str(" changed");
+        assert_eq!(Some(&"second".to_string()), map.get(&TransactionId(2))?);
+        assert_eq!(
+            Some(&"first changed".to_string()),
+            map.get(&TransactionId(1))?
+        );
+        assert_eq!(None, map.get(&TransactionId(3))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_peek_leaves_spilled_entry_on_disk() -> Result<()> {
+        let mut map = spill_map(1)?;
+        map.insert(TransactionId(1), "first".to_string());
+        map.insert(TransactionId(2), "second".to_string());
+
+        assert_eq!(
+            Some("first"),
+            map.peek(&TransactionId(1))?.as_deref().map(String::as_str)
+        );
+        assert_eq!(
+            Some("second"),
+            map.peek(&TransactionId(2))?.as_deref().map(String::as_str)
+        );
+        assert_eq!(None, map.peek(&TransactionId(3))?);
+        assert!(!map.memory.contains_key(&TransactionId(1)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_when_spilled() -> Result<()> {
+        let mut map = spill_map(1)?;
+        map.insert(TransactionId(1), "first".to_string());
+        map.insert(TransactionId(2), "second".to_string());
+
+        assert!(map.remove(&TransactionId(1)));
+        assert!(!map.remove(&TransactionId(1)));
+        assert_eq!(None, map.get(&TransactionId(1))?);
+        assert_eq!(1, map.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_when_spilled_repeatedly_compacts_file() -> Result<()> {
+        let mut map = spill_map(2)?;
+        for tx in 1..=4 {
+            map.insert(TransactionId(tx), format!("entry {}", tx));
+        }
+        for _ in 0..10 {
+            for tx in 1..=4 {
+                assert_eq!(Some(&format!("entry {}", tx)), map.get(&TransactionId(tx))?);
+            }
+        }
+
+        let spill = map.spill.as_ref().unwrap();
+        assert_eq!(2, spill.spilled.len());
+        assert!(spill.written <= 4);
+        assert!(spill.order.len() <= 4);
+        assert_eq!(4, map.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_and_remove_keeps_order_bounded() -> Result<()> {
+        let mut map = spill_map(2)?;
+        for tx in 1..=100 {
+            map.insert(TransactionId(tx), format!("entry {}", tx));
+            map.insert(TransactionId(tx % 3), format!("entry {}", tx));
+            assert!(map.remove(&TransactionId(tx)));
+        }
+
+        let spill = map.spill.as_ref().unwrap();
+        assert!(spill.order.len() <= 2 * map.memory.len());
+        assert_eq!(3, map.len());
+        assert_eq!(Some(&"entry 100".to_string()), map.get(&TransactionId(1))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_when_spilled_across_index_pages() -> Result<()> {
+        let mut map = spill_map(1)?;
+        let txs = [1, PAGE_SLOTS as u32 + 1, u32::MAX];
+        for tx in txs {
+            map.insert(TransactionId(tx), format!("entry {}", tx));
+        }
+        map.insert(TransactionId(2), "last".to_string());
+
+        for tx in txs {
+            assert_eq!(
+                Some(format!("entry {}", tx)),
+                map.peek(&TransactionId(tx))?.map(Cow::into_owned)
+            );
+        }
+        assert_eq!(3, map.spill.as_ref().unwrap().index.pages.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_serde_includes_spilled_entries() -> Result<()> {
+        let mut map = spill_map(1)?;
+        map.insert(TransactionId(1), "first".to_string());
+        map.insert(TransactionId(2), "second".to_string());
+
+        let json = serde_json::to_string(&map)?;
+        let mut restored: SpillMap<String> = serde_json::from_str(&json)?;
+        assert_eq!(2, restored.memory.len());
+        assert_eq!(Some(&"first".to_string()), restored.get(&TransactionId(1))?);
+
+        Ok(())
+    }
+}
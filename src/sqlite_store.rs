@@ -0,0 +1,478 @@
+use std::{
+    borrow::Borrow,
+    collections::VecDeque,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, OptionalExtension, Row, TransactionBehavior};
+use rust_decimal::Decimal;
+
+use crate::{Account, AccountError, AccountStore, AccountVisitor, ClientId};
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS accounts (
+    client INTEGER PRIMARY KEY,
+    held TEXT NOT NULL,
+    total TEXT NOT NULL,
+    locked INTEGER NOT NULL
+)";
+
+/// Number of accounts read from the database at a time on export.
+const EXPORT_BATCH_SIZE: u32 = 1024;
+
+/// A persistent implementation of [`AccountStore`], kept in a SQLite database so that accounts
+/// survive the process exiting.
+///
+/// Each client's account is a row of the `accounts` table, with the held and total funds as
+/// text so no precision is lost. Every change is made in its own database transaction, so the
+/// accounts are consistent even if the process crashes part way through. Export reads the rows
+/// in client order a batch at a time rather than loading them all into memory.
+///
+/// As with [`FileAccountStore`](crate::FileAccountStore), the deposits and disputes needed to
+/// process later runs are kept by the [`TransactionProcessor`](crate::TransactionProcessor);
+/// save them alongside the database in [`SqliteAccountStore::state_path`].
+pub struct SqliteAccountStore {
+    connection: Connection,
+    path: PathBuf,
+    overdraft_limit: Decimal,
+}
+
+impl SqliteAccountStore {
+    /// Open the store in the database file, creating it if it does not exist.
+    ///
+    /// Every account of an existing store is checked, so a corrupted or unrecognised database
+    /// is reported before any transactions are processed.
+    ///
+    /// ### Parameters
+    /// - path: The database file of the store.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let connection =
+            Connection::open(&path).with_context(|| format!("Could not open store {:?}", path))?;
+        let store = SqliteAccountStore {
+            connection,
+            path,
+            overdraft_limit: Decimal::ZERO,
+        };
+        store
+            .connection
+            .execute(SCHEMA, [])
+            .map_err(Into::into)
+            .and_then(|_| store.check())
+            .with_context(|| format!("Corrupted store {:?}", store.path))?;
+        Ok(store)
+    }
+
+    /// Allow withdrawals to overdraw an account by up to `overdraft_limit`, like
+    /// [`InMemoryAccountStore::with_overdraft_limit`](crate::InMemoryAccountStore::with_overdraft_limit).
+    ///
+    /// The limit is not saved in the store.
+    pub fn with_overdraft_limit(mut self, overdraft_limit: Decimal) -> Self {
+        self.overdraft_limit = overdraft_limit;
+        self
+    }
+
+    /// The file alongside the database in which to save the processing state, such as with
+    /// [`TransactionProcessor::save_state`](crate::TransactionProcessor::save_state).
+    pub fn state_path(&self) -> PathBuf {
+        let mut path = OsString::from(&self.path);
+        path.push(".state.json");
+        path.into()
+    }
+
+    /// Reads every account, failing at the first which is not valid.
+    fn check(&self) -> Result<()> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT client, held, total, locked FROM accounts")?;
+        let mut rows = statement.query([])?;
+        while let Some(row) = rows.next()? {
+            decode(row)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the client's account, if they have one.
+    fn read(&self, client: ClientId) -> Result<Option<Account>> {
+        read(&self.connection, client).with_context(|| {
+            format!(
+                "Could not read client {} from store {:?}",
+                client, self.path
+            )
+        })
+    }
+
+    /// Applies the operation to the client's account, opening an empty one if they have none,
+    /// within a single database transaction.
+    ///
+    /// As with [`InMemoryAccountStore`](crate::InMemoryAccountStore), the account is only
+    /// written if the operation succeeds, so a failed operation never opens an account.
+    fn update(
+        &mut self,
+        client: ClientId,
+        operation: impl FnOnce(&mut Account) -> Result<(), AccountError>,
+    ) -> Result<()> {
+        self.update_with(client, |account| {
+            let mut account = account.unwrap_or_else(|| Account::empty(client));
+            operation(&mut account)?;
+            Ok(account)
+        })
+    }
+
+    /// Applies the operation to the client's account, failing if they have none.
+    fn update_existing(
+        &mut self,
+        client: ClientId,
+        operation: impl FnOnce(&mut Account) -> Result<(), AccountError>,
+    ) -> Result<()> {
+        self.update_with(client, |account| match account {
+            Some(mut account) => {
+                operation(&mut account)?;
+                Ok(account)
+            }
+            None => Err(AccountError::NoSuchAccount(client)),
+        })
+    }
+
+    fn update_with(
+        &mut self,
+        client: ClientId,
+        operation: impl FnOnce(Option<Account>) -> Result<Account, AccountError>,
+    ) -> Result<()> {
+        let transaction = self
+            .connection
+            .transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let account = read(&transaction, client)?;
+        let account = operation(account)?;
+        transaction
+            .execute(
+                "INSERT OR REPLACE INTO accounts (client, held, total, locked)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    account.client().0,
+                    account.held().to_string(),
+                    account.total().to_string(),
+                    account.locked(),
+                ],
+            )
+            .and_then(|_| transaction.commit())
+            .with_context(|| format!("Could not write to store {:?}", self.path))
+    }
+}
+
+fn read(connection: &Connection, client: ClientId) -> Result<Option<Account>> {
+    let row = connection
+        .query_row(
+            "SELECT client, held, total, locked FROM accounts WHERE client = ?1",
+            [client.0],
+            |row| Ok(decode(row)),
+        )
+        .optional()?;
+    row.transpose()
+}
+
+fn decode(row: &Row) -> Result<Account> {
+    let client = ClientId(row.get(0)?);
+    decode_balances(client, row)
+        .with_context(|| format!("The account of client {} is corrupted", client))
+}
+
+fn decode_balances(client: ClientId, row: &Row) -> Result<Account> {
+    let held: String = row.get(1)?;
+    let total: String = row.get(2)?;
+    Ok(Account::builder(client)
+        .held(Decimal::from_str(&held)?)
+        .total(Decimal::from_str(&total)?)
+        .locked(row.get(3)?)
+        .build()?)
+}
+
+/// Reads the accounts from the database in client order, a batch at a time.
+struct SqliteAccounts<C> {
+    connection: C,
+    path: PathBuf,
+    batch: VecDeque<Account>,
+    next: u32,
+}
+
+impl<C: Borrow<Connection>> SqliteAccounts<C> {
+    /// Reads the next batch of accounts, from the lowest client not yet read.
+    fn read_batch(&mut self) -> Result<()> {
+        let mut statement = self.connection.borrow().prepare_cached(
+            "SELECT client, held, total, locked FROM accounts
+             WHERE client >= ?1 ORDER BY client LIMIT ?2",
+        )?;
+        let mut rows = statement.query([self.next, EXPORT_BATCH_SIZE])?;
+        while let Some(row) = rows.next()? {
+            let account = decode(row)?;
+            self.next = account.client().0 as u32 + 1;
+            self.batch.push_back(account);
+        }
+        Ok(())
+    }
+}
+
+impl<C: Borrow<Connection>> Iterator for SqliteAccounts<C> {
+    type Item = Account;
+
+    fn next(&mut self) -> Option<Account> {
+        if self.batch.is_empty() && self.next <= u16::MAX as u32 {
+            if let Err(err) = self.read_batch() {
+                log::error!("Could not export store {:?}: {:#}", self.path, err);
+                self.next = u16::MAX as u32 + 1;
+                self.batch.clear();
+            }
+        }
+        self.batch.pop_front()
+    }
+}
+
+impl AccountStore for SqliteAccountStore {
+    fn add_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.update(client, |account| account.credit(amount))
+    }
+
+    fn remove_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        let overdraft_limit = self.overdraft_limit;
+        self.update(client, |account| account.debit(amount, overdraft_limit))
+    }
+
+    fn chargeback_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.update(client, |account| account.charge_back(amount))
+    }
+
+    fn force_chargeback_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.update(client, |account| account.force_charge_back(amount))
+    }
+
+    fn hold_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.update(client, |account| account.hold(amount))
+    }
+
+    fn release_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.update(client, |account| account.release(amount))
+    }
+
+    fn credit_held_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.update(client, |account| account.credit_held(amount))
+    }
+
+    fn remove_held_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.update(client, |account| account.debit_held(amount))
+    }
+
+    fn lock(&mut self, client: ClientId) -> Result<()> {
+        self.update_existing(client, Account::lock)
+    }
+
+    fn unlock(&mut self, client: ClientId) -> Result<()> {
+        self.update_existing(client, Account::unlock)
+    }
+
+    fn account(&self, client: ClientId) -> Option<Account> {
+        self.read(client).unwrap_or_else(|err| {
+            log::error!("{:#}", err);
+            None
+        })
+    }
+
+    fn for_each_account(&self, visit: &mut AccountVisitor) -> Result<()> {
+        let mut accounts = SqliteAccounts {
+            connection: &self.connection,
+            path: self.path.clone(),
+            batch: VecDeque::new(),
+            next: 0,
+        };
+        accounts.try_for_each(|account| visit(&account))
+    }
+
+    fn export(self) -> Box<dyn Iterator<Item = Account>> {
+        Box::new(SqliteAccounts {
+            connection: self.connection,
+            path: self.path,
+            batch: VecDeque::new(),
+            next: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use rust_decimal_macros::dec;
+
+    use crate::AccountSummary;
+
+    use super::*;
+
+    fn summaries(store: SqliteAccountStore) -> Vec<AccountSummary> {
+        store
+            .export()
+            .map(|account| account.try_into().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_from_path_when_reopened_keeps_accounts() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("accounts.db");
+        let mut store = SqliteAccountStore::from_path(&path)?;
+        store.add_funds(ClientId(2), dec!(1.5))?;
+        store.add_funds(ClientId(1), dec!(20))?;
+        store.hold_funds(ClientId(1), dec!(5))?;
+        store.lock(ClientId(2))?;
+        drop(store);
+
+        let mut store = SqliteAccountStore::from_path(&path)?;
+        store.remove_funds(ClientId(1), dec!(2.25))?;
+        assert_eq!(
+            vec![
+                AccountSummary::new(ClientId(1), dec!(5), dec!(17.75), false).unwrap(),
+                AccountSummary::new(ClientId(2), dec!(0), dec!(1.5), true).unwrap(),
+            ],
+            summaries(store)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_path_when_crashed_keeps_committed_accounts() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("accounts.db");
+        let mut store = SqliteAccountStore::from_path(&path)?;
+        store.add_funds(ClientId(1), dec!(20))?;
+        store.add_funds(ClientId(1), dec!(0.0001))?;
+        // never closed, as if the process had been killed
+        std::mem::forget(store);
+
+        let store = SqliteAccountStore::from_path(&path)?;
+        assert_eq!(
+            vec![AccountSummary::new(ClientId(1), dec!(0), dec!(20.0001), false).unwrap()],
+            summaries(store)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_when_more_than_a_batch() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut store = SqliteAccountStore::from_path(dir.path().join("accounts.db"))?;
+        let clients = EXPORT_BATCH_SIZE as u16 * 2 + 1;
+        for client in (0..clients).rev() {
+            store.add_funds(ClientId(client), dec!(1))?;
+        }
+        store.add_funds(ClientId(u16::MAX), dec!(1))?;
+
+        let exported: Vec<ClientId> = store.export().map(|account| account.client()).collect();
+        let expected: Vec<ClientId> = (0..clients).chain([u16::MAX]).map(ClientId).collect();
+        assert_eq!(expected, exported);
+        Ok(())
+    }
+
+    #[test]
+    fn test_for_each_account_when_more_than_a_batch() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut store = SqliteAccountStore::from_path(dir.path().join("accounts.db"))?;
+        let clients = EXPORT_BATCH_SIZE as u16 * 2 + 1;
+        for client in (0..clients).rev() {
+            store.add_funds(ClientId(client), dec!(1))?;
+        }
+
+        let mut visited = Vec::new();
+        store.for_each_account(&mut |account| {
+            visited.push(account.client());
+            Ok(())
+        })?;
+        let expected: Vec<ClientId> = (0..clients).map(ClientId).collect();
+        assert_eq!(expected, visited);
+
+        // the store is left to process more transactions
+        store.add_funds(ClientId(0), dec!(1))?;
+        assert_eq!(dec!(2), store.account(ClientId(0)).unwrap().total());
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_funds_when_no_account() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut store = SqliteAccountStore::from_path(dir.path().join("accounts.db"))?;
+        let err = store.remove_funds(ClientId(1), dec!(5)).unwrap_err();
+        assert_eq!(
+            Some(&AccountError::InsufficientFunds {
+                client: ClientId(1),
+                amount: dec!(5)
+            }),
+            err.downcast_ref::<AccountError>()
+        );
+        assert!(store.account(ClientId(1)).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_lock_when_no_account() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut store = SqliteAccountStore::from_path(dir.path().join("accounts.db"))?;
+        let err = store.lock(ClientId(1)).unwrap_err();
+        assert_eq!(
+            Some(&AccountError::NoSuchAccount(ClientId(1))),
+            err.downcast_ref::<AccountError>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_funds_when_locked() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut store = SqliteAccountStore::from_path(dir.path().join("accounts.db"))?;
+        store.add_funds(ClientId(1), dec!(20))?;
+        store.lock(ClientId(1))?;
+        assert!(store.add_funds(ClientId(1), dec!(5)).is_err());
+        assert_eq!(dec!(20), store.account(ClientId(1)).unwrap().total());
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_path_when_not_a_store() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("accounts.db");
+        fs::write(&path, "type,client,tx,amount\n")?;
+
+        let err = SqliteAccountStore::from_path(&path).err().unwrap();
+        assert!(err.to_string().starts_with("Corrupted store"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_path_when_account_corrupted() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("accounts.db");
+        let mut store = SqliteAccountStore::from_path(&path)?;
+        store.add_funds(ClientId(1), dec!(20))?;
+        store
+            .connection
+            .execute("UPDATE accounts SET held = '-1' WHERE client = 1", [])?;
+        drop(store);
+
+        let err = SqliteAccountStore::from_path(&path).err().unwrap();
+        assert!(err.to_string().starts_with("Corrupted store"));
+        assert_eq!(
+            "The account of client 1 is corrupted",
+            format!("{}", err.chain().nth(1).unwrap())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_state_path() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let store = SqliteAccountStore::from_path(dir.path().join("accounts.db"))?;
+        assert_eq!(
+            dir.path().join("accounts.db.state.json"),
+            store.state_path()
+        );
+        Ok(())
+    }
+}
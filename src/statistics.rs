@@ -0,0 +1,271 @@
+//! Serdes for summary statistics
+
+use std::{fmt, fs::File, path::Path};
+
+use anyhow::Result;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{AccountSummary, ClientId, TransactionType};
+
+/// Totals across all accounts and transactions processed.
+///
+/// Transaction counts include transactions which were skipped, which are also counted
+/// separately. Records which could not be read or were malformed are not counted.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SummaryStatistics {
+    /// Number of client accounts exported.
+    pub clients: u64,
+    /// Sum of the total funds of all accounts, saturating at the bounds of [`Decimal`].
+    pub total: Decimal,
+    /// Sum of the held funds of all accounts, saturating at the bounds of [`Decimal`].
+    pub held: Decimal,
+    /// Number of locked accounts.
+    pub locked: u64,
+    pub deposits: u64,
+    pub withdrawals: u64,
+    pub disputes: u64,
+    pub resolves: u64,
+    pub chargebacks: u64,
+    pub unlocks: u64,
+    /// Number of transactions which could not be applied.
+    pub skipped: u64,
+    /// Client with the most total funds, the lowest such client if tied, if there are accounts.
+    #[serde(default)]
+    pub largest_client: Option<ClientId>,
+    /// Total funds of the largest client's account.
+    #[serde(default)]
+    pub largest_total: Decimal,
+}
+
+impl SummaryStatistics {
+    /// Counts a transaction of the given type.
+    pub(crate) fn record_transaction(&mut self, transaction_type: TransactionType) {
+        let count = match transaction_type {
+            TransactionType::Deposit => &mut self.deposits,
+            TransactionType::Withdrawal => &mut self.withdrawals,
+            TransactionType::Dispute => &mut self.disputes,
+            TransactionType::Resolve => &mut self.resolves,
+            TransactionType::Chargeback => &mut self.chargebacks,
+            TransactionType::Unlock => &mut self.unlocks,
+        };
+        *count += 1;
+    }
+
+    /// Adds an exported account to the totals.
+    pub(crate) fn record_account(&mut self, account: &AccountSummary) {
+        self.clients += 1;
+        self.total = self.total.saturating_add(account.total());
+        self.held = self.held.saturating_add(account.held());
+        if account.locked() {
+            self.locked += 1;
+        }
+        self.record_largest(account.client(), account.total());
+    }
+
+    /// Keeps the client as the largest account if it has more total funds than the largest so far.
+    fn record_largest(&mut self, client: ClientId, total: Decimal) {
+        let larger = match self.largest_client {
+            Some(largest) => {
+                total > self.largest_total || (total == self.largest_total && client < largest)
+            }
+            None => true,
+        };
+        if larger {
+            self.largest_client = Some(client);
+            self.largest_total = total;
+        }
+    }
+
+    /// Adds the counts and totals of statistics for other accounts and transactions.
+    pub(crate) fn merge(&mut self, other: &SummaryStatistics) {
+        self.clients += other.clients;
+        self.total = self.total.saturating_add(other.total);
+        self.held = self.held.saturating_add(other.held);
+        self.locked += other.locked;
+        self.deposits += other.deposits;
+        self.withdrawals += other.withdrawals;
+        self.disputes += other.disputes;
+        self.resolves += other.resolves;
+        self.chargebacks += other.chargebacks;
+        self.unlocks += other.unlocks;
+        self.skipped += other.skipped;
+        if let Some(client) = other.largest_client {
+            self.record_largest(client, other.largest_total);
+        }
+    }
+
+    /// Write the statistics to a file, as CSV if the path has a `.csv` extension
+    /// and JSON otherwise.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("csv") => {
+                let mut wtr = csv::Writer::from_path(path)?;
+                wtr.serialize(self)?;
+                wtr.flush()?;
+            }
+            _ => serde_json::to_writer_pretty(File::create(path)?, self)?,
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SummaryStatistics {
+    /// Summarises the accounts on one line, such as
+    /// `3 accounts, 1 locked, total 17.5, held 2.5, largest 10 (client 2)`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} accounts, {} locked, total {}, held {}",
+            self.clients, self.locked, self.total, self.held
+        )?;
+        if let Some(client) = self.largest_client {
+            write!(f, ", largest {} (client {})", self.largest_total, client)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use crate::ClientId;
+
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let mut stats = SummaryStatistics::default();
+        stats.record_transaction(TransactionType::Deposit);
+        stats.record_transaction(TransactionType::Deposit);
+        stats.record_transaction(TransactionType::Chargeback);
+        stats.record_account(&AccountSummary::new(ClientId(1), dec!(0), dec!(5), true).unwrap());
+        stats
+            .record_account(&AccountSummary::new(ClientId(2), dec!(2.5), dec!(10), false).unwrap());
+
+        let expected = SummaryStatistics {
+            clients: 2,
+            total: dec!(15),
+            held: dec!(2.5),
+            locked: 1,
+            deposits: 2,
+            chargebacks: 1,
+            largest_client: Some(ClientId(2)),
+            largest_total: dec!(10),
+            ..Default::default()
+        };
+        assert_eq!(expected, stats);
+    }
+
+    #[test]
+    fn test_record_when_empty() {
+        let stats = SummaryStatistics::default();
+        assert_eq!(None, stats.largest_client);
+        assert_eq!("0 accounts, 0 locked, total 0, held 0", stats.to_string());
+    }
+
+    #[test]
+    fn test_record_when_locked_accounts() {
+        let mut stats = SummaryStatistics::default();
+        stats.record_account(&AccountSummary::new(ClientId(1), dec!(0), dec!(-5), true).unwrap());
+        stats.record_account(&AccountSummary::new(ClientId(2), dec!(1), dec!(3), true).unwrap());
+        stats.record_account(&AccountSummary::new(ClientId(3), dec!(0), dec!(3), false).unwrap());
+
+        assert_eq!(3, stats.clients);
+        assert_eq!(2, stats.locked);
+        assert_eq!(
+            "3 accounts, 2 locked, total 1, held 1, largest 3 (client 2)",
+            stats.to_string()
+        );
+    }
+
+    #[test]
+    fn test_record_keeps_decimal_precision() {
+        let mut stats = SummaryStatistics::default();
+        for client in 0..10 {
+            stats.record_account(
+                &AccountSummary::new(
+                    ClientId(client),
+                    dec!(0.0001),
+                    dec!(1000000000000000.1),
+                    false,
+                )
+                .unwrap(),
+            );
+        }
+        // summed as f64 both would be off, e.g. 0.1 + 0.2 != 0.3
+        assert_eq!(dec!(10000000000000001), stats.total);
+        assert_eq!(dec!(0.001), stats.held);
+        assert_eq!(Some(ClientId(0)), stats.largest_client);
+    }
+
+    #[test]
+    fn test_record_when_totals_would_overflow_saturates() {
+        let mut stats = SummaryStatistics::default();
+        stats.record_account(
+            &AccountSummary::new(ClientId(1), dec!(0), Decimal::MAX, false).unwrap(),
+        );
+        stats.record_account(&AccountSummary::new(ClientId(2), dec!(0), dec!(5), false).unwrap());
+
+        assert_eq!(2, stats.clients);
+        assert_eq!(Decimal::MAX, stats.total);
+        assert_eq!(Some(ClientId(1)), stats.largest_client);
+    }
+
+    #[test]
+    fn test_merge_keeps_largest() {
+        let mut stats = SummaryStatistics::default();
+        stats.record_account(&AccountSummary::new(ClientId(4), dec!(0), dec!(5), false).unwrap());
+        let mut other = SummaryStatistics::default();
+        other.record_account(&AccountSummary::new(ClientId(2), dec!(0), dec!(5), false).unwrap());
+        other.record_account(&AccountSummary::new(ClientId(3), dec!(0), dec!(1), false).unwrap());
+
+        stats.merge(&other);
+        assert_eq!(3, stats.clients);
+        assert_eq!(Some(ClientId(2)), stats.largest_client);
+        assert_eq!(dec!(5), stats.largest_total);
+
+        stats.merge(&SummaryStatistics::default());
+        assert_eq!(Some(ClientId(2)), stats.largest_client);
+    }
+
+    #[test]
+    fn test_save_when_csv() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("stats.csv");
+        let stats = SummaryStatistics {
+            clients: 2,
+            total: dec!(15),
+            deposits: 2,
+            ..Default::default()
+        };
+        stats.save(&path)?;
+
+        let expected = "\
+            clients,total,held,locked,deposits,withdrawals,disputes,resolves,chargebacks,unlocks,skipped,largest_client,largest_total\n\
+            2,15,0,0,2,0,0,0,0,0,0,,0\n\
+        ";
+        assert_eq!(expected, std::fs::read_to_string(&path)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_when_json() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("stats.json");
+        let stats = SummaryStatistics {
+            clients: 1,
+            held: dec!(1.5),
+            ..Default::default()
+        };
+        stats.save(&path)?;
+
+        let saved: SummaryStatistics = serde_json::from_str(&std::fs::read_to_string(&path)?)?;
+        assert_eq!(stats, saved);
+
+        Ok(())
+    }
+}
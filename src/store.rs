@@ -1,17 +1,24 @@
 use std::collections::HashMap;
 
-use anyhow::{Error, Result};
+use anyhow::Result;
 use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
 use crate::ClientId;
 
 /// Internal state of a client's account
-#[derive(Debug)]
+///
+/// Balances are only changed through its methods, which refuse any operation on a locked
+/// account, any amount which is not positive, or any which would overflow a balance or the funds
+/// available, and never let held funds go negative, so every [`AccountStore`] shares the same
+/// account math.
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Account {
-    pub client: ClientId,
-    pub held: Decimal,
-    pub total: Decimal,
-    pub locked: bool,
+    client: ClientId,
+    held: Decimal,
+    total: Decimal,
+    locked: bool,
 }
 
 impl Account {
@@ -25,11 +32,271 @@ impl Account {
         }
     }
 
-    pub fn get_available(&self) -> Decimal {
-        self.total - self.held
+    /// Start building an account with existing balances, such as one being restored.
+    pub fn builder(client: ClientId) -> AccountBuilder {
+        AccountBuilder {
+            account: Account::empty(client),
+        }
+    }
+
+    pub fn client(&self) -> ClientId {
+        self.client
+    }
+
+    pub fn held(&self) -> Decimal {
+        self.held
+    }
+
+    pub fn total(&self) -> Decimal {
+        self.total
+    }
+
+    pub fn locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Funds available for withdrawal, i.e. `total - held`.
+    ///
+    /// Fails only if the balances were not set through the methods of the account, which never
+    /// let the available funds overflow.
+    pub fn get_available(&self) -> Result<Decimal, AccountError> {
+        self.total
+            .checked_sub(self.held)
+            .ok_or(AccountError::BalanceOverflow(self.client))
+    }
+
+    /// Adds funds to the account.
+    pub fn credit(&mut self, amount: Decimal) -> Result<(), AccountError> {
+        self.ensure_positive(amount)?;
+        self.ensure_unlocked()?;
+        self.update(amount, self.total.checked_add(amount), Some(self.held))
+    }
+
+    /// Removes available funds from the account, which may be overdrawn by up to `overdraft_limit`.
+    pub fn debit(&mut self, amount: Decimal, overdraft_limit: Decimal) -> Result<(), AccountError> {
+        self.ensure_positive(amount)?;
+        self.ensure_unlocked()?;
+        if amount > self.get_available()?.saturating_add(overdraft_limit) {
+            return Err(AccountError::InsufficientFunds {
+                client: self.client,
+                amount,
+            });
+        }
+        self.update(amount, self.total.checked_sub(amount), Some(self.held))
+    }
+
+    /// Holds funds for a dispute, even if that leaves less than nothing available.
+    pub fn hold(&mut self, amount: Decimal) -> Result<(), AccountError> {
+        self.ensure_positive(amount)?;
+        self.ensure_unlocked()?;
+        self.update(amount, Some(self.total), self.held.checked_add(amount))
+    }
+
+    /// Releases held funds back to those available.
+    pub fn release(&mut self, amount: Decimal) -> Result<(), AccountError> {
+        self.ensure_positive(amount)?;
+        self.ensure_unlocked()?;
+        self.ensure_held(amount)?;
+        self.update(amount, Some(self.total), self.held.checked_sub(amount))
+    }
+
+    /// Removes held funds from the account and locks it.
+    pub fn charge_back(&mut self, amount: Decimal) -> Result<(), AccountError> {
+        self.debit_held(amount)?;
+        self.locked = true;
+        Ok(())
+    }
+
+    /// Removes held funds from the account and locks it, even if it is already locked.
+    ///
+    /// Logs a warning when bypassing the lock, as a locked account is otherwise changed by
+    /// nothing but unlocking it.
+    pub fn force_charge_back(&mut self, amount: Decimal) -> Result<(), AccountError> {
+        if !self.locked {
+            return self.charge_back(amount);
+        }
+        self.ensure_positive(amount)?;
+        self.ensure_held(amount)?;
+        self.update(
+            amount,
+            self.total.checked_sub(amount),
+            self.held.checked_sub(amount),
+        )?;
+        log::warn!(
+            "Charged back {} from the locked account of client {}",
+            amount,
+            self.client
+        );
+        Ok(())
+    }
+
+    /// Adds funds to the account and holds them, such as a disputed withdrawal.
+    pub fn credit_held(&mut self, amount: Decimal) -> Result<(), AccountError> {
+        self.ensure_positive(amount)?;
+        self.ensure_unlocked()?;
+        self.update(
+            amount,
+            self.total.checked_add(amount),
+            self.held.checked_add(amount),
+        )
+    }
+
+    /// Removes held funds from the account, without locking it.
+    pub fn debit_held(&mut self, amount: Decimal) -> Result<(), AccountError> {
+        self.ensure_positive(amount)?;
+        self.ensure_unlocked()?;
+        self.ensure_held(amount)?;
+        self.update(
+            amount,
+            self.total.checked_sub(amount),
+            self.held.checked_sub(amount),
+        )
+    }
+
+    /// Locks the account, so that no further operations are applied until it is unlocked.
+    pub fn lock(&mut self) -> Result<(), AccountError> {
+        self.ensure_unlocked()?;
+        self.locked = true;
+        Ok(())
+    }
+
+    /// Unlocks a locked account.
+    pub fn unlock(&mut self) -> Result<(), AccountError> {
+        match self.locked {
+            true => {
+                self.locked = false;
+                Ok(())
+            }
+            false => Err(AccountError::NotLocked(self.client)),
+        }
+    }
+
+    /// Checks the balances are consistent, which the methods of the account maintain.
+    ///
+    /// Held funds must not be negative. They may exceed the total, such as when a deposit which
+    /// has since been withdrawn is disputed, and the total may be negative after a chargeback or
+    /// an overdraft, but the funds available must not overflow.
+    pub fn validate(&self) -> Result<(), AccountError> {
+        if self.held < Decimal::ZERO {
+            return Err(AccountError::NegativeHeldFunds {
+                client: self.client,
+                held: self.held,
+            });
+        }
+        self.get_available().map(|_| ())
+    }
+
+    fn ensure_positive(&self, amount: Decimal) -> Result<(), AccountError> {
+        match amount > Decimal::ZERO {
+            true => Ok(()),
+            false => Err(AccountError::NonPositiveAmount {
+                client: self.client,
+                amount,
+            }),
+        }
     }
+
+    fn ensure_unlocked(&self) -> Result<(), AccountError> {
+        match self.locked {
+            true => Err(AccountError::Locked(self.client)),
+            false => Ok(()),
+        }
+    }
+
+    fn ensure_no_overflow(
+        &self,
+        amount: Decimal,
+        balance: Option<Decimal>,
+    ) -> Result<Decimal, AccountError> {
+        balance.ok_or(AccountError::Overflow {
+            client: self.client,
+            amount,
+        })
+    }
+
+    /// Sets the balances, or fails without changing them if either balance, or the funds
+    /// available, would overflow.
+    fn update(
+        &mut self,
+        amount: Decimal,
+        total: Option<Decimal>,
+        held: Option<Decimal>,
+    ) -> Result<(), AccountError> {
+        let total = self.ensure_no_overflow(amount, total)?;
+        let held = self.ensure_no_overflow(amount, held)?;
+        self.ensure_no_overflow(amount, total.checked_sub(held))?;
+        self.total = total;
+        self.held = held;
+        Ok(())
+    }
+
+    fn ensure_held(&self, amount: Decimal) -> Result<(), AccountError> {
+        match amount > self.held {
+            true => Err(AccountError::InsufficientHeldFunds {
+                client: self.client,
+                amount,
+            }),
+            false => Ok(()),
+        }
+    }
+}
+
+/// Builds an [`Account`] with existing balances, checking they are consistent.
+#[derive(Debug, Clone)]
+pub struct AccountBuilder {
+    account: Account,
+}
+
+impl AccountBuilder {
+    pub fn held(mut self, held: Decimal) -> Self {
+        self.account.held = held;
+        self
+    }
+
+    pub fn total(mut self, total: Decimal) -> Self {
+        self.account.total = total;
+        self
+    }
+
+    pub fn locked(mut self, locked: bool) -> Self {
+        self.account.locked = locked;
+        self
+    }
+
+    /// Returns the account, or an error if its balances are not consistent, as checked by
+    /// [`Account::validate`].
+    pub fn build(self) -> Result<Account, AccountError> {
+        self.account.validate()?;
+        Ok(self.account)
+    }
+}
+
+/// Errors returned when an operation cannot be applied to an account.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum AccountError {
+    #[error("Account is locked for client {0}")]
+    Locked(ClientId),
+    #[error("Insufficient funds available to withdraw '{amount}' for client {client}")]
+    InsufficientFunds { client: ClientId, amount: Decimal },
+    #[error("Insufficient funds held to release '{amount}' for client {client}")]
+    InsufficientHeldFunds { client: ClientId, amount: Decimal },
+    #[error("Amount '{amount}' is not positive for client {client}")]
+    NonPositiveAmount { client: ClientId, amount: Decimal },
+    #[error("Held funds '{held}' are negative for client {client}")]
+    NegativeHeldFunds { client: ClientId, held: Decimal },
+    #[error("Account is not locked for client {0}")]
+    NotLocked(ClientId),
+    #[error("No such account for client {0}")]
+    NoSuchAccount(ClientId),
+    #[error("Amount '{amount}' would overflow the balances for client {client}")]
+    Overflow { client: ClientId, amount: Decimal },
+    #[error("Balances overflow for client {0}")]
+    BalanceOverflow(ClientId),
 }
 
+/// Visits an account lent by [`AccountStore::for_each_account`], failing to stop the visit.
+pub type AccountVisitor<'a> = dyn FnMut(&Account) -> Result<()> + 'a;
+
 /// A trait for any account store implementation.
 #[cfg_attr(test, mockall::automock)]
 pub trait AccountStore {
@@ -39,103 +306,504 @@ pub trait AccountStore {
     /// Removes funds from a client's account.
     fn remove_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()>;
 
-    /// Removes funds from a client's account even if insufficient funds are available and freezes the account.
-    fn force_remove_funds_and_lock(&mut self, client: ClientId, amount: Decimal) -> Result<()>;
+    /// Removes held funds from both the held and total funds of a client's account, even if
+    /// that leaves the total negative, and freezes the account.
+    ///
+    /// Fails if the account is locked or less than `amount` is held.
+    fn chargeback_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()>;
+
+    /// Charges back held funds like [`AccountStore::chargeback_funds`], even if the account is
+    /// already locked, logging a warning when it is.
+    ///
+    /// Fails if less than `amount` is held.
+    fn force_chargeback_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()>;
 
     /// Holds funds from a client's account.
     fn hold_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()>;
 
     /// Release held funds to a client's account.
+    ///
+    /// Fails if the account is locked or less than `amount` is held.
     fn release_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()>;
 
-    /// Exports all accounts as an iterator, consuming the store.
+    /// Adds funds to a client's account and holds them, such as a disputed withdrawal.
+    ///
+    /// Fails if the account is locked.
+    fn credit_held_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()>;
+
+    /// Removes held funds from both the held and total funds of a client's account, without
+    /// freezing it.
+    ///
+    /// Fails if the account is locked or less than `amount` is held.
+    fn remove_held_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()>;
+
+    /// Freezes a client's account.
+    ///
+    /// Fails if the account does not exist or is already locked.
+    fn lock(&mut self, client: ClientId) -> Result<()>;
+
+    /// Unfreezes a client's locked account.
+    ///
+    /// Fails if the account does not exist or is not locked.
+    fn unlock(&mut self, client: ClientId) -> Result<()>;
+
+    /// Returns a copy of a client's account, if they have one.
+    fn account(&self, client: ClientId) -> Option<Account>;
+
+    /// Visits all accounts, in ascending client order, without consuming the store, stopping
+    /// at the first error returned by `visit`.
+    ///
+    /// Stores which keep their accounts in memory lend them rather than copying them.
+    fn for_each_account<'a>(&self, visit: &mut AccountVisitor<'a>) -> Result<()>;
+
+    /// Exports all accounts as an iterator, in ascending client order, consuming the store.
     fn export(self) -> Box<dyn Iterator<Item = Account>>;
 }
 
 /// An in-memory implementation of [`AccountStore`].
+#[derive(Default, Clone, Deserialize, Serialize)]
 pub struct InMemoryAccountStore {
     accounts: HashMap<ClientId, Account>,
+    #[serde(default)]
+    overdraft_limit: Decimal,
 }
 
 impl InMemoryAccountStore {
     /// Construct a new [`InMemoryAccountStore`].
     pub fn new() -> Self {
+        InMemoryAccountStore::default()
+    }
+
+    /// Construct a new [`InMemoryAccountStore`] with space for at least `capacity` clients.
+    ///
+    /// Pre-allocating avoids rehashing as the number of clients grows.
+    pub fn with_capacity(capacity: usize) -> Self {
         InMemoryAccountStore {
-            accounts: HashMap::new(),
+            accounts: HashMap::with_capacity(capacity),
+            ..Default::default()
         }
     }
 
-    fn get_account(&mut self, client: ClientId) -> Result<&mut Account> {
-        let account = self
-            .accounts
-            .entry(client)
-            .or_insert_with(|| Account::empty(client));
-        match account.locked {
-            true => Err(Error::msg(format!("Account is locked: {:?}", account))),
-            false => Ok(account),
+    /// Construct a new [`InMemoryAccountStore`] which allows withdrawals to overdraw an
+    /// account by up to `overdraft_limit`.
+    ///
+    /// Only withdrawals may use the overdraft; holding funds for a dispute is unaffected.
+    pub fn with_overdraft_limit(overdraft_limit: Decimal) -> Self {
+        InMemoryAccountStore {
+            overdraft_limit,
+            ..Default::default()
         }
     }
+
+    /// Applies an operation to a copy of the client's account, which is only stored if it
+    /// succeeds, so a failed operation never opens an account or leaves it half updated.
+    fn update(
+        &mut self,
+        client: ClientId,
+        operation: impl FnOnce(&mut Account) -> Result<(), AccountError>,
+    ) -> Result<()> {
+        let mut account = self
+            .accounts
+            .get(&client)
+            .cloned()
+            .unwrap_or_else(|| Account::empty(client));
+        operation(&mut account)?;
+        debug_assert_eq!(Ok(()), account.validate());
+        self.accounts.insert(client, account);
+        Ok(())
+    }
 }
 
 impl AccountStore for InMemoryAccountStore {
     fn add_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
-        let account = self.get_account(client)?;
-        account.total += amount;
-        Ok(())
+        self.update(client, |account| account.credit(amount))
     }
 
     fn remove_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
-        let account = self.get_account(client)?;
-        if amount > account.get_available() {
-            return Err(Error::msg(format!(
-                "Insufficient funds available to withdraw '{}' for {:?}",
-                amount, account
-            )));
+        let overdraft_limit = self.overdraft_limit;
+        // An unknown client has no funds, so fail without opening an account for them
+        if amount > overdraft_limit && !self.accounts.contains_key(&client) {
+            return Err(AccountError::InsufficientFunds { client, amount }.into());
         }
-        account.total -= amount;
-        Ok(())
+        self.update(client, |account| account.debit(amount, overdraft_limit))
     }
 
-    fn force_remove_funds_and_lock(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
-        let account = self.get_account(client)?;
-        account.held -= amount;
-        account.total -= amount;
-        account.locked = true;
-        Ok(())
+    fn chargeback_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.update(client, |account| account.charge_back(amount))
+    }
+
+    fn force_chargeback_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.update(client, |account| account.force_charge_back(amount))
     }
 
     fn hold_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
-        let account = self.get_account(client)?;
-        account.held += amount;
-        Ok(())
+        self.update(client, |account| account.hold(amount))
     }
 
     fn release_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
-        let account = self.get_account(client)?;
-        account.held -= amount;
-        Ok(())
+        self.update(client, |account| account.release(amount))
+    }
+
+    fn credit_held_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.update(client, |account| account.credit_held(amount))
+    }
+
+    fn remove_held_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.update(client, |account| account.debit_held(amount))
+    }
+
+    fn lock(&mut self, client: ClientId) -> Result<()> {
+        match self.accounts.get_mut(&client) {
+            Some(account) => Ok(account.lock()?),
+            None => Err(AccountError::NoSuchAccount(client).into()),
+        }
+    }
+
+    fn unlock(&mut self, client: ClientId) -> Result<()> {
+        match self.accounts.get_mut(&client) {
+            Some(account) => Ok(account.unlock()?),
+            None => Err(AccountError::NoSuchAccount(client).into()),
+        }
+    }
+
+    fn account(&self, client: ClientId) -> Option<Account> {
+        self.accounts.get(&client).cloned()
     }
 
-    fn export(self) -> Box<dyn Iterator<Item = Account>> {
-        Box::new(self.accounts.into_iter().map(|(_, account)| account))
+    fn for_each_account(&self, visit: &mut AccountVisitor) -> Result<()> {
+        let mut accounts: Vec<&Account> = self.accounts.values().collect();
+        accounts.sort_unstable_by_key(|account| account.client());
+        accounts.into_iter().try_for_each(visit)
+    }
+
+    fn export(mut self) -> Box<dyn Iterator<Item = Account>> {
+        // only the clients are sorted, each account is moved out of the map as it is exported
+        let mut clients: Vec<ClientId> = self.accounts.keys().copied().collect();
+        clients.sort_unstable();
+        Box::new(
+            clients
+                .into_iter()
+                .filter_map(move |client| self.accounts.remove(&client)),
+        )
     }
 }
 
 #[cfg(test)]
 mod test {
     use rust_decimal_macros::dec;
+    use test_case::test_case;
 
     use super::*;
 
+    fn locked_account() -> Account {
+        Account::builder(ClientId(1))
+            .held(dec!(5))
+            .total(dec!(20))
+            .locked(true)
+            .build()
+            .unwrap()
+    }
+
+    fn assert_balances(account: &Account, held: Decimal, total: Decimal, locked: bool) {
+        assert_eq!(held, account.held());
+        assert_eq!(total, account.total());
+        assert_eq!(locked, account.locked());
+    }
+
+    #[test]
+    fn test_builder() {
+        let account = locked_account();
+        assert_eq!(ClientId(1), account.client());
+        assert_eq!(dec!(15), account.get_available().unwrap());
+        assert_balances(&account, dec!(5), dec!(20), true);
+    }
+
     #[test]
-    fn test_get_account() {
+    fn test_builder_when_held_negative() {
+        let result = Account::builder(ClientId(1)).held(dec!(-0.01)).build();
+        assert_eq!(
+            AccountError::NegativeHeldFunds {
+                client: ClientId(1),
+                held: dec!(-0.01)
+            },
+            result.unwrap_err()
+        );
+    }
+
+    #[test]
+    fn test_builder_when_available_overflows() {
+        let result = Account::builder(ClientId(1))
+            .held(Decimal::MAX)
+            .total(-Decimal::MAX)
+            .build();
+        assert_eq!(
+            AccountError::BalanceOverflow(ClientId(1)),
+            result.unwrap_err()
+        );
+    }
+
+    #[test]
+    fn test_get_available_when_overflows() -> Result<()> {
+        let account: Account = serde_json::from_str(&format!(
+            r#"{{"client":1,"held":"{max}","total":"-{max}","locked":false}}"#,
+            max = Decimal::MAX
+        ))?;
+        assert_eq!(
+            Err(AccountError::BalanceOverflow(ClientId(1))),
+            account.get_available()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_hold_when_available_would_overflow_returns_error() {
+        let mut account = Account::builder(ClientId(1))
+            .total(-Decimal::MAX)
+            .build()
+            .unwrap();
+        assert_eq!(
+            Err(AccountError::Overflow {
+                client: ClientId(1),
+                amount: dec!(1)
+            }),
+            account.hold(dec!(1))
+        );
+        assert_balances(&account, dec!(0), -Decimal::MAX, false);
+    }
+
+    #[test]
+    fn test_credit() {
+        let mut account = Account::empty(ClientId(1));
+        assert_eq!(Ok(()), account.credit(dec!(20)));
+        assert_eq!(Ok(()), account.credit(dec!(0.5)));
+        assert_balances(&account, dec!(0), dec!(20.5), false);
+    }
+
+    #[test_case(|account| account.credit(dec!(1));      "when credited")]
+    #[test_case(|account| account.hold(dec!(1));        "when held")]
+    #[test_case(|account| account.credit_held(dec!(1)); "when credited and held")]
+    fn test_when_balance_would_overflow_returns_error(
+        operation: fn(&mut Account) -> Result<(), AccountError>,
+    ) {
+        let mut account = Account::builder(ClientId(1))
+            .held(Decimal::MAX)
+            .total(Decimal::MAX)
+            .build()
+            .unwrap();
+        assert_eq!(
+            Err(AccountError::Overflow {
+                client: ClientId(1),
+                amount: dec!(1)
+            }),
+            operation(&mut account)
+        );
+        assert_balances(&account, Decimal::MAX, Decimal::MAX, false);
+    }
+
+    #[test]
+    fn test_credit_held_when_held_would_overflow_leaves_total() {
+        let mut account = Account::builder(ClientId(1))
+            .held(Decimal::MAX)
+            .build()
+            .unwrap();
+        assert!(account.credit_held(dec!(1)).is_err());
+        assert_balances(&account, Decimal::MAX, dec!(0), false);
+    }
+
+    #[test_case(dec!(20),    dec!(0),  true;  "when exactly available")]
+    #[test_case(dec!(20.01), dec!(0),  false; "when over available")]
+    #[test_case(dec!(25),    dec!(10), true;  "when within overdraft limit")]
+    #[test_case(dec!(30.01), dec!(10), false; "when over overdraft limit")]
+    fn test_debit(amount: Decimal, overdraft_limit: Decimal, allowed: bool) {
+        let mut account = Account::builder(ClientId(1))
+            .held(dec!(5))
+            .total(dec!(25))
+            .build()
+            .unwrap();
+        let result = account.debit(amount, overdraft_limit);
+
+        match allowed {
+            true => {
+                assert_eq!(Ok(()), result);
+                assert_balances(&account, dec!(5), dec!(25) - amount, false);
+            }
+            false => {
+                let client = ClientId(1);
+                assert_eq!(
+                    Err(AccountError::InsufficientFunds { client, amount }),
+                    result
+                );
+                assert_balances(&account, dec!(5), dec!(25), false);
+            }
+        }
+    }
+
+    #[test]
+    fn test_hold_and_release() {
+        let mut account = Account::empty(ClientId(1));
+        account.credit(dec!(20)).unwrap();
+        assert_eq!(Ok(()), account.hold(dec!(25)));
+        assert_balances(&account, dec!(25), dec!(20), false);
+        assert_eq!(dec!(-5), account.get_available().unwrap());
+
+        assert_eq!(Ok(()), account.release(dec!(25)));
+        assert_balances(&account, dec!(0), dec!(20), false);
+    }
+
+    #[test]
+    fn test_release_when_insufficient_held() {
+        let mut account = Account::empty(ClientId(1));
+        account.hold(dec!(5)).unwrap();
+        assert_eq!(
+            Err(AccountError::InsufficientHeldFunds {
+                client: ClientId(1),
+                amount: dec!(5.01)
+            }),
+            account.release(dec!(5.01))
+        );
+        assert_balances(&account, dec!(5), dec!(0), false);
+    }
+
+    #[test]
+    fn test_charge_back() {
+        let mut account = Account::empty(ClientId(1));
+        account.credit(dec!(20)).unwrap();
+        account.hold(dec!(5)).unwrap();
+        assert_eq!(Ok(()), account.charge_back(dec!(5)));
+        assert_balances(&account, dec!(0), dec!(15), true);
+    }
+
+    #[test]
+    fn test_charge_back_when_insufficient_held() {
+        let mut account = Account::empty(ClientId(1));
+        account.credit(dec!(20)).unwrap();
+        assert_eq!(
+            Err(AccountError::InsufficientHeldFunds {
+                client: ClientId(1),
+                amount: dec!(5)
+            }),
+            account.charge_back(dec!(5))
+        );
+        assert_balances(&account, dec!(0), dec!(20), false);
+    }
+
+    #[test]
+    fn test_force_charge_back_when_locked() {
+        testing_logger::setup();
+        let mut account = Account::empty(ClientId(1));
+        account.credit(dec!(20)).unwrap();
+        account.hold(dec!(15)).unwrap();
+        account.charge_back(dec!(5)).unwrap();
+
+        assert_eq!(Ok(()), account.force_charge_back(dec!(10)));
+        assert_balances(&account, dec!(0), dec!(5), true);
+        testing_logger::validate(|captured_logs| {
+            let warnings: Vec<&str> = captured_logs
+                .iter()
+                .filter(|log| log.level == log::Level::Warn)
+                .map(|log| log.body.as_str())
+                .collect();
+            assert_eq!(
+                vec!["Charged back 10 from the locked account of client 1"],
+                warnings
+            );
+        });
+    }
+
+    #[test]
+    fn test_force_charge_back_when_unlocked() {
+        testing_logger::setup();
+        let mut account = Account::empty(ClientId(1));
+        account.credit(dec!(20)).unwrap();
+        account.hold(dec!(5)).unwrap();
+
+        assert_eq!(Ok(()), account.force_charge_back(dec!(5)));
+        assert_balances(&account, dec!(0), dec!(15), true);
+        testing_logger::validate(|captured_logs| assert!(captured_logs.is_empty()));
+    }
+
+    #[test]
+    fn test_force_charge_back_when_locked_and_insufficient_held() {
+        let mut account = locked_account();
+        assert_eq!(
+            Err(AccountError::InsufficientHeldFunds {
+                client: ClientId(1),
+                amount: dec!(10)
+            }),
+            account.force_charge_back(dec!(10))
+        );
+        assert_balances(&account, dec!(5), dec!(20), true);
+    }
+
+    #[test]
+    fn test_credit_held_and_debit_held() {
+        let mut account = Account::empty(ClientId(1));
+        account.credit(dec!(10)).unwrap();
+        account.credit_held(dec!(4)).unwrap();
+        assert_balances(&account, dec!(4), dec!(14), false);
+        assert_eq!(dec!(10), account.get_available().unwrap());
+
+        account.debit_held(dec!(4)).unwrap();
+        assert_balances(&account, dec!(0), dec!(10), false);
+    }
+
+    #[test]
+    fn test_debit_held_when_insufficient_held() {
+        let mut account = Account::empty(ClientId(1));
+        account.credit_held(dec!(4)).unwrap();
+        assert_eq!(
+            Err(AccountError::InsufficientHeldFunds {
+                client: ClientId(1),
+                amount: dec!(5)
+            }),
+            account.debit_held(dec!(5))
+        );
+        assert_balances(&account, dec!(4), dec!(4), false);
+    }
+
+    #[test]
+    fn test_lock_and_unlock() {
+        let mut account = Account::empty(ClientId(1));
+        assert_eq!(Err(AccountError::NotLocked(ClientId(1))), account.unlock());
+
+        assert_eq!(Ok(()), account.lock());
+        assert!(account.locked());
+        assert_eq!(Ok(()), account.unlock());
+        assert!(!account.locked());
+    }
+
+    #[test_case(|account| account.credit(dec!(1));              "when credit")]
+    #[test_case(|account| account.debit(dec!(1), dec!(0));      "when debit")]
+    #[test_case(|account| account.hold(dec!(1));                "when hold")]
+    #[test_case(|account| account.release(dec!(1));             "when release")]
+    #[test_case(|account| account.charge_back(dec!(1));         "when charge back")]
+    #[test_case(|account| account.credit_held(dec!(1));         "when credit held")]
+    #[test_case(|account| account.debit_held(dec!(1));          "when debit held")]
+    #[test_case(Account::lock;                                  "when lock")]
+    fn test_when_locked_returns_error(operation: fn(&mut Account) -> Result<(), AccountError>) {
+        let mut account = locked_account();
+        assert_eq!(
+            Err(AccountError::Locked(ClientId(1))),
+            operation(&mut account)
+        );
+        assert_balances(&account, dec!(5), dec!(20), true);
+    }
+
+    #[test]
+    fn test_add_funds_when_locked() -> Result<()> {
         let mut store = InMemoryAccountStore::new();
-        let result = store.get_account(ClientId(1));
-        assert_eq!(true, result.is_ok());
+        store.add_funds(ClientId(1), dec!(20))?;
+        store.hold_funds(ClientId(1), dec!(5))?;
+        store.chargeback_funds(ClientId(1), dec!(5))?;
 
-        result.unwrap().locked = true;
-        let result = store.get_account(ClientId(1));
-        assert_eq!(true, result.is_err());
+        let result = store.add_funds(ClientId(1), dec!(1));
+        assert_eq!(
+            Some(&AccountError::Locked(ClientId(1))),
+            result.unwrap_err().downcast_ref()
+        );
+        assert_eq!(dec!(15), store.account(ClientId(1)).unwrap().total());
+
+        Ok(())
     }
 
     #[test]
@@ -144,9 +812,40 @@ mod test {
         store.add_funds(ClientId(2), dec!(20))?;
         store.add_funds(ClientId(2), dec!(5))?;
 
-        let account = store.get_account(ClientId(2))?;
-        assert_eq!(dec!(25), account.total);
-        assert_eq!(dec!(0), account.held);
+        let account = store.account(ClientId(2)).unwrap();
+        assert_eq!(dec!(25), account.total());
+        assert_eq!(dec!(0), account.held());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_funds_when_total_would_overflow() -> Result<()> {
+        let mut store = InMemoryAccountStore::new();
+        store.add_funds(ClientId(2), Decimal::MAX - dec!(1))?;
+        let err = store.add_funds(ClientId(2), dec!(2)).unwrap_err();
+        assert_eq!(
+            Some(&AccountError::Overflow {
+                client: ClientId(2),
+                amount: dec!(2)
+            }),
+            err.downcast_ref::<AccountError>()
+        );
+
+        let account = store.account(ClientId(2)).unwrap();
+        assert_eq!(Decimal::MAX - dec!(1), account.total());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_funds_when_overdraft_would_overflow() -> Result<()> {
+        let mut store = InMemoryAccountStore::with_overdraft_limit(Decimal::MAX);
+        store.remove_funds(ClientId(2), Decimal::MAX)?;
+        assert!(store.remove_funds(ClientId(2), dec!(1)).is_err());
+
+        let account = store.account(ClientId(2)).unwrap();
+        assert_eq!(-Decimal::MAX, account.total());
 
         Ok(())
     }
@@ -157,22 +856,84 @@ mod test {
         store.add_funds(ClientId(2), dec!(20))?;
         store.remove_funds(ClientId(2), dec!(5))?;
 
-        let account = store.get_account(ClientId(2))?;
-        assert_eq!(dec!(15), account.total);
-        assert_eq!(dec!(0), account.held);
+        let account = store.account(ClientId(2)).unwrap();
+        assert_eq!(dec!(15), account.total());
+        assert_eq!(dec!(0), account.held());
 
         Ok(())
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_remove_funds_when_insufficient_available() -> Result<()> {
         let mut store = InMemoryAccountStore::new();
         store.add_funds(ClientId(2), dec!(20))?;
         assert_eq!(true, store.remove_funds(ClientId(2), dec!(100)).is_err());
 
-        let account = store.get_account(ClientId(2))?;
-        assert_eq!(dec!(20), account.total);
-        assert_eq!(dec!(0), account.held);
+        let account = store.account(ClientId(2)).unwrap();
+        assert_eq!(dec!(20), account.total());
+        assert_eq!(dec!(0), account.held());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_funds_when_no_account_does_not_open_account() {
+        let mut store = InMemoryAccountStore::new();
+        let result = store.remove_funds(ClientId(2), dec!(1));
+        assert_eq!(
+            Some(&AccountError::InsufficientFunds {
+                client: ClientId(2),
+                amount: dec!(1)
+            }),
+            result.unwrap_err().downcast_ref()
+        );
+        assert_eq!(0, store.export().count());
+    }
+
+    #[test_case(dec!(30),    true;  "when exactly at limit")]
+    #[test_case(dec!(30.01), false; "when one cent over limit")]
+    fn test_remove_funds_when_overdraft_limit(amount: Decimal, allowed: bool) -> Result<()> {
+        let mut store = InMemoryAccountStore::with_overdraft_limit(dec!(10));
+        store.add_funds(ClientId(2), dec!(20))?;
+        assert_eq!(allowed, store.remove_funds(ClientId(2), amount).is_ok());
+
+        let account = store.account(ClientId(2)).unwrap();
+        let expected = if allowed { dec!(20) - amount } else { dec!(20) };
+        assert_eq!(expected, account.total());
+        assert_eq!(dec!(0), account.held());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_funds_when_overdraft_limit_and_funds_held() -> Result<()> {
+        let mut store = InMemoryAccountStore::with_overdraft_limit(dec!(10));
+        store.add_funds(ClientId(2), dec!(20))?;
+        store.remove_funds(ClientId(2), dec!(25))?;
+        // holding funds for a dispute ignores the overdraft, so may exceed it
+        store.hold_funds(ClientId(2), dec!(20))?;
+        assert!(store.remove_funds(ClientId(2), dec!(0.01)).is_err());
+
+        let account = store.account(ClientId(2)).unwrap();
+        assert_eq!(dec!(-5), account.total());
+        assert_eq!(dec!(20), account.held());
+        assert_eq!(dec!(-25), account.get_available().unwrap());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_funds_when_overdraft_limit_and_no_account() -> Result<()> {
+        let mut store = InMemoryAccountStore::with_overdraft_limit(dec!(10));
+        assert!(store.remove_funds(ClientId(2), dec!(10.01)).is_err());
+        assert!(store.accounts.is_empty());
+
+        store.remove_funds(ClientId(2), dec!(10))?;
+        assert_eq!(
+            dec!(-10),
+            store.account(ClientId(2)).unwrap().get_available().unwrap()
+        );
 
         Ok(())
     }
@@ -183,10 +944,68 @@ mod test {
         store.add_funds(ClientId(2), dec!(20))?;
         store.hold_funds(ClientId(2), dec!(25))?;
 
-        let account = store.get_account(ClientId(2))?;
-        assert_eq!(dec!(20), account.total);
-        assert_eq!(dec!(25), account.held);
-        assert_eq!(dec!(-5), account.get_available());
+        let account = store.account(ClientId(2)).unwrap();
+        assert_eq!(dec!(20), account.total());
+        assert_eq!(dec!(25), account.held());
+        assert_eq!(dec!(-5), account.get_available().unwrap());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unlock() -> Result<()> {
+        let mut store = InMemoryAccountStore::new();
+        store.add_funds(ClientId(2), dec!(20))?;
+        store.hold_funds(ClientId(2), dec!(5))?;
+        store.chargeback_funds(ClientId(2), dec!(5))?;
+        assert!(store.add_funds(ClientId(2), dec!(1)).is_err());
+
+        store.unlock(ClientId(2))?;
+        store.add_funds(ClientId(2), dec!(1))?;
+
+        let account = store.account(ClientId(2)).unwrap();
+        assert_eq!(dec!(16), account.total());
+        assert!(!account.locked());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lock() -> Result<()> {
+        let mut store = InMemoryAccountStore::new();
+        assert!(store.lock(ClientId(2)).is_err());
+
+        store.add_funds(ClientId(2), dec!(20))?;
+        store.lock(ClientId(2))?;
+        assert!(store.account(ClientId(2)).unwrap().locked());
+        assert!(store.lock(ClientId(2)).is_err());
+        assert!(store.add_funds(ClientId(2), dec!(1)).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_account() -> Result<()> {
+        let mut store = InMemoryAccountStore::new();
+        assert!(store.account(ClientId(2)).is_none());
+
+        store.add_funds(ClientId(2), dec!(20))?;
+        store.hold_funds(ClientId(2), dec!(5))?;
+        let account = store.account(ClientId(2)).unwrap();
+        assert_eq!(dec!(20), account.total());
+        assert_eq!(dec!(5), account.held());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unlock_when_not_locked_or_no_account() -> Result<()> {
+        let mut store = InMemoryAccountStore::new();
+        assert!(store.unlock(ClientId(2)).is_err());
+        assert!(store.accounts.is_empty());
+
+        store.add_funds(ClientId(2), dec!(20))?;
+        assert!(store.unlock(ClientId(2)).is_err());
 
         Ok(())
     }
@@ -198,10 +1017,298 @@ mod test {
         store.hold_funds(ClientId(2), dec!(25))?;
         store.release_funds(ClientId(2), dec!(25))?;
 
-        let account = store.get_account(ClientId(2))?;
-        assert_eq!(dec!(20), account.total);
-        assert_eq!(dec!(0), account.held);
-        assert_eq!(dec!(20), account.get_available());
+        let account = store.account(ClientId(2)).unwrap();
+        assert_eq!(dec!(20), account.total());
+        assert_eq!(dec!(0), account.held());
+        assert_eq!(dec!(20), account.get_available().unwrap());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_release_funds_when_held_would_go_negative() -> Result<()> {
+        let mut store = InMemoryAccountStore::new();
+        store.add_funds(ClientId(2), dec!(20))?;
+        store.hold_funds(ClientId(2), dec!(5))?;
+
+        let result = store.release_funds(ClientId(2), dec!(5.01));
+        assert_eq!(
+            Some(&AccountError::InsufficientHeldFunds {
+                client: ClientId(2),
+                amount: dec!(5.01)
+            }),
+            result.unwrap_err().downcast_ref()
+        );
+        assert_eq!(dec!(5), store.account(ClientId(2)).unwrap().held());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_chargeback_funds() -> Result<()> {
+        let mut store = InMemoryAccountStore::new();
+        store.add_funds(ClientId(2), dec!(5))?;
+        store.remove_funds(ClientId(2), dec!(2))?;
+        store.hold_funds(ClientId(2), dec!(5))?;
+        store.chargeback_funds(ClientId(2), dec!(5))?;
+
+        let account = store.account(ClientId(2)).unwrap();
+        assert_eq!(dec!(0), account.held());
+        assert_eq!(dec!(-2), account.total());
+        assert!(account.locked());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_chargeback_funds_when_held_would_go_negative() -> Result<()> {
+        let mut store = InMemoryAccountStore::new();
+        store.add_funds(ClientId(2), dec!(20))?;
+        store.hold_funds(ClientId(2), dec!(5))?;
+
+        assert!(store.chargeback_funds(ClientId(2), dec!(10)).is_err());
+
+        let account = store.account(ClientId(2)).unwrap();
+        assert_eq!(dec!(5), account.held());
+        assert_eq!(dec!(20), account.total());
+        assert!(!account.locked());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_force_chargeback_funds_when_locked() -> Result<()> {
+        let mut store = InMemoryAccountStore::new();
+        store.add_funds(ClientId(2), dec!(20))?;
+        store.hold_funds(ClientId(2), dec!(15))?;
+        store.chargeback_funds(ClientId(2), dec!(5))?;
+        store.force_chargeback_funds(ClientId(2), dec!(10))?;
+
+        let account = store.account(ClientId(2)).unwrap();
+        assert_eq!(dec!(0), account.held());
+        assert_eq!(dec!(5), account.total());
+        assert!(account.locked());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_force_chargeback_funds_when_total_goes_negative() -> Result<()> {
+        let mut store = InMemoryAccountStore::new();
+        store.add_funds(ClientId(2), dec!(10))?;
+        store.remove_funds(ClientId(2), dec!(8))?;
+        store.hold_funds(ClientId(2), dec!(4))?;
+        store.hold_funds(ClientId(2), dec!(6))?;
+        store.chargeback_funds(ClientId(2), dec!(4))?;
+        store.force_chargeback_funds(ClientId(2), dec!(6))?;
+
+        let account = store.account(ClientId(2)).unwrap();
+        assert_eq!(dec!(0), account.held());
+        assert_eq!(dec!(-8), account.total());
+        assert!(account.locked());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_release_and_chargeback_funds_when_locked() -> Result<()> {
+        let mut store = InMemoryAccountStore::new();
+        store.add_funds(ClientId(2), dec!(20))?;
+        store.hold_funds(ClientId(2), dec!(15))?;
+        store.chargeback_funds(ClientId(2), dec!(5))?;
+
+        for result in [
+            store.release_funds(ClientId(2), dec!(5)),
+            store.chargeback_funds(ClientId(2), dec!(5)),
+        ] {
+            assert_eq!(
+                Some(&AccountError::Locked(ClientId(2))),
+                result.unwrap_err().downcast_ref()
+            );
+        }
+        assert_eq!(dec!(10), store.account(ClientId(2)).unwrap().held());
+
+        Ok(())
+    }
+
+    type AccountOperation = fn(&mut Account, Decimal) -> Result<(), AccountError>;
+
+    #[test_case(dec!(0);     "when zero")]
+    #[test_case(dec!(-0.01); "when negative")]
+    fn test_when_amount_not_positive_returns_error(amount: Decimal) {
+        let operations: [AccountOperation; 8] = [
+            Account::credit,
+            |account, amount| account.debit(amount, dec!(100)),
+            Account::hold,
+            Account::release,
+            Account::charge_back,
+            Account::force_charge_back,
+            Account::credit_held,
+            Account::debit_held,
+        ];
+        for operation in operations {
+            let mut account = Account::builder(ClientId(1))
+                .held(dec!(5))
+                .total(dec!(20))
+                .build()
+                .unwrap();
+            assert_eq!(
+                Err(AccountError::NonPositiveAmount {
+                    client: ClientId(1),
+                    amount
+                }),
+                operation(&mut account, amount)
+            );
+            assert_balances(&account, dec!(5), dec!(20), false);
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(Ok(()), locked_account().validate());
+        // held funds may exceed the total, such as a dispute of a deposit since withdrawn
+        let account = Account::builder(ClientId(1))
+            .held(dec!(20))
+            .total(dec!(-5))
+            .build()
+            .unwrap();
+        assert_eq!(Ok(()), account.validate());
+    }
+
+    type StoreOperation = fn(&mut InMemoryAccountStore, ClientId, Decimal) -> Result<()>;
+
+    #[test_case(InMemoryAccountStore::add_funds,         dec!(0);     "when add funds zero")]
+    #[test_case(InMemoryAccountStore::add_funds,         dec!(-1);    "when add funds negative")]
+    #[test_case(InMemoryAccountStore::remove_funds,      dec!(0);     "when remove funds zero")]
+    #[test_case(InMemoryAccountStore::remove_funds,      dec!(-1);    "when remove funds negative")]
+    #[test_case(InMemoryAccountStore::hold_funds,        dec!(0);     "when hold funds zero")]
+    #[test_case(InMemoryAccountStore::hold_funds,        dec!(-1);    "when hold funds negative")]
+    #[test_case(InMemoryAccountStore::release_funds,     dec!(0);     "when release funds zero")]
+    #[test_case(InMemoryAccountStore::release_funds,     dec!(-1);    "when release funds negative")]
+    #[test_case(InMemoryAccountStore::chargeback_funds,  dec!(0);     "when chargeback funds zero")]
+    #[test_case(InMemoryAccountStore::chargeback_funds,  dec!(-1);    "when chargeback funds negative")]
+    #[test_case(InMemoryAccountStore::credit_held_funds, dec!(0);     "when credit held funds zero")]
+    #[test_case(InMemoryAccountStore::credit_held_funds, dec!(-1);    "when credit held funds negative")]
+    #[test_case(InMemoryAccountStore::remove_held_funds, dec!(0);     "when remove held funds zero")]
+    #[test_case(InMemoryAccountStore::remove_held_funds, dec!(-1);    "when remove held funds negative")]
+    fn test_store_when_amount_not_positive(
+        operation: StoreOperation,
+        amount: Decimal,
+    ) -> Result<()> {
+        let mut store = InMemoryAccountStore::with_overdraft_limit(dec!(10));
+        store.add_funds(ClientId(1), dec!(20))?;
+        store.hold_funds(ClientId(1), dec!(5))?;
+
+        let result = operation(&mut store, ClientId(1), amount);
+        assert_eq!(
+            Some(&AccountError::NonPositiveAmount {
+                client: ClientId(1),
+                amount
+            }),
+            result.unwrap_err().downcast_ref()
+        );
+        assert_balances(
+            &store.account(ClientId(1)).unwrap(),
+            dec!(5),
+            dec!(20),
+            false,
+        );
+
+        // a failed operation never opens an account
+        assert!(operation(&mut store, ClientId(2), amount).is_err());
+        assert!(store.account(ClientId(2)).is_none());
+
+        Ok(())
+    }
+
+    #[test_case(InMemoryAccountStore::release_funds;     "when release funds")]
+    #[test_case(InMemoryAccountStore::chargeback_funds;  "when chargeback funds")]
+    #[test_case(InMemoryAccountStore::remove_held_funds; "when remove held funds")]
+    fn test_store_when_held_would_go_negative(operation: StoreOperation) -> Result<()> {
+        let mut store = InMemoryAccountStore::new();
+        store.add_funds(ClientId(1), dec!(20))?;
+        store.hold_funds(ClientId(1), dec!(5))?;
+        store.release_funds(ClientId(1), dec!(3))?;
+
+        let result = operation(&mut store, ClientId(1), dec!(2.01));
+        assert_eq!(
+            Some(&AccountError::InsufficientHeldFunds {
+                client: ClientId(1),
+                amount: dec!(2.01)
+            }),
+            result.unwrap_err().downcast_ref()
+        );
+        let account = store.account(ClientId(1)).unwrap();
+        assert_eq!(dec!(2), account.held());
+        assert_eq!(Ok(()), account.validate());
+
+        // no account holds funds to release or charge back
+        assert!(operation(&mut store, ClientId(2), dec!(1)).is_err());
+        assert!(store.account(ClientId(2)).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_in_client_order() -> Result<()> {
+        let mut store = InMemoryAccountStore::new();
+        for client in [300, 7, 65535, 0, 42] {
+            store.add_funds(ClientId(client), dec!(1))?;
+        }
+
+        let clients: Vec<_> = store.export().map(|account| account.client()).collect();
+        assert_eq!(
+            vec![
+                ClientId(0),
+                ClientId(7),
+                ClientId(42),
+                ClientId(300),
+                ClientId(65535)
+            ],
+            clients
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_for_each_account_in_client_order() -> Result<()> {
+        let mut store = InMemoryAccountStore::new();
+        for client in [300, 7, 0] {
+            store.add_funds(ClientId(client), dec!(1))?;
+        }
+
+        let mut clients = Vec::new();
+        store.for_each_account(&mut |account| {
+            clients.push(account.client());
+            Ok(())
+        })?;
+        assert_eq!(vec![ClientId(0), ClientId(7), ClientId(300)], clients);
+
+        // the store is left to process more transactions
+        store.add_funds(ClientId(7), dec!(1))?;
+        assert_eq!(dec!(2), store.account(ClientId(7)).unwrap().total());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_for_each_account_stops_at_error() -> Result<()> {
+        let mut store = InMemoryAccountStore::new();
+        for client in [1, 2, 3] {
+            store.add_funds(ClientId(client), dec!(1))?;
+        }
+
+        let mut visited = 0;
+        let result = store.for_each_account(&mut |account| {
+            visited += 1;
+            match account.client() {
+                ClientId(2) => Err(anyhow::anyhow!("failed")),
+                _ => Ok(()),
+            }
+        });
+        assert_eq!("failed", result.unwrap_err().to_string());
+        assert_eq!(2, visited);
 
         Ok(())
     }
@@ -1,154 +1,476 @@
 //! Serdes for transactions
 
-use anyhow::{Context, Error, Result};
-use rust_decimal::Decimal;
-use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
 
-use crate::{client::ClientId, TransactionRecord, TransactionType};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Deserializer, Serialize};
+use thiserror::Error;
+
+use crate::{
+    client::ClientId,
+    transaction_record::{check_id, deserialize_id, parse_id, IdError},
+    ExcessPrecision, TransactionRecord, TransactionType,
+};
+
+/// Number of decimal places amounts are kept to, from the input through to the output.
+pub const AMOUNT_DECIMAL_PLACES: u32 = 4;
+
+/// Rounds an amount to [`AMOUNT_DECIMAL_PLACES`] using banker's rounding, so a midpoint such
+/// as `1.00005` rounds to the even `1.0000` rather than always up.
+pub fn round_amount(amount: Decimal) -> Decimal {
+    amount.round_dp_with_strategy(AMOUNT_DECIMAL_PLACES, RoundingStrategy::MidpointNearestEven)
+}
 
 /// Represents a transaction ID as it's own type.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+///
+/// Displayed as the plain number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
 pub struct TransactionId(pub u32);
 
+impl fmt::Display for TransactionId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for TransactionId {
+    type Err = IdError;
+
+    /// Parses a transaction ID, rejecting the same values as deserializing one, such as
+    /// `transaction id -1 is negative`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_id(s, "transaction id", u32::MAX.into()).map(|id| TransactionId(id as u32))
+    }
+}
+
+impl From<u32> for TransactionId {
+    fn from(id: u32) -> Self {
+        TransactionId(id)
+    }
+}
+
+impl TryFrom<u64> for TransactionId {
+    type Error = IdError;
+
+    /// Converts a transaction ID from another system, failing if it is out of range, such as
+    /// `transaction id 4294967296 out of range 0..=4294967295`.
+    fn try_from(id: u64) -> Result<Self, Self::Error> {
+        check_id(id, "transaction id", u32::MAX.into()).map(|id| TransactionId(id as u32))
+    }
+}
+
+impl<'de> Deserialize<'de> for TransactionId {
+    /// Deserializes a transaction ID, rejecting negative, fractional and out of range values
+    /// with an error naming the value, such as `transaction id -1 is negative`.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_id(deserializer, "transaction id", u32::MAX.into())
+            .map(|id| TransactionId(id as u32))
+    }
+}
+
 /// Internal transaction representation.
 ///
 /// Each transaction variant is implemented as its own struct.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Transaction {
     Deposit(Deposit),
     Withdrawal(Withdrawal),
     Dispute(Dispute),
     Resolve(Resolve),
     Chargeback(Chargeback),
+    Unlock(Unlock),
 }
 
-#[derive(Debug)]
+impl Transaction {
+    /// The client the transaction applies to.
+    pub fn client(&self) -> ClientId {
+        match self {
+            Transaction::Deposit(tx) => tx.client,
+            Transaction::Withdrawal(tx) => tx.client,
+            Transaction::Dispute(tx) => tx.client,
+            Transaction::Resolve(tx) => tx.client,
+            Transaction::Chargeback(tx) => tx.client,
+            Transaction::Unlock(tx) => tx.client,
+        }
+    }
+
+    /// The ID of the transaction, or of the transaction it refers to.
+    pub fn tx(&self) -> TransactionId {
+        match self {
+            Transaction::Deposit(tx) => tx.tx,
+            Transaction::Withdrawal(tx) => tx.tx,
+            Transaction::Dispute(tx) => tx.tx,
+            Transaction::Resolve(tx) => tx.tx,
+            Transaction::Chargeback(tx) => tx.tx,
+            Transaction::Unlock(tx) => tx.tx,
+        }
+    }
+
+    /// The [`TransactionType`] of this transaction.
+    pub fn transaction_type(&self) -> TransactionType {
+        match self {
+            Transaction::Deposit(_) => TransactionType::Deposit,
+            Transaction::Withdrawal(_) => TransactionType::Withdrawal,
+            Transaction::Dispute(_) => TransactionType::Dispute,
+            Transaction::Resolve(_) => TransactionType::Resolve,
+            Transaction::Chargeback(_) => TransactionType::Chargeback,
+            Transaction::Unlock(_) => TransactionType::Unlock,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Deposit {
     pub client: ClientId,
     pub tx: TransactionId,
     pub amount: Decimal,
+    pub timestamp: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Withdrawal {
     pub client: ClientId,
     pub tx: TransactionId,
     pub amount: Decimal,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Dispute {
     pub client: ClientId,
     pub tx: TransactionId,
+    pub timestamp: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Resolve {
     pub client: ClientId,
     pub tx: TransactionId,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Chargeback {
     pub client: ClientId,
     pub tx: TransactionId,
 }
 
+/// An administrative operation to unfreeze a locked account.
+#[derive(Debug, Clone)]
+pub struct Unlock {
+    pub client: ClientId,
+    pub tx: TransactionId,
+}
+
+/// Why a [`TransactionRecord`] is not a valid [`Transaction`].
+///
+/// The record is kept rather than described up front, so that nothing is allocated unless
+/// the error is displayed.
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum TransactionValidationError {
+    /// A dispute, resolve, chargeback or unlock has an amount.
+    #[error("Unexpected amount field in {0:?}")]
+    UnexpectedAmount(TransactionRecord),
+    /// A deposit or withdrawal amount is not positive, once rounded.
+    #[error("Expected positive amount for {0:?}")]
+    NonPositiveAmount(TransactionRecord),
+    /// A deposit or withdrawal has no amount.
+    #[error("Expected amount for {0:?}")]
+    MissingAmount(TransactionRecord),
+    /// An amount has more than [`AMOUNT_DECIMAL_PLACES`] decimal places, and is not rounded.
+    #[error("Expected at most 4 decimal places for {0:?}")]
+    ExcessPrecision(TransactionRecord),
+}
+
+impl Transaction {
+    /// Converts a [`TransactionRecord`] to a [`Transaction`], as [`Transaction::try_from`]
+    /// does, first rejecting an amount with more than [`AMOUNT_DECIMAL_PLACES`] decimal places
+    /// unless `precision` is [`ExcessPrecision::Round`].
+    ///
+    /// Trailing zeros are not counted, so `10.00000` is accepted either way.
+    pub fn from_record(
+        record: TransactionRecord,
+        precision: ExcessPrecision,
+    ) -> Result<Self, TransactionValidationError> {
+        if let (ExcessPrecision::Reject, Some(amount)) = (precision, record.amount) {
+            if round_amount(amount) != amount {
+                return Err(TransactionValidationError::ExcessPrecision(record));
+            }
+        }
+        Transaction::try_from(record)
+    }
+}
+
 /// Supports conversion of a [`TransactionRecord`] to a [`Transaction`].
 // Having to convert from the TransactionRecord serde to a Transaction is a bit verbose
 // and is due to lacking features in rust-csv where internally-tagged enums are not supported.
 // However, it does allow more optimal usage of the rust type system.
 // Additionally it provides an opportunity for more advanced validations.
-impl From<TransactionRecord> for Result<Transaction> {
-    /// Converts a [`TransactionRecord`] to a [`Result<Transaction>`].
+impl TryFrom<TransactionRecord> for Transaction {
+    type Error = TransactionValidationError;
+
+    /// Converts a [`TransactionRecord`] to a [`Transaction`].
     /// An error is returned if validation fails or if expected fields are missing.
-    fn from(record: TransactionRecord) -> Self {
-        // validate the record fields
-        if let Some(amount) = record.amount {
-            // dispute, resolve and chargeback transactions should not have an amount
-            if let TransactionType::Dispute
-            | TransactionType::Resolve
-            | TransactionType::Chargeback = record.transaction_type
-            {
-                return Err(Error::msg(format!(
-                    "Unexpected amount field in {:?}",
-                    &record
-                )));
-            }
-            // amount must be a positive non-zero number
-            if amount <= 0.into() {
-                return Err(Error::msg(format!(
-                    "Expected positive amount for {:?}",
-                    &record
-                )));
+    fn try_from(record: TransactionRecord) -> Result<Self, Self::Error> {
+        let amount = match (record.transaction_type, record.amount) {
+            // dispute, resolve, chargeback and unlock transactions should not have an amount
+            (
+                TransactionType::Dispute
+                | TransactionType::Resolve
+                | TransactionType::Chargeback
+                | TransactionType::Unlock,
+                Some(_),
+            ) => return Err(TransactionValidationError::UnexpectedAmount(record)),
+            (_, Some(amount)) => {
+                // amount must be a positive non-zero number, once rounded
+                let amount = round_amount(amount);
+                if amount <= Decimal::ZERO {
+                    return Err(TransactionValidationError::NonPositiveAmount(record));
+                }
+                Some(amount)
             }
-        }
+            (_, None) => None,
+        };
 
         // attempt to convert records to transactions
+        let TransactionRecord {
+            client,
+            tx,
+            timestamp,
+            ..
+        } = record;
         match record.transaction_type {
             TransactionType::Deposit => Ok(Transaction::Deposit(Deposit {
-                client: record.client,
-                tx: record.tx,
-                amount: record
-                    .amount
-                    .with_context(|| format!("Expected amount for {:?}", &record))?
-                    .round_dp(4),
+                client,
+                tx,
+                amount: amount.ok_or(TransactionValidationError::MissingAmount(record))?,
+                timestamp,
             })),
             TransactionType::Withdrawal => Ok(Transaction::Withdrawal(Withdrawal {
-                client: record.client,
-                tx: record.tx,
-                amount: record
-                    .amount
-                    .with_context(|| format!("Expected amount for {:?}", &record))?
-                    .round_dp(4),
+                client,
+                tx,
+                amount: amount.ok_or(TransactionValidationError::MissingAmount(record))?,
             })),
             TransactionType::Dispute => Ok(Transaction::Dispute(Dispute {
-                client: record.client,
-                tx: record.tx,
-            })),
-            TransactionType::Resolve => Ok(Transaction::Resolve(Resolve {
-                client: record.client,
-                tx: record.tx,
-            })),
-            TransactionType::Chargeback => Ok(Transaction::Chargeback(Chargeback {
-                client: record.client,
-                tx: record.tx,
+                client,
+                tx,
+                timestamp,
             })),
+            TransactionType::Resolve => Ok(Transaction::Resolve(Resolve { client, tx })),
+            TransactionType::Chargeback => Ok(Transaction::Chargeback(Chargeback { client, tx })),
+            TransactionType::Unlock => Ok(Transaction::Unlock(Unlock { client, tx })),
         }
     }
 }
 
+impl From<TransactionRecord> for Result<Transaction> {
+    /// Converts a [`TransactionRecord`] to a [`Result<Transaction>`], as
+    /// [`Transaction::try_from`] does but with an [`anyhow::Error`].
+    fn from(record: TransactionRecord) -> Self {
+        Ok(Transaction::try_from(record)?)
+    }
+}
+
+impl From<Deposit> for TransactionRecord {
+    fn from(deposit: Deposit) -> Self {
+        let record = TransactionRecord::deposit(deposit.client, deposit.tx, deposit.amount);
+        match deposit.timestamp {
+            Some(timestamp) => record.with_timestamp(timestamp),
+            None => record,
+        }
+    }
+}
+
+impl From<Withdrawal> for TransactionRecord {
+    fn from(withdrawal: Withdrawal) -> Self {
+        TransactionRecord::withdrawal(withdrawal.client, withdrawal.tx, withdrawal.amount)
+    }
+}
+
+impl From<Dispute> for TransactionRecord {
+    fn from(dispute: Dispute) -> Self {
+        let record = TransactionRecord::dispute(dispute.client, dispute.tx);
+        match dispute.timestamp {
+            Some(timestamp) => record.with_timestamp(timestamp),
+            None => record,
+        }
+    }
+}
+
+impl From<Resolve> for TransactionRecord {
+    fn from(resolve: Resolve) -> Self {
+        TransactionRecord::resolve(resolve.client, resolve.tx)
+    }
+}
+
+impl From<Chargeback> for TransactionRecord {
+    fn from(chargeback: Chargeback) -> Self {
+        TransactionRecord::chargeback(chargeback.client, chargeback.tx)
+    }
+}
+
+impl From<Unlock> for TransactionRecord {
+    fn from(unlock: Unlock) -> Self {
+        TransactionRecord::unlock(unlock.client, unlock.tx)
+    }
+}
+
+/// Supports conversion of a [`Transaction`] back to a [`TransactionRecord`], such as to journal it.
+impl From<Transaction> for TransactionRecord {
+    fn from(transaction: Transaction) -> Self {
+        match transaction {
+            Transaction::Deposit(tx) => tx.into(),
+            Transaction::Withdrawal(tx) => tx.into(),
+            Transaction::Dispute(tx) => tx.into(),
+            Transaction::Resolve(tx) => tx.into(),
+            Transaction::Chargeback(tx) => tx.into(),
+            Transaction::Unlock(tx) => tx.into(),
+        }
+    }
+}
+
+impl From<&Transaction> for TransactionRecord {
+    fn from(transaction: &Transaction) -> Self {
+        transaction.clone().into()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     use anyhow::Result;
+    use chrono::TimeZone;
+    use csv::{ReaderBuilder, Trim};
     use rust_decimal_macros::dec;
     use test_case::test_case;
 
-    #[test_case(TransactionType::Deposit,    ClientId(1), TransactionId(1), Some(dec!(10)); "when deposit")]
-    #[test_case(TransactionType::Withdrawal, ClientId(1), TransactionId(1), Some(dec!(10)); "when withdrawal")]
-    #[test_case(TransactionType::Dispute,    ClientId(1), TransactionId(1), None;           "when dispute")]
-    #[test_case(TransactionType::Resolve,    ClientId(1), TransactionId(1), None;           "when resolve")]
-    #[test_case(TransactionType::Chargeback, ClientId(1), TransactionId(1), None;           "when chargeback")]
-    fn test_from_when_valid_record(
-        transaction_type: TransactionType,
-        client: ClientId,
-        tx: TransactionId,
-        amount: Option<Decimal>,
-    ) {
-        let record = TransactionRecord::new(transaction_type, client, tx, amount);
-        let result: Result<Transaction> = record.into();
-        result.unwrap();
+    #[test_case("0",           Ok(TransactionId(0));          "when zero")]
+    #[test_case("4294967295",  Ok(TransactionId(u32::MAX));   "when max")]
+    #[test_case("4294967296",  Err("transaction id 4294967296 out of range 0..=4294967295"); "when out of range")]
+    #[test_case("-1",          Err("transaction id -1 is negative");          "when negative")]
+    #[test_case("-0.5",        Err("transaction id -0.5 is not an integer");  "when negative fraction")]
+    #[test_case("12.0",        Err("transaction id 12.0 is not an integer");  "when zero fraction")]
+    #[test_case("1e3",         Err("transaction id 1000.0 is not an integer"); "when scientific")]
+    #[test_case("0x10",        Err("transaction id '0x10' is not a number");  "when hexadecimal")]
+    #[test_case("\"\"",        Err("transaction id is missing");              "when missing")]
+    fn test_deserialize_transaction_id(text: &str, expected: Result<TransactionId, &str>) {
+        let mut rdr = ReaderBuilder::new()
+            .has_headers(false)
+            .trim(Trim::All)
+            .from_reader(text.as_bytes());
+        let record = rdr.records().next().unwrap().unwrap();
+        let result = record
+            .deserialize::<(TransactionId,)>(None)
+            .map(|(tx,)| tx)
+            .map_err(|err| match err.into_kind() {
+                csv::ErrorKind::Deserialize { err, .. } => err.kind().to_string(),
+                kind => format!("{:?}", kind),
+            });
+        assert_eq!(expected.map_err(str::to_string), result);
+    }
+
+    #[test_case("12",         Ok(TransactionId(12));                                   "when number")]
+    #[test_case("4294967296", Err("transaction id 4294967296 out of range 0..=4294967295"); "when out of range")]
+    #[test_case("-1",         Err("transaction id -1 is negative");                    "when negative")]
+    #[test_case("x1",         Err("transaction id 'x1' is not a number");              "when not a number")]
+    fn test_transaction_id_from_str(text: &str, expected: Result<TransactionId, &str>) {
+        let result = text.parse::<TransactionId>().map_err(|err| err.to_string());
+        assert_eq!(expected.map_err(str::to_string), result);
+    }
+
+    #[test_case(TransactionId(0);        "when zero")]
+    #[test_case(TransactionId(12);       "when number")]
+    #[test_case(TransactionId(u32::MAX); "when max")]
+    fn test_transaction_id_display_and_from_str_round_trip(tx: TransactionId) {
+        assert_eq!(Ok(tx), tx.to_string().parse::<TransactionId>());
+    }
+
+    #[test]
+    fn test_transaction_id_try_from_u64() {
+        assert_eq!(
+            Ok(TransactionId(u32::MAX)),
+            TransactionId::try_from(u64::from(u32::MAX))
+        );
+        assert_eq!(
+            "transaction id 4294967296 out of range 0..=4294967295",
+            TransactionId::try_from(u64::from(u32::MAX) + 1)
+                .unwrap_err()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_transaction_id_display_and_ord() {
+        assert_eq!("7", TransactionId::from(7).to_string());
+        assert!(TransactionId(2) < TransactionId(10));
+    }
+
+    #[test_case(TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10));    "when deposit")]
+    #[test_case(TransactionRecord::withdrawal(ClientId(1), TransactionId(1), dec!(10)); "when withdrawal")]
+    #[test_case(TransactionRecord::dispute(ClientId(1), TransactionId(1));              "when dispute")]
+    #[test_case(TransactionRecord::resolve(ClientId(1), TransactionId(1));              "when resolve")]
+    #[test_case(TransactionRecord::chargeback(ClientId(1), TransactionId(1));           "when chargeback")]
+    #[test_case(TransactionRecord::unlock(ClientId(1), TransactionId(1));               "when unlock")]
+    fn test_from_when_valid_record(record: TransactionRecord) {
+        let result: Result<Transaction> = record.clone().into();
+        let transaction = result.unwrap();
+        assert_eq!(record, TransactionRecord::from(&transaction));
+        assert_eq!(record, TransactionRecord::from(transaction));
+    }
+
+    #[test_case(dec!(1.00005),                        dec!(1.0000);                 "when midpoint rounds to even")]
+    #[test_case(dec!(1.00015),                        dec!(1.0002);                 "when midpoint rounds up to even")]
+    #[test_case(dec!(20.000011),                      dec!(20.0000);                "when more than four places")]
+    #[test_case(dec!(0.5),                            dec!(0.5);                    "when fewer than four places")]
+    #[test_case(dec!(79228162514264.33759354395033),  dec!(79228162514264.3376);    "when large with many places")]
+    #[test_case(dec!(79228162514264337593543950335),  dec!(79228162514264337593543950335); "when maximum")]
+    fn test_from_rounds_amount(amount: Decimal, expected: Decimal) {
+        let record = TransactionRecord::deposit(ClientId(1), TransactionId(1), amount);
+        match Result::<Transaction>::from(record).unwrap() {
+            Transaction::Deposit(deposit) => {
+                assert_eq!(expected.to_string(), deposit.amount.to_string())
+            }
+            transaction => panic!("Expected a deposit, got {:?}", transaction),
+        }
+    }
+
+    #[test]
+    fn test_from_when_timestamp_keeps_timestamp() {
+        let timestamp = Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+        let deposit = Deposit {
+            client: ClientId(1),
+            tx: TransactionId(1),
+            amount: dec!(10),
+            timestamp: Some(timestamp),
+        };
+        let dispute = Dispute {
+            client: ClientId(1),
+            tx: TransactionId(1),
+            timestamp: Some(timestamp),
+        };
+
+        assert_eq!(
+            TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10))
+                .with_timestamp(timestamp),
+            deposit.into()
+        );
+        assert_eq!(
+            TransactionRecord::dispute(ClientId(1), TransactionId(1)).with_timestamp(timestamp),
+            dispute.into()
+        );
     }
 
     #[test_case(TransactionType::Deposit,    ClientId(1), TransactionId(1), Some(dec!(-10)); "when deposit and negative amount")]
     #[test_case(TransactionType::Withdrawal, ClientId(1), TransactionId(1), Some(dec!(-10)); "when withdrawal and negative amount")]
+    #[test_case(TransactionType::Deposit,    ClientId(1), TransactionId(1), Some(dec!(0.00004)); "when deposit rounds to zero")]
+    #[test_case(TransactionType::Withdrawal, ClientId(1), TransactionId(1), Some(dec!(0.00005)); "when withdrawal rounds to zero")]
     #[test_case(TransactionType::Deposit,    ClientId(1), TransactionId(1), None;            "when deposit and missing amount")]
     #[test_case(TransactionType::Withdrawal, ClientId(1), TransactionId(1), None;            "when withdrawal and missing amount")]
     #[test_case(TransactionType::Dispute,    ClientId(1), TransactionId(1), Some(dec!(10));  "when dispute and some ammount")]
     #[test_case(TransactionType::Resolve,    ClientId(1), TransactionId(1), Some(dec!(10));  "when resolve and some ammount")]
     #[test_case(TransactionType::Chargeback, ClientId(1), TransactionId(1), Some(dec!(10));  "when chargeback and some ammount")]
+    #[test_case(TransactionType::Unlock,     ClientId(1), TransactionId(1), Some(dec!(10));  "when unlock and some ammount")]
     #[should_panic]
     fn test_from_when_invalid_record(
         transaction_type: TransactionType,
@@ -160,4 +482,44 @@ mod tests {
         let result: Result<Transaction> = record.into();
         result.unwrap();
     }
+
+    #[test_case(dec!(10.00001), ExcessPrecision::Reject, Err(()); "when rejected")]
+    #[test_case(dec!(10.00001), ExcessPrecision::Round,  Ok(dec!(10));  "when rounded")]
+    #[test_case(dec!(10.00000), ExcessPrecision::Reject, Ok(dec!(10));  "when trailing zeros")]
+    #[test_case(dec!(10.0001),  ExcessPrecision::Reject, Ok(dec!(10.0001)); "when four places")]
+    fn test_from_record_with_excess_precision(
+        amount: Decimal,
+        precision: ExcessPrecision,
+        expected: Result<Decimal, ()>,
+    ) {
+        let record = TransactionRecord::deposit(ClientId(1), TransactionId(1), amount);
+        let result = Transaction::from_record(record.clone(), precision);
+        match (expected, result) {
+            (Ok(expected), Ok(Transaction::Deposit(deposit))) => {
+                assert_eq!(expected, deposit.amount)
+            }
+            (Err(()), Err(err)) => {
+                assert_eq!(TransactionValidationError::ExcessPrecision(record), err)
+            }
+            (expected, result) => panic!("Expected {:?}, got {:?}", expected, result),
+        }
+    }
+
+    #[test_case(TransactionType::Deposit, Some(dec!(-10)), TransactionValidationError::NonPositiveAmount; "when negative amount")]
+    #[test_case(TransactionType::Withdrawal, None,         TransactionValidationError::MissingAmount;     "when missing amount")]
+    #[test_case(TransactionType::Dispute, Some(dec!(10)),  TransactionValidationError::UnexpectedAmount;  "when unexpected amount")]
+    fn test_try_from_when_invalid_record(
+        transaction_type: TransactionType,
+        amount: Option<Decimal>,
+        expected: fn(TransactionRecord) -> TransactionValidationError,
+    ) {
+        let record =
+            TransactionRecord::new(transaction_type, ClientId(1), TransactionId(1), amount);
+        let err = Transaction::try_from(record.clone()).unwrap_err();
+        assert_eq!(expected(record.clone()), err);
+
+        // the anyhow conversion keeps the message
+        let result: Result<Transaction> = record.into();
+        assert_eq!(err.to_string(), result.unwrap_err().to_string());
+    }
 }
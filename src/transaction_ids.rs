@@ -0,0 +1,295 @@
+//! A compact set of transaction IDs.
+
+use std::{collections::BTreeMap, fmt};
+
+use serde::{
+    de::{SeqAccess, Visitor},
+    ser::SerializeSeq,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::TransactionId;
+
+/// Number of IDs a container holds as a sorted list before switching to a bitmap, where both
+/// take 8 KiB.
+const MAX_SPARSE: usize = 4096;
+
+/// Number of words in the bitmap of a dense container, one bit for each of 65,536 IDs.
+const DENSE_WORDS: usize = 1024;
+
+/// The IDs of a set which share their upper 16 bits.
+#[derive(Debug, Clone)]
+enum Container {
+    /// The lower 16 bits of each ID, sorted.
+    Sparse(Vec<u16>),
+    /// One bit for each ID, with the number set.
+    Dense(Box<[u64; DENSE_WORDS]>, usize),
+}
+
+impl Container {
+    fn contains(&self, low: u16) -> bool {
+        match self {
+            Container::Sparse(ids) => ids.binary_search(&low).is_ok(),
+            Container::Dense(bits, _) => bits[low as usize / 64] & (1 << (low % 64)) != 0,
+        }
+    }
+
+    fn insert(&mut self, low: u16) -> bool {
+        match self {
+            Container::Sparse(ids) => match ids.binary_search(&low) {
+                Ok(_) => return false,
+                Err(i) => ids.insert(i, low),
+            },
+            Container::Dense(bits, len) => {
+                let (word, bit) = (low as usize / 64, 1 << (low % 64));
+                if bits[word] & bit != 0 {
+                    return false;
+                }
+                bits[word] |= bit;
+                *len += 1;
+            }
+        }
+        if let Container::Sparse(ids) = self {
+            if ids.len() > MAX_SPARSE {
+                let mut bits = Box::new([0; DENSE_WORDS]);
+                for &id in ids.iter() {
+                    bits[id as usize / 64] |= 1 << (id % 64);
+                }
+                *self = Container::Dense(bits, ids.len());
+            }
+        }
+        true
+    }
+
+    fn remove(&mut self, low: u16) -> bool {
+        match self {
+            Container::Sparse(ids) => match ids.binary_search(&low) {
+                Ok(i) => {
+                    ids.remove(i);
+                }
+                Err(_) => return false,
+            },
+            Container::Dense(bits, len) => {
+                let (word, bit) = (low as usize / 64, 1 << (low % 64));
+                if bits[word] & bit == 0 {
+                    return false;
+                }
+                bits[word] &= !bit;
+                *len -= 1;
+            }
+        }
+        // switch back only well below the limit, so an ID added and removed at the limit does
+        // not switch every time
+        if let Container::Dense(_, len) = self {
+            if *len <= MAX_SPARSE / 2 {
+                let ids = self.iter().collect();
+                *self = Container::Sparse(ids);
+            }
+        }
+        true
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Container::Sparse(ids) => ids.len(),
+            Container::Dense(_, len) => *len,
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = u16> + '_> {
+        match self {
+            Container::Sparse(ids) => Box::new(ids.iter().copied()),
+            Container::Dense(bits, _) => Box::new(
+                (0..=u16::MAX).filter(|&low| bits[low as usize / 64] & (1 << (low % 64)) != 0),
+            ),
+        }
+    }
+}
+
+/// A set of transaction IDs, such as those used by deposits and withdrawals.
+///
+/// IDs are grouped by their upper 16 bits, and each group held as a sorted list until it is
+/// dense enough to be held as a bitmap instead. So a set takes at most a few bytes per ID, and
+/// never more than 512 MiB however many IDs it holds, where a `HashSet` of every possible ID
+/// would take tens of gigabytes. IDs are iterated in order.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TransactionIdSet {
+    containers: BTreeMap<u16, Container>,
+    len: usize,
+}
+
+impl TransactionIdSet {
+    /// Adds an ID, returning whether it was not already in the set.
+    pub(crate) fn insert(&mut self, tx: TransactionId) -> bool {
+        let (high, low) = split(tx);
+        let inserted = self
+            .containers
+            .entry(high)
+            .or_insert_with(|| Container::Sparse(Vec::new()))
+            .insert(low);
+        if inserted {
+            self.len += 1;
+        }
+        inserted
+    }
+
+    /// Returns whether the ID is in the set.
+    pub(crate) fn contains(&self, tx: &TransactionId) -> bool {
+        let (high, low) = split(*tx);
+        self.containers
+            .get(&high)
+            .is_some_and(|container| container.contains(low))
+    }
+
+    /// Removes an ID, returning whether it was in the set.
+    pub(crate) fn remove(&mut self, tx: &TransactionId) -> bool {
+        let (high, low) = split(*tx);
+        let container = match self.containers.get_mut(&high) {
+            Some(container) => container,
+            None => return false,
+        };
+        let removed = container.remove(low);
+        if container.len() == 0 {
+            self.containers.remove(&high);
+        }
+        if removed {
+            self.len -= 1;
+        }
+        removed
+    }
+
+    /// Returns the number of IDs in the set.
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns every ID in the set, lowest first.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = TransactionId> + '_ {
+        self.containers.iter().flat_map(|(&high, container)| {
+            container
+                .iter()
+                .map(move |low| TransactionId(u32::from(high) << 16 | u32::from(low)))
+        })
+    }
+}
+
+/// Splits an ID into its upper and lower 16 bits.
+fn split(tx: TransactionId) -> (u16, u16) {
+    ((tx.0 >> 16) as u16, tx.0 as u16)
+}
+
+impl FromIterator<TransactionId> for TransactionIdSet {
+    fn from_iter<I: IntoIterator<Item = TransactionId>>(iter: I) -> Self {
+        let mut set = TransactionIdSet::default();
+        for tx in iter {
+            set.insert(tx);
+        }
+        set
+    }
+}
+
+/// Serializes as a list of IDs, as a [`std::collections::HashSet`] does, so checkpoints and
+/// state files written before are still read.
+impl Serialize for TransactionIdSet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.len))?;
+        for tx in self.iter() {
+            seq.serialize_element(&tx)?;
+        }
+        seq.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for TransactionIdSet {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct IdsVisitor;
+
+        impl<'de> Visitor<'de> for IdsVisitor {
+            type Value = TransactionIdSet;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a list of transaction ids")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut set = TransactionIdSet::default();
+                while let Some(tx) = seq.next_element()? {
+                    set.insert(tx);
+                }
+                Ok(set)
+            }
+        }
+
+        deserializer.deserialize_seq(IdsVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn test_remove_across_dense_limit() {
+        let mut set = TransactionIdSet::default();
+        let mut expected = HashSet::new();
+        for tx in (0..70_000).step_by(3).map(TransactionId) {
+            set.insert(tx);
+            expected.insert(tx);
+        }
+
+        for tx in (0..60_000).map(TransactionId) {
+            assert_eq!(expected.remove(&tx), set.remove(&tx));
+        }
+        assert!(matches!(set.containers[&0], Container::Sparse(_)));
+
+        assert_eq!(expected.len(), set.len());
+        assert!((0..70_000)
+            .map(TransactionId)
+            .all(|tx| set.contains(&tx) == expected.contains(&tx)));
+    }
+
+    #[test]
+    fn test_insert_across_dense_limit() {
+        let mut set = TransactionIdSet::default();
+        // every third ID, so the first container becomes dense and the second stays sparse
+        for tx in (0..70_000).step_by(3).map(TransactionId) {
+            assert!(set.insert(tx));
+        }
+        assert!(!set.insert(TransactionId(3)));
+        assert!(!set.insert(TransactionId(69_999)));
+        assert!(matches!(set.containers[&0], Container::Dense(..)));
+        assert!(matches!(set.containers[&1], Container::Sparse(_)));
+
+        let expected: Vec<_> = (0..70_000).step_by(3).collect();
+        assert_eq!(expected, set.iter().map(|tx| tx.0).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_iter_is_in_order() {
+        let set: TransactionIdSet = [u32::MAX, 70_000, 5, 0]
+            .into_iter()
+            .map(TransactionId)
+            .collect();
+        let ids: Vec<_> = set.iter().map(|tx| tx.0).collect();
+        assert_eq!(vec![0, 5, 70_000, u32::MAX], ids);
+    }
+
+    #[test]
+    fn test_remove_last_id_drops_container() {
+        let mut set: TransactionIdSet = [TransactionId(1)].into_iter().collect();
+        assert!(set.remove(&TransactionId(1)));
+        assert!(!set.remove(&TransactionId(1)));
+        assert!(set.containers.is_empty());
+        assert_eq!(0, set.len());
+    }
+
+    #[test]
+    fn test_serde_matches_hash_set() -> anyhow::Result<()> {
+        let ids: HashSet<TransactionId> = [3, 1, 2].into_iter().map(TransactionId).collect();
+        let set: TransactionIdSet = serde_json::from_str(&serde_json::to_string(&ids)?)?;
+        assert_eq!("[1,2,3]", serde_json::to_string(&set)?);
+        Ok(())
+    }
+}
@@ -1,12 +1,16 @@
 //! Serdes for transactions
 
+use std::{fmt, str::FromStr};
+
+use chrono::{DateTime, TimeZone, Utc};
 use rust_decimal::Decimal;
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize};
+use thiserror::Error;
 
 use crate::{client::ClientId, TransactionId};
 
 /// Supported transaction types
-#[derive(Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum TransactionType {
     Deposit,
@@ -14,6 +18,7 @@ pub enum TransactionType {
     Dispute,
     Resolve,
     Chargeback,
+    Unlock,
 }
 
 /// Record of a transaction
@@ -22,17 +27,31 @@ pub enum TransactionType {
 //  exist for deposit/withdrawal variants.
 //  However, in rust-csv internally-tagged enums are not supported:
 //    https://github.com/BurntSushi/rust-csv/issues/211
-#[derive(Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub struct TransactionRecord {
     #[serde(rename = "type")]
     pub transaction_type: TransactionType,
     pub client: ClientId,
     pub tx: TransactionId,
+    /// Plain decimals, such as `1234.56`, and scientific notation, such as `1.23456e3`, are accepted.
+    #[serde(default, deserialize_with = "deserialize_amount")]
     pub amount: Option<Decimal>,
+    /// When the transaction occurred, if known.
+    ///
+    /// The column is optional; files without it deserialize with no timestamp.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_timestamp",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub timestamp: Option<DateTime<Utc>>,
 }
 
 impl TransactionRecord {
     // Create a transaction
+    //
+    // Prefer the constructor for the type, such as `TransactionRecord::deposit`, which
+    // only takes the fields the type has.
     pub fn new(
         transaction_type: TransactionType,
         client: ClientId,
@@ -44,16 +63,338 @@ impl TransactionRecord {
             client,
             tx,
             amount,
+            timestamp: None,
+        }
+    }
+
+    /// Create a deposit of `amount` into the client's account.
+    pub fn deposit(client: ClientId, tx: TransactionId, amount: Decimal) -> Self {
+        TransactionRecord::new(TransactionType::Deposit, client, tx, Some(amount))
+    }
+
+    /// Create a withdrawal of `amount` from the client's account.
+    pub fn withdrawal(client: ClientId, tx: TransactionId, amount: Decimal) -> Self {
+        TransactionRecord::new(TransactionType::Withdrawal, client, tx, Some(amount))
+    }
+
+    /// Create a dispute of the client's deposit `tx`.
+    pub fn dispute(client: ClientId, tx: TransactionId) -> Self {
+        TransactionRecord::new(TransactionType::Dispute, client, tx, None)
+    }
+
+    /// Create a resolve of the client's disputed deposit `tx`.
+    pub fn resolve(client: ClientId, tx: TransactionId) -> Self {
+        TransactionRecord::new(TransactionType::Resolve, client, tx, None)
+    }
+
+    /// Create a chargeback of the client's disputed deposit `tx`.
+    pub fn chargeback(client: ClientId, tx: TransactionId) -> Self {
+        TransactionRecord::new(TransactionType::Chargeback, client, tx, None)
+    }
+
+    /// Create an unlock of the client's account.
+    pub fn unlock(client: ClientId, tx: TransactionId) -> Self {
+        TransactionRecord::new(TransactionType::Unlock, client, tx, None)
+    }
+
+    // Set when the transaction occurred
+    pub fn with_timestamp(mut self, timestamp: DateTime<Utc>) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+}
+
+/// Visits an optional amount, reading the text of an amount as is rather than as a float.
+struct AmountVisitor;
+
+impl<'de> de::Visitor<'de> for AmountVisitor {
+    type Value = Option<Decimal>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a decimal amount")
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_str(self)
+    }
+
+    fn visit_str<E: de::Error>(self, text: &str) -> Result<Self::Value, E> {
+        match text.trim() {
+            "" => Ok(None),
+            text => parse_amount(text).map(Some).map_err(E::custom),
+        }
+    }
+
+    fn visit_u64<E: de::Error>(self, amount: u64) -> Result<Self::Value, E> {
+        Ok(Some(amount.into()))
+    }
+
+    fn visit_i64<E: de::Error>(self, amount: i64) -> Result<Self::Value, E> {
+        Ok(Some(amount.into()))
+    }
+}
+
+/// Deserializes an optional amount from a plain decimal or scientific notation.
+fn deserialize_amount<'de, D>(deserializer: D) -> Result<Option<Decimal>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_option(AmountVisitor)
+}
+
+/// Parses an amount from a plain decimal or scientific notation.
+///
+/// Commas are rejected: a decimal comma, such as `1.234,56`, is ambiguous and thousands separators,
+/// such as `1,234.56` or `1_000`, must first be removed with [`strip_thousands_separators`].
+/// Accounting-style negatives, such as `(5.00)`, and more than one decimal point are rejected too.
+fn parse_amount(text: &str) -> Result<Decimal, String> {
+    if text.starts_with('(') && text.ends_with(')') {
+        return Err(format!(
+            "Unexpected parentheses in amount '{}': negative amounts use a minus sign",
+            text
+        ));
+    }
+    if text.matches('.').count() > 1 {
+        return Err(format!(
+            "Invalid amount '{}': more than one decimal point",
+            text
+        ));
+    }
+    // `Decimal` would otherwise silently ignore underscores, such as `1__0` or `1.5_`
+    if text.contains('_') {
+        return Err(format!(
+            "Unexpected thousands separator in amount '{}': enable lenient amounts to accept it",
+            text
+        ));
+    }
+    if let Some(comma) = text.rfind(',') {
+        return match text.rfind('.') {
+            Some(point) if point < comma => Err(format!(
+                "Ambiguous amount '{}': decimal commas are not supported",
+                text
+            )),
+            _ => Err(format!(
+                "Unexpected thousands separator in amount '{}': enable lenient amounts to accept it",
+                text
+            )),
+        };
+    }
+    let amount = match text.contains(['e', 'E']) {
+        true => Decimal::from_scientific(text),
+        false => Decimal::from_str(text),
+    };
+    amount.map_err(|err| format!("Invalid amount '{}': {}", text, err))
+}
+
+/// Removes comma or underscore thousands separators from an amount, such as `1,234.56` or `1_000`.
+///
+/// Returns `None` unless the amount is correctly grouped in threes by one kind of separator, so that
+/// a decimal comma, such as `1.234,56` or `12,5`, is left to be rejected.
+pub(crate) fn strip_thousands_separators(text: &str) -> Option<String> {
+    let (sign, unsigned) = match text.strip_prefix(['-', '+']) {
+        Some(unsigned) => (&text[..1], unsigned),
+        None => ("", text),
+    };
+    let (integer, fraction) = match unsigned.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (unsigned, None),
+    };
+    let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+
+    let separator = match (integer.contains(','), integer.contains('_')) {
+        (true, false) => ',',
+        (false, true) => '_',
+        _ => return None,
+    };
+    let mut groups = integer.split(separator);
+    let first = groups.next()?;
+    let rest: Vec<&str> = groups.collect();
+    let grouped = !rest.is_empty()
+        && digits(first)
+        && first.len() <= 3
+        && rest.iter().all(|group| digits(group) && group.len() == 3)
+        && fraction.is_none_or(digits);
+    if !grouped {
+        return None;
+    }
+
+    let mut stripped = format!("{}{}{}", sign, first, rest.concat());
+    if let Some(fraction) = fraction {
+        stripped.push('.');
+        stripped.push_str(fraction);
+    }
+    Some(stripped)
+}
+
+/// Visits an ID, such as a client or transaction ID, describing exactly why a value is rejected.
+struct IdVisitor {
+    /// Name of the ID in error messages, such as `client id`.
+    name: &'static str,
+    max: u64,
+}
+
+impl IdVisitor {
+    fn out_of_range<E: de::Error>(&self, id: impl fmt::Display) -> E {
+        E::custom(format!(
+            "{} {} out of range 0..={}",
+            self.name, id, self.max
+        ))
+    }
+
+    fn negative<E: de::Error>(&self, id: impl fmt::Display) -> E {
+        E::custom(format!("{} {} is negative", self.name, id))
+    }
+}
+
+impl<'de> de::Visitor<'de> for IdVisitor {
+    type Value = u64;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a {} in the range 0..={}", self.name, self.max)
+    }
+
+    fn visit_u64<E: de::Error>(self, id: u64) -> Result<Self::Value, E> {
+        match id <= self.max {
+            true => Ok(id),
+            false => Err(self.out_of_range(id)),
+        }
+    }
+
+    fn visit_i64<E: de::Error>(self, id: i64) -> Result<Self::Value, E> {
+        match u64::try_from(id) {
+            Ok(id) => self.visit_u64(id),
+            Err(_) => Err(self.negative(id)),
+        }
+    }
+
+    fn visit_u128<E: de::Error>(self, id: u128) -> Result<Self::Value, E> {
+        Err(self.out_of_range(id))
+    }
+
+    fn visit_i128<E: de::Error>(self, id: i128) -> Result<Self::Value, E> {
+        match id < 0 {
+            true => Err(self.negative(id)),
+            false => Err(self.out_of_range(id)),
+        }
+    }
+
+    fn visit_f64<E: de::Error>(self, id: f64) -> Result<Self::Value, E> {
+        Err(E::custom(format!(
+            "{} {:?} is not an integer",
+            self.name, id
+        )))
+    }
+
+    fn visit_str<E: de::Error>(self, text: &str) -> Result<Self::Value, E> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(E::custom(format!("{} is missing", self.name)));
+        }
+        if let Ok(id) = text.parse::<i128>() {
+            return match u64::try_from(id) {
+                Ok(id) => self.visit_u64(id),
+                Err(_) => self.visit_i128(id),
+            };
+        }
+        match text.parse::<f64>() {
+            Ok(id) if id.is_finite() => Err(E::custom(format!(
+                "{} {} is not an integer",
+                self.name, text
+            ))),
+            _ => Err(E::custom(format!(
+                "{} '{}' is not a number",
+                self.name, text
+            ))),
         }
     }
 }
 
+/// Deserializes an ID no greater than `max`, with an error naming the ID and the value rejected.
+///
+/// Negative, fractional and out of range values are each rejected with their own message, such
+/// as `client id 70000 out of range 0..=65535`.
+pub(crate) fn deserialize_id<'de, D>(
+    deserializer: D,
+    name: &'static str,
+    max: u64,
+) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_any(IdVisitor { name, max })
+}
+
+/// An invalid client or transaction ID, such as `client id 70000 out of range 0..=65535`.
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+#[error("{0}")]
+pub struct IdError(String);
+
+/// Parses an ID no greater than `max`, rejecting the same values, with the same messages, as
+/// [`deserialize_id`].
+pub(crate) fn parse_id(text: &str, name: &'static str, max: u64) -> Result<u64, IdError> {
+    de::Visitor::visit_str(IdVisitor { name, max }, text)
+        .map_err(|err: de::value::Error| IdError(err.to_string()))
+}
+
+/// Checks an ID from another system is no greater than `max`.
+pub(crate) fn check_id(id: u64, name: &'static str, max: u64) -> Result<u64, IdError> {
+    de::Visitor::visit_u64(IdVisitor { name, max }, id)
+        .map_err(|err: de::value::Error| IdError(err.to_string()))
+}
+
+/// Removes a zero fraction from an ID, such as `42.0`, as written by spreadsheets.
+///
+/// Returns `None` unless the ID is digits followed by a point and only zeros.
+pub(crate) fn strip_zero_fraction(text: &str) -> Option<String> {
+    let (integer, fraction) = text.split_once('.')?;
+    let digits = !integer.is_empty() && integer.bytes().all(|b| b.is_ascii_digit());
+    let zeros = !fraction.is_empty() && fraction.bytes().all(|b| b == b'0');
+    (digits && zeros).then(|| integer.to_string())
+}
+
+/// Raw representation of a timestamp; either epoch seconds or an ISO-8601/RFC 3339 string.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawTimestamp {
+    Seconds(i64),
+    Text(String),
+}
+
+/// Deserializes an optional timestamp from epoch seconds or an ISO-8601/RFC 3339 string.
+fn deserialize_timestamp<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<RawTimestamp>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(RawTimestamp::Seconds(seconds)) => Utc
+            .timestamp_opt(seconds, 0)
+            .single()
+            .map(Some)
+            .ok_or_else(|| de::Error::custom(format!("invalid epoch timestamp: {}", seconds))),
+        Some(RawTimestamp::Text(text)) if text.trim().is_empty() => Ok(None),
+        Some(RawTimestamp::Text(text)) => DateTime::parse_from_rfc3339(text.trim())
+            .map(|timestamp| Some(timestamp.with_timezone(&Utc)))
+            .map_err(|err| de::Error::custom(format!("invalid timestamp '{}': {}", text, err))),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     use anyhow::Result;
     use csv::{Reader, ReaderBuilder, Trim, Writer};
+    use itertools::Itertools;
+    use rust_decimal_macros::dec;
     use test_case::test_case;
 
     #[test]
@@ -86,6 +427,113 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_serde_when_timestamp_column() -> Result<()> {
+        let input = "\
+            type,client,tx,amount,timestamp\n\
+            deposit,1,1,10,2022-05-01T12:00:00Z\n\
+            deposit,1,2,10,1651406400\n\
+            dispute,1,1,,2022-05-02T12:00:00+01:00\n\
+            dispute,1,2,,\n\
+        ";
+
+        let mut rdr = ReaderBuilder::new()
+            .trim(Trim::All)
+            .from_reader(input.as_bytes());
+        let records = rdr
+            .deserialize()
+            .collect::<Result<Vec<TransactionRecord>, _>>()?;
+
+        let timestamps = records.iter().map(|record| record.timestamp).collect_vec();
+        assert_eq!(
+            vec![
+                Some(Utc.with_ymd_and_hms(2022, 5, 1, 12, 0, 0).unwrap()),
+                Some(Utc.with_ymd_and_hms(2022, 5, 1, 12, 0, 0).unwrap()),
+                Some(Utc.with_ymd_and_hms(2022, 5, 2, 11, 0, 0).unwrap()),
+                None,
+            ],
+            timestamps
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_serde_when_no_timestamp_column() -> Result<()> {
+        let input = "type,client,tx,amount\ndeposit,1,1,10\n";
+
+        let mut rdr = Reader::from_reader(input.as_bytes());
+        let record: TransactionRecord = rdr.deserialize().next().unwrap()?;
+        assert_eq!(None, record.timestamp);
+
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid timestamp")]
+    fn test_serde_when_invalid_timestamp() {
+        let input = "type,client,tx,amount,timestamp\ndeposit,1,1,10,yesterday\n";
+
+        let mut rdr = Reader::from_reader(input.as_bytes());
+        let _: TransactionRecord = rdr.deserialize().next().unwrap().unwrap();
+    }
+
+    #[test_case("10",         dec!(10);      "when integer")]
+    #[test_case("-1.9999",    dec!(-1.9999); "when negative decimal")]
+    #[test_case("1e3",        dec!(1000);    "when scientific")]
+    #[test_case("1.5E-2",     dec!(0.015);   "when scientific with negative exponent")]
+    #[test_case("2.5e+1",     dec!(25);      "when scientific with positive exponent")]
+    #[test_case("0.1234567890123456789", dec!(0.1234567890123456789); "when more precise than a float")]
+    fn test_serde_when_valid_amount(amount: &str, expected: Decimal) -> Result<()> {
+        let input = format!("type,client,tx,amount\ndeposit,1,1,{}\n", amount);
+
+        let mut rdr = Reader::from_reader(input.as_bytes());
+        let record: TransactionRecord = rdr.deserialize().next().unwrap()?;
+        assert_eq!(Some(expected), record.amount);
+
+        Ok(())
+    }
+
+    #[test_case("1,234.56", "Unexpected thousands separator"; "when thousands separator")]
+    #[test_case("1.234,56", "Ambiguous amount";               "when decimal comma")]
+    #[test_case("12,5",     "Unexpected thousands separator"; "when comma only")]
+    #[test_case("1e",       "Invalid amount";                 "when missing exponent")]
+    #[test_case("ten",      "Invalid amount";                 "when not a number")]
+    #[test_case("1_000",    "Unexpected thousands separator"; "when underscore separator")]
+    #[test_case("1.5_",     "Unexpected thousands separator"; "when trailing underscore")]
+    #[test_case("(5.00)",   "Unexpected parentheses in amount '(5.00)'"; "when accounting negative")]
+    #[test_case("1.2.3",    "Invalid amount '1.2.3': more than one decimal point"; "when two decimal points")]
+    #[test_case("1.2e3.4",  "more than one decimal point";    "when two decimal points and scientific")]
+    fn test_serde_when_invalid_amount(amount: &str, expected: &str) {
+        let input = format!("type,client,tx,amount\ndeposit,1,1,\"{}\"\n", amount);
+
+        let mut rdr = Reader::from_reader(input.as_bytes());
+        let result: Result<TransactionRecord, _> = rdr.deserialize().next().unwrap();
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains(expected), "{}", err);
+    }
+
+    #[test_case("1,234",         Some("1234");       "when thousands")]
+    #[test_case("-1,234,567.89", Some("-1234567.89"); "when negative millions with fraction")]
+    #[test_case("+12,345.6",     Some("+12345.6");    "when explicit sign")]
+    #[test_case("1234.56",       None;                "when no separator")]
+    #[test_case("1.234,56",      None;                "when decimal comma")]
+    #[test_case("12,5",          None;                "when short group")]
+    #[test_case("1234,567",      None;                "when long first group")]
+    #[test_case(",123",          None;                "when missing first group")]
+    #[test_case("1,234.5e3",     None;                "when scientific")]
+    #[test_case("1,234.5678",    Some("1234.5678");   "when four places")]
+    #[test_case("1_000",         Some("1000");        "when underscore")]
+    #[test_case("-1_234_567.8",  Some("-1234567.8");  "when negative underscores")]
+    #[test_case("1__000",        None;                "when double underscore")]
+    #[test_case("1_234,567",     None;                "when mixed separators")]
+    fn test_strip_thousands_separators(amount: &str, expected: Option<&str>) {
+        assert_eq!(
+            expected.map(str::to_string),
+            strip_thousands_separators(amount)
+        );
+    }
+
     #[test_case(",         1,  1, 10"; "when missing transaction type")]
     #[test_case("borrow,   1,  1, 10"; "when unknown transaction type")]
     #[test_case("deposit,   ,  1, 10"; "when missing client ID")]
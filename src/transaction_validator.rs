@@ -0,0 +1,168 @@
+//! Business rules checked before each transaction is applied.
+
+use std::collections::HashSet;
+
+use rust_decimal::Decimal;
+use thiserror::Error;
+
+use crate::{Account, ClientId, Transaction};
+
+/// The reason a [`TransactionValidator`] rejected a transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{0}")]
+pub struct ValidationError(String);
+
+impl ValidationError {
+    /// Create an error with the reason the transaction was rejected.
+    pub fn new(reason: impl Into<String>) -> Self {
+        ValidationError(reason.into())
+    }
+
+    pub fn reason(&self) -> &str {
+        &self.0
+    }
+}
+
+/// The state a transaction is validated against.
+#[derive(Debug)]
+pub struct ValidationContext {
+    account: Option<Account>,
+}
+
+impl ValidationContext {
+    /// Create a context with the client's account, before the transaction is applied.
+    pub fn new(account: Option<Account>) -> Self {
+        ValidationContext { account }
+    }
+
+    /// The client's account before the transaction is applied, if they have one.
+    pub fn account(&self) -> Option<&Account> {
+        self.account.as_ref()
+    }
+}
+
+/// A business rule checked before each transaction is applied.
+///
+/// A transaction rejected by any validator is skipped, with the validator's reason logged.
+pub trait TransactionValidator {
+    /// Returns an error if the transaction must not be applied.
+    fn validate(&self, tx: &Transaction, ctx: &ValidationContext) -> Result<(), ValidationError>;
+
+    /// Whether disputes, resolves and chargebacks are validated too.
+    ///
+    /// They are not by default, so that a rule on new transactions cannot prevent earlier ones
+    /// being disputed, or the disputes from being settled.
+    fn validates_disputes(&self) -> bool {
+        false
+    }
+}
+
+/// Rejects deposits and withdrawals of more than a maximum amount.
+#[derive(Debug, Clone)]
+pub struct MaxAmountValidator {
+    max: Decimal,
+}
+
+impl MaxAmountValidator {
+    pub fn new(max: Decimal) -> Self {
+        MaxAmountValidator { max }
+    }
+}
+
+impl TransactionValidator for MaxAmountValidator {
+    fn validate(&self, tx: &Transaction, _ctx: &ValidationContext) -> Result<(), ValidationError> {
+        let amount = match tx {
+            Transaction::Deposit(deposit) => deposit.amount,
+            Transaction::Withdrawal(withdrawal) => withdrawal.amount,
+            _ => return Ok(()),
+        };
+        match amount > self.max {
+            true => Err(ValidationError::new(format!(
+                "Amount {} is over the maximum of {}",
+                amount, self.max
+            ))),
+            false => Ok(()),
+        }
+    }
+}
+
+/// Rejects every transaction of the denied clients.
+#[derive(Debug, Clone)]
+pub struct DeniedClientsValidator {
+    clients: HashSet<ClientId>,
+}
+
+impl DeniedClientsValidator {
+    pub fn new(clients: impl IntoIterator<Item = ClientId>) -> Self {
+        DeniedClientsValidator {
+            clients: clients.into_iter().collect(),
+        }
+    }
+}
+
+impl TransactionValidator for DeniedClientsValidator {
+    fn validate(&self, tx: &Transaction, _ctx: &ValidationContext) -> Result<(), ValidationError> {
+        match self.clients.contains(&tx.client()) {
+            true => Err(ValidationError::new(format!(
+                "Client {} is denied",
+                tx.client()
+            ))),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+    use test_case::test_case;
+
+    use crate::{Deposit, TransactionId, Unlock, Withdrawal};
+
+    use super::*;
+
+    fn deposit(client: u16, amount: Decimal) -> Transaction {
+        Transaction::Deposit(Deposit {
+            client: ClientId(client),
+            tx: TransactionId(1),
+            amount,
+            timestamp: None,
+        })
+    }
+
+    fn withdrawal(client: u16, amount: Decimal) -> Transaction {
+        Transaction::Withdrawal(Withdrawal {
+            client: ClientId(client),
+            tx: TransactionId(2),
+            amount,
+        })
+    }
+
+    #[test_case(deposit(1, dec!(100)),       None;                                            "when deposit at maximum")]
+    #[test_case(deposit(1, dec!(100.01)),    Some("Amount 100.01 is over the maximum of 100"); "when deposit over maximum")]
+    #[test_case(withdrawal(1, dec!(100.01)), Some("Amount 100.01 is over the maximum of 100"); "when withdrawal over maximum")]
+    fn test_max_amount_validator(transaction: Transaction, expected: Option<&str>) {
+        let validator = MaxAmountValidator::new(dec!(100));
+        let result = validator.validate(&transaction, &ValidationContext::new(None));
+        assert_eq!(expected, result.as_ref().err().map(ValidationError::reason));
+    }
+
+    #[test_case(deposit(1, dec!(1)), None;                      "when client allowed")]
+    #[test_case(deposit(3, dec!(1)), Some("Client 3 is denied"); "when client denied")]
+    #[test_case(
+        Transaction::Unlock(Unlock { client: ClientId(3), tx: TransactionId(3) }),
+        Some("Client 3 is denied");
+        "when unlock for denied client"
+    )]
+    fn test_denied_clients_validator(transaction: Transaction, expected: Option<&str>) {
+        let validator = DeniedClientsValidator::new([ClientId(3), ClientId(4)]);
+        let result = validator.validate(&transaction, &ValidationContext::new(None));
+        assert_eq!(expected, result.as_ref().err().map(ValidationError::reason));
+    }
+
+    #[test]
+    fn test_built_in_validators_do_not_validate_disputes() {
+        assert!(!MaxAmountValidator::new(dec!(1)).validates_disputes());
+        assert!(!DeniedClientsValidator::new([]).validates_disputes());
+    }
+}
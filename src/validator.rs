@@ -0,0 +1,283 @@
+//! Validation of input without processing it.
+
+use std::{collections::HashMap, fmt};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{ClientId, Transaction, TransactionId, TransactionRecord, TransactionType};
+
+/// The kind of problem found in a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProblemKind {
+    /// The record could not be read.
+    Unreadable,
+    /// The record was read but is not a valid transaction.
+    Invalid,
+    /// The transaction ID was already used by an earlier deposit or withdrawal.
+    DuplicateTransaction,
+    /// The referenced transaction is not an earlier deposit or withdrawal.
+    UnknownTransaction,
+    /// The client does not match the client of the referenced transaction.
+    ClientMismatch,
+    /// The referenced transaction has already been disputed.
+    DuplicateDispute,
+    /// The referenced transaction has no open dispute.
+    UnknownDispute,
+}
+
+/// A problem found in a record, and the line the record starts on.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ValidationProblem {
+    /// Line number the record starts on, starting at 1.
+    pub line: u64,
+    /// Kind of problem.
+    pub kind: ProblemKind,
+    /// Description of the problem.
+    pub message: String,
+}
+
+impl fmt::Display for ValidationProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.message)
+    }
+}
+
+/// Validates records without applying them to any accounts.
+///
+/// Each record is converted to a [`Transaction`], exactly as it would be when processed, and
+/// checked against the records before it for problems that can be found without any accounts:
+/// reused transaction IDs, and disputes, resolves and chargebacks that reference a transaction
+/// or dispute that does not come earlier.
+#[derive(Debug, Default)]
+pub struct Validator {
+    transactions: HashMap<TransactionId, (TransactionType, ClientId, u64)>,
+    disputes: HashMap<TransactionId, bool>,
+    records: u64,
+    problems: Vec<ValidationProblem>,
+}
+
+impl Validator {
+    /// Create a validator which has not yet seen any records.
+    pub fn new() -> Self {
+        Validator::default()
+    }
+
+    /// Validate every record, each paired with the line it starts on.
+    pub fn validate_all<I>(&mut self, records: I)
+    where
+        I: IntoIterator<Item = (Result<TransactionRecord>, u64)>,
+    {
+        for (result, line) in records {
+            self.validate(result, line);
+        }
+    }
+
+    /// Validate the next record, which starts on the given line.
+    pub fn validate(&mut self, result: Result<TransactionRecord>, line: u64) {
+        self.records += 1;
+        let transaction = match result {
+            Ok(record) => Result::<Transaction>::from(record),
+            Err(err) => return self.report(line, ProblemKind::Unreadable, err.to_string()),
+        };
+        match transaction {
+            Ok(transaction) => self.check_references(&transaction, line),
+            Err(err) => self.report(line, ProblemKind::Invalid, err.to_string()),
+        }
+    }
+
+    /// Returns the number of records validated.
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    /// Returns the problems found so far, in the order of the records.
+    pub fn problems(&self) -> &[ValidationProblem] {
+        &self.problems
+    }
+
+    /// Returns the problems found, in the order of the records.
+    pub fn into_problems(self) -> Vec<ValidationProblem> {
+        self.problems
+    }
+
+    fn check_references(&mut self, transaction: &Transaction, line: u64) {
+        let transaction_type = transaction.transaction_type();
+        let client = transaction.client();
+        let tx = transaction.tx();
+        match transaction_type {
+            TransactionType::Deposit | TransactionType::Withdrawal => {
+                match self.transactions.get(&tx) {
+                    Some((_, _, earlier)) => {
+                        let message =
+                            format!("Transaction ID {} was already used on line {}", tx, earlier);
+                        self.report(line, ProblemKind::DuplicateTransaction, message);
+                    }
+                    None => {
+                        self.transactions
+                            .insert(tx, (transaction_type, client, line));
+                    }
+                }
+            }
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback => {
+                let (kind, message) = match self.transactions.get(&tx) {
+                    Some((disputed_type, owner, _)) if *owner != client => {
+                        let verb = match disputed_type {
+                            TransactionType::Withdrawal => "withdrawn",
+                            _ => "deposited",
+                        };
+                        (
+                            ProblemKind::ClientMismatch,
+                            format!(
+                                "{:?} by client {} of transaction {}, which was {} by client {}",
+                                transaction_type, client, tx, verb, owner
+                            ),
+                        )
+                    }
+                    Some(_) => {
+                        // A transaction can only be disputed once, whether or not its dispute
+                        // is still open, as when processed
+                        let open = self.disputes.get(&tx).copied();
+                        match (transaction_type, open) {
+                            (TransactionType::Dispute, None) => {
+                                self.disputes.insert(tx, true);
+                                return;
+                            }
+                            (TransactionType::Dispute, Some(_)) => (
+                                ProblemKind::DuplicateDispute,
+                                format!("Transaction {} has already been disputed", tx),
+                            ),
+                            (_, Some(true)) => {
+                                self.disputes.insert(tx, false);
+                                return;
+                            }
+                            (_, _) => (
+                                ProblemKind::UnknownDispute,
+                                format!(
+                                    "{:?} of transaction {}, which has no open dispute",
+                                    transaction_type, tx
+                                ),
+                            ),
+                        }
+                    }
+                    None => (
+                        ProblemKind::UnknownTransaction,
+                        format!(
+                            "{:?} of transaction {}, which is not an earlier deposit or withdrawal",
+                            transaction_type, tx
+                        ),
+                    ),
+                };
+                self.report(line, kind, message);
+            }
+            TransactionType::Unlock => {}
+        }
+    }
+
+    fn report(&mut self, line: u64, kind: ProblemKind, message: String) {
+        self.problems.push(ValidationProblem {
+            line,
+            kind,
+            message,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::NamedTempFile;
+
+    use crate::CsvTransactionReader;
+
+    use super::*;
+
+    /// Validates the input, returning the line and kind of each problem found.
+    fn validate(input: &str) -> Result<Vec<(u64, ProblemKind)>> {
+        let mut file = NamedTempFile::new()?;
+        write!(file, "{}", input)?;
+        let mut rdr = CsvTransactionReader::from_path(file.path())?;
+
+        let mut validator = Validator::new();
+        validator.validate_all(rdr.read_with_lines()?);
+        Ok(validator
+            .into_problems()
+            .into_iter()
+            .map(|problem| (problem.line, problem.kind))
+            .collect())
+    }
+
+    #[test]
+    fn test_validate() -> Result<()> {
+        let input = "\
+            type, client, tx, amount\n\
+            deposit, 1, 1, 10\n\
+            deposit, 2, 2, 5\n\
+            \n\
+            borrow, 1, 3, 5\n\
+            deposit, 1, 4, -5\n\
+            withdrawal, 2, 2, 1\n\
+            dispute, 1, 9,\n\
+            dispute, 2, 1,\n\
+            resolve, 1, 1,\n\
+            dispute, 1, 1,\n\
+            dispute, 1, 1,\n\
+            chargeback, 1, 1,\n\
+            resolve, 1, 1,\n\
+            dispute, 1, 1, 10\n\
+        ";
+
+        let expected = vec![
+            (5, ProblemKind::Unreadable),
+            (6, ProblemKind::Invalid),
+            (7, ProblemKind::DuplicateTransaction),
+            (8, ProblemKind::UnknownTransaction),
+            (9, ProblemKind::ClientMismatch),
+            (10, ProblemKind::UnknownDispute),
+            (12, ProblemKind::DuplicateDispute),
+            (14, ProblemKind::UnknownDispute),
+            (15, ProblemKind::Invalid),
+        ];
+        assert_eq!(expected, validate(input)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_when_valid() -> Result<()> {
+        let input = "\
+            type, client, tx, amount\n\
+            deposit, 1, 1, 10\n\
+            withdrawal, 1, 2, 5\n\
+            dispute, 1, 1,\n\
+            resolve, 1, 1,\n\
+            dispute, 1, 2,\n\
+            chargeback, 1, 2,\n\
+            unlock, 1, 1,\n\
+        ";
+
+        assert!(validate(input)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_problem_message() {
+        let mut validator = Validator::new();
+        let deposit = |tx| TransactionRecord::deposit(ClientId(1), TransactionId(tx), 10.into());
+        validator.validate(Ok(deposit(1)), 2);
+        validator.validate(Ok(deposit(1)), 3);
+
+        assert_eq!(2, validator.records());
+        assert_eq!(
+            vec!["Line 3: Transaction ID 1 was already used on line 2"],
+            validator
+                .problems()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        );
+    }
+}
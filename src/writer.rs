@@ -1,13 +1,42 @@
+use std::{
+    collections::HashMap,
+    io::{BufWriter, Write},
+};
+
 use anyhow::{Error, Result};
 use csv::{Writer, WriterBuilder};
 
-use crate::AccountSummary;
+use crate::{AccountDelta, AccountSummary, ClientId, DisputeSummary, RejectedTransaction};
 
 /// A trait for any account writer implementation.
 #[cfg_attr(test, mockall::automock)]
 pub trait AccountWriter {
     // Writes an account
     fn write(&mut self, account: &AccountSummary) -> Result<()>;
+
+    /// Completes the output once every account has been written, such as by writing a header
+    /// row when there were none. Does nothing by default.
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The header row of the CSV account output, written even when there are no accounts.
+const ACCOUNT_HEADER: [&str; 5] = ["client", "available", "held", "total", "locked"];
+
+/// The header row of the CSV account delta output.
+const DELTA_HEADER: [&str; 5] = ["client", "available", "held", "total", "locked_changed"];
+
+/// Allows a writer to be lent to a consumer, such as
+/// [`TransactionProcessor::export`](crate::TransactionProcessor::export), and inspected afterwards.
+impl<W: AccountWriter + ?Sized> AccountWriter for &mut W {
+    fn write(&mut self, account: &AccountSummary) -> Result<()> {
+        (**self).write(account)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        (**self).finish()
+    }
 }
 
 /// Account writer for CSV files
@@ -17,6 +46,8 @@ where
     W: std::io::Write + Send + Sync + 'static,
 {
     writer: Option<Writer<W>>,
+    /// Whether a row, and so the header, has been written.
+    written: bool,
 }
 
 impl<W> CsvAccountWriter<W>
@@ -28,6 +59,7 @@ where
         let writer = WriterBuilder::new().has_headers(true).from_writer(wtr);
         CsvAccountWriter {
             writer: Some(writer),
+            written: false,
         }
     }
 
@@ -47,26 +79,376 @@ where
 {
     /// Serializes and writes an account
     fn write(&mut self, account: &AccountSummary) -> Result<()> {
+        self.written = true;
         match self.writer.as_mut() {
             Some(wtr) => wtr.serialize(account).map_err(Error::from),
             None => unreachable!(),
         }
     }
+
+    /// Writes the header row if no account was written, so the output is never empty
+    fn finish(&mut self) -> Result<()> {
+        match (self.written, self.writer.as_mut()) {
+            (false, Some(wtr)) => {
+                self.written = true;
+                wtr.write_record(ACCOUNT_HEADER).map_err(Error::from)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Account writer for JSON Lines, one JSON object per account.
+///
+/// Each account is written as soon as it is exported, so the output can be streamed.
+pub struct JsonAccountWriter<W>
+where
+    W: std::io::Write + Send + Sync + 'static,
+{
+    writer: BufWriter<W>,
+}
+
+impl<W> JsonAccountWriter<W>
+where
+    W: std::io::Write + Send + Sync + 'static,
+{
+    /// Returns an account JSON Lines writer that writes data to wtr.
+    pub fn from_writer(wtr: W) -> Self {
+        JsonAccountWriter {
+            writer: BufWriter::new(wtr),
+        }
+    }
+
+    /// Flush the contents of the internal buffer and return the underlying writer.
+    pub fn into_inner(self) -> Result<W> {
+        self.writer
+            .into_inner()
+            .map_err(|err| Error::from(err.into_error()))
+    }
+}
+
+impl<W> AccountWriter for JsonAccountWriter<W>
+where
+    W: std::io::Write + Send + Sync + 'static,
+{
+    /// Serializes and writes an account, followed by a newline
+    fn write(&mut self, account: &AccountSummary) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, account)?;
+        self.writer.write_all(b"\n").map_err(Error::from)
+    }
+}
+
+/// Account writer for a single JSON array, of one object per account.
+///
+/// The array is opened by the first account and closed by [`finish`](AccountWriter::finish),
+/// or [`into_inner`](JsonArrayAccountWriter::into_inner) if not finished, so an empty export is
+/// written as `[]`.
+pub struct JsonArrayAccountWriter<W>
+where
+    W: std::io::Write + Send + Sync + 'static,
+{
+    writer: BufWriter<W>,
+    /// Whether an account, and so the opening bracket, has been written.
+    written: bool,
+    /// Whether the closing bracket has been written.
+    finished: bool,
+}
+
+impl<W> JsonArrayAccountWriter<W>
+where
+    W: std::io::Write + Send + Sync + 'static,
+{
+    /// Returns an account JSON array writer that writes data to wtr.
+    pub fn from_writer(wtr: W) -> Self {
+        JsonArrayAccountWriter {
+            writer: BufWriter::new(wtr),
+            written: false,
+            finished: false,
+        }
+    }
+
+    /// Close the array, flush the contents of the internal buffer and return the underlying
+    /// writer.
+    pub fn into_inner(mut self) -> Result<W> {
+        self.finish()?;
+        self.writer
+            .into_inner()
+            .map_err(|err| Error::from(err.into_error()))
+    }
+}
+
+impl<W> AccountWriter for JsonArrayAccountWriter<W>
+where
+    W: std::io::Write + Send + Sync + 'static,
+{
+    /// Serializes and writes an account, after the opening bracket or a comma
+    fn write(&mut self, account: &AccountSummary) -> Result<()> {
+        // serialized in full first, so an account which fails to be written is not counted
+        let mut element = if self.written {
+            b",".to_vec()
+        } else {
+            b"[".to_vec()
+        };
+        serde_json::to_writer(&mut element, account)?;
+        self.writer.write_all(&element)?;
+        self.written = true;
+        Ok(())
+    }
+
+    /// Closes the array, opening it first if no account was written
+    fn finish(&mut self) -> Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        if !self.written {
+            self.writer.write_all(b"[")?;
+            self.written = true;
+        }
+        self.writer.write_all(b"]\n")?;
+        self.finished = true;
+        Ok(())
+    }
+}
+
+/// Account writer for CSV files of the change in each account, rather than its balance.
+///
+/// Each account is compared with its state before the run, from the baseline, and the
+/// difference written as an [`AccountDelta`]. Accounts without a baseline were untouched by
+/// the run and are not written. See
+/// [`TransactionProcessor::with_deltas`](crate::TransactionProcessor::with_deltas).
+pub struct DeltaAccountWriter<W>
+where
+    W: std::io::Write + Send + Sync + 'static,
+{
+    writer: Writer<W>,
+    baseline: HashMap<ClientId, AccountSummary>,
+    /// Whether a row, and so the header, has been written.
+    written: bool,
+}
+
+impl<W> DeltaAccountWriter<W>
+where
+    W: std::io::Write + Send + Sync + 'static,
+{
+    /// Returns an account delta CSV writer that writes data to wtr.
+    ///
+    /// ### Parameters
+    /// - wtr: The underlying writer.
+    /// - baseline: The state of each account touched by the run, before it was touched.
+    ///
+    pub fn from_writer(wtr: W, baseline: HashMap<ClientId, AccountSummary>) -> Self {
+        let writer = WriterBuilder::new().has_headers(true).from_writer(wtr);
+        DeltaAccountWriter {
+            writer,
+            baseline,
+            written: false,
+        }
+    }
+
+    /// Flush the contents of the internal buffer and return the underlying writer.
+    pub fn into_inner(self) -> Result<W> {
+        self.writer.into_inner().map_err(Error::from)
+    }
+}
+
+impl<W> AccountWriter for DeltaAccountWriter<W>
+where
+    W: std::io::Write + Send + Sync + 'static,
+{
+    /// Serializes and writes the change in an account, if it was touched
+    fn write(&mut self, account: &AccountSummary) -> Result<()> {
+        match self.baseline.get(&account.client()) {
+            Some(before) => {
+                self.written = true;
+                self.writer
+                    .serialize(AccountDelta::between(before, account)?)
+                    .map_err(Error::from)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Writes the header row if no account changed, so the output is never empty
+    fn finish(&mut self) -> Result<()> {
+        if self.written {
+            return Ok(());
+        }
+        self.written = true;
+        self.writer.write_record(DELTA_HEADER).map_err(Error::from)
+    }
+}
+
+/// Account writer which collects accounts in memory.
+///
+/// Useful for testing, where the accounts exported can be inspected directly rather than parsed
+/// back from CSV.
+#[derive(Debug, Default)]
+pub struct VecAccountWriter {
+    accounts: Vec<AccountSummary>,
+}
+
+impl VecAccountWriter {
+    /// Create a new, empty, writer.
+    pub fn new() -> Self {
+        VecAccountWriter::default()
+    }
+
+    /// Returns the accounts written, in order.
+    pub fn into_accounts(self) -> Vec<AccountSummary> {
+        self.accounts
+    }
+}
+
+impl AccountWriter for VecAccountWriter {
+    /// Collects a copy of the account
+    fn write(&mut self, account: &AccountSummary) -> Result<()> {
+        self.accounts.push(*account);
+        Ok(())
+    }
+}
+
+/// A trait for any dispute writer implementation.
+#[cfg_attr(test, mockall::automock)]
+pub trait DisputeWriter {
+    // Writes a dispute case
+    fn write(&mut self, dispute: &DisputeSummary) -> Result<()>;
+}
+
+/// Allows a writer to be lent to
+/// [`TransactionProcessor::export_disputes`](crate::TransactionProcessor::export_disputes).
+impl<W: DisputeWriter + ?Sized> DisputeWriter for &mut W {
+    fn write(&mut self, dispute: &DisputeSummary) -> Result<()> {
+        (**self).write(dispute)
+    }
+}
+
+/// Dispute writer for CSV files
+pub struct CsvDisputeWriter<W>
+where
+    W: std::io::Write + Send + Sync + 'static,
+{
+    writer: Writer<W>,
+}
+
+impl<W> CsvDisputeWriter<W>
+where
+    W: std::io::Write + Send + Sync + 'static,
+{
+    /// Returns a dispute CSV writer that writes data to wtr.
+    pub fn from_writer(wtr: W) -> Self {
+        let writer = WriterBuilder::new().has_headers(true).from_writer(wtr);
+        CsvDisputeWriter { writer }
+    }
+
+    /// Flush the contents of the internal buffer and return the underlying writer.
+    pub fn into_inner(self) -> Result<W> {
+        self.writer.into_inner().map_err(Error::from)
+    }
+}
+
+impl<W> DisputeWriter for CsvDisputeWriter<W>
+where
+    W: std::io::Write + Send + Sync + 'static,
+{
+    /// Serializes and writes a dispute case
+    fn write(&mut self, dispute: &DisputeSummary) -> Result<()> {
+        self.writer.serialize(dispute).map_err(Error::from)
+    }
+}
+
+/// A trait for any rejected transaction writer implementation.
+#[cfg_attr(test, mockall::automock)]
+pub trait RejectedTransactionWriter {
+    // Writes a rejected transaction
+    fn write(&mut self, rejected: &RejectedTransaction) -> Result<()>;
+
+    // Flushes any rejected transactions buffered
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Rejected transaction writer for CSV files, with the columns
+/// `line,type,client,tx,amount,reason`.
+pub struct CsvRejectedTransactionWriter<W>
+where
+    W: std::io::Write + Send + Sync + 'static,
+{
+    writer: Writer<W>,
+}
+
+impl<W> CsvRejectedTransactionWriter<W>
+where
+    W: std::io::Write + Send + Sync + 'static,
+{
+    /// Returns a rejected transaction CSV writer that writes data to wtr.
+    pub fn from_writer(wtr: W) -> Self {
+        let writer = WriterBuilder::new().has_headers(true).from_writer(wtr);
+        CsvRejectedTransactionWriter { writer }
+    }
+
+    /// Flush the contents of the internal buffer and return the underlying writer.
+    pub fn into_inner(self) -> Result<W> {
+        self.writer.into_inner().map_err(Error::from)
+    }
+}
+
+impl<W> RejectedTransactionWriter for CsvRejectedTransactionWriter<W>
+where
+    W: std::io::Write + Send + Sync + 'static,
+{
+    /// Serializes and writes a rejected transaction
+    fn write(&mut self, rejected: &RejectedTransaction) -> Result<()> {
+        self.writer.serialize(rejected).map_err(Error::from)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush().map_err(Error::from)
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    };
+
+    use rust_decimal_macros::dec;
+    use serde::Serialize;
+
     use crate::ClientId;
 
     use super::*;
 
+    /// Returns the header row csv derives from the serde field names of a value.
+    fn serialized_header(value: impl Serialize) -> Result<Vec<String>> {
+        let mut wtr = Writer::from_writer(vec![]);
+        wtr.serialize(value)?;
+        let data = wtr
+            .into_inner()
+            .map_err(|err| Error::from(err.into_error()))?;
+        let mut rdr = csv::Reader::from_reader(data.as_slice());
+        Ok(rdr.headers()?.iter().map(String::from).collect())
+    }
+
+    #[test]
+    fn test_headers_match_serialized_fields() -> Result<()> {
+        let account = AccountSummary::empty(ClientId(1));
+        let delta = AccountDelta::between(&account, &account)?;
+        assert_eq!(ACCOUNT_HEADER.to_vec(), serialized_header(account)?);
+        assert_eq!(DELTA_HEADER.to_vec(), serialized_header(delta)?);
+
+        Ok(())
+    }
+
     #[test]
     fn test_write() -> Result<()> {
         let mut wtr = CsvAccountWriter::from_writer(vec![]);
 
         let accounts = vec![
-            AccountSummary::new(ClientId(1), 0.into(), 50.into(), false),
-            AccountSummary::new(ClientId(2), 10.into(), 40.into(), false),
+            AccountSummary::new(ClientId(1), 0.into(), 50.into(), false).unwrap(),
+            AccountSummary::new(ClientId(2), 10.into(), 40.into(), false).unwrap(),
         ];
 
         for account in accounts {
@@ -84,4 +466,195 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_csv_writer_finish_writes_header_once() -> Result<()> {
+        let mut wtr = CsvAccountWriter::from_writer(vec![]);
+        wtr.finish()?;
+        wtr.finish()?;
+        assert_eq!(
+            "client,available,held,total,locked\n",
+            String::from_utf8(wtr.into_inner()?)?
+        );
+
+        let mut wtr = CsvAccountWriter::from_writer(vec![]);
+        wtr.write(&AccountSummary::empty(ClientId(1)))?;
+        wtr.finish()?;
+        assert_eq!(
+            "client,available,held,total,locked\n1,0,0,0,false\n",
+            String::from_utf8(wtr.into_inner()?)?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_delta_writer_finish_when_no_account_changed() -> Result<()> {
+        let mut wtr = DeltaAccountWriter::from_writer(vec![], HashMap::new());
+        wtr.write(&AccountSummary::empty(ClientId(1)))?;
+        wtr.finish()?;
+        assert_eq!(
+            "client,available,held,total,locked_changed\n",
+            String::from_utf8(wtr.into_inner()?)?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_writer_round_trip() -> Result<()> {
+        let mut wtr = JsonAccountWriter::from_writer(vec![]);
+
+        let accounts = vec![
+            AccountSummary::new(ClientId(1), dec!(0.5), dec!(10.1234), false).unwrap(),
+            AccountSummary::new(ClientId(2), 0.into(), dec!(-3), true).unwrap(),
+        ];
+        for account in &accounts {
+            wtr.write(account)?;
+        }
+
+        let result = String::from_utf8(wtr.into_inner()?)?;
+        let expected = "\
+            {\"client\":1,\"available\":\"9.6234\",\"held\":\"0.5\",\"total\":\"10.1234\",\"locked\":false}\n\
+            {\"client\":2,\"available\":\"-3\",\"held\":\"0\",\"total\":\"-3\",\"locked\":true}\n\
+        ";
+        assert_eq!(expected, result);
+
+        let read: Vec<AccountSummary> = result
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        assert_eq!(accounts, read);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_array_writer() -> Result<()> {
+        let mut wtr = JsonArrayAccountWriter::from_writer(vec![]);
+
+        let accounts = vec![
+            AccountSummary::new(ClientId(1), 0.into(), 50.into(), false).unwrap(),
+            AccountSummary::new(ClientId(2), 10.into(), 40.into(), false).unwrap(),
+        ];
+        for account in &accounts {
+            wtr.write(account)?;
+        }
+
+        let result = String::from_utf8(wtr.into_inner()?)?;
+        let expected = "[\
+            {\"client\":1,\"available\":\"50\",\"held\":\"0\",\"total\":\"50\",\"locked\":false},\
+            {\"client\":2,\"available\":\"30\",\"held\":\"10\",\"total\":\"40\",\"locked\":false}\
+        ]\n";
+        assert_eq!(expected, result);
+
+        let read: Vec<AccountSummary> = serde_json::from_str(&result)?;
+        assert_eq!(accounts, read);
+
+        Ok(())
+    }
+
+    /// Fails every write while `failing` is set.
+    struct FlakyWriter {
+        written: Arc<Mutex<Vec<u8>>>,
+        failing: Arc<AtomicBool>,
+    }
+
+    impl std::io::Write for FlakyWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.failing.load(Ordering::SeqCst) {
+                return Err(std::io::Error::other("failed"));
+            }
+            self.written.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_json_array_writer_when_write_fails_keeps_array_valid() -> Result<()> {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let failing = Arc::new(AtomicBool::new(true));
+        let mut wtr = JsonArrayAccountWriter::from_writer(FlakyWriter {
+            written: Arc::clone(&written),
+            failing: Arc::clone(&failing),
+        });
+
+        // the accounts are buffered until the buffer is full and the failing write is reached
+        let account = AccountSummary::new(ClientId(1), 0.into(), 50.into(), false).unwrap();
+        let mut accounts = 0;
+        while wtr.write(&account).is_ok() {
+            accounts += 1;
+        }
+        failing.store(false, Ordering::SeqCst);
+        wtr.into_inner()?;
+
+        let read: Vec<AccountSummary> = serde_json::from_slice(&written.lock().unwrap())?;
+        assert_eq!(vec![account; accounts], read);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_array_writer_finish_closes_array_once() -> Result<()> {
+        let mut wtr = JsonArrayAccountWriter::from_writer(vec![]);
+        wtr.finish()?;
+        wtr.finish()?;
+        assert_eq!("[]\n", String::from_utf8(wtr.into_inner()?)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_delta_writer_skips_untouched_accounts() -> Result<()> {
+        let baseline = HashMap::from([
+            (
+                ClientId(1),
+                AccountSummary::new(ClientId(1), 0.into(), 20.into(), false).unwrap(),
+            ),
+            (ClientId(3), AccountSummary::empty(ClientId(3))),
+        ]);
+        let mut wtr = DeltaAccountWriter::from_writer(vec![], baseline);
+
+        let accounts = vec![
+            AccountSummary::new(ClientId(1), 5.into(), 12.into(), true).unwrap(),
+            AccountSummary::new(ClientId(2), 10.into(), 40.into(), false).unwrap(),
+            AccountSummary::new(ClientId(3), 0.into(), 7.into(), false).unwrap(),
+        ];
+        for account in accounts {
+            wtr.write(&account)?;
+        }
+
+        let result = String::from_utf8(wtr.into_inner()?)?;
+        let expected = "\
+            client,available,held,total,locked_changed\n\
+            1,-13,5,-8,true\n\
+            3,7,0,7,false\n\
+        ";
+        assert_eq!(expected.to_string(), result);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_vec_writer_when_borrowed() -> Result<()> {
+        let accounts = vec![
+            AccountSummary::new(ClientId(1), 0.into(), 50.into(), false).unwrap(),
+            AccountSummary::new(ClientId(2), 10.into(), 40.into(), true).unwrap(),
+        ];
+
+        fn write_all(mut wtr: impl AccountWriter, accounts: &[AccountSummary]) -> Result<()> {
+            accounts.iter().try_for_each(|account| wtr.write(account))
+        }
+
+        let mut wtr = VecAccountWriter::new();
+        write_all(&mut wtr, &accounts)?;
+
+        assert_eq!(accounts, wtr.into_accounts());
+
+        Ok(())
+    }
 }
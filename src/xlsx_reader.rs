@@ -0,0 +1,216 @@
+use std::{fs::File, io::BufReader, path::Path};
+
+use anyhow::{anyhow, bail, Context, Error, Result};
+use calamine::{open_workbook, Data, Range, Reader, Xlsx};
+use csv::StringRecord;
+
+use crate::{TransactionReader, TransactionRecord};
+
+/// Columns which must be in the header row.
+const REQUIRED_COLUMNS: [&str; 4] = ["type", "client", "tx", "amount"];
+
+/// Transaction reader for Excel workbooks.
+///
+/// The first worksheet is read, with a header row naming the same columns as a CSV file.
+/// Records end at the first row without a type. Each cell is converted to text before it is
+/// deserialized, exactly as a CSV field would be, so numbers are read as the shortest decimal
+/// which the cell holds rather than the nearest binary fraction, e.g. `0.1` not
+/// `0.1000000000000000055511151231257827`.
+pub struct XlsxTransactionReader {
+    range: Range<Data>,
+}
+
+impl XlsxTransactionReader {
+    /// Create a new Excel reader for the given file path.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut workbook: Xlsx<BufReader<File>> = open_workbook(path)?;
+        let range = workbook
+            .worksheet_range_at(0)
+            .context("Workbook has no worksheets")??;
+        Ok(XlsxTransactionReader { range })
+    }
+
+    /// Returns an iterator over deserialized [`TransactionRecord`]s, each paired with the
+    /// number of its row, starting at 1.
+    ///
+    /// An error is returned if the header row is missing a column.
+    pub fn read_with_rows(
+        &self,
+    ) -> Result<impl Iterator<Item = (Result<TransactionRecord>, u64)> + '_> {
+        let first_row = self.range.start().map_or(0, |(row, _)| row as u64) + 1;
+        let mut rows = self.range.rows();
+        let headers = headers(rows.next().unwrap_or_default())?;
+        let kind = headers.iter().position(|header| header == "type");
+        Ok(rows
+            .take_while(move |cells| {
+                kind.and_then(|kind| cells.get(kind))
+                    .is_some_and(|cell| !cell_is_blank(cell))
+            })
+            .zip(first_row + 1..)
+            .map(move |(cells, row)| {
+                let record = cells
+                    .iter()
+                    .map(cell_text)
+                    .collect::<Result<StringRecord>>()
+                    .and_then(|record| {
+                        record
+                            .deserialize::<TransactionRecord>(Some(&headers))
+                            .map_err(Error::from)
+                    })
+                    .map_err(|err| anyhow!("Invalid row {}: {}", row, err));
+                (record, row)
+            }))
+    }
+}
+
+impl TransactionReader for XlsxTransactionReader {
+    /// Returns an iterator over deserialized [`TransactionRecord`]s.
+    fn read<'a>(&'a mut self) -> Box<dyn Iterator<Item = Result<TransactionRecord>> + 'a> {
+        match self.read_with_rows() {
+            Ok(records) => Box::new(records.map(|(result, _)| result)),
+            Err(err) => Box::new(std::iter::once(Err(err))),
+        }
+    }
+
+    /// Returns an iterator over deserialized [`TransactionRecord`]s, each paired with the row
+    /// of the worksheet it is on.
+    fn read_with_line_numbers<'a>(
+        &'a mut self,
+    ) -> Box<dyn Iterator<Item = (Result<TransactionRecord>, u64)> + 'a> {
+        match self.read_with_rows() {
+            Ok(records) => Box::new(records),
+            Err(err) => Box::new(std::iter::once((Err(err), 1))),
+        }
+    }
+}
+
+/// Reads the header row, checking it has every required column.
+fn headers(cells: &[Data]) -> Result<StringRecord> {
+    let headers = cells
+        .iter()
+        .map(cell_text)
+        .collect::<Result<StringRecord>>()?;
+    for column in REQUIRED_COLUMNS {
+        if !headers.iter().any(|header| header == column) {
+            bail!("Missing column: {}", column);
+        }
+    }
+    Ok(headers)
+}
+
+fn cell_is_blank(cell: &Data) -> bool {
+    match cell {
+        Data::Empty => true,
+        Data::String(text) => text.trim().is_empty(),
+        _ => false,
+    }
+}
+
+/// Converts a cell to the text of the equivalent CSV field.
+fn cell_text(cell: &Data) -> Result<String> {
+    match cell {
+        Data::Empty => Ok(String::new()),
+        Data::String(text) => Ok(text.trim().to_string()),
+        Data::Int(number) => Ok(number.to_string()),
+        // the shortest text which parses back to the same float, as it was likely written
+        Data::Float(number) => Ok(number.to_string()),
+        Data::Bool(value) => Ok(value.to_string()),
+        Data::DateTime(value) => value
+            .as_datetime()
+            .map(|value| value.format("%Y-%m-%dT%H:%M:%S%.fZ").to_string())
+            .ok_or_else(|| anyhow!("Invalid date: {}", value)),
+        Data::DateTimeIso(text) | Data::DurationIso(text) => Ok(text.clone()),
+        Data::Error(err) => bail!("Cell error: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use crate::{ClientId, TransactionId, TransactionType};
+
+    use super::*;
+
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/transactions.xlsx"
+    );
+
+    #[test]
+    fn test_read_with_rows() -> Result<()> {
+        let rdr = XlsxTransactionReader::from_path(FIXTURE)?;
+        let records: Vec<_> = rdr.read_with_rows()?.collect();
+
+        let record = |transaction_type, client, tx, amount| {
+            TransactionRecord::new(
+                transaction_type,
+                ClientId(client),
+                TransactionId(tx),
+                amount,
+            )
+        };
+        let expected = vec![
+            (record(TransactionType::Deposit, 1, 1, Some(dec!(0.1))), 2),
+            (record(TransactionType::Deposit, 1, 2, Some(dec!(2.675))), 3),
+            (
+                record(TransactionType::Deposit, 2, 3, Some(dec!(1234.5678))),
+                4,
+            ),
+            (
+                record(TransactionType::Withdrawal, 1, 4, Some(dec!(0.05))),
+                5,
+            ),
+            (record(TransactionType::Dispute, 2, 3, None), 6),
+            (record(TransactionType::Deposit, 3, 6, Some(dec!(10))), 8),
+        ];
+        let (valid, invalid): (Vec<_>, Vec<_>) =
+            records.into_iter().partition(|(result, _)| result.is_ok());
+        assert_eq!(
+            expected,
+            valid
+                .into_iter()
+                .map(|(result, row)| (result.unwrap(), row))
+                .collect::<Vec<_>>()
+        );
+
+        // the row after the first without a type is not read
+        assert_eq!(1, invalid.len());
+        let (err, row) = &invalid[0];
+        assert_eq!(7, *row);
+        let err = err.as_ref().unwrap_err().to_string();
+        assert!(err.starts_with("Invalid row 7: "), "{}", err);
+        assert!(err.contains("unknown variant `borrow`"), "{}", err);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_headers_when_missing_column() {
+        let cells = [
+            Data::String("type".to_string()),
+            Data::String(" client ".to_string()),
+            Data::String("amount".to_string()),
+        ];
+        assert_eq!(
+            "Missing column: tx",
+            headers(&cells).unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_cell_text() -> Result<()> {
+        assert_eq!("0.1", cell_text(&Data::Float(0.1))?);
+        assert_eq!("0.0000001", cell_text(&Data::Float(1e-7))?);
+        assert_eq!("12", cell_text(&Data::Float(12.0))?);
+        assert_eq!("-3", cell_text(&Data::Int(-3))?);
+        assert_eq!(
+            "deposit",
+            cell_text(&Data::String(" deposit ".to_string()))?
+        );
+        assert_eq!("", cell_text(&Data::Empty)?);
+        assert!(cell_text(&Data::Error(calamine::CellErrorType::Div0)).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+//! Exercises the `ffi` feature through the C ABI, as declared in `include/rusty_bank.h`.
+#![cfg(feature = "ffi")]
+
+use std::ffi::c_void;
+use std::os::raw::c_int;
+
+use itertools::Itertools;
+use rusty_bank::{RbStatus, RbWriteCallback};
+
+/// The opaque handle, as seen from C.
+#[repr(C)]
+struct RbProcessor {
+    _private: [u8; 0],
+}
+
+extern "C" {
+    fn rb_processor_new() -> *mut RbProcessor;
+    fn rb_process_csv_buffer(processor: *mut RbProcessor, data: *const u8, len: usize) -> RbStatus;
+    fn rb_export_csv(
+        processor: *mut RbProcessor,
+        callback: RbWriteCallback,
+        user_data: *mut c_void,
+    ) -> RbStatus;
+    fn rb_processor_free(processor: *mut RbProcessor);
+}
+
+/// Appends the exported CSV to the `Vec<u8>` passed as user data.
+extern "C" fn collect(data: *const u8, len: usize, user_data: *mut c_void) -> c_int {
+    let output = unsafe { &mut *(user_data as *mut Vec<u8>) };
+    output.extend_from_slice(unsafe { std::slice::from_raw_parts(data, len) });
+    0
+}
+
+extern "C" fn fail(_: *const u8, _: usize, _: *mut c_void) -> c_int {
+    1
+}
+
+fn process(processor: *mut RbProcessor, input: &str) -> RbStatus {
+    unsafe { rb_process_csv_buffer(processor, input.as_ptr(), input.len()) }
+}
+
+fn export(processor: *mut RbProcessor) -> (RbStatus, String) {
+    let mut output: Vec<u8> = vec![];
+    let status = unsafe {
+        rb_export_csv(
+            processor,
+            Some(collect),
+            &mut output as *mut Vec<u8> as *mut c_void,
+        )
+    };
+    (status, String::from_utf8(output).unwrap())
+}
+
+#[test]
+fn test_process_and_export() {
+    let processor = unsafe { rb_processor_new() };
+    assert!(!processor.is_null());
+
+    let first = "\
+        type, client, tx, amount\n\
+        deposit, 1, 1, 10.0\n\
+        deposit, 2, 2, 5.0\n\
+        dispute, 2, 2,\n\
+    ";
+    let second = "\
+        type, client, tx, amount\n\
+        withdrawal, 1, 3, 2.5\n\
+        chargeback, 2, 2,\n\
+    ";
+    assert_eq!(RbStatus::Ok, process(processor, first));
+    assert_eq!(RbStatus::Ok, process(processor, second));
+
+    let (status, output) = export(processor);
+    assert_eq!(RbStatus::Ok, status);
+    let expected = "\
+        1,7.5,0,7.5,false\n\
+        2,0,0,0,true\n\
+    ";
+    let mut lines = output.lines();
+    assert_eq!(Some("client,available,held,total,locked"), lines.next());
+    assert_eq!(
+        expected,
+        lines.sorted().map(|line| line.to_string() + "\n").join("")
+    );
+
+    unsafe { rb_processor_free(processor) };
+}
+
+#[test]
+fn test_process_when_amount_column_omitted() {
+    let processor = unsafe { rb_processor_new() };
+
+    let input = "\
+        type,client,tx,amount\n\
+        deposit,1,3,10\n\
+        dispute,1,3\n\
+    ";
+    assert_eq!(RbStatus::Ok, process(processor, input));
+
+    let (status, output) = export(processor);
+    assert_eq!(RbStatus::Ok, status);
+    assert_eq!(
+        "client,available,held,total,locked\n1,0,10,10,false\n",
+        output
+    );
+
+    unsafe { rb_processor_free(processor) };
+}
+
+#[test]
+fn test_errors_are_returned_as_status_codes() {
+    let processor = unsafe { rb_processor_new() };
+
+    let input = "type, client, tx, amount\n";
+    let null = std::ptr::null_mut();
+    assert_eq!(RbStatus::NullPointer, process(null, input));
+    assert_eq!(RbStatus::NullPointer, unsafe {
+        rb_process_csv_buffer(processor, std::ptr::null(), 0)
+    });
+    let invalid = b"\xff\xfe\n";
+    assert_eq!(RbStatus::InvalidCsv, unsafe {
+        rb_process_csv_buffer(processor, invalid.as_ptr(), invalid.len())
+    });
+    assert_eq!(RbStatus::NullPointer, unsafe {
+        rb_export_csv(processor, None, null as *mut c_void)
+    });
+
+    assert_eq!(RbStatus::WriteFailed, unsafe {
+        rb_export_csv(processor, Some(fail), null as *mut c_void)
+    });
+    assert_eq!(RbStatus::AlreadyExported, export(processor).0);
+    assert_eq!(RbStatus::AlreadyExported, process(processor, input));
+
+    unsafe {
+        rb_processor_free(processor);
+        rb_processor_free(null);
+    }
+}
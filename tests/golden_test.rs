@@ -0,0 +1,95 @@
+//! Runs the binary over every scenario in `tests/fixtures/golden` and compares the accounts
+//! written with the golden file alongside.
+//!
+//! Each scenario is a `<name>.input.csv`, the `<name>.expected.csv` accounts it should produce,
+//! compared byte for byte as accounts are always written in client order, and optionally a
+//! `<name>.args` file of extra arguments, one per line. Run with `UPDATE_GOLDEN=1` to rewrite
+//! the golden files from the current output, then review the change with `git diff`.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use assert_cmd::prelude::*;
+use itertools::Itertools;
+
+const INPUT_SUFFIX: &str = ".input.csv";
+
+/// Returns the input files of every scenario, by name.
+fn scenarios(dir: &Path) -> Vec<PathBuf> {
+    fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.to_string_lossy().ends_with(INPUT_SUFFIX))
+        .sorted()
+        .collect()
+}
+
+/// Runs the binary over the input, returning the accounts written.
+fn run(input: &Path, args: &[String]) -> String {
+    let output = Command::cargo_bin("rusty-bank")
+        .unwrap()
+        .args(args)
+        .arg(input)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{:?} failed: {}",
+        input,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Describes the lines only in the expected output with `-`, and only in the actual with `+`.
+fn diff(expected: &str, actual: &str) -> String {
+    let missing = expected
+        .lines()
+        .filter(|line| !actual.lines().contains(line))
+        .map(|line| format!("-{}", line));
+    let unexpected = actual
+        .lines()
+        .filter(|line| !expected.lines().contains(line))
+        .map(|line| format!("+{}", line));
+    let diff = missing.chain(unexpected).join("\n");
+    match diff.is_empty() {
+        true => "The same lines in a different order".to_string(),
+        false => diff,
+    }
+}
+
+#[test]
+fn test_golden_files() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/golden");
+    let update = env::var("UPDATE_GOLDEN").is_ok_and(|value| value == "1");
+
+    let inputs = scenarios(&dir);
+    assert!(!inputs.is_empty(), "No scenarios found in {:?}", dir);
+
+    let mut failures = Vec::new();
+    for input in inputs {
+        let file_name = input.file_name().unwrap().to_string_lossy();
+        let name = file_name.strip_suffix(INPUT_SUFFIX).unwrap();
+        let args = fs::read_to_string(dir.join(format!("{}.args", name)))
+            .map(|args| args.lines().map(str::to_string).collect_vec())
+            .unwrap_or_default();
+        let golden = dir.join(format!("{}.expected.csv", name));
+
+        let actual = run(&input, &args);
+        if update {
+            fs::write(&golden, &actual).unwrap();
+            continue;
+        }
+        let expected = fs::read_to_string(&golden).unwrap_or_default();
+        if expected != actual {
+            failures.push(format!("{}:\n{}", name, diff(&expected, &actual)));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "Output differs from the golden files, run with UPDATE_GOLDEN=1 to accept it:\n\n{}",
+        failures.join("\n\n")
+    );
+}
@@ -1,7 +1,10 @@
 use std::io::Write;
-use std::process::Command;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use assert_cmd::prelude::*;
+use flate2::{write::GzEncoder, Compression};
 use itertools::Itertools;
 use predicates::prelude::*;
 
@@ -24,443 +27,950 @@ fn test_failure_when_no_file() {
         .failure();
 }
 
-fn assert_stdout_eq(input: &str, expected: &'static str) {
+/// Runs over the input, asserting the accounts written are the expected ones, in exactly the
+/// expected order if `exact`, otherwise in any order.
+fn assert_stdout_eq_with_args(args: &[&str], input: &str, expected: &'static str, exact: bool) {
     let mut file = NamedTempFile::new().unwrap();
     write!(file, "{}", input).unwrap();
 
     let mut cmd = Command::cargo_bin("rusty-bank").unwrap();
 
-    let cmd = cmd.arg(file.path());
+    let cmd = cmd.args(args).arg(file.path());
     cmd.assert().success();
 
     let buf = cmd.output().unwrap().stdout;
-    let expected = expected
-        .replace(' ', "")
-        .split("\n")
-        .sorted()
-        .rev()
-        .join("\n");
-    let output = String::from_utf8_lossy(&buf)
-        .split("\n")
-        .sorted()
-        .rev()
-        .join("\n");
-    assert_eq!(expected, output);
+    let expected = expected.replace(' ', "");
+    let output = String::from_utf8_lossy(&buf).to_string();
+    match exact {
+        true => assert_eq!(expected, output),
+        false => assert_eq!(
+            expected.split("\n").sorted().rev().join("\n"),
+            output.split("\n").sorted().rev().join("\n")
+        ),
+    }
 }
 
 #[test]
-fn test_success_when_empty() {
-    let input = "type, client, tx, amount\n";
-    let expected = "";
-    assert_stdout_eq(input, expected);
-}
-
-#[test]
-fn test_deposit_does_change_available_and_total() {
+fn test_accounts_written_in_client_order() {
     let input = "\
-        type,      client, tx, amount\n\
-        deposit,        1,  1,     10\n\
-        deposit,        2,  2,     20\n\
+        type,       client, tx, amount\n\
+        deposit,        10,  1,      1\n\
+        deposit,         2,  2,      2\n\
+        deposit,       300,  3,      3\n\
+        withdrawal,      7,  4,      5\n\
+        deposit,         1,  5,      1\n\
     ";
     let expected = "\
         client, available, held, total, locked\n\
-             1,        10,    0,    10,  false\n\
-             2,        20,    0,    20,  false\n\
+             1,         1,    0,     1,  false\n\
+             2,         2,    0,     2,  false\n\
+             7,         0,    0,     0,  false\n\
+            10,         1,    0,     1,  false\n\
+           300,         3,    0,     3,  false\n\
     ";
-    assert_stdout_eq(input, expected);
+    assert_stdout_eq_with_args(&["--export-untouched-accounts"], input, expected, true);
 }
 
 #[test]
-fn test_deposit_and_withdrawal_does_change_available_and_total() {
+fn test_check_invariants_leaves_accounts_unchanged() {
     let input = "\
-        type,      client, tx, amount\n\
-        deposit,        1,  1,     10\n\
-        deposit,        2,  2,     20\n\
-        withdrawal,     1,  3,      5\n\
+        type,       client, tx, amount\n\
+        deposit,         1,  1,     10\n\
+        withdrawal,      1,  2,      4\n\
+        deposit,         2,  3,      5\n\
+        dispute,         2,  3,       \n\
+        chargeback,      2,  3,       \n\
+        deposit,         2,  4,      1\n\
+        dispute,         1,  1,       \n\
     ";
     let expected = "\
         client, available, held, total, locked\n\
-             1,         5,    0,     5,  false\n\
-             2,        20,    0,    20,  false\n\
+             1,        -4,   10,     6,  false\n\
+             2,         0,    0,     0,   true\n\
     ";
-    assert_stdout_eq(input, expected);
+    assert_stdout_eq_with_args(&["--check-invariants"], input, expected, true);
 }
 
 #[test]
-fn test_deposit_and_withdrawal_precision() {
-    let input = "\
+fn test_stats_writes_summary_statistics() {
+    let mut file = NamedTempFile::new().unwrap();
+    write!(
+        file,
+        "\
         type,      client, tx, amount\n\
-        deposit,        1,  1,     10.9999\n\
-        withdrawal,     1,  2,      5.9999\n\
-        deposit,        2,  3,     20.0000\n\
-        deposit,        3,  4,     10.999999\n\
-        withdrawal,     3,  5,      5.999999\n\
-        deposit,        4,  6,     20.000011\n\
-        deposit,        5,  7,     50.99\n\
-        deposit,        6,  8,     50.9999\n\
-    ";
+        deposit,        1,  1,     10\n\
+        deposit,        2,  2,     20\n\
+        withdrawal,     1,  3,     50\n\
+        dispute,        2,  2,       \n\
+        chargeback,     2,  2,       \n\
+        deposit,        1,  4,    2.5\n\
+    "
+    )
+    .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let stats = dir.path().join("stats.csv");
+
     let expected = "\
-        client, available, held,   total, locked\n\
-             1,         5,    0,       5,  false\n\
-             2,        20,    0,      20,  false\n\
-             3,         5,    0,       5,  false\n\
-             4,        20,    0,      20,  false\n\
-             5,     50.99,    0,   50.99,  false\n\
-             6,   50.9999,    0, 50.9999,  false\n\
-    ";
-    assert_stdout_eq(input, expected);
-}
+        1,12.5,0,12.5,false\n\
+        2,0,0,0,true\n\
+        client,available,held,total,locked";
+    let output = sorted_stdout(
+        Command::cargo_bin("rusty-bank")
+            .unwrap()
+            .args(["--stats", stats.to_str().unwrap()])
+            .arg(file.path()),
+    );
+    assert_eq!(expected, output);
 
-#[test]
-fn test_withdrawal_when_insufficient_funds_does_not_change_available_and_total() {
-    let input = "\
-        type,      client, tx, amount\n\
-        withdrawal,     1,  1,      5\n\
-        deposit,        1,  2,     10\n\
-        withdrawal,     2,  3,     20\n\
-    ";
     let expected = "\
-        client, available, held, total, locked\n\
-             1,        10,    0,    10,  false\n\
-             2,         0,    0,     0,  false\n\
+        clients,total,held,locked,deposits,withdrawals,disputes,resolves,chargebacks,unlocks,skipped,largest_client,largest_total\n\
+        2,12.5,0,1,3,1,1,0,1,0,1,1,12.5\n\
     ";
-    assert_stdout_eq(input, expected);
+    assert_eq!(expected, std::fs::read_to_string(stats).unwrap());
 }
 
 #[test]
-fn test_dispute_when_no_resolve_or_chargeback_does_change_available_and_held_funds() {
-    let input = "\
+fn test_summary_prints_to_stderr() {
+    let mut file = NamedTempFile::new().unwrap();
+    write!(
+        file,
+        "\
         type,      client, tx, amount\n\
         deposit,        1,  1,     10\n\
-        deposit,        2,  2,     20\n\
-        deposit,        1,  3,      5\n\
-        dispute,        1,  3,       \n\
+        deposit,        2,  2,    7.5\n\
         dispute,        2,  2,       \n\
-        deposit,        1,  4,      1\n\
-        deposit,        2,  5,      1\n\
-    ";
-    let expected = "\
-        client, available, held, total, locked\n\
-             1,        11,    5,    16,  false\n\
-             2,         1,   20,    21,  false\n\
-    ";
-    assert_stdout_eq(input, expected);
+        chargeback,     2,  2,       \n\
+        deposit,        3,  3,    2.5\n\
+    "
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("rusty-bank").unwrap();
+    cmd.arg("--summary")
+        .arg(file.path())
+        .assert()
+        .stdout(predicate::str::contains("Summary").not())
+        .stderr(predicate::str::contains(
+            "Summary: 3 accounts, 1 locked, total 12.5, held 0, largest 10 (client 1)",
+        ))
+        .success();
 }
 
 #[test]
-fn test_dispute_when_withdrawal_does_not_change_available_and_held_funds() {
+fn test_spill_dir_when_spilled_still_disputes() {
     let input = "\
-        type,      client, tx, amount\n\
-        deposit,        1,  1,     10\n\
-        withdrawal,     1,  3,      5\n\
-        dispute,        1,  3,       \n\
-    ";
+        type,       client, tx, amount\n\
+        deposit,         1,  1,     10\n\
+        deposit,         2,  2,     20\n\
+        deposit,         1,  3,      5\n\
+        deposit,         2,  4,      1\n\
+        dispute,         1,  1,       \n\
+        dispute,         2,  2,       \n\
+        dispute,         2,  4,       \n\
+        resolve,         1,  1,       \n\
+        chargeback,      2,  2,       \n\
+    ";
+    let dir = tempfile::tempdir().unwrap();
     let expected = "\
         client, available, held, total, locked\n\
-             1,         5,    0,     5,  false\n\
-    ";
-    assert_stdout_eq(input, expected);
+             1,        15,    0,    15,  false\n\
+             2,         1,    0,     1,   true\n\
+    ";
+    let args = [
+        "--spill-dir",
+        dir.path().to_str().unwrap(),
+        "--spill-threshold",
+        "1",
+    ];
+    assert_stdout_eq_with_args(&args, input, expected, true);
 }
 
 #[test]
-fn test_dispute_when_insufficient_funds_does_change_funds() {
-    let input = "\
+fn test_disputes_output_writes_every_case() {
+    let mut file = NamedTempFile::new().unwrap();
+    write!(
+        file,
+        "\
         type,      client, tx, amount\n\
         deposit,        1,  1,     10\n\
-        withdrawal,     1,  3,     10\n\
-        dispute,        1,  1,       \n\
-    ";
+        deposit,        1,  2,      5\n\
+        deposit,        2,  3,     20\n\
+        dispute,        1,  2,       \n\
+        resolve,        1,  2,       \n\
+        dispute,        2,  3,       \n\
+    "
+    )
+    .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let disputes = dir.path().join("disputes.csv");
+
     let expected = "\
-        client, available, held, total, locked\n\
-             1,       -10,   10,     0,  false\n\
+        1,15,0,15,false\n\
+        2,0,20,20,false\n\
+        client,available,held,total,locked";
+    let output = sorted_stdout(
+        Command::cargo_bin("rusty-bank")
+            .unwrap()
+            .args(["--disputes-output", disputes.to_str().unwrap()])
+            .arg(file.path()),
+    );
+    assert_eq!(expected, output);
+
+    let expected = "\
+        tx,client,amount,status\n\
+        2,1,5,resolved\n\
+        3,2,20,open\n\
     ";
-    assert_stdout_eq(input, expected);
+    assert_eq!(expected, std::fs::read_to_string(disputes).unwrap());
 }
 
 #[test]
-fn test_dispute_when_transaction_does_not_exist_does_not_change_available_and_held_funds() {
-    let input = "\
-        type,      client, tx, amount\n\
-        dispute,        1,  1,       \n\
-        deposit,        1,  2,     10\n\
-        dispute,        1,  5,       \n\
-    ";
+fn test_output_writes_accounts_to_file_not_stdout() {
+    let mut file = NamedTempFile::new().unwrap();
+    write!(file, "type,client,tx,amount\ndeposit,1,1,10\n").unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("accounts.csv");
+
+    Command::cargo_bin("rusty-bank")
+        .unwrap()
+        .args(["--output", output.to_str().unwrap()])
+        .arg(file.path())
+        .assert()
+        .success()
+        .stdout("");
+
     let expected = "\
-        client, available, held, total, locked\n\
-             1,        10,    0,    10,  false\n\
+        client,available,held,total,locked\n\
+        1,10,0,10,false\n\
     ";
-    assert_stdout_eq(input, expected);
+    assert_eq!(expected, std::fs::read_to_string(&output).unwrap());
+    // only the output remains, the temporary file having been renamed over it
+    assert_eq!(1, std::fs::read_dir(dir.path()).unwrap().count());
 }
 
 #[test]
-fn test_dispute_when_transaction_does_not_match_client_id_does_not_change_available_and_held_funds()
-{
-    let input = "\
-        type,      client, tx, amount\n\
-        deposit,        1,  1,     10\n\
-        dispute,        2,  1,       \n\
-    ";
-    let expected = "\
-        client, available, held, total, locked\n\
-             1,        10,    0,    10,  false\n\
-    ";
-    assert_stdout_eq(input, expected);
+fn test_output_when_export_fails_leaves_no_partial_file() {
+    let mut file = NamedTempFile::new().unwrap();
+    write!(file, "type,client,tx,amount\ndeposit,1,1,10\n").unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("accounts.csv");
+    // the statistics cannot be saved, failing the export after the accounts are written
+    let stats = dir.path().join("missing").join("stats.csv");
+
+    Command::cargo_bin("rusty-bank")
+        .unwrap()
+        .args(["--output", output.to_str().unwrap()])
+        .args(["--stats", stats.to_str().unwrap()])
+        .arg(file.path())
+        .assert()
+        .failure();
+
+    assert!(!output.exists());
+    assert_eq!(0, std::fs::read_dir(dir.path()).unwrap().count());
 }
 
 #[test]
-fn test_resolve_when_dispute_does_change_available_and_held_funds() {
-    let input = "\
-        type,      client, tx, amount\n\
-        deposit,        1,  1,     10\n\
-        deposit,        2,  2,     20\n\
-        deposit,        1,  3,      5\n\
-        dispute,        1,  1,       \n\
-        dispute,        2,  2,       \n\
-        resolve,        1,  1,       \n\
-        resolve,        2,  2,       \n\
-    ";
+fn test_output_format_json_writes_json_lines() {
+    let mut file = NamedTempFile::new().unwrap();
+    write!(
+        file,
+        "type,client,tx,amount\n\
+        deposit,1,1,10.25\n\
+        deposit,2,2,5\n\
+        dispute,2,2,\n\
+        chargeback,2,2,\n"
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("rusty-bank").unwrap();
+    cmd.args(["--output-format", "json"]).arg(file.path());
+
     let expected = "\
-        client, available, held, total, locked\n\
-             1,        15,    0,    15,  false\n\
-             2,        20,    0,    20,  false\n\
+        {\"client\":1,\"available\":\"10.25\",\"held\":\"0\",\"total\":\"10.25\",\"locked\":false}\n\
+        {\"client\":2,\"available\":\"0\",\"held\":\"0\",\"total\":\"0\",\"locked\":true}\
     ";
-    assert_stdout_eq(input, expected);
+    assert_eq!(expected, sorted_stdout(&mut cmd));
 }
 
 #[test]
-fn test_resolve_when_transaction_does_not_exist_does_not_change_available_and_held_funds() {
-    let input = "\
-        type,      client, tx, amount\n\
-        resolve,        1,  1,       \n\
-        resolve,        2,  2,       \n\
-        deposit,        2,  2,     20\n\
-    ";
+fn test_output_format_json_array_writes_one_array() {
+    let mut file = NamedTempFile::new().unwrap();
+    write!(
+        file,
+        "type,client,tx,amount\n\
+        deposit,1,1,10.25\n"
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("rusty-bank").unwrap();
+    cmd.args(["--output-format", "json-array"]).arg(file.path());
+
     let expected = "\
-        client, available, held, total, locked\n\
-             2,        20,    0,    20,  false\n\
+        [{\"client\":1,\"available\":\"10.25\",\"held\":\"0\",\"total\":\"10.25\",\"locked\":false}]\n\
     ";
-    assert_stdout_eq(input, expected);
+    cmd.assert().success().stdout(expected);
 }
 
 #[test]
-fn test_resolve_when_transaction_does_not_match_client_id_does_not_change_available_and_held_funds()
-{
-    let input = "\
-        type,      client, tx, amount\n\
-        deposit,        1,  1,     10\n\
-        dispute,        1,  1,       \n\
-        resolve,        2,  1,       \n\
-    ";
-    let expected = "\
-        client, available, held, total, locked\n\
-             1,         0,   10,    10,  false\n\
-    ";
-    assert_stdout_eq(input, expected);
+#[cfg(feature = "file-store")]
+fn test_store_keeps_accounts_between_runs() {
+    let store = tempfile::tempdir().unwrap();
+    let mut first = NamedTempFile::new().unwrap();
+    write!(
+        first,
+        "type,client,tx,amount\n\
+        deposit,1,1,10\n\
+        deposit,2,2,5\n\
+        deposit,3,3,1\n"
+    )
+    .unwrap();
+    let mut second = NamedTempFile::new().unwrap();
+    write!(
+        second,
+        "type,client,tx,amount\n\
+        withdrawal,1,4,3\n\
+        dispute,1,1,\n\
+        deposit,2,2,5\n\
+        deposit,2,5,1.5\n"
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("rusty-bank").unwrap();
+    cmd.arg("--store").arg(store.path()).arg(first.path());
+    assert_eq!(
+        "1,10,0,10,false\n\
+        2,5,0,5,false\n\
+        3,1,0,1,false\n\
+        client,available,held,total,locked",
+        sorted_stdout(&mut cmd)
+    );
+
+    // the dispute references a deposit from the first run, and its duplicate is still skipped
+    let mut cmd = Command::cargo_bin("rusty-bank").unwrap();
+    cmd.arg("--store").arg(store.path()).arg(second.path());
+    assert_eq!(
+        "1,-3,10,7,false\n\
+        2,6.5,0,6.5,false\n\
+        3,1,0,1,false\n\
+        client,available,held,total,locked",
+        sorted_stdout(&mut cmd)
+    );
 }
 
 #[test]
-fn test_resolve_when_dispute_does_not_exist_does_not_change_available_and_held_funds() {
-    let input = "\
-        type,      client, tx, amount\n\
-        deposit,        1,  1,     10\n\
-        resolve,        1,  1,       \n\
-        deposit,        2,  2,     20\n\
-        resolve,        2,  2,       \n\
-        dispute,        2,  2,       \n\
-        resolve,        2,  2,       \n\
-    ";
-    let expected = "\
-        client, available, held, total, locked\n\
-             1,        10,    0,    10,  false\n\
-             2,        20,    0,    20,  false\n\
-    ";
-    assert_stdout_eq(input, expected);
+#[cfg(feature = "file-store")]
+fn test_store_when_corrupted() {
+    let store = tempfile::tempdir().unwrap();
+    std::fs::write(store.path().join("accounts"), "not an account store").unwrap();
+    let mut file = NamedTempFile::new().unwrap();
+    write!(file, "type,client,tx,amount\ndeposit,1,1,10\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("rusty-bank").unwrap();
+    cmd.arg("--store")
+        .arg(store.path())
+        .arg(file.path())
+        .assert()
+        .stderr(predicate::str::contains("Corrupted store"))
+        .stderr(predicate::str::contains("Not an account store"))
+        .failure();
 }
 
 #[test]
-fn test_duplicate_dispute_before_resolve_is_ignored() {
-    let input = "\
-        type,      client, tx, amount\n\
-        deposit,        1,  1,     10\n\
-        deposit,        1,  2,      5\n\
-        dispute,        1,  1,       \n\
-        dispute,        1,  1,       \n\
-        resolve,        1,  1,       \n\
-    ";
-    let expected = "\
-        client, available, held, total, locked\n\
-             1,        15,    0,    15,  false\n\
-    ";
-    assert_stdout_eq(input, expected);
+#[cfg(feature = "sqlite-store")]
+fn test_sqlite_store_keeps_accounts_between_runs() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = format!("sqlite:{}", dir.path().join("accounts.db").display());
+    let mut first = NamedTempFile::new().unwrap();
+    write!(
+        first,
+        "type,client,tx,amount\n\
+        deposit,1,1,10\n\
+        deposit,2,2,5\n"
+    )
+    .unwrap();
+    let mut second = NamedTempFile::new().unwrap();
+    write!(
+        second,
+        "type,client,tx,amount\n\
+        withdrawal,1,3,3\n\
+        dispute,1,1,\n\
+        deposit,2,2,5\n"
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("rusty-bank").unwrap();
+    cmd.arg("--store").arg(&store).arg(first.path());
+    assert_eq!(
+        "1,10,0,10,false\n\
+        2,5,0,5,false\n\
+        client,available,held,total,locked",
+        sorted_stdout(&mut cmd)
+    );
+
+    let mut cmd = Command::cargo_bin("rusty-bank").unwrap();
+    cmd.arg("--store").arg(&store).arg(second.path());
+    assert_eq!(
+        "1,-3,10,7,false\n\
+        2,5,0,5,false\n\
+        client,available,held,total,locked",
+        sorted_stdout(&mut cmd)
+    );
 }
 
 #[test]
-fn test_duplicate_dispute_after_resolve_is_ignored() {
-    let input = "\
-        type,      client, tx, amount\n\
-        deposit,        1,  1,     10\n\
-        deposit,        1,  2,      5\n\
-        dispute,        1,  1,       \n\
-        resolve,        1,  1,       \n\
-        dispute,        1,  1,       \n\
-    ";
+fn test_format_jsonl_reads_json_lines() {
+    let mut file = NamedTempFile::new().unwrap();
+    let lines = [
+        r#"{"type":"deposit","client":1,"tx":1,"amount":"10.5"}"#,
+        r#"{"type":"withdrawal","client":1,"tx":2,"amount":"0.5"}"#,
+        r#"{"type":"deposit","client":2,"tx":3,"amount":"2"}"#,
+    ];
+    writeln!(file, "{}", lines.join("\n")).unwrap();
+
+    let mut cmd = Command::cargo_bin("rusty-bank").unwrap();
+    cmd.args(["--format", "jsonl"]).arg(file.path());
+
     let expected = "\
-        client, available, held, total, locked\n\
-             1,        15,    0,    15,  false\n\
+        1,10,0,10,false\n\
+        2,2,0,2,false\n\
+        client,available,held,total,locked\
     ";
-    assert_stdout_eq(input, expected);
+    assert_eq!(expected, sorted_stdout(&mut cmd));
+}
+
+/// Runs once with the input piped to stdin and once with it in a file, returning both stdouts.
+fn stdin_and_file_stdout(args: &[&str], input: &str) -> (String, String) {
+    let output = assert_cmd::Command::cargo_bin("rusty-bank")
+        .unwrap()
+        .args(args)
+        .arg("-")
+        .write_stdin(input)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let from_stdin = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .sorted()
+        .join("\n");
+
+    let mut file = NamedTempFile::new().unwrap();
+    write!(file, "{}", input).unwrap();
+    let from_file = sorted_stdout(
+        Command::cargo_bin("rusty-bank")
+            .unwrap()
+            .args(args)
+            .arg(file.path()),
+    );
+    (from_stdin, from_file)
 }
 
 #[test]
-fn test_chargeback_when_dispute_does_change_total_and_held_funds_and_freeze() {
+fn test_stdin_matches_file() {
     let input = "\
-        type,      client, tx, amount\n\
-        deposit,        1,  1,     10\n\
-        deposit,        1,  2,      5\n\
-        dispute,        1,  2,       \n\
-        chargeback,     1,  2,       \n\
+        type,client,tx,amount\n\
+        deposit,1,1,10\n\
+        deposit,2,2,3\n\
+        withdrawal,1,3,4\n\
+        dispute,2,2,\n\
     ";
+    let (from_stdin, from_file) = stdin_and_file_stdout(&[], input);
     let expected = "\
-        client, available, held, total, locked\n\
-             1,        10,    0,    10,   true\n\
+        1,6,0,6,false\n\
+        2,0,3,3,false\n\
+        client,available,held,total,locked\
     ";
-    assert_stdout_eq(input, expected);
+    assert_eq!(expected, from_stdin);
+    assert_eq!(from_file, from_stdin);
 }
 
 #[test]
-fn test_chargeback_when_transaction_does_not_exist_does_not_change_total_and_held_funds() {
-    let input = "\
-        type,      client, tx, amount\n\
-        chargeback,     1,  1,       \n\
-        deposit,        1,  2,     10\n\
-        chargeback,     1,  1,       \n\
-        dispute,        1,  3,       \n\
-        chargeback,     1,  3,       \n\
-    ";
-    let expected = "\
-        client, available, held, total, locked\n\
-             1,        10,    0,    10,  false\n\
-    ";
-    assert_stdout_eq(input, expected);
+fn test_stdin_when_empty() {
+    for input in ["", "type,client,tx,amount\n"] {
+        let (from_stdin, from_file) = stdin_and_file_stdout(&[], input);
+        assert_eq!(from_file, from_stdin);
+    }
 }
 
 #[test]
-fn test_chargeback_when_transaction_does_not_match_client_id_does_not_change_total_and_held_funds()
-{
-    let input = "\
-        type,      client, tx, amount\n\
-        deposit,        1,  2,      5\n\
-        dispute,        1,  2,       \n\
-        chargeback,     2,  2,       \n\
-    ";
-    let expected = "\
-        client, available, held, total, locked\n\
-             1,         0,    5,     5,  false\n\
-    ";
-    assert_stdout_eq(input, expected);
+fn test_stdin_when_json_lines() {
+    let input = r#"{"type":"deposit","client":1,"tx":1,"amount":"2.5"}"#;
+    let (from_stdin, from_file) = stdin_and_file_stdout(&["--format", "jsonl"], input);
+    assert_eq!(
+        "1,2.5,0,2.5,false\nclient,available,held,total,locked",
+        from_stdin
+    );
+    assert_eq!(from_file, from_stdin);
 }
 
 #[test]
-fn test_chargeback_when_dispute_does_not_exist_does_not_change_total_and_held_funds() {
+fn test_rejected_writes_every_rejected_record() {
+    let mut file = NamedTempFile::new().unwrap();
     let input = "\
-        type,      client, tx, amount\n\
-        deposit,        1,  2,     10\n\
-        chargeback,     1,  2,       \n\
+        type,client,tx,amount\n\
+        deposit,1,1,10\n\
+        withdrawal,1,2,20\n\
+        deposit,1,x,5\n\
+        dispute,1,99,\n\
+        dispute,2,1,\n\
+        dispute,1,1,\n\
+        dispute,1,1,\n\
+        chargeback,1,1,\n\
+        deposit,1,3,5\n\
+        deposit,2,4,\n\
     ";
+    write!(file, "{}", input).unwrap();
+    let rejected = NamedTempFile::new().unwrap();
+
+    Command::cargo_bin("rusty-bank")
+        .unwrap()
+        .arg("--rejected")
+        .arg(rejected.path())
+        .arg(file.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1,0,0,0,true"));
+
     let expected = "\
-        client, available, held, total, locked\n\
-             1,        10,    0,    10,  false\n\
-    ";
-    assert_stdout_eq(input, expected);
+        line,type,client,tx,amount,reason\n\
+        3,withdrawal,1,2,20,insufficient_funds\n\
+        4,,,,,malformed\n\
+        5,dispute,1,99,,unknown_transaction\n\
+        6,dispute,2,1,,client_mismatch\n\
+        8,dispute,1,1,,duplicate_dispute\n\
+        10,deposit,1,3,5,account_locked\n\
+        11,deposit,2,4,,malformed\n\
+    ";
+    assert_eq!(expected, std::fs::read_to_string(rejected.path()).unwrap());
 }
 
 #[test]
-fn test_chargeback_when_insufficient_funds_does_change_total_funds_to_negative_and_decrease_held_funds_and_freeze(
-) {
+fn test_anomalies_writes_every_anomaly() {
+    let mut file = NamedTempFile::new().unwrap();
     let input = "\
-        type,      client, tx, amount\n\
-        deposit,        1,  1,      5\n\
-        withdrawal,     1,  2,      2\n\
-        dispute,        1,  1,       \n\
-        chargeback,     1,  1,       \n\
+        type,client,tx,amount\n\
+        deposit,1,1,10\n\
+        deposit,1,2,5\n\
+        dispute,1,99,\n\
+        dispute,2,1,\n\
+        dispute,1,1,\n\
+        dispute,1,1,\n\
+        resolve,2,1,\n\
+        chargeback,1,1,\n\
+        dispute,1,2,\n\
     ";
+    write!(file, "{}", input).unwrap();
+    let anomalies = NamedTempFile::new().unwrap();
+
+    Command::cargo_bin("rusty-bank")
+        .unwrap()
+        .arg("--anomalies")
+        .arg(anomalies.path())
+        .arg(file.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1,5,0,5,true"));
+
     let expected = "\
-        client, available, held, total, locked\n\
-             1,        -2,    0,    -2,   true\n\
-    ";
-    assert_stdout_eq(input, expected);
+        type,anomaly,client,tx,expected_client\n\
+        dispute,unknown_transaction,,99,\n\
+        dispute,client_mismatch,2,1,1\n\
+        dispute,duplicate_dispute,,1,\n\
+        resolve,client_mismatch,2,1,1\n\
+        dispute,action_on_locked_account,1,2,\n\
+    ";
+    assert_eq!(expected, std::fs::read_to_string(anomalies.path()).unwrap());
 }
 
 #[test]
-fn test_duplicate_dispute_before_chargeback_does_decrease_funds_and_freeze() {
-    let input = "\
-        type,      client, tx, amount\n\
-        deposit,        1,  1,      5\n\
-        dispute,        1,  1,       \n\
-        dispute,        1,  1,       \n\
-        chargeback,     1,  1,       \n\
-    ";
-    let expected = "\
-        client, available, held, total, locked\n\
-             1,         0,    0,     0,   true\n\
-    ";
-    assert_stdout_eq(input, expected);
+fn test_failure_when_unknown_option() {
+    let mut cmd = Command::cargo_bin("rusty-bank").unwrap();
+    cmd.args(["--frobnicate", "a.csv"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Unknown option: --frobnicate"))
+        .stderr(predicate::str::contains("Usage: "));
+}
+
+/// Runs to completion and returns stdout with the account rows sorted.
+fn sorted_stdout(cmd: &mut Command) -> String {
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .sorted()
+        .join("\n")
 }
 
 #[test]
-fn test_any_transaction_after_chargeback_is_ignored() {
-    let input = "\
-        type,      client, tx, amount\n\
-        deposit,        1,  0,     10\n\
-        deposit,        1,  1,      5\n\
-        dispute,        1,  1,       \n\
-        chargeback,     1,  1,       \n\
-        dispute,        1,  1,       \n\
-        deposit,        1,  2,     50\n\
-        deposit,        1,  3,     20\n\
-        dispute,        1,  3,       \n\
-        withdrawal,     1,  4,      3\n\
-        dispute,        1,  2,       \n\
-        resolve,        1,  2,       \n\
-        chargeback,     1,  3,       \n\
-        deposit,        2,  5,     80\n\
-    ";
-    let expected = "\
-        client, available, held, total, locked\n\
-             1,        10,    0,    10,   true\n\
-             2,        80,    0,    80,  false\n\
-    ";
-    assert_stdout_eq(input, expected);
+fn test_checkpoint_when_killed_resumes_with_same_output() {
+    let mut file = NamedTempFile::new().unwrap();
+    writeln!(file, "type,client,tx,amount").unwrap();
+    for tx in 1..=20_000u32 {
+        let client = tx % 97;
+        match tx % 10 {
+            // dispute, then resolve or chargeback, an earlier deposit by the same client
+            3 => writeln!(file, "dispute,{},{},", client, tx.saturating_sub(3 * 97)),
+            5 if tx % 4 == 1 => {
+                writeln!(file, "chargeback,{},{},", client, tx.saturating_sub(5 * 97))
+            }
+            5 => writeln!(file, "resolve,{},{},", client, tx.saturating_sub(5 * 97)),
+            7 => writeln!(file, "withdrawal,{},{},{}.5", client, tx, tx % 13),
+            _ => writeln!(file, "deposit,{},{},{}.25", client, tx, tx % 17),
+        }
+        .unwrap();
+    }
+
+    let expected = sorted_stdout(Command::cargo_bin("rusty-bank").unwrap().arg(file.path()));
+
+    let dir = tempfile::tempdir().unwrap();
+    let checkpoint = dir.path().join("run.checkpoint");
+    let args = [
+        "--checkpoint",
+        checkpoint.to_str().unwrap(),
+        "--checkpoint-interval",
+        "1000",
+        file.path().to_str().unwrap(),
+    ];
+
+    // interrupt the run as soon as the first checkpoint is written
+    let mut child = Command::cargo_bin("rusty-bank")
+        .unwrap()
+        .args(args)
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+    let started = Instant::now();
+    while !checkpoint.exists() {
+        assert!(started.elapsed() < Duration::from_secs(30), "no checkpoint");
+        thread::sleep(Duration::from_millis(1));
+    }
+    child.kill().unwrap();
+    child.wait().unwrap();
+    assert!(checkpoint.exists(), "run completed before it was killed");
+
+    let resumed = sorted_stdout(Command::cargo_bin("rusty-bank").unwrap().args(args));
+    assert_eq!(expected, resumed);
+    assert!(!checkpoint.exists());
 }
 
 #[test]
-fn test_resolve_after_chargeback_is_ignored() {
-    let input = "\
-        type,      client, tx, amount\n\
-        deposit,        1,  1,      5\n\
-        dispute,        1,  1,       \n\
-        chargeback,     1,  1,       \n\
-        resolve,        1,  1,       \n\
-    ";
-    let expected = "\
-        client, available, held, total, locked\n\
-             1,         0,    0,     0,   true\n\
-    ";
-    assert_stdout_eq(input, expected);
+fn test_idempotency_journal_when_run_twice_has_same_output() {
+    let mut file = NamedTempFile::new().unwrap();
+    write!(
+        file,
+        "\
+        type,client,tx,amount\n\
+        deposit,1,1,10\n\
+        deposit,2,2,5\n\
+        withdrawal,1,3,15\n\
+        deposit,1,4,10\n\
+        dispute,2,2,\n\
+        chargeback,2,2,\n\
+        dispute,1,1,\n\
+        "
+    )
+    .unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let journal = dir.path().join("run.journal");
+    let args = [
+        "--idempotency-journal",
+        journal.to_str().unwrap(),
+        file.path().to_str().unwrap(),
+    ];
+
+    let once = sorted_stdout(Command::cargo_bin("rusty-bank").unwrap().args(args));
+    let twice = sorted_stdout(Command::cargo_bin("rusty-bank").unwrap().args(args));
+    assert_eq!(once, twice);
+    assert_eq!(
+        "1,10,10,20,false\n2,0,0,0,true\nclient,available,held,total,locked",
+        once
+    );
 }
 
 #[test]
-fn test_chargeback_after_resolve_is_ignored() {
+fn test_output_mode_deltas_when_resumed_from_journal_has_changes_of_run() {
+    let mut first = NamedTempFile::new().unwrap();
+    write!(
+        first,
+        "\
+        type,client,tx,amount\n\
+        deposit,1,1,10\n\
+        deposit,2,2,5\n\
+        deposit,3,3,1\n\
+        "
+    )
+    .unwrap();
+    let mut second = NamedTempFile::new().unwrap();
+    write!(
+        second,
+        "\
+        type,client,tx,amount\n\
+        withdrawal,1,4,2.5\n\
+        dispute,2,2,\n\
+        chargeback,2,2,\n\
+        withdrawal,3,5,100\n\
+        deposit,4,6,7\n\
+        "
+    )
+    .unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let journal = dir.path().join("run.journal");
+    let journal = journal.to_str().unwrap();
+    sorted_stdout(Command::cargo_bin("rusty-bank").unwrap().args([
+        "--idempotency-journal",
+        journal,
+        first.path().to_str().unwrap(),
+    ]));
+    let deltas = sorted_stdout(Command::cargo_bin("rusty-bank").unwrap().args([
+        "--idempotency-journal",
+        journal,
+        "--output-mode",
+        "deltas",
+        second.path().to_str().unwrap(),
+    ]));
+
+    assert_eq!(
+        "1,-2.5,0,-2.5,false\n\
+        2,-5,0,-5,true\n\
+        4,7,0,7,false\n\
+        client,available,held,total,locked_changed",
+        deltas
+    );
+}
+
+#[test]
+fn test_messy_input_is_skipped_without_strict() {
+    let mut cmd = Command::cargo_bin("rusty-bank").unwrap();
+    cmd.arg("tests/fixtures/messy.csv").assert().success();
+}
+
+#[test]
+fn test_strict_fails_at_first_malformed_record() {
+    let mut cmd = Command::cargo_bin("rusty-bank").unwrap();
+    cmd.args(["--strict", "tests/fixtures/messy.csv"])
+        .assert()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains(
+            "Error: Stopped at record 3: Could not read transaction record: \
+            CSV deserialize error: unknown variant `borrow`",
+        ))
+        .stderr(predicate::str::contains("at line 5: borrow,1,3,5"))
+        .failure();
+}
+
+#[test]
+fn test_invalid_ids_are_logged_with_value_and_line() {
+    let mut file = NamedTempFile::new().unwrap();
+    write!(
+        file,
+        "type, client, tx, amount\n\
+        deposit, 1, 1, 10\n\
+        deposit, 70000, 2, 5\n\
+        deposit, 1, abc, 5\n\
+        deposit, 2, 3, 1\n"
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("rusty-bank").unwrap();
+    cmd.env("RUST_LOG", "error")
+        .arg(file.path())
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1,10,0,10,false\n2,1,0,1,false\n")
+        .stderr(predicate::str::contains(
+            "client id 70000 out of range 0..=65535 at line 3: deposit,70000,2,5",
+        ))
+        .stderr(predicate::str::contains(
+            "transaction id 'abc' is not a number at line 4: deposit,1,abc,5",
+        ));
+}
+
+#[test]
+fn test_strict_fails_at_invalid_transaction() {
+    let mut file = NamedTempFile::new().unwrap();
+    write!(
+        file,
+        "type,client,tx,amount\n\
+        deposit,1,1,10\n\
+        withdrawal,1,2,\n"
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("rusty-bank").unwrap();
+    cmd.arg("--strict")
+        .arg(file.path())
+        .assert()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains("Expected amount"))
+        .stderr(predicate::str::contains("at line 3: withdrawal,1,2,"))
+        .failure();
+}
+
+#[test]
+fn test_validate_only_reports_problems_by_line() {
+    let mut cmd = Command::cargo_bin("rusty-bank").unwrap();
+    let output = cmd
+        .args(["--validate-only", "tests/fixtures/messy.csv"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .starts_with("Error: Found 11 problems in 15 records"));
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let problems: Vec<_> = stdout.lines().collect();
+    let expected = [
+        "Line 5: CSV deserialize error",
+        "Line 6: Expected positive amount",
+        "Line 7: Transaction ID 2 was already used on line 3",
+        "Line 8: Dispute of transaction 9, which is not an earlier deposit or withdrawal",
+        "Line 9: Dispute by client 2 of transaction 1, which was deposited by client 1",
+        "Line 10: Resolve of transaction 1, which has no open dispute",
+        "Line 12: Transaction 1 has already been disputed",
+        "Line 14: Resolve of transaction 1, which has no open dispute",
+        "Line 15: CSV error",
+        "Line 16: Expected amount",
+        "Line 17: Unexpected amount field",
+    ];
+    assert_eq!(expected.len(), problems.len(), "{}", stdout);
+    for (expected, problem) in expected.iter().zip(problems) {
+        assert!(problem.starts_with(expected), "{}", problem);
+    }
+}
+
+#[test]
+fn test_validate_only_when_valid_has_no_output() {
+    let mut file = NamedTempFile::new().unwrap();
+    write!(
+        file,
+        "\
+        type,client,tx,amount\n\
+        deposit,1,1,10\n\
+        dispute,1,1,\n\
+        "
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("rusty-bank").unwrap();
+    cmd.arg("--validate-only")
+        .arg(file.path())
+        .assert()
+        .stdout("")
+        .success();
+}
+
+#[test]
+#[cfg(feature = "xlsx")]
+fn test_xlsx_input() {
+    let mut cmd = Command::cargo_bin("rusty-bank").unwrap();
+    let output = sorted_stdout(cmd.arg("tests/fixtures/transactions.xlsx"));
+    assert_eq!(
+        "1,2.725,0,2.725,false\n\
+        2,0.0000,1234.5678,1234.5678,false\n\
+        3,10,0,10,false\n\
+        client,available,held,total,locked",
+        output
+    );
+}
+
+#[test]
+fn test_gzipped_input_matches_plain() {
     let input = "\
-        type,      client, tx, amount\n\
-        deposit,        1,  1,      5\n\
-        dispute,        1,  1,       \n\
-        resolve,        1,  1,       \n\
-        chargeback,     1,  1,       \n\
+        type,       client, tx, amount\n\
+        deposit,         1,  1,     10\n\
+        withdrawal,      1,  2,      4\n\
+        deposit,         2,  3,      5\n\
+        dispute,         2,  3,       \n\
     ";
-    let expected = "\
-        client, available, held, total, locked\n\
-             1,         5,    0,     5,  false\n\
-    ";
-    assert_stdout_eq(input, expected);
+    let mut file = NamedTempFile::with_suffix(".csv.gz").unwrap();
+    let mut encoder = GzEncoder::new(&mut file, Compression::default());
+    encoder.write_all(input.as_bytes()).unwrap();
+    encoder.finish().unwrap();
+
+    let mut cmd = Command::cargo_bin("rusty-bank").unwrap();
+    cmd.arg(file.path())
+        .assert()
+        .stdout("client,available,held,total,locked\n1,6,0,6,false\n2,0,5,5,false\n")
+        .success();
+}
+
+#[test]
+fn test_failure_when_gzipped_input_with_checkpoint() {
+    let mut file = NamedTempFile::with_suffix(".csv.gz").unwrap();
+    let mut encoder = GzEncoder::new(&mut file, Compression::default());
+    encoder.write_all(b"type,client,tx,amount\n").unwrap();
+    encoder.finish().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+
+    let mut cmd = Command::cargo_bin("rusty-bank").unwrap();
+    cmd.arg("--checkpoint")
+        .arg(dir.path().join("run.checkpoint"))
+        .arg(file.path())
+        .assert()
+        .stderr(predicate::str::contains(
+            "Error: --checkpoint cannot be used with gzip compressed input",
+        ))
+        .failure();
+}
+
+#[test]
+fn test_multiple_files_share_account_and_dispute_state() {
+    let mut first = NamedTempFile::new().unwrap();
+    write!(
+        first,
+        "type,client,tx,amount\ndeposit,1,1,10\ndeposit,2,2,3\n"
+    )
+    .unwrap();
+    let mut second = NamedTempFile::new().unwrap();
+    write!(
+        second,
+        "type,client,tx,amount\ndispute,1,1,\nwithdrawal,2,3,1\n"
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("rusty-bank").unwrap();
+    cmd.arg(first.path())
+        .arg(second.path())
+        .assert()
+        .stdout("client,available,held,total,locked\n1,0,10,10,false\n2,2,0,2,false\n")
+        .success();
+}
+
+#[test]
+fn test_directory_files_read_in_name_order() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("2024-01-01T10.csv"),
+        "type,client,tx,amount\nchargeback,1,1,\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.path().join("2024-01-01T09.csv"),
+        "type,client,tx,amount\ndeposit,1,1,10\ndispute,1,1,\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("rusty-bank").unwrap();
+    cmd.arg(dir.path())
+        .assert()
+        .stdout("client,available,held,total,locked\n1,0,0,0,true\n")
+        .success();
+}
+
+#[test]
+fn test_failure_when_multiple_files_with_checkpoint() {
+    let first = NamedTempFile::new().unwrap();
+    let second = NamedTempFile::new().unwrap();
+
+    let mut cmd = Command::cargo_bin("rusty-bank").unwrap();
+    cmd.args(["--checkpoint", "run.checkpoint"])
+        .arg(first.path())
+        .arg(second.path())
+        .assert()
+        .stderr(predicate::str::contains(
+            "Error: --checkpoint cannot be used with more than one input file",
+        ))
+        .failure();
 }
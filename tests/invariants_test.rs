@@ -0,0 +1,262 @@
+//! Property tests asserting account invariants hold for arbitrary transaction streams.
+//!
+//! Every store is wrapped in a [`CheckingAccountStore`], so the invariants are also asserted
+//! after each operation the processor applies, not only on the accounts exported.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use anyhow::{anyhow, Result};
+use proptest::prelude::*;
+use rust_decimal::Decimal;
+
+use rusty_bank::{
+    AccountStore, AccountSummary, AccountVisitor, CheckingAccountStore, ClientId,
+    InMemoryAccountStore, TransactionId, TransactionProcessor, TransactionProcessorBuilder,
+    TransactionReader, TransactionRecord, TransactionType, VecAccountWriter,
+};
+
+/// Replays a fixed list of records.
+struct ReplayReader(Vec<Result<TransactionRecord, String>>);
+
+impl TransactionReader for ReplayReader {
+    fn read<'a>(&'a mut self) -> Box<dyn Iterator<Item = Result<TransactionRecord>> + 'a> {
+        Box::new(
+            self.0
+                .iter()
+                .map(|result| result.clone().map_err(|err| anyhow!(err))),
+        )
+    }
+}
+
+/// Net funds moved by operations the store accepted.
+#[derive(Default)]
+struct Ledger {
+    added: Decimal,
+    removed: Decimal,
+    charged_back: Decimal,
+}
+
+/// Wraps an [`InMemoryAccountStore`], recording the funds moved by each successful operation.
+struct LedgerStore {
+    store: InMemoryAccountStore,
+    ledger: Rc<RefCell<Ledger>>,
+}
+
+impl AccountStore for LedgerStore {
+    fn add_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.store.add_funds(client, amount)?;
+        self.ledger.borrow_mut().added += amount;
+        Ok(())
+    }
+
+    fn remove_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.store.remove_funds(client, amount)?;
+        self.ledger.borrow_mut().removed += amount;
+        Ok(())
+    }
+
+    fn chargeback_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.store.chargeback_funds(client, amount)?;
+        self.ledger.borrow_mut().charged_back += amount;
+        Ok(())
+    }
+
+    fn force_chargeback_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.store.force_chargeback_funds(client, amount)?;
+        self.ledger.borrow_mut().charged_back += amount;
+        Ok(())
+    }
+
+    fn hold_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.store.hold_funds(client, amount)
+    }
+
+    fn release_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.store.release_funds(client, amount)
+    }
+
+    fn credit_held_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.store.credit_held_funds(client, amount)?;
+        self.ledger.borrow_mut().added += amount;
+        Ok(())
+    }
+
+    fn remove_held_funds(&mut self, client: ClientId, amount: Decimal) -> Result<()> {
+        self.store.remove_held_funds(client, amount)?;
+        self.ledger.borrow_mut().removed += amount;
+        Ok(())
+    }
+
+    fn lock(&mut self, client: ClientId) -> Result<()> {
+        self.store.lock(client)
+    }
+
+    fn unlock(&mut self, client: ClientId) -> Result<()> {
+        self.store.unlock(client)
+    }
+
+    fn account(&self, client: ClientId) -> Option<rusty_bank::Account> {
+        self.store.account(client)
+    }
+
+    fn for_each_account(&self, visit: &mut AccountVisitor) -> Result<()> {
+        self.store.for_each_account(visit)
+    }
+
+    fn export(self) -> Box<dyn Iterator<Item = rusty_bank::Account>> {
+        self.store.export()
+    }
+}
+
+/// Processes the records and returns the exported accounts along with the ledger of funds moved.
+fn run(records: &[Result<TransactionRecord, String>]) -> (HashMap<u16, AccountSummary>, Ledger) {
+    let ledger = Rc::new(RefCell::new(Ledger::default()));
+    let store = LedgerStore {
+        store: InMemoryAccountStore::new(),
+        ledger: Rc::clone(&ledger),
+    };
+
+    let mut processor = TransactionProcessor::new(CheckingAccountStore::new(store));
+    processor.process(ReplayReader(records.to_vec()));
+
+    let mut writer = VecAccountWriter::new();
+    processor.export(&mut writer).unwrap();
+    let accounts = writer
+        .into_accounts()
+        .into_iter()
+        .map(|account| (account.client().0, account))
+        .collect();
+
+    let ledger = Rc::try_unwrap(ledger).ok().unwrap().into_inner();
+    (accounts, ledger)
+}
+
+fn transaction_type() -> impl Strategy<Value = TransactionType> {
+    prop_oneof![
+        3 => Just(TransactionType::Deposit),
+        2 => Just(TransactionType::Withdrawal),
+        2 => Just(TransactionType::Dispute),
+        1 => Just(TransactionType::Resolve),
+        1 => Just(TransactionType::Chargeback),
+        1 => Just(TransactionType::Unlock),
+    ]
+}
+
+fn amount() -> impl Strategy<Value = Option<Decimal>> {
+    prop_oneof![
+        8 => (1..100_000i64).prop_map(|cents| Some(Decimal::new(cents, 2))),
+        1 => (-100i64..=0).prop_map(|cents| Some(Decimal::new(cents, 2))),
+        1 => Just(None),
+    ]
+}
+
+/// A mix of valid and invalid records over a small set of clients, referencing transaction IDs
+/// below `max_tx`.
+fn record(max_tx: u32) -> impl Strategy<Value = Result<TransactionRecord, String>> {
+    let record = (transaction_type(), 0..4u16, 0..max_tx, amount()).prop_map(
+        |(transaction_type, client, tx, amount)| {
+            let amount = match transaction_type {
+                TransactionType::Deposit | TransactionType::Withdrawal => amount,
+                // mostly well-formed, occasionally with an unexpected amount
+                _ => amount.filter(|amount| *amount < Decimal::ZERO),
+            };
+            TransactionRecord::new(
+                transaction_type,
+                ClientId(client),
+                TransactionId(tx),
+                amount,
+            )
+        },
+    );
+    prop_oneof![
+        20 => record.prop_map(Ok),
+        1 => Just(Err("unreadable record".to_string())),
+    ]
+}
+
+/// Up to `max` records, where deposits and withdrawals mostly have the transaction ID of their
+/// position, so disputes, resolves and chargebacks reference a transaction before or after them,
+/// and occasionally duplicate an earlier ID.
+fn records(max: usize) -> impl Strategy<Value = Vec<Result<TransactionRecord, String>>> {
+    let unique = prop::bool::weighted(0.9);
+    prop::collection::vec((record(max as u32), unique), 0..max).prop_map(|records| {
+        records
+            .into_iter()
+            .enumerate()
+            .map(|(i, (result, unique))| {
+                result.map(|mut record| {
+                    if let (TransactionType::Deposit | TransactionType::Withdrawal, true) =
+                        (record.transaction_type, unique)
+                    {
+                        record.tx = TransactionId(i as u32);
+                    }
+                    record
+                })
+            })
+            .collect()
+    })
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(256))]
+
+    #[test]
+    fn test_total_is_available_plus_held(records in records(64)) {
+        let (accounts, _) = run(&records);
+        for account in accounts.values() {
+            prop_assert_eq!(account.total(), account.available() + account.held());
+        }
+    }
+
+    #[test]
+    fn test_held_is_never_negative(records in records(64)) {
+        let (accounts, _) = run(&records);
+        for account in accounts.values() {
+            prop_assert!(account.held() >= Decimal::ZERO, "{:?}", account);
+        }
+    }
+
+    #[test]
+    fn test_totals_equal_deposits_less_withdrawals_and_chargebacks(
+        records in records(64)
+    ) {
+        let (accounts, ledger) = run(&records);
+        let total: Decimal = accounts.values().map(AccountSummary::total).sum();
+        prop_assert_eq!(ledger.added - ledger.removed - ledger.charged_back, total);
+    }
+
+    #[test]
+    fn test_locked_accounts_never_change(records in records(32)) {
+        let mut frozen: HashMap<u16, AccountSummary> = HashMap::new();
+        for n in 0..=records.len() {
+            let (accounts, _) = run(&records[..n]);
+            for (client, account) in frozen.iter() {
+                prop_assert_eq!(Some(account), accounts.get(client));
+            }
+            for (client, account) in accounts {
+                if account.locked() {
+                    frozen.entry(client).or_insert(account);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_checked_under_any_policies(
+        records in records(64),
+        withdrawal_disputes in any::<bool>(),
+        dispute_expiry in prop::option::of(1..8u64),
+        overdraft_cents in 0..10_000i64,
+    ) {
+        let store = InMemoryAccountStore::with_overdraft_limit(Decimal::new(overdraft_cents, 2));
+        let mut processor = TransactionProcessorBuilder::new()
+            .allow_withdrawal_disputes(withdrawal_disputes)
+            .dispute_expiry(dispute_expiry)
+            .build(CheckingAccountStore::new(store))
+            .with_admin_ops(true);
+        processor.process(ReplayReader(records));
+        processor.export(VecAccountWriter::new()).unwrap();
+    }
+}
@@ -0,0 +1,72 @@
+//! Runs the library pipeline in process over every scenario in `tests/fixtures/golden` which
+//! needs no extra arguments, and compares the accounts written with the golden file alongside.
+
+use std::fs;
+use std::path::Path;
+
+use itertools::Itertools;
+use rust_decimal_macros::dec;
+
+use rusty_bank::{
+    run_csv_file, run_pipeline, AccountSummary, ClientId, InMemoryAccountStore, TransactionId,
+    TransactionRecord, VecAccountWriter, VecTransactionReader,
+};
+
+const INPUT_SUFFIX: &str = ".input.csv";
+
+/// Returns the header and then the sorted account rows.
+fn sorted(output: &str) -> String {
+    let mut lines = output.lines();
+    let header = lines.next();
+    header
+        .into_iter()
+        .chain(lines.sorted())
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+#[test]
+fn test_run_csv_file_matches_golden_files() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/golden");
+
+    let mut scenarios = 0;
+    for entry in fs::read_dir(&dir).unwrap() {
+        let input = entry.unwrap().path();
+        let file_name = input.file_name().unwrap().to_string_lossy();
+        let Some(name) = file_name.strip_suffix(INPUT_SUFFIX) else {
+            continue;
+        };
+        if dir.join(format!("{}.args", name)).exists() {
+            continue;
+        }
+        scenarios += 1;
+
+        let (_, output) = run_csv_file(&input, Vec::new()).unwrap();
+        let expected = fs::read_to_string(dir.join(format!("{}.expected.csv", name))).unwrap();
+        assert_eq!(
+            expected,
+            sorted(&String::from_utf8(output).unwrap()),
+            "{}",
+            name
+        );
+    }
+    assert!(scenarios > 0, "No scenarios found in {:?}", dir);
+}
+
+#[test]
+fn test_run_pipeline_in_memory() {
+    let reader = VecTransactionReader::new(vec![
+        TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(10)),
+        TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+        TransactionRecord::chargeback(ClientId(1), TransactionId(1)),
+        TransactionRecord::deposit(ClientId(1), TransactionId(2), dec!(5)),
+    ]);
+    let mut writer = VecAccountWriter::new();
+
+    let report = run_pipeline(reader, InMemoryAccountStore::new(), &mut writer).unwrap();
+    assert_eq!(4, report.records);
+    assert_eq!(
+        vec![AccountSummary::new(ClientId(1), dec!(0), dec!(0), true).unwrap()],
+        writer.into_accounts()
+    );
+}
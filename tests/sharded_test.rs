@@ -0,0 +1,122 @@
+//! Asserts that sharded processing matches processing the same records in order.
+
+use rust_decimal::Decimal;
+
+use rusty_bank::{
+    AccountSummary, ClientId, InMemoryAccountStore, ProcessingReport, ShardedTransactionProcessor,
+    SkipReason, SummaryStatistics, TransactionId, TransactionProcessor, TransactionRecord,
+    TransactionType, VecAccountWriter, VecTransactionReader,
+};
+
+/// A deterministic pseudo-random number generator, so failures can be reproduced.
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self, bound: u32) -> u32 {
+        self.0 = self
+            .0
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        ((self.0 >> 33) % bound as u64) as u32
+    }
+}
+
+/// Generates deposits, withdrawals and disputes of them across many clients, where some
+/// deposits reuse another transaction's ID and some are missing their amount.
+///
+/// Disputes, resolves and chargebacks only reference the client's own transactions.
+fn generate(records: usize, clients: u32) -> Vec<TransactionRecord> {
+    let mut rng = Lcg(42);
+    let mut transactions: Vec<Vec<u32>> = vec![Vec::new(); clients as usize];
+    let mut generated = Vec::with_capacity(records);
+    let mut next_tx = 0;
+    while generated.len() < records {
+        let client = rng.next(clients);
+        let own = &mut transactions[client as usize];
+        let record = |transaction_type, tx, amount| {
+            TransactionRecord::new(
+                transaction_type,
+                ClientId(client as u16),
+                TransactionId(tx),
+                amount,
+            )
+        };
+        let amount = Some(Decimal::new(rng.next(100_000) as i64 + 1, 2));
+        generated.push(match rng.next(100) {
+            0..=44 => {
+                next_tx += 1;
+                own.push(next_tx);
+                record(TransactionType::Deposit, next_tx, amount)
+            }
+            45..=69 => {
+                next_tx += 1;
+                own.push(next_tx);
+                record(TransactionType::Withdrawal, next_tx, amount)
+            }
+            70..=71 if next_tx > 0 => {
+                record(TransactionType::Deposit, rng.next(next_tx) + 1, amount)
+            }
+            72 => {
+                next_tx += 1;
+                record(TransactionType::Deposit, next_tx, None)
+            }
+            n if !own.is_empty() => {
+                let tx = own[rng.next(own.len() as u32) as usize];
+                let transaction_type = match n {
+                    73..=89 => TransactionType::Dispute,
+                    90..=97 => TransactionType::Resolve,
+                    _ => TransactionType::Chargeback,
+                };
+                record(transaction_type, tx, None)
+            }
+            _ => continue,
+        });
+    }
+    generated
+}
+
+fn sorted(writer: VecAccountWriter) -> Vec<AccountSummary> {
+    let mut accounts = writer.into_accounts();
+    accounts.sort_by_key(|account| account.client().0);
+    accounts
+}
+
+fn run_sequential(
+    records: &[TransactionRecord],
+) -> (ProcessingReport, Vec<AccountSummary>, SummaryStatistics) {
+    let mut processor =
+        TransactionProcessor::new(InMemoryAccountStore::new()).with_untouched_accounts(true);
+    let report = processor.process(VecTransactionReader::new(records.to_vec()));
+    let mut writer = VecAccountWriter::new();
+    let statistics = processor.export(&mut writer).unwrap();
+    (report, sorted(writer), statistics)
+}
+
+fn run_sharded(
+    records: &[TransactionRecord],
+    shards: usize,
+) -> (ProcessingReport, Vec<AccountSummary>, SummaryStatistics) {
+    let mut processor = ShardedTransactionProcessor::new(shards, || {
+        Ok(TransactionProcessor::new(InMemoryAccountStore::new()).with_untouched_accounts(true))
+    });
+    let report = processor.process(VecTransactionReader::new(records.to_vec()));
+    let mut writer = VecAccountWriter::new();
+    let statistics = processor.export(&mut writer).unwrap();
+    (report, sorted(writer), statistics)
+}
+
+#[test]
+fn test_sharded_matches_sequential_for_large_input() {
+    let records = generate(200_000, 1_000);
+    let (report, accounts, statistics) = run_sequential(&records);
+    assert!(report.skipped(SkipReason::DuplicateTransaction) > 0);
+    assert!(report.malformed > 0);
+    assert!(accounts.iter().any(AccountSummary::locked));
+
+    for shards in [1, 4, 7] {
+        let (sharded_report, sharded_accounts, sharded_statistics) = run_sharded(&records, shards);
+        assert_eq!(report, sharded_report, "shards={}", shards);
+        assert_eq!(accounts, sharded_accounts, "shards={}", shards);
+        assert_eq!(statistics, sharded_statistics, "shards={}", shards);
+    }
+}
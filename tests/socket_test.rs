@@ -0,0 +1,110 @@
+//! Exercises reading transaction streams from sockets, and the daemon mode of the binary.
+#![cfg(unix)]
+
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use assert_cmd::prelude::*;
+use rust_decimal_macros::dec;
+use rusty_bank::{
+    AccountSummary, ClientId, InMemoryAccountStore, SocketTransactionReader, StreamFormat,
+    TransactionProcessor, VecAccountWriter,
+};
+
+#[test]
+fn test_socket_reader_over_unix_stream_pair() {
+    let (mut sender, receiver) = UnixStream::pair().unwrap();
+    let writer = thread::spawn(move || {
+        sender
+            .write_all(b"type,client,tx,amount\ndeposit,1,1,10\n")
+            .unwrap();
+        sender
+            .write_all(b"withdrawal,1,2,4\ndispute,1,1,\n")
+            .unwrap();
+        // dropped part way through a record, which is discarded
+        sender.write_all(b"deposit,2,3,1").unwrap();
+    });
+
+    let mut processor = TransactionProcessor::new(InMemoryAccountStore::new());
+    processor.process(SocketTransactionReader::from_reader(
+        receiver,
+        StreamFormat::Csv,
+    ));
+    writer.join().unwrap();
+
+    let mut snapshot = VecAccountWriter::new();
+    processor.snapshot(&mut snapshot).unwrap();
+    assert_eq!(
+        vec![AccountSummary::new(ClientId(1), dec!(10), dec!(6), false).unwrap()],
+        snapshot.into_accounts()
+    );
+}
+
+/// Connects to the daemon, once it is listening, and sends the input.
+fn send(socket: &Path, input: &str) {
+    let started = Instant::now();
+    let mut stream = loop {
+        match UnixStream::connect(socket) {
+            Ok(stream) => break stream,
+            Err(_) => {
+                assert!(started.elapsed() < Duration::from_secs(30), "not listening");
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+    };
+    stream.write_all(input.as_bytes()).unwrap();
+}
+
+/// Waits until the file has the given number of lines.
+fn wait_for_lines(path: &Path, lines: usize) -> String {
+    let started = Instant::now();
+    loop {
+        let contents = std::fs::read_to_string(path).unwrap_or_default();
+        if contents.lines().count() >= lines {
+            return contents;
+        }
+        assert!(started.elapsed() < Duration::from_secs(30), "{}", contents);
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn test_listen_appends_snapshot_per_connection() {
+    let dir = tempfile::tempdir().unwrap();
+    let socket = dir.path().join("rusty-bank.sock");
+    let snapshots = dir.path().join("snapshots.csv");
+
+    let mut child = Command::cargo_bin("rusty-bank")
+        .unwrap()
+        .args(["--listen", socket.to_str().unwrap()])
+        .args(["--snapshots", snapshots.to_str().unwrap()])
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    send(
+        &socket,
+        "type,client,tx,amount\ndeposit,1,1,10\ndeposit,2,2,5\n",
+    );
+    wait_for_lines(&snapshots, 3);
+    send(
+        &socket,
+        "type,client,tx,amount\nwithdrawal,1,3,2.5\ndeposit,2,4,",
+    );
+    let contents = wait_for_lines(&snapshots, 6);
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    let mut snapshots = contents.split("client,available,held,total,locked\n");
+    assert_eq!(Some(""), snapshots.next());
+    let mut first: Vec<_> = snapshots.next().unwrap().lines().collect();
+    first.sort_unstable();
+    assert_eq!(vec!["1,10,0,10,false", "2,5,0,5,false"], first);
+    let mut second: Vec<_> = snapshots.next().unwrap().lines().collect();
+    second.sort_unstable();
+    assert_eq!(vec!["1,7.5,0,7.5,false", "2,5,0,5,false"], second);
+}
@@ -0,0 +1,93 @@
+//! Exercises validation rules supplied by a library consumer.
+
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use rusty_bank::{
+    AccountSummary, ClientId, InMemoryAccountStore, MaxAmountValidator, Transaction, TransactionId,
+    TransactionProcessor, TransactionRecord, TransactionValidator, ValidationContext,
+    ValidationError, VecAccountWriter, VecTransactionReader,
+};
+
+/// Rejects deposits which would take a client's balance over a limit.
+struct MaxBalanceValidator(Decimal);
+
+impl TransactionValidator for MaxBalanceValidator {
+    fn validate(&self, tx: &Transaction, ctx: &ValidationContext) -> Result<(), ValidationError> {
+        let deposit = match tx {
+            Transaction::Deposit(deposit) => deposit,
+            _ => return Ok(()),
+        };
+        let total = ctx
+            .account()
+            .map_or(Decimal::ZERO, |account| account.total());
+        match total + deposit.amount > self.0 {
+            true => Err(ValidationError::new("Balance would be over the limit")),
+            false => Ok(()),
+        }
+    }
+}
+
+#[test]
+fn test_custom_validator_sees_account() {
+    let reader = VecTransactionReader::new(vec![
+        TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(60)),
+        // rejected, as the balance would be 110
+        TransactionRecord::deposit(ClientId(1), TransactionId(2), dec!(50)),
+        TransactionRecord::withdrawal(ClientId(1), TransactionId(3), dec!(20)),
+        TransactionRecord::deposit(ClientId(1), TransactionId(4), dec!(50)),
+        // rejected, as it is over the maximum amount
+        TransactionRecord::withdrawal(ClientId(1), TransactionId(5), dec!(75)),
+    ]);
+
+    let mut processor =
+        TransactionProcessor::new(InMemoryAccountStore::new()).with_validators(vec![
+            Box::new(MaxBalanceValidator(dec!(100))),
+            Box::new(MaxAmountValidator::new(dec!(70))),
+        ]);
+    processor.process(reader);
+
+    let mut writer = VecAccountWriter::new();
+    let statistics = processor.export(&mut writer).unwrap();
+    assert_eq!(
+        vec![AccountSummary::new(ClientId(1), dec!(0), dec!(90), false).unwrap()],
+        writer.into_accounts()
+    );
+    assert_eq!(2, statistics.skipped);
+}
+
+/// Rejects every transaction once a client has any funds.
+struct SingleDepositValidator;
+
+impl TransactionValidator for SingleDepositValidator {
+    fn validate(&self, _tx: &Transaction, ctx: &ValidationContext) -> Result<(), ValidationError> {
+        match ctx
+            .account()
+            .is_some_and(|account| account.total() > Decimal::ZERO)
+        {
+            true => Err(ValidationError::new("Only a single deposit is allowed")),
+            false => Ok(()),
+        }
+    }
+}
+
+#[test]
+fn test_custom_validator_does_not_block_disputes() {
+    let reader = VecTransactionReader::new(vec![
+        TransactionRecord::deposit(ClientId(1), TransactionId(1), dec!(100)),
+        TransactionRecord::deposit(ClientId(1), TransactionId(2), dec!(5)),
+        TransactionRecord::dispute(ClientId(1), TransactionId(1)),
+        TransactionRecord::chargeback(ClientId(1), TransactionId(1)),
+    ]);
+
+    let mut processor = TransactionProcessor::new(InMemoryAccountStore::new())
+        .with_validators(vec![Box::new(SingleDepositValidator)]);
+    processor.process(reader);
+
+    let mut writer = VecAccountWriter::new();
+    let statistics = processor.export(&mut writer).unwrap();
+    assert_eq!(
+        vec![AccountSummary::new(ClientId(1), dec!(0), dec!(0), true).unwrap()],
+        writer.into_accounts()
+    );
+    assert_eq!(1, statistics.skipped);
+}